@@ -0,0 +1,316 @@
+//! The host side of the link: framing, and the sequence numbers that get an
+//! [`Ack`] back for every request.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::Path,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use rover_lib::timesync::{ClockSync, SyncSample};
+use rover_proto::{framing, Ack, AckCode, Hello, RxBody, RxMessage, TxMessage, PROTOCOL_VERSION};
+
+use crate::{
+    protocol::{self, Encoding},
+    serial,
+};
+
+const ACK_TIMEOUT: Duration = Duration::from_millis(500);
+const ATTEMPTS: usize = 3;
+const SYNC_SPACING: Duration = Duration::from_millis(20);
+
+/// The host clock for [`RxBody::TimeSync`], in ms since the Unix epoch to
+/// compare with host logs.
+pub fn host_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Encode,
+    /// No answer, even after retrying.
+    Timeout,
+    Nack(AckCode),
+    Closed,
+    /// No [`Hello`] back, the firmware predating the handshake.
+    NoHello,
+    /// The protocol version of the rover.
+    Incompatible(u16),
+    /// The move was stopped before its end.
+    Aborted,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "link error: {e}"),
+            Self::Encode => write!(f, "message too long to encode"),
+            Self::Timeout => write!(f, "no answer from the rover"),
+            Self::Nack(code) => write!(f, "refused by the rover: {code:?}"),
+            Self::Closed => write!(f, "link closed"),
+            Self::NoHello => write!(f, "no hello from the rover, its firmware is too old"),
+            Self::Incompatible(protocol) => write!(
+                f,
+                "the rover speaks protocol version {protocol}, this tool {PROTOCOL_VERSION}: \
+                 update the older one"
+            ),
+            Self::Aborted => write!(f, "aborted"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+pub struct Link {
+    tx: Box<dyn Write + Send>,
+    rx: mpsc::Receiver<TxMessage>,
+    /// Received while waiting for an ACK.
+    pending: VecDeque<TxMessage>,
+    encoding: Encoding,
+    seq: u32,
+    /// The rover talked to, others on the bus being ignored.
+    address: u8,
+}
+
+impl Link {
+    /// `port` is a serial device, or `tcp:<address>` for the simulator.
+    pub fn open(port: &str, baud: u32, encoding: Encoding, address: u8) -> io::Result<Self> {
+        let (tx, rx): (Box<dyn Write + Send>, Box<dyn Read + Send>) =
+            match port.strip_prefix("tcp:") {
+                Some(address) => {
+                    let stream = TcpStream::connect(address)?;
+                    (Box::new(stream.try_clone()?), Box::new(stream))
+                }
+                None => {
+                    let file = serial::open(Path::new(port), baud)?;
+                    (Box::new(file.try_clone()?), Box::new(file))
+                }
+            };
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || receive(rx, address, sender));
+        Ok(Self {
+            tx,
+            rx: receiver,
+            pending: VecDeque::new(),
+            encoding,
+            seq: 0,
+            address,
+        })
+    }
+
+    fn write_to(&mut self, address: u8, msg: &RxMessage) -> Result<(), Error> {
+        let packet = protocol::encode(address, msg, self.encoding).ok_or(Error::Encode)?;
+        self.tx.write_all(&packet)?;
+        Ok(())
+    }
+
+    fn write(&mut self, msg: &RxMessage) -> Result<(), Error> {
+        self.write_to(self.address, msg)
+    }
+
+    /// Sends `body` once to every rover on the bus, none answering.
+    pub fn broadcast(&mut self, body: RxBody) -> Result<(), Error> {
+        self.write_to(
+            framing::BROADCAST,
+            &RxMessage {
+                seq: None,
+                body,
+                at_ms: None,
+            },
+        )
+    }
+
+    /// Sends `body` once, without asking for an ACK.
+    pub fn send(&mut self, body: RxBody) -> Result<(), Error> {
+        self.write(&RxMessage {
+            seq: None,
+            body,
+            at_ms: None,
+        })
+    }
+
+    /// Sends `body` until it's acknowledged, a NACK being an error. Whatever
+    /// else arrives meanwhile is kept for [`receive`](Self::receive).
+    pub fn request(&mut self, body: RxBody) -> Result<(), Error> {
+        self.request_at(body, None)
+    }
+
+    /// Like [`request`](Self::request), for the rover to hold `body` until
+    /// its uptime reaches `at_ms`, see [`sync_clock`](Self::sync_clock).
+    pub fn request_at(&mut self, body: RxBody, at_ms: Option<u64>) -> Result<(), Error> {
+        self.seq = self.seq.wrapping_add(1);
+        let msg = RxMessage {
+            seq: Some(self.seq),
+            body,
+            at_ms,
+        };
+
+        for _ in 0..ATTEMPTS {
+            self.write(&msg)?;
+            let deadline = Instant::now() + ACK_TIMEOUT;
+            while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+                match self.rx.recv_timeout(timeout) {
+                    Ok(TxMessage::Ack(Ack { seq, code })) if seq == self.seq => {
+                        return match code {
+                            AckCode::Ok => Ok(()),
+                            code => Err(Error::Nack(code)),
+                        };
+                    }
+                    // A late one, for an attempt already given up on
+                    Ok(TxMessage::Ack(_)) => {}
+                    Ok(other) => self.pending.push_back(other),
+                    Err(mpsc::RecvTimeoutError::Timeout) => break,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return Err(Error::Closed),
+                }
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Sends `body` once and gathers the replies `part` picks out, which
+    /// may take a while: the rover streams them ahead of the ACK, so only
+    /// silence times out.
+    pub fn request_all(
+        &mut self,
+        body: RxBody,
+        part: impl Fn(&TxMessage) -> bool,
+    ) -> Result<Vec<TxMessage>, Error> {
+        self.seq = self.seq.wrapping_add(1);
+        self.write(&RxMessage {
+            seq: Some(self.seq),
+            body,
+            at_ms: None,
+        })?;
+
+        let mut parts = Vec::new();
+        let mut deadline = Instant::now() + ACK_TIMEOUT;
+        while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+            match self.rx.recv_timeout(timeout) {
+                Ok(TxMessage::Ack(Ack { seq, code })) if seq == self.seq => {
+                    return match code {
+                        AckCode::Ok => Ok(parts),
+                        code => Err(Error::Nack(code)),
+                    };
+                }
+                Ok(msg) if part(&msg) => {
+                    parts.push(msg);
+                    deadline = Instant::now() + ACK_TIMEOUT;
+                }
+                Ok(TxMessage::Ack(_)) => {}
+                Ok(other) => self.pending.push_back(other),
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err(Error::Closed),
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Exchanges [`Hello`]s, failing unless the rover speaks this protocol
+    /// version.
+    pub fn hello(&mut self) -> Result<Hello, Error> {
+        match self.request(RxBody::Hello {
+            protocol: PROTOCOL_VERSION,
+        }) {
+            Ok(()) | Err(Error::Nack(AckCode::Incompatible)) => {}
+            Err(Error::Timeout) => return Err(Error::NoHello),
+            Err(e) => return Err(e),
+        }
+        // Sent just before the ACK
+        let position = self
+            .pending
+            .iter()
+            .position(|msg| matches!(msg, TxMessage::Hello(_)));
+        let Some(TxMessage::Hello(hello)) = position.and_then(|i| self.pending.remove(i)) else {
+            return Err(Error::NoHello);
+        };
+        if hello.protocol != PROTOCOL_VERSION {
+            return Err(Error::Incompatible(hello.protocol));
+        }
+        Ok(hello)
+    }
+
+    /// Lines the rover's uptime up with [`host_ms`] over `exchanges`
+    /// [`RxBody::TimeSync`]s, skipping those going unanswered. Anything
+    /// else arriving meanwhile is dropped.
+    pub fn sync_clock(&mut self, exchanges: u32) -> Result<ClockSync, Error> {
+        let mut clock = ClockSync::new();
+        for i in 0..exchanges {
+            if i > 0 {
+                // Apart, for no late answer to be taken for the next
+                thread::sleep(SYNC_SPACING);
+            }
+            let sent_ms = host_ms();
+            self.send(RxBody::TimeSync(sent_ms))?;
+            loop {
+                match self.receive_timeout(ACK_TIMEOUT) {
+                    Ok(TxMessage::TimeSync(reply)) if reply.host_ms == sent_ms => {
+                        clock.add(SyncSample::new(sent_ms, reply.rover_ms, host_ms()));
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(Error::Timeout) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        match clock.best() {
+            Some(_) => Ok(clock),
+            None => Err(Error::Timeout),
+        }
+    }
+
+    /// The next message, `None` for the link closed.
+    pub fn receive(&mut self) -> Option<TxMessage> {
+        self.pending.pop_front().or_else(|| self.rx.recv().ok())
+    }
+
+    /// Like [`receive`](Self::receive), giving up after `timeout`.
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Result<TxMessage, Error> {
+        if let Some(msg) = self.pending.pop_front() {
+            return Ok(msg);
+        }
+        self.rx.recv_timeout(timeout).map_err(|e| match e {
+            mpsc::RecvTimeoutError::Timeout => Error::Timeout,
+            mpsc::RecvTimeoutError::Disconnected => Error::Closed,
+        })
+    }
+}
+
+/// Splits the byte stream into packets on the zero terminators, until it
+/// closes, keeping those from the rover at `address`.
+fn receive(mut rx: Box<dyn Read + Send>, address: u8, sender: mpsc::Sender<TxMessage>) {
+    let mut packet = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let n = match rx.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        for &byte in &buf[..n] {
+            if byte != 0 {
+                packet.push(byte);
+                continue;
+            }
+            if let Some((_, msg)) = protocol::decode(&packet).filter(|&(from, _)| from == address) {
+                if sender.send(msg).is_err() {
+                    return;
+                }
+            }
+            packet.clear();
+        }
+    }
+}
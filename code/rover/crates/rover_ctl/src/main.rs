@@ -0,0 +1,576 @@
+//! Drives and configures the rover from a host, over its serial link or the
+//! simulator: the reference implementation of the host side of the protocol.
+
+mod link;
+mod protocol;
+mod ros;
+mod serial;
+
+use std::{
+    io::Write,
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant},
+};
+
+use uom::si::{angle::degree, f32::Length, length::meter};
+
+use rover_lib::{
+    event_log::Entry,
+    iface::{MecanumPower, Turn},
+    log::{Category, Level},
+    Angle, DriveFrame, GeoPoint, Move, MoveOutcome,
+};
+use rover_proto::{
+    framing, AckCode, AuxAction, ConfigMessage, DriveMessage, GpsRoute, Hello, NavEvent, Param,
+    ParamName, RxBody, TxMessage,
+};
+
+use link::{host_ms, Error, Link};
+use protocol::Encoding;
+
+const USAGE: &str = "\
+usage: rover_ctl [--binary] [--baud <rate>] [--id <rover id>] <port> <command>
+
+<port> is a serial device, or tcp:<address> for rover_sim. --id picks one of
+the rovers sharing the bus, 0 by default.
+
+commands:
+    arm|disarm                               drive commands are refused until armed
+    drive <power> <angle°> <turn> [seconds]  drive for a second by default, then stop
+    stop
+    estop [all]                              latch the e-stop, of every rover on the
+                                             bus with `all`, until clear-estop
+    move <meters> <angle°>                   drive a distance on odometry, 90° forward
+    rotate <angle°>                          turn on the spot, counter-clockwise
+    goto <lat,lon>...                        drive through GPS waypoints, in degrees
+    clear-estop
+    compass calibrate                        turn on the spot to calibrate the compass
+    config get
+    config set <name> <value>                e.g. `config set SlewRate 2.5`, in JSON
+    config save
+    frame robot|field                        frame of the drive angle
+    hello                                    firmware version and capabilities
+    link                                     link health every second, with the
+                                             round trip
+    ping [count]                             time the round trip, once a second
+    sync                                     the rover's uptime against this clock
+    at <unix seconds> <command>              run drive, stop, arm, disarm, move,
+                                             rotate or macro run at a time, on
+                                             every rover synced to this clock
+    log                                      raw defmt frames of a uart_log build,
+                                             for `| defmt-print -e <elf>`
+    log-level <category> <level>             e.g. `log-level comms warn`, until reset
+    macro list|run <name>|delete <name>
+    param list|get <name>
+    param set <name> <value>                 a single value of the config, by name
+    events dump|clear                        the fault log kept in flash
+    servo <index> <angle°>                   move an auxiliary servo, 0° centered
+    aux <name> on|off|toggle                 switch an AUX output, e.g. `aux lights on`
+    telemetry watch                          print everything received, in JSON
+    ros <host:port> [m/s] [°/s]              bridge to ROS 2 through rosbridge, the
+                                             speed and turn rate at full power,
+                                             1 m/s and 180°/s by default";
+
+/// Well within the default safety timeout.
+const DRIVE_PERIOD: Duration = Duration::from_millis(100);
+/// The quickest of them is kept.
+const SYNC_EXCHANGES: u32 = 8;
+const ESTOP_BROADCASTS: u32 = 3;
+
+fn main() -> ExitCode {
+    let mut encoding = Encoding::Json;
+    let mut baud = 115_200;
+    let mut id = 0;
+    let mut positional = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--binary" => encoding = Encoding::Binary,
+            "--baud" => match args.next().and_then(|b| b.parse().ok()) {
+                Some(b) => baud = b,
+                None => return usage(),
+            },
+            "--id" => match args.next().and_then(|id| id.parse().ok()) {
+                Some(arg) if arg != framing::BROADCAST => id = arg,
+                _ => return usage(),
+            },
+            _ => positional.push(arg),
+        }
+    }
+    let Some((port, command)) = positional.split_first() else {
+        return usage();
+    };
+    let command: Vec<&str> = command.iter().map(String::as_str).collect();
+
+    let mut link = match Link::open(port, baud, encoding, id) {
+        Ok(link) => link,
+        Err(e) => {
+            eprintln!("failed to open {port}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    // Nobody answers, to say hello first
+    if command == ["estop", "all"] {
+        return match estop_all(&mut link) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let hello = match link.hello() {
+        Ok(hello) => hello,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&mut link, &hello, &command) {
+        Some(Ok(())) => ExitCode::SUCCESS,
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+        None => usage(),
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{USAGE}");
+    ExitCode::FAILURE
+}
+
+/// `None` for a malformed command.
+fn run(link: &mut Link, hello: &Hello, command: &[&str]) -> Option<Result<(), Error>> {
+    let number = |arg: &str| arg.parse::<f32>().ok();
+    Some(match command {
+        ["drive", p, th, tu, rest @ ..] => {
+            let seconds = match rest {
+                [] => 1.0,
+                [seconds] => number(seconds)?,
+                _ => return None,
+            };
+            let drive = DriveMessage {
+                p: Some(MecanumPower::new(number(p)?)),
+                th: Some(Angle::new::<degree>(number(th)?)),
+                tu: Some(Turn::new(number(tu)?)),
+            };
+            drive_for(link, drive, Duration::from_secs_f32(seconds))
+        }
+        ["move", distance, angle] => run_move(
+            link,
+            Move::Drive {
+                distance: Length::new::<meter>(number(distance)?),
+                angle: Angle::new::<degree>(number(angle)?),
+            },
+        ),
+        ["rotate", angle] => run_move(
+            link,
+            Move::Rotate {
+                angle: Angle::new::<degree>(number(angle)?),
+            },
+        ),
+        ["goto", points @ ..] if !points.is_empty() => {
+            let point = |arg: &str| {
+                let (lat, lon) = arg.split_once(',')?;
+                let e7 = |deg: &str| Some((deg.parse::<f64>().ok()? * 1e7).round() as i32);
+                Some(GeoPoint {
+                    lat_e7: e7(lat)?,
+                    lon_e7: e7(lon)?,
+                })
+            };
+            let route = points
+                .iter()
+                .map(|arg| point(arg))
+                .collect::<Option<GpsRoute>>()?;
+            navigate_gps(link, route)
+        }
+        ["compass", "calibrate"] => compass_calibrate(link),
+        ["arm"] => link.request(RxBody::Arm(true)),
+        ["disarm"] => link.request(RxBody::Arm(false)),
+        ["stop"] => stop(link),
+        ["estop"] => link.request(RxBody::Estop),
+        ["clear-estop"] => link.request(RxBody::ClearEstop),
+        ["log-level", category, level] => link.request(RxBody::SetLogLevel {
+            category: Category::from_name(category)?,
+            level: Level::from_name(level)?,
+        }),
+        ["config", "get"] => config_get(link),
+        ["config", "set", name, value] => {
+            let value = serde_json::from_str(value)
+                .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+            let msg: ConfigMessage =
+                serde_json::from_value(serde_json::json!({ *name: value })).ok()?;
+            link.request(RxBody::Config(msg))
+        }
+        ["config", "save"] => link.request(RxBody::SaveConfig),
+        ["frame", frame] => link.request(RxBody::SetDriveFrame(match *frame {
+            "robot" => DriveFrame::Robot,
+            "field" => DriveFrame::Field,
+            _ => return None,
+        })),
+        ["hello"] => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(hello).unwrap_or_default()
+            );
+            Ok(())
+        }
+        ["link"] => watch_link(link),
+        ["ping", rest @ ..] => {
+            let count = match rest {
+                [] => 4,
+                [count] => count.parse().ok()?,
+                _ => return None,
+            };
+            ping(link, count)
+        }
+        ["sync"] => sync(link),
+        ["at", seconds, command @ ..] => {
+            let at_ms = (seconds.parse::<f64>().ok()? * 1000.0) as u64;
+            run_at(link, at_ms, &scheduled(command)?)
+        }
+        ["events", "dump"] => events_dump(link),
+        ["events", "clear"] => link.request(RxBody::ClearEvents),
+        ["param", "list"] => param_list(link),
+        ["param", "get", name] => param_get(link, (*name).try_into().ok()?).map(|param| {
+            println!("{}", param.value);
+        }),
+        ["param", "set", name, value] => {
+            let name: ParamName = (*name).try_into().ok()?;
+            param_get(link, name.clone()).and_then(|param| {
+                let Some(value) = param.value.parse_as(value) else {
+                    return Err(Error::Nack(AckCode::OutOfRange));
+                };
+                link.request(RxBody::SetParam(Param { name, value }))
+            })
+        }
+        ["servo", index, angle] => link.request(RxBody::SetServo {
+            index: index.parse().ok()?,
+            angle: Angle::new::<degree>(number(angle)?),
+        }),
+        ["aux", name, action] => link.request(RxBody::Aux {
+            name: (*name).try_into().ok()?,
+            action: match *action {
+                "on" => AuxAction::Set,
+                "off" => AuxAction::Clear,
+                "toggle" => AuxAction::Toggle,
+                _ => return None,
+            },
+        }),
+        ["macro", "list"] => macro_list(link),
+        ["macro", "run", name] => link.request(RxBody::RunMacro((*name).try_into().ok()?)),
+        ["macro", "delete", name] => link.request(RxBody::DeleteMacro((*name).try_into().ok()?)),
+        ["log"] => {
+            let mut stdout = std::io::stdout().lock();
+            while let Some(msg) = link.receive() {
+                let TxMessage::Log(frame) = msg else {
+                    continue;
+                };
+                if stdout
+                    .write_all(&frame)
+                    .and_then(|()| stdout.flush())
+                    .is_err()
+                {
+                    return Some(Ok(()));
+                }
+            }
+            Err(Error::Closed)
+        }
+        ["ros", address, limits @ ..] => ros::bridge(link, address, ros::limits(limits)?),
+        ["telemetry", "watch"] => {
+            let mut stdout = std::io::stdout().lock();
+            while let Some(msg) = link.receive() {
+                let json = serde_json::to_string(&msg).unwrap_or_default();
+                // Piped into something that quit
+                if writeln!(stdout, "{json}").is_err() {
+                    return Some(Ok(()));
+                }
+            }
+            Err(Error::Closed)
+        }
+        _ => return None,
+    })
+}
+
+/// Repeats `drive` to keep the safety timer from stopping the rover, stops
+/// after `duration`.
+fn drive_for(link: &mut Link, drive: DriveMessage, duration: Duration) -> Result<(), Error> {
+    let start = Instant::now();
+    let result = (|| {
+        while start.elapsed() < duration {
+            link.request(RxBody::Drive(drive))?;
+            thread::sleep(DRIVE_PERIOD);
+        }
+        Ok(())
+    })();
+    stop(link).and(result)
+}
+
+/// Waits for the end of the move.
+fn run_move(link: &mut Link, mv: Move) -> Result<(), Error> {
+    link.request(RxBody::Move(mv))?;
+    while let Some(msg) = link.receive() {
+        if let TxMessage::Move(outcome) = msg {
+            return match outcome {
+                MoveOutcome::Done => Ok(()),
+                MoveOutcome::Aborted => Err(Error::Aborted),
+            };
+        }
+    }
+    Err(Error::Closed)
+}
+
+/// Waits for the end of the route, printing the waypoints reached.
+fn navigate_gps(link: &mut Link, route: GpsRoute) -> Result<(), Error> {
+    link.request(RxBody::NavigateGps(route))?;
+    while let Some(msg) = link.receive() {
+        match msg {
+            TxMessage::Navigation(NavEvent::Reached { waypoint }) => {
+                println!("reached waypoint {waypoint}")
+            }
+            TxMessage::Navigation(NavEvent::Done) => return Ok(()),
+            TxMessage::Navigation(NavEvent::Cancelled) => return Err(Error::Aborted),
+            _ => {}
+        }
+    }
+    Err(Error::Closed)
+}
+
+/// Waits for the calibration, applied in RAM until `config save`.
+fn compass_calibrate(link: &mut Link) -> Result<(), Error> {
+    link.request(RxBody::CalibrateCompass)?;
+    while let Some(msg) = link.receive() {
+        if let TxMessage::CompassCalibration(calibration) = msg {
+            return match calibration {
+                Some(calibration) => {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&calibration).unwrap_or_default()
+                    );
+                    Ok(())
+                }
+                None => Err(Error::Aborted),
+            };
+        }
+    }
+    Err(Error::Closed)
+}
+
+/// Prints each [`TxMessage::LinkStats`], having stamped the link before it
+/// for the round trip.
+fn watch_link(link: &mut Link) -> Result<(), Error> {
+    let start = Instant::now();
+    let host_ms = || start.elapsed().as_millis() as u32;
+    loop {
+        link.request(RxBody::Stamp(host_ms()))?;
+        let stats = loop {
+            if let TxMessage::LinkStats(stats) = link.receive_timeout(Duration::from_secs(2))? {
+                break stats;
+            }
+        };
+        let round_trip = match stats.echo {
+            Some(echo) => format!("{} ms", echo.round_trip_ms(host_ms())),
+            None => "-".into(),
+        };
+        println!(
+            "{} frames, {} CRC errors, {} decode errors, {} overflows, {:.1} commands/s, \
+             round trip {round_trip}",
+            stats.frames,
+            stats.crc_errors,
+            stats.decode_errors,
+            stats.overflows,
+            stats.commands_per_second,
+        );
+    }
+}
+
+/// Sends `count` pings a second apart, each waiting for its pong.
+fn ping(link: &mut Link, count: u32) -> Result<(), Error> {
+    let start = Instant::now();
+    for i in 0..count {
+        if i > 0 {
+            thread::sleep(Duration::from_secs(1));
+        }
+        let sent_ms = start.elapsed().as_millis() as u32;
+        link.send(RxBody::Ping(sent_ms))?;
+        let pong = loop {
+            match link.receive_timeout(Duration::from_secs(1)) {
+                Ok(TxMessage::Pong(pong)) if pong.host_ms == sent_ms => break Some(pong),
+                Ok(_) => {}
+                Err(Error::Timeout) => break None,
+                Err(e) => return Err(e),
+            }
+        };
+        match pong {
+            Some(pong) => println!(
+                "pong in {} ms: {:?}, uptime {:.1} s",
+                (start.elapsed().as_millis() as u32).wrapping_sub(pong.host_ms),
+                pong.mode,
+                pong.uptime_ms as f32 / 1000.0,
+            ),
+            None => println!("no pong"),
+        }
+    }
+    Ok(())
+}
+
+fn sync(link: &mut Link) -> Result<(), Error> {
+    let clock = link.sync_clock(SYNC_EXCHANGES)?;
+    let Some(best) = clock.best() else {
+        return Err(Error::Timeout);
+    };
+    println!(
+        "rover uptime is host time {:+} ms, within {} ms",
+        best.offset_ms,
+        best.round_trip_ms.div_ceil(2)
+    );
+    if let Some(boot_ms) = clock.to_host(0) {
+        println!(
+            "booted at {:.3} s since the Unix epoch",
+            boot_ms as f64 / 1000.0
+        );
+    }
+    Ok(())
+}
+
+fn stop(link: &mut Link) -> Result<(), Error> {
+    link.request(RxBody::Drive(stop_message()))
+}
+
+/// Unacknowledged, so sent a few times against a packet lost.
+fn estop_all(link: &mut Link) -> Result<(), Error> {
+    for _ in 0..ESTOP_BROADCASTS {
+        link.broadcast(RxBody::Estop)?;
+        thread::sleep(DRIVE_PERIOD);
+    }
+    Ok(())
+}
+
+fn stop_message() -> DriveMessage {
+    DriveMessage {
+        p: Some(MecanumPower::new(0.0)),
+        ..Default::default()
+    }
+}
+
+/// The messages of a command for [`run_at`], each with its delay in ms.
+fn scheduled(command: &[&str]) -> Option<Vec<(u64, RxBody)>> {
+    let number = |arg: &str| arg.parse::<f32>().ok();
+    Some(match command {
+        ["drive", p, th, tu, rest @ ..] => {
+            let seconds = match rest {
+                [] => 1.0,
+                [seconds] => number(seconds)?,
+                _ => return None,
+            };
+            let drive = DriveMessage {
+                p: Some(MecanumPower::new(number(p)?)),
+                th: Some(Angle::new::<degree>(number(th)?)),
+                tu: Some(Turn::new(number(tu)?)),
+            };
+            vec![
+                (0, RxBody::Drive(drive)),
+                ((seconds * 1000.0) as u64, RxBody::Drive(stop_message())),
+            ]
+        }
+        ["stop"] => vec![(0, RxBody::Drive(stop_message()))],
+        ["arm"] => vec![(0, RxBody::Arm(true))],
+        ["disarm"] => vec![(0, RxBody::Arm(false))],
+        ["move", distance, angle] => vec![(
+            0,
+            RxBody::Move(Move::Drive {
+                distance: Length::new::<meter>(number(distance)?),
+                angle: Angle::new::<degree>(number(angle)?),
+            }),
+        )],
+        ["rotate", angle] => vec![(
+            0,
+            RxBody::Move(Move::Rotate {
+                angle: Angle::new::<degree>(number(angle)?),
+            }),
+        )],
+        ["macro", "run", name] => vec![(0, RxBody::RunMacro((*name).try_into().ok()?))],
+        _ => return None,
+    })
+}
+
+/// Has the rover hold `messages` until `at_ms` by this clock, then pings it
+/// past the last so the safety timer doesn't stop it. Several hosts synced to
+/// the same time source get their rovers moving together.
+fn run_at(link: &mut Link, at_ms: u64, messages: &[(u64, RxBody)]) -> Result<(), Error> {
+    let clock = link.sync_clock(SYNC_EXCHANGES)?;
+    for (delay_ms, body) in messages {
+        // Past due, or never synced, it runs at once.
+        link.request_at(body.clone(), clock.to_rover(at_ms + delay_ms))?;
+    }
+    let last_ms = at_ms
+        + messages
+            .iter()
+            .map(|&(delay_ms, _)| delay_ms)
+            .max()
+            .unwrap_or(0);
+    while host_ms() <= last_ms {
+        link.send(RxBody::Ping(host_ms() as u32))?;
+        thread::sleep(DRIVE_PERIOD);
+    }
+    Ok(())
+}
+
+fn macro_list(link: &mut Link) -> Result<(), Error> {
+    link.request(RxBody::ListMacros)?;
+    loop {
+        if let TxMessage::Macros(names) = link.receive_timeout(Duration::from_secs(1))? {
+            names.iter().for_each(|name| println!("{name}"));
+            return Ok(());
+        }
+    }
+}
+
+fn events_dump(link: &mut Link) -> Result<(), Error> {
+    let events = link.request_all(RxBody::DumpEvents, |msg| matches!(msg, TxMessage::Event(_)))?;
+    for msg in events {
+        if let TxMessage::Event(Entry {
+            seq,
+            uptime_ms,
+            event,
+        }) = msg
+        {
+            println!("#{seq} at {:.3} s: {event:?}", uptime_ms as f32 / 1000.0);
+        }
+    }
+    Ok(())
+}
+
+fn param_get(link: &mut Link, name: ParamName) -> Result<Param, Error> {
+    let replies = link.request_all(RxBody::GetParam(name), |msg| {
+        matches!(msg, TxMessage::Param(_))
+    })?;
+    match replies.into_iter().next() {
+        Some(TxMessage::Param(param)) => Ok(param),
+        _ => Err(Error::Timeout),
+    }
+}
+
+fn param_list(link: &mut Link) -> Result<(), Error> {
+    let params = link.request_all(RxBody::ListParams, |msg| matches!(msg, TxMessage::Param(_)))?;
+    for msg in params {
+        if let TxMessage::Param(Param { name, value }) = msg {
+            println!("{name}: {value}");
+        }
+    }
+    Ok(())
+}
+
+fn config_get(link: &mut Link) -> Result<(), Error> {
+    link.request(RxBody::GetConfig)?;
+    loop {
+        if let TxMessage::Config(config) = link.receive_timeout(Duration::from_secs(1))? {
+            let json = serde_json::to_string_pretty(&config).unwrap_or_default();
+            println!("{json}");
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Packing of the [`rover_proto`] messages, the same way the firmware does.
+
+use rover_proto::{
+    framing::{self, Packet},
+    RxMessage, TxMessage, RX_SIZE, TX_SIZE,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    Binary,
+}
+
+/// A whole packet for the rover at `address`, terminating zero included.
+pub fn encode(address: u8, msg: &RxMessage, encoding: Encoding) -> Option<Vec<u8>> {
+    let raw = match encoding {
+        Encoding::Json => serde_json::to_vec(msg).ok()?,
+        Encoding::Binary => {
+            let mut raw = [0u8; RX_SIZE];
+            rover_lib::wire::to_frame(msg, &mut raw).ok()?.to_vec()
+        }
+    };
+    let mut packet = vec![0u8; framing::max_packet_len(raw.len())];
+    let n = framing::encode(address, &raw, &mut packet).ok()?;
+    packet.truncate(n);
+    Some(packet)
+}
+
+/// A packet, without its terminating zero, in either encoding, and the
+/// address of the rover that sent it.
+pub fn decode(packet: &[u8]) -> Option<(u8, TxMessage)> {
+    let mut raw = [0u8; framing::HEADER_LEN + TX_SIZE + framing::TRAILER_LEN];
+    let Packet { address, payload } = framing::decode(packet, &mut raw)
+        .inspect_err(|e| eprintln!("dropping packet: {e}"))
+        .ok()?;
+    let msg = if payload.first() == Some(&b'{') {
+        serde_json::from_slice(payload)
+            .inspect_err(|e| eprintln!("error decoding JSON: {e}"))
+            .ok()?
+    } else {
+        rover_lib::wire::from_frame(payload)
+            .inspect_err(|e| eprintln!("error decoding binary frame: {e}"))
+            .ok()?
+    };
+    Some((address, msg))
+}
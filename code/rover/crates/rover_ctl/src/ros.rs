@@ -0,0 +1,405 @@
+//! A bridge to ROS 2 through rosbridge (`ros2 launch rosbridge_server
+//! rosbridge_websocket_launch.xml`): the rover drives on `/cmd_vel` and
+//! publishes `/odom` and `/imu`, in the ROS conventions of REP 103 and 105.
+//!
+//! rosbridge speaks JSON over a WebSocket, of which only the little a
+//! client needs is implemented here.
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+use uom::si::{
+    angle::{degree, radian},
+    angular_velocity::radian_per_second,
+    length::meter,
+    velocity::meter_per_second,
+};
+
+use rover_lib::{
+    iface::{MecanumPower, Turn},
+    Angle, Attitude, Pose,
+};
+use rover_proto::{DriveMessage, RxBody, Telemetry, TxMessage};
+
+use crate::{
+    link::{Error, Link},
+    DRIVE_PERIOD,
+};
+
+/// Older than this, a `cmd_vel` stops the rover, like most ROS drivers do.
+const CMD_VEL_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// What full power and a full turn amount to, the rover taking powers.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// In m/s.
+    pub speed: f32,
+    /// In rad/s.
+    pub turn_rate: f32,
+}
+
+/// The parts of a `geometry_msgs/msg/Twist` a mecanum base can follow.
+#[derive(Debug, Clone, Copy)]
+struct Twist {
+    forward: f32,
+    left: f32,
+    /// Counter-clockwise.
+    turn_rate: f32,
+}
+
+/// Arms the rover and runs until either side closes.
+pub fn bridge(link: &mut Link, address: &str, limits: Limits) -> Result<(), Error> {
+    let mut socket = WebSocket::connect(address)?;
+    link.request(RxBody::Arm(true))?;
+    socket.send(&json!({"op": "advertise", "topic": "/odom", "type": "nav_msgs/msg/Odometry"}))?;
+    socket.send(&json!({"op": "advertise", "topic": "/imu", "type": "sensor_msgs/msg/Imu"}))?;
+    socket.send(
+        &json!({"op": "subscribe", "topic": "/cmd_vel", "type": "geometry_msgs/msg/Twist"}),
+    )?;
+    let (sender, twists) = mpsc::channel();
+    let reader = socket.try_clone()?;
+    thread::spawn(move || receive_twists(reader, sender));
+
+    let mut twist: Option<(Twist, Instant)> = None;
+    let mut last_drive = Instant::now();
+    let mut stopped = true;
+    let mut refused = None;
+    let mut last_pose = None;
+    loop {
+        match link.receive_timeout(DRIVE_PERIOD) {
+            Ok(TxMessage::Telemetry(telemetry)) => {
+                if let Some(odometry) = odometry(&telemetry, &mut last_pose) {
+                    socket.send(&odometry)?;
+                }
+                if let Some(attitude) = telemetry.attitude {
+                    socket.send(&imu(&attitude))?;
+                }
+            }
+            Ok(_) | Err(Error::Timeout) => {}
+            Err(e) => return Err(e),
+        }
+        loop {
+            match twists.try_recv() {
+                Ok(new) => twist = Some((new, Instant::now())),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => return Err(Error::Closed),
+            }
+        }
+
+        if last_drive.elapsed() < DRIVE_PERIOD {
+            continue;
+        }
+        last_drive = Instant::now();
+        let result = match twist.filter(|(_, at)| at.elapsed() < CMD_VEL_TIMEOUT) {
+            Some((twist, _)) => {
+                stopped = false;
+                link.request(RxBody::Drive(drive(twist, limits)))
+            }
+            None if !stopped => {
+                stopped = true;
+                crate::stop(link)
+            }
+            None => Ok(()),
+        };
+        // Estopped, or someone else driving: keep bridging
+        match result {
+            Err(Error::Nack(code)) if refused != Some(code) => {
+                eprintln!("cmd_vel refused: {code:?}");
+                refused = Some(code);
+            }
+            Err(Error::Nack(_)) => {}
+            result => {
+                result?;
+                refused = None;
+            }
+        }
+    }
+}
+
+fn drive(twist: Twist, limits: Limits) -> DriveMessage {
+    let forward = twist.forward / limits.speed;
+    let right = -twist.left / limits.speed;
+    DriveMessage {
+        p: Some(MecanumPower::new(
+            forward.hypot(right).min(MecanumPower::MAX),
+        )),
+        th: Some(Angle::new::<radian>(forward.atan2(right))),
+        // Clockwise
+        tu: Some(Turn::new(
+            (-twist.turn_rate / limits.turn_rate).clamp(Turn::MIN, Turn::MAX),
+        )),
+    }
+}
+
+fn receive_twists(mut socket: WebSocket, sender: mpsc::Sender<Twist>) {
+    while let Ok(Some(text)) = socket.receive() {
+        let Ok(msg) = serde_json::from_str::<Value>(&text) else {
+            continue;
+        };
+        if msg["op"] != "publish" || msg["topic"] != "/cmd_vel" {
+            continue;
+        }
+        let twist = &msg["msg"];
+        let component = |vector: &str, axis: &str| twist[vector][axis].as_f64().unwrap_or(0.0);
+        let twist = Twist {
+            forward: component("linear", "x") as f32,
+            left: component("linear", "y") as f32,
+            turn_rate: component("angular", "z") as f32,
+        };
+        if sender.send(twist).is_err() {
+            return;
+        }
+    }
+}
+
+/// The pose in the `odom` frame, x forward and y left of where the rover
+/// started, and its velocity: the estimate's, or from the last pose.
+fn odometry(telemetry: &Telemetry, last_pose: &mut Option<(u64, Pose)>) -> Option<Value> {
+    let pose = telemetry.pose?;
+    let x = pose.y.get::<meter>();
+    let y = -pose.x.get::<meter>();
+    let yaw = pose.heading.get::<radian>();
+
+    let (forward, left, turn_rate) = match telemetry.estimate {
+        Some(estimate) => (
+            estimate.velocity.vy.get::<meter_per_second>(),
+            -estimate.velocity.vx.get::<meter_per_second>(),
+            estimate.velocity.omega.get::<radian_per_second>(),
+        ),
+        None => match last_pose.filter(|(at, _)| *at < telemetry.uptime_ms) {
+            Some((at, last)) => {
+                let dt = (telemetry.uptime_ms - at) as f32 / 1000.0;
+                let dx = x - last.y.get::<meter>();
+                let dy = y + last.x.get::<meter>();
+                let dyaw = rover_lib::odometry::wrap_angle(yaw - last.heading.get::<radian>());
+                let (sin, cos) = yaw.sin_cos();
+                (
+                    (cos * dx + sin * dy) / dt,
+                    (cos * dy - sin * dx) / dt,
+                    dyaw / dt,
+                )
+            }
+            None => (0.0, 0.0, 0.0),
+        },
+    };
+    *last_pose = Some((telemetry.uptime_ms, pose));
+
+    let mut covariance = [0.0f32; 36];
+    if let Some(estimate) = telemetry.estimate {
+        let position = estimate.position_std.get::<meter>();
+        let heading = estimate.heading_std.get::<radian>();
+        // Each half of the spread along x and y
+        covariance[0] = position * position / 2.0;
+        covariance[7] = position * position / 2.0;
+        covariance[35] = heading * heading;
+    }
+
+    Some(json!({
+        "op": "publish",
+        "topic": "/odom",
+        "msg": {
+            "header": header("odom"),
+            "child_frame_id": "base_link",
+            "pose": {
+                "pose": {
+                    "position": {"x": x, "y": y, "z": 0.0},
+                    "orientation": quaternion(0.0, 0.0, yaw),
+                },
+                "covariance": covariance.as_slice(),
+            },
+            "twist": {
+                "twist": {
+                    "linear": {"x": forward, "y": left, "z": 0.0},
+                    "angular": {"x": 0.0, "y": 0.0, "z": turn_rate},
+                },
+                "covariance": vec![0.0f32; 36],
+            },
+        },
+    }))
+}
+
+/// Only the orientation is in the telemetry, the rest marked unknown.
+fn imu(attitude: &Attitude) -> Value {
+    let mut unknown = [0.0; 9];
+    unknown[0] = -1.0;
+    json!({
+        "op": "publish",
+        "topic": "/imu",
+        "msg": {
+            "header": header("base_link"),
+            // The rover pitches nose up positive, about its right
+            "orientation": quaternion(
+                attitude.roll.get::<radian>(),
+                -attitude.pitch.get::<radian>(),
+                attitude.heading.get::<radian>(),
+            ),
+            "orientation_covariance": vec![0.0f32; 9],
+            "angular_velocity": {"x": 0.0, "y": 0.0, "z": 0.0},
+            "angular_velocity_covariance": unknown,
+            "linear_acceleration": {"x": 0.0, "y": 0.0, "z": 0.0},
+            "linear_acceleration_covariance": unknown,
+        },
+    })
+}
+
+/// Stamped with the host clock, the rover's uptime meaning nothing to ROS.
+fn header(frame_id: &str) -> Value {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    json!({
+        "stamp": {"sec": now.as_secs(), "nanosec": now.subsec_nanos()},
+        "frame_id": frame_id,
+    })
+}
+
+fn quaternion(roll: f32, pitch: f32, yaw: f32) -> Value {
+    let (sr, cr) = (roll / 2.0).sin_cos();
+    let (sp, cp) = (pitch / 2.0).sin_cos();
+    let (sy, cy) = (yaw / 2.0).sin_cos();
+    json!({
+        "x": sr * cp * cy - cr * sp * sy,
+        "y": cr * sp * cy + sr * cp * sy,
+        "z": cr * cp * sy - sr * sp * cy,
+        "w": cr * cp * cy + sr * sp * sy,
+    })
+}
+
+/// A WebSocket client of text messages, writes shared between clones so
+/// the pongs of the reader don't cut into them.
+struct WebSocket {
+    reader: TcpStream,
+    writer: Arc<Mutex<TcpStream>>,
+}
+
+impl WebSocket {
+    const TEXT: u8 = 0x1;
+    const CLOSE: u8 = 0x8;
+    const PING: u8 = 0x9;
+    const PONG: u8 = 0xA;
+
+    fn connect(address: &str) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(address)?;
+        // The key is only there to tell WebSocket servers from others
+        write!(
+            stream,
+            "GET / HTTP/1.1\r\nHost: {address}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        )?;
+        // Byte by byte, so none of the first frame gets read with it
+        let mut response = Vec::new();
+        let mut byte = [0];
+        while !response.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte)?;
+            response.push(byte[0]);
+        }
+        if !response.starts_with(b"HTTP/1.1 101") {
+            return Err(io::Error::other(format!(
+                "{address} isn't a WebSocket server: {}",
+                String::from_utf8_lossy(&response)
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+            )));
+        }
+        Ok(Self {
+            writer: Arc::new(Mutex::new(stream.try_clone()?)),
+            reader: stream,
+        })
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            reader: self.reader.try_clone()?,
+            writer: self.writer.clone(),
+        })
+    }
+
+    fn send(&mut self, msg: &Value) -> io::Result<()> {
+        self.send_frame(Self::TEXT, msg.to_string().as_bytes())
+    }
+
+    /// Masked, as clients must, with a key that only has to differ from
+    /// frame to frame.
+    fn send_frame(&self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            len if len < 126 => frame.push(0x80 | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend((len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend((len as u64).to_be_bytes());
+            }
+        }
+        let mask = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos()
+            .to_le_bytes();
+        frame.extend(mask);
+        frame.extend(payload.iter().zip(mask.iter().cycle()).map(|(b, m)| b ^ m));
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writer.write_all(&frame)
+    }
+
+    /// The next text message, `None` once the server closes.
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        let mut message = Vec::new();
+        loop {
+            let mut head = [0; 2];
+            self.reader.read_exact(&mut head)?;
+            let len = match head[1] & 0x7F {
+                126 => {
+                    let mut len = [0; 2];
+                    self.reader.read_exact(&mut len)?;
+                    u16::from_be_bytes(len) as usize
+                }
+                127 => {
+                    let mut len = [0; 8];
+                    self.reader.read_exact(&mut len)?;
+                    u64::from_be_bytes(len) as usize
+                }
+                len => len as usize,
+            };
+            // Servers don't mask
+            let mut payload = vec![0; len];
+            self.reader.read_exact(&mut payload)?;
+
+            match head[0] & 0x0F {
+                Self::CLOSE => return Ok(None),
+                Self::PING => self.send_frame(Self::PONG, &payload)?,
+                Self::PONG => {}
+                _ => {
+                    message.extend(payload);
+                    if head[0] & 0x80 != 0 {
+                        return Ok(Some(String::from_utf8_lossy(&message).into_owned()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `[speed] [turn rate]` of `ros`, in m/s and °/s.
+pub fn limits(args: &[&str]) -> Option<Limits> {
+    let number = |arg: &&str| arg.parse::<f32>().ok().filter(|n| *n > 0.0);
+    let (speed, turn_rate) = match args {
+        [] => (1.0, 180.0),
+        [speed] => (number(speed)?, 180.0),
+        [speed, turn_rate] => (number(speed)?, number(turn_rate)?),
+        _ => return None,
+    };
+    Some(Limits {
+        speed,
+        turn_rate: Angle::new::<degree>(turn_rate).get::<radian>(),
+    })
+}
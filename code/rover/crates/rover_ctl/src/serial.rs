@@ -0,0 +1,46 @@
+//! Raw mode serial ports, through termios.
+
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::fd::AsRawFd,
+    path::Path,
+};
+
+/// Opens `path` as a raw 8N1 port at `baud`, which must be one of the
+/// standard rates.
+pub fn open(path: &Path, baud: u32) -> io::Result<File> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let speed = match baud {
+        9_600 => libc::B9600,
+        19_200 => libc::B19200,
+        38_400 => libc::B38400,
+        57_600 => libc::B57600,
+        115_200 => libc::B115200,
+        230_400 => libc::B230400,
+        460_800 => libc::B460800,
+        921_600 => libc::B921600,
+        _ => return Err(io::ErrorKind::InvalidInput.into()),
+    };
+
+    let fd = file.as_raw_fd();
+    // SAFETY: `fd` is open for as long as `file`, and `termios` is only read
+    // after `tcgetattr` filled it
+    unsafe {
+        let mut termios = core::mem::zeroed::<libc::termios>();
+        if libc::tcgetattr(fd, &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut termios);
+        termios.c_cflag |= libc::CLOCAL | libc::CREAD;
+        // Blocking reads, returning whatever arrived
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        if libc::cfsetspeed(&mut termios, speed) != 0
+            || libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(file)
+}
@@ -0,0 +1,233 @@
+//! Whether drive commands may reach the motors: the robot powers up
+//! disarmed and only drives once armed, while the e-stop and a fault take
+//! over from any mode.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+pub enum Mode {
+    /// Drive commands are refused.
+    #[default]
+    Disarmed,
+    /// Ready to drive, nothing driving.
+    Armed,
+    Driving,
+    /// The robot couldn't be stopped, until reset.
+    Fault,
+    /// Latched by the e-stop, disarmed once cleared.
+    Estop,
+    /// The host was lost for too long, see [`crate::failsafe`], until armed
+    /// again.
+    Failsafe,
+}
+
+impl Mode {
+    pub fn may_drive(self) -> bool {
+        matches!(self, Mode::Armed | Mode::Driving)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ModeEvent {
+    Arm,
+    Disarm,
+    /// A drive command, about to be applied.
+    Drive,
+    /// The commands stopped, the safety timer putting the robot in neutral.
+    Stopped,
+    Estop,
+    EstopCleared,
+    Fault,
+    /// The last stage of the [`crate::failsafe`].
+    Failsafe,
+}
+
+/// Why a [`ModeEvent`] was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[non_exhaustive]
+pub enum ArmingError {
+    /// Driving before arming.
+    NotArmed,
+    /// Anything but clearing the e-stop while latched.
+    Estopped,
+    /// Anything at all after a fault.
+    Faulted,
+}
+
+impl core::fmt::Display for ArmingError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for ArmingError {}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Arming {
+    mode: Mode,
+}
+
+impl Arming {
+    /// Disarmed.
+    pub const fn new() -> Self {
+        Self {
+            mode: Mode::Disarmed,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Moves to the mode `event` leads to and returns it, or stays put if
+    /// the current mode refuses it.
+    pub fn handle(&mut self, event: ModeEvent) -> Result<Mode, ArmingError> {
+        use ModeEvent as E;
+
+        self.mode = match (self.mode, event) {
+            (Mode::Fault, _) => return Err(ArmingError::Faulted),
+            (_, E::Fault) => Mode::Fault,
+            (_, E::Estop) => Mode::Estop,
+            (Mode::Estop, E::EstopCleared) => Mode::Disarmed,
+            (Mode::Estop, _) => return Err(ArmingError::Estopped),
+            (Mode::Disarmed | Mode::Failsafe, E::Drive) => return Err(ArmingError::NotArmed),
+            (Mode::Disarmed | Mode::Failsafe, E::Arm) => Mode::Armed,
+            (_, E::Disarm) => Mode::Disarmed,
+            (Mode::Armed | Mode::Driving, E::Drive) => Mode::Driving,
+            (Mode::Driving, E::Stopped) => Mode::Armed,
+            (Mode::Armed | Mode::Driving, E::Failsafe) => Mode::Failsafe,
+            (mode, E::Arm | E::Stopped | E::EstopCleared | E::Failsafe) => mode,
+        };
+        Ok(self.mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn driving() -> Arming {
+        let mut arming = Arming::new();
+        arming.handle(ModeEvent::Arm).unwrap();
+        arming.handle(ModeEvent::Drive).unwrap();
+        arming
+    }
+
+    #[test]
+    fn arms_then_drives() {
+        let mut arming = Arming::new();
+        assert_eq!(arming.mode(), Mode::Disarmed);
+        assert!(!arming.mode().may_drive());
+
+        assert_eq!(arming.handle(ModeEvent::Arm), Ok(Mode::Armed));
+        assert_eq!(arming.handle(ModeEvent::Drive), Ok(Mode::Driving));
+        assert_eq!(arming.handle(ModeEvent::Drive), Ok(Mode::Driving));
+        assert!(arming.mode().may_drive());
+        assert_eq!(arming.handle(ModeEvent::Stopped), Ok(Mode::Armed));
+        assert!(arming.mode().may_drive());
+        // Arming again changes nothing
+        assert_eq!(arming.handle(ModeEvent::Arm), Ok(Mode::Armed));
+
+        assert_eq!(arming.handle(ModeEvent::Disarm), Ok(Mode::Disarmed));
+        assert_eq!(driving().handle(ModeEvent::Disarm), Ok(Mode::Disarmed));
+    }
+
+    #[test]
+    fn driving_needs_arming() {
+        let mut arming = Arming::new();
+        assert_eq!(arming.handle(ModeEvent::Drive), Err(ArmingError::NotArmed));
+        assert_eq!(arming.mode(), Mode::Disarmed);
+        for event in [ModeEvent::Stopped, ModeEvent::EstopCleared] {
+            assert_eq!(arming.handle(event), Ok(Mode::Disarmed));
+        }
+    }
+
+    #[test]
+    fn estop_latches_until_cleared() {
+        for mut arming in [Arming::new(), driving()] {
+            assert_eq!(arming.handle(ModeEvent::Estop), Ok(Mode::Estop));
+            assert!(!arming.mode().may_drive());
+            for event in [
+                ModeEvent::Arm,
+                ModeEvent::Disarm,
+                ModeEvent::Drive,
+                ModeEvent::Stopped,
+                ModeEvent::Failsafe,
+            ] {
+                assert_eq!(arming.handle(event), Err(ArmingError::Estopped));
+                assert_eq!(arming.mode(), Mode::Estop);
+            }
+            assert_eq!(arming.handle(ModeEvent::Estop), Ok(Mode::Estop));
+
+            // Cleared, it has to be armed again
+            assert_eq!(arming.handle(ModeEvent::EstopCleared), Ok(Mode::Disarmed));
+            assert_eq!(arming.handle(ModeEvent::Drive), Err(ArmingError::NotArmed));
+        }
+    }
+
+    #[test]
+    fn fault_latches_for_good() {
+        let mut arming = driving();
+        arming.handle(ModeEvent::Estop).unwrap();
+        // Over the e-stop too
+        assert_eq!(arming.handle(ModeEvent::Fault), Ok(Mode::Fault));
+        assert!(!arming.mode().may_drive());
+        for event in [
+            ModeEvent::Arm,
+            ModeEvent::Disarm,
+            ModeEvent::Drive,
+            ModeEvent::Stopped,
+            ModeEvent::Estop,
+            ModeEvent::EstopCleared,
+            ModeEvent::Fault,
+            ModeEvent::Failsafe,
+        ] {
+            assert_eq!(arming.handle(event), Err(ArmingError::Faulted));
+            assert_eq!(arming.mode(), Mode::Fault);
+        }
+    }
+
+    #[test]
+    fn faulted_refuses_driving_until_armed() {
+        let mut arming = driving();
+        assert_eq!(arming.handle(ModeEvent::Failsafe), Ok(Mode::Failsafe));
+        // The link coming back, the safety timer stopping, aren't enough
+        for event in [
+            ModeEvent::Stopped,
+            ModeEvent::EstopCleared,
+            ModeEvent::Failsafe,
+        ] {
+            assert_eq!(arming.handle(event), Ok(Mode::Failsafe));
+        }
+        assert_eq!(arming.handle(ModeEvent::Drive), Err(ArmingError::NotArmed));
+        assert_eq!(arming.mode(), Mode::Failsafe);
+
+        assert_eq!(arming.handle(ModeEvent::Arm), Ok(Mode::Armed));
+        assert_eq!(arming.handle(ModeEvent::Drive), Ok(Mode::Driving));
+    }
+
+    #[test]
+    fn only_an_armed_robot_faults() {
+        let mut arming = Arming::new();
+        assert_eq!(arming.handle(ModeEvent::Failsafe), Ok(Mode::Disarmed));
+
+        let mut arming = Arming::new();
+        arming.handle(ModeEvent::Arm).unwrap();
+        assert_eq!(arming.handle(ModeEvent::Failsafe), Ok(Mode::Failsafe));
+
+        let mut arming = driving();
+        arming.handle(ModeEvent::Estop).unwrap();
+        assert_eq!(
+            arming.handle(ModeEvent::Failsafe),
+            Err(ArmingError::Estopped)
+        );
+    }
+
+    #[test]
+    fn failsafe_can_be_disarmed() {
+        let mut arming = driving();
+        arming.handle(ModeEvent::Failsafe).unwrap();
+        assert_eq!(arming.handle(ModeEvent::Disarm), Ok(Mode::Disarmed));
+        assert_eq!(arming.handle(ModeEvent::Drive), Err(ArmingError::NotArmed));
+    }
+}
@@ -0,0 +1,123 @@
+//! Async versions of the [`iface`](crate::iface) traits, for drivers behind
+//! a bus (I2C, SPI, CAN) that have to wait on it.
+//!
+//! Every blocking [`Motor`] and [`DriveBase`] is an async one too, so code
+//! written against these traits takes both, wrapped in a
+//! [`StabilizedRobot`](crate::StabilizedRobot) or not. A [`MyFourWheelRobot`] of
+//! async motors is an [`AsyncFourWheeledRobot`], mixed into an
+//! [`AsyncMecanumRobot`] by [`AsyncMecanum`].
+//!
+//! [`MyFourWheelRobot`]: crate::MyFourWheelRobot
+
+use crate::iface::{mix, Angle, DriveBase, FWRMerror, MecanumPower, Motor, MotorPower, Turn};
+
+// The futures are only polled by the single threaded embassy executor,
+// `Send` bounds would only get in the way
+#[allow(async_fn_in_trait)]
+pub trait AsyncMotor {
+    type Error: core::error::Error;
+
+    async fn drive(&mut self, power: MotorPower) -> Result<(), Self::Error>;
+    async fn neutral(&mut self) -> Result<(), Self::Error>;
+    async fn brake(&mut self) -> Result<(), Self::Error> {
+        self.neutral().await
+    }
+    /// Last power actually applied, zero when in neutral.
+    fn power(&self) -> MotorPower;
+}
+
+impl<T: Motor> AsyncMotor for T {
+    type Error = T::Error;
+
+    async fn drive(&mut self, power: MotorPower) -> Result<(), Self::Error> {
+        Motor::drive(self, power)
+    }
+    async fn neutral(&mut self) -> Result<(), Self::Error> {
+        Motor::neutral(self)
+    }
+    async fn brake(&mut self) -> Result<(), Self::Error> {
+        Motor::brake(self)
+    }
+    fn power(&self) -> MotorPower {
+        Motor::power(self)
+    }
+}
+
+#[allow(async_fn_in_trait)]
+pub trait AsyncFourWheeledRobot {
+    type Error: core::error::Error;
+
+    async fn drive(
+        &mut self,
+        fl: MotorPower,
+        fr: MotorPower,
+        bl: MotorPower,
+        br: MotorPower,
+    ) -> Result<(), Self::Error>;
+    async fn neutral(&mut self) -> Result<(), Self::Error>;
+    async fn brake(&mut self) -> Result<(), Self::Error> {
+        self.neutral().await
+    }
+}
+
+#[allow(async_fn_in_trait)]
+pub trait AsyncMecanumRobot {
+    type Error: core::error::Error;
+
+    async fn drive(
+        &mut self,
+        power: MecanumPower,
+        theta: Angle,
+        turn: Turn,
+    ) -> Result<(), Self::Error>;
+    async fn neutral(&mut self) -> Result<(), Self::Error>;
+    async fn brake(&mut self) -> Result<(), Self::Error> {
+        self.neutral().await
+    }
+}
+
+impl<T: DriveBase> AsyncMecanumRobot for T {
+    type Error = T::Error;
+
+    async fn drive(
+        &mut self,
+        power: MecanumPower,
+        theta: Angle,
+        turn: Turn,
+    ) -> Result<(), Self::Error> {
+        DriveBase::drive(self, power, theta, turn)
+    }
+    async fn neutral(&mut self) -> Result<(), Self::Error> {
+        DriveBase::neutral(self)
+    }
+    async fn brake(&mut self) -> Result<(), Self::Error> {
+        DriveBase::brake(self)
+    }
+}
+
+/// Mecanum mixing over an [`AsyncFourWheeledRobot`], what the blanket
+/// [`MecanumRobot`] impl is to blocking ones.
+pub struct AsyncMecanum<R>(pub R);
+
+impl<R: AsyncFourWheeledRobot> AsyncMecanumRobot for AsyncMecanum<R> {
+    type Error = FWRMerror<R::Error>;
+
+    async fn drive(
+        &mut self,
+        power: MecanumPower,
+        theta: Angle,
+        turn: Turn,
+    ) -> Result<(), Self::Error> {
+        let [fl, fr, bl, br] = mix(power, theta, turn);
+        self.0
+            .drive(fl, fr, bl, br)
+            .await
+            .map_err(FWRMerror::Internal)
+    }
+    async fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.0.neutral().await.map_err(FWRMerror::Internal)
+    }
+    async fn brake(&mut self) -> Result<(), Self::Error> {
+        self.0.brake().await.map_err(FWRMerror::Internal)
+    }
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use uom::si::{electric_potential::volt, f32::ElectricPotential};
+
+/// What to do once the battery is low.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LowVoltageAction {
+    /// Only report it.
+    #[default]
+    Warn,
+    /// Keep driving at reduced power.
+    LimitPower,
+    /// Stop and refuse to drive.
+    Neutral,
+}
+
+/// Low pass filtered pack voltage with a low threshold. Leaving the low state
+/// takes `hysteresis` more than the threshold, so the sag under load doesn't
+/// flip it back and forth.
+pub struct BatteryMonitor {
+    /// Weight of the previous estimate, in [0, 1].
+    alpha: f32,
+    hysteresis: f32,
+    voltage: Option<f32>,
+    low: bool,
+}
+
+impl BatteryMonitor {
+    pub fn new(alpha: f32, hysteresis: ElectricPotential) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 1.0),
+            hysteresis: hysteresis.get::<volt>(),
+            voltage: None,
+            low: false,
+        }
+    }
+
+    pub fn voltage(&self) -> Option<ElectricPotential> {
+        self.voltage.map(ElectricPotential::new::<volt>)
+    }
+
+    pub fn is_low(&self) -> bool {
+        self.low
+    }
+
+    /// Feeds a new measurement and returns whether the battery is low.
+    pub fn update(&mut self, measured: ElectricPotential, threshold: ElectricPotential) -> bool {
+        let measured = measured.get::<volt>();
+        let voltage = match self.voltage {
+            Some(v) => self.alpha * v + (1.0 - self.alpha) * measured,
+            None => measured,
+        };
+        self.voltage = Some(voltage);
+
+        let threshold = threshold.get::<volt>();
+        self.low = if self.low {
+            voltage < threshold + self.hysteresis
+        } else {
+            voltage < threshold
+        };
+        self.low
+    }
+}
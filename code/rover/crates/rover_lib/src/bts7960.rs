@@ -0,0 +1,136 @@
+//! BTS7960 (IBT-2 boards) half-bridge pair: a PWM input per side and no
+//! direction pins, the side getting the duty cycle picks the direction.
+//!
+//! Both inputs low turns both low sides on, which brakes. Coasting needs
+//! the R_EN and L_EN lines, tied together on one pin, taken low.
+
+use embedded_hal_1::{digital::OutputPin, pwm::SetDutyCycle};
+
+use crate::iface::{Motor, MotorPower, NeutralMode};
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+#[non_exhaustive]
+pub enum Bts7960Error<R, L, E> {
+    RPwm(R),
+    LPwm(L),
+    Enable(E),
+}
+
+impl<R: core::fmt::Debug, L: core::fmt::Debug, E: core::fmt::Debug> core::fmt::Display
+    for Bts7960Error<R, L, E>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl<R: core::fmt::Debug, L: core::fmt::Debug, E: core::fmt::Debug> core::error::Error
+    for Bts7960Error<R, L, E>
+{
+}
+
+type Bts7960ErrorOf<R, L, E> = Bts7960Error<
+    <R as embedded_hal_1::pwm::ErrorType>::Error,
+    <L as embedded_hal_1::pwm::ErrorType>::Error,
+    <E as embedded_hal_1::digital::ErrorType>::Error,
+>;
+
+/// Forward on RPWM.
+pub struct Bts7960Motor<R, L, E> {
+    rpwm: R,
+    lpwm: L,
+    enable: E,
+    neutral_mode: NeutralMode,
+    power: MotorPower,
+}
+
+impl<R, L, E> Bts7960Motor<R, L, E> {
+    pub fn new(rpwm: R, lpwm: L, enable: E) -> Self {
+        Self {
+            rpwm,
+            lpwm,
+            enable,
+            neutral_mode: Default::default(),
+            power: Default::default(),
+        }
+    }
+
+    pub fn neutral_mode(&self) -> NeutralMode {
+        self.neutral_mode
+    }
+
+    /// What [`Motor::neutral`] does, [`Motor::brake`] always brakes.
+    pub fn set_neutral_mode(&mut self, mode: NeutralMode) {
+        self.neutral_mode = mode;
+    }
+}
+
+impl<R: SetDutyCycle, L: SetDutyCycle, E: OutputPin> Bts7960Motor<R, L, E> {
+    fn inputs_off(&mut self) -> Result<(), Bts7960ErrorOf<R, L, E>> {
+        self.rpwm
+            .set_duty_cycle_fully_off()
+            .map_err(Bts7960Error::RPwm)?;
+        self.lpwm
+            .set_duty_cycle_fully_off()
+            .map_err(Bts7960Error::LPwm)
+    }
+
+    /// Bridges disabled, outputs floating.
+    fn coast(&mut self) -> Result<(), Bts7960ErrorOf<R, L, E>> {
+        self.enable.set_low().map_err(Bts7960Error::Enable)?;
+        self.inputs_off()?;
+        self.power = Default::default();
+        Ok(())
+    }
+
+    /// Both low sides on.
+    fn short(&mut self) -> Result<(), Bts7960ErrorOf<R, L, E>> {
+        self.inputs_off()?;
+        self.enable.set_high().map_err(Bts7960Error::Enable)?;
+        self.power = Default::default();
+        Ok(())
+    }
+}
+
+impl<R: SetDutyCycle, L: SetDutyCycle, E: OutputPin> Motor for Bts7960Motor<R, L, E> {
+    type Error = Bts7960ErrorOf<R, L, E>;
+
+    fn drive(&mut self, power: MotorPower) -> Result<(), Self::Error> {
+        let inner_power = power.inner();
+        let duty_percent = ((libm::fabsf(inner_power) / MotorPower::MAX) * 100.0) as u8;
+
+        // The idle side goes off first, so both sides never drive at once
+        if inner_power >= 0.0 {
+            self.lpwm
+                .set_duty_cycle_fully_off()
+                .map_err(Bts7960Error::LPwm)?;
+            self.rpwm
+                .set_duty_cycle_percent(duty_percent)
+                .map_err(Bts7960Error::RPwm)?;
+        } else {
+            self.rpwm
+                .set_duty_cycle_fully_off()
+                .map_err(Bts7960Error::RPwm)?;
+            self.lpwm
+                .set_duty_cycle_percent(duty_percent)
+                .map_err(Bts7960Error::LPwm)?;
+        }
+        self.enable.set_high().map_err(Bts7960Error::Enable)?;
+
+        self.power = power;
+
+        Ok(())
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        match self.neutral_mode {
+            NeutralMode::Coast => self.coast(),
+            NeutralMode::Brake => self.short(),
+        }
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.short()
+    }
+    fn power(&self) -> MotorPower {
+        self.power
+    }
+}
@@ -0,0 +1,274 @@
+//! Gestures of a push button, from its raw level: contact bounce is ignored
+//! and each press is told apart as short, long or double.
+//!
+//! A short press is only known once no second press followed it within
+//! [`ButtonTiming::double_press_ms`], while a long press is reported as soon
+//! as it's been held long enough, without waiting for the release.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Gesture {
+    Short,
+    Long,
+    Double,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonTiming {
+    /// The level must hold this long to count.
+    pub debounce_ms: u32,
+    /// Held this long, a press is a long press.
+    pub long_press_ms: u32,
+    /// From a release to the next press, for a double press.
+    pub double_press_ms: u32,
+}
+
+impl Default for ButtonTiming {
+    fn default() -> Self {
+        Self {
+            debounce_ms: 20,
+            long_press_ms: 1_000,
+            double_press_ms: 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Idle,
+    /// Down since `at`, the second press of a double if `second`.
+    Pressed {
+        at: u64,
+        second: bool,
+    },
+    /// Long press reported, waiting for the release.
+    Held,
+    /// Up since `at` after a short press, maybe the first of a double.
+    Released {
+        at: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureDecoder {
+    timing: ButtonTiming,
+    raw: bool,
+    raw_since: u64,
+    pressed: bool,
+    phase: Phase,
+}
+
+impl GestureDecoder {
+    /// Starts released.
+    pub const fn new(timing: ButtonTiming) -> Self {
+        Self {
+            timing,
+            raw: false,
+            raw_since: 0,
+            pressed: false,
+            phase: Phase::Idle,
+        }
+    }
+
+    /// The debounced level.
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Takes the raw level at `now_ms`, to be called on every edge and at
+    /// the [`deadline`](Self::deadline), more often doing no harm.
+    pub fn update(&mut self, pressed: bool, now_ms: u64) -> Option<Gesture> {
+        if pressed != self.raw {
+            self.raw = pressed;
+            self.raw_since = now_ms;
+        }
+        let settled = now_ms.saturating_sub(self.raw_since) >= self.timing.debounce_ms as u64;
+        if self.raw != self.pressed && settled {
+            self.pressed = self.raw;
+            // From when it last bounced, not from when it was noticed
+            let at = self.raw_since;
+            let gesture = if self.pressed {
+                self.press(at)
+            } else {
+                self.release(at)
+            };
+            if gesture.is_some() {
+                return gesture;
+            }
+        }
+        self.timeout(now_ms)
+    }
+
+    /// When [`update`](Self::update) should be called next if the level
+    /// doesn't change before, `None` if not until it does.
+    pub fn deadline(&self) -> Option<u64> {
+        let debounce =
+            (self.raw != self.pressed).then(|| self.raw_since + self.timing.debounce_ms as u64);
+        let phase = match self.phase {
+            Phase::Pressed { at, second: false } => Some(at + self.timing.long_press_ms as u64),
+            Phase::Released { at } => Some(at + self.timing.double_press_ms as u64),
+            _ => None,
+        };
+        match (debounce, phase) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn press(&mut self, at: u64) -> Option<Gesture> {
+        let (second, gesture) = match self.phase {
+            Phase::Released { at: released }
+                if at - released < self.timing.double_press_ms as u64 =>
+            {
+                (true, None)
+            }
+            // Too late for a double, the short press wasn't timed out yet
+            Phase::Released { .. } => (false, Some(Gesture::Short)),
+            _ => (false, None),
+        };
+        self.phase = Phase::Pressed { at, second };
+        gesture
+    }
+
+    fn release(&mut self, at: u64) -> Option<Gesture> {
+        let (phase, gesture) = match self.phase {
+            Phase::Pressed { second: true, .. } => (Phase::Idle, Some(Gesture::Double)),
+            Phase::Pressed { second: false, .. } => (Phase::Released { at }, None),
+            _ => (Phase::Idle, None),
+        };
+        self.phase = phase;
+        gesture
+    }
+
+    fn timeout(&mut self, now_ms: u64) -> Option<Gesture> {
+        let elapsed = |at: u64| now_ms.saturating_sub(at);
+        match self.phase {
+            Phase::Pressed { at, second: false }
+                if elapsed(at) >= self.timing.long_press_ms as u64 =>
+            {
+                self.phase = Phase::Held;
+                Some(Gesture::Long)
+            }
+            Phase::Released { at } if elapsed(at) >= self.timing.double_press_ms as u64 => {
+                self.phase = Phase::Idle;
+                Some(Gesture::Short)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoder() -> GestureDecoder {
+        GestureDecoder::new(ButtonTiming::default())
+    }
+
+    /// A clean edge at `at_ms` and the update once it settled, 20 ms on.
+    fn edge(decoder: &mut GestureDecoder, pressed: bool, at_ms: u64) -> Option<Gesture> {
+        assert_eq!(decoder.update(pressed, at_ms), None);
+        decoder.update(pressed, at_ms + 20)
+    }
+
+    #[test]
+    fn bounces_are_ignored() {
+        let mut decoder = decoder();
+        for (pressed, ms) in [(true, 0), (false, 5), (true, 10), (true, 29)] {
+            assert_eq!(decoder.update(pressed, ms), None);
+            assert!(!decoder.is_pressed());
+        }
+        assert_eq!(decoder.update(true, 30), None);
+        assert!(decoder.is_pressed());
+
+        // A glitch while held doesn't release it
+        for (pressed, ms) in [(false, 100), (true, 105), (true, 130)] {
+            assert_eq!(decoder.update(pressed, ms), None);
+            assert!(decoder.is_pressed());
+        }
+        // Timed from the last bounce
+        assert_eq!(decoder.deadline(), Some(10 + 1_000));
+    }
+
+    #[test]
+    fn short_press() {
+        let mut decoder = decoder();
+        assert_eq!(edge(&mut decoder, true, 0), None);
+        assert_eq!(edge(&mut decoder, false, 100), None);
+        // Not until no second press can come
+        assert_eq!(decoder.update(false, 399), None);
+        assert_eq!(decoder.update(false, 400), Some(Gesture::Short));
+        assert_eq!(decoder.update(false, 2_000), None);
+    }
+
+    #[test]
+    fn long_press_is_reported_while_held() {
+        let mut decoder = decoder();
+        assert_eq!(edge(&mut decoder, true, 0), None);
+        assert_eq!(decoder.update(true, 999), None);
+        assert_eq!(decoder.update(true, 1_000), Some(Gesture::Long));
+        assert_eq!(decoder.update(true, 5_000), None);
+        // Nor is the release a short press
+        assert_eq!(edge(&mut decoder, false, 5_000), None);
+        assert_eq!(decoder.update(false, 10_000), None);
+    }
+
+    #[test]
+    fn double_press() {
+        let mut decoder = decoder();
+        assert_eq!(edge(&mut decoder, true, 0), None);
+        assert_eq!(edge(&mut decoder, false, 100), None);
+        assert_eq!(edge(&mut decoder, true, 399), None);
+        assert_eq!(edge(&mut decoder, false, 500), Some(Gesture::Double));
+        assert_eq!(decoder.update(false, 5_000), None);
+
+        // The second press held long is still a double, on release
+        assert_eq!(edge(&mut decoder, true, 10_000), None);
+        assert_eq!(edge(&mut decoder, false, 10_100), None);
+        assert_eq!(edge(&mut decoder, true, 10_200), None);
+        assert_eq!(decoder.update(true, 13_000), None);
+        assert_eq!(edge(&mut decoder, false, 13_000), Some(Gesture::Double));
+    }
+
+    #[test]
+    fn a_late_second_press_reports_the_first() {
+        let mut decoder = decoder();
+        assert_eq!(edge(&mut decoder, true, 0), None);
+        assert_eq!(edge(&mut decoder, false, 100), None);
+        // No update at the deadline, the next press comes too late
+        assert_eq!(decoder.update(true, 450), Some(Gesture::Short));
+        assert_eq!(decoder.update(true, 470), None);
+        // And starts a gesture of its own
+        assert_eq!(edge(&mut decoder, false, 500), None);
+        assert_eq!(decoder.update(false, 799), None);
+        assert_eq!(decoder.update(false, 800), Some(Gesture::Short));
+    }
+
+    #[test]
+    fn deadlines() {
+        let mut decoder = decoder();
+        assert_eq!(decoder.deadline(), None);
+
+        decoder.update(true, 0);
+        assert_eq!(decoder.deadline(), Some(20));
+        decoder.update(true, 20);
+        assert_eq!(decoder.deadline(), Some(1_000));
+
+        // The debounce of a release comes first
+        decoder.update(false, 100);
+        assert_eq!(decoder.deadline(), Some(120));
+        decoder.update(false, 120);
+        assert_eq!(decoder.deadline(), Some(400));
+
+        // Nothing to time during the second press
+        edge(&mut decoder, true, 200);
+        assert_eq!(decoder.deadline(), None);
+        edge(&mut decoder, false, 300);
+        assert_eq!(decoder.deadline(), None);
+
+        edge(&mut decoder, true, 1_000);
+        decoder.update(true, 2_000);
+        // Held: nothing until the release
+        assert_eq!(decoder.deadline(), None);
+    }
+}
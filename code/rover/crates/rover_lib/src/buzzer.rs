@@ -0,0 +1,70 @@
+//! Tunes for a passive buzzer driven by a PWM at the note's frequency, one
+//! per [`Alert`] so they can be told apart by ear.
+
+/// A tone of `hz` for `ms`, or silence with `hz` zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Note {
+    pub hz: u16,
+    pub ms: u16,
+}
+
+impl Note {
+    pub const fn tone(hz: u16, ms: u16) -> Self {
+        Self { hz, ms }
+    }
+
+    pub const fn rest(ms: u16) -> Self {
+        Self { hz: 0, ms }
+    }
+
+    pub fn is_rest(&self) -> bool {
+        self.hz == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Alert {
+    /// Up and running.
+    Startup,
+    /// The commands stopped while driving.
+    LinkLost,
+    LowBattery,
+    Estop,
+    SelfTestFailed,
+}
+
+// Around 2 to 4 kHz, where small buzzers are loudest
+const C7: u16 = 2_093;
+const E7: u16 = 2_637;
+const G7: u16 = 3_136;
+const A6: u16 = 1_760;
+
+/// Rising arpeggio.
+const STARTUP: &[Note] = &[
+    Note::tone(C7, 100),
+    Note::tone(E7, 100),
+    Note::tone(G7, 150),
+];
+/// Falling pair.
+const LINK_LOST: &[Note] = &[Note::tone(G7, 150), Note::tone(C7, 250)];
+const LOW_BATTERY: &[Note] = &[
+    Note::tone(A6, 80),
+    Note::rest(80),
+    Note::tone(A6, 80),
+    Note::rest(80),
+    Note::tone(A6, 80),
+];
+const ESTOP: &[Note] = &[Note::tone(G7, 600)];
+const SELF_TEST_FAILED: &[Note] = &[Note::tone(A6, 300), Note::rest(100), Note::tone(A6, 300)];
+
+impl Alert {
+    pub fn tune(self) -> &'static [Note] {
+        match self {
+            Alert::Startup => STARTUP,
+            Alert::LinkLost => LINK_LOST,
+            Alert::LowBattery => LOW_BATTERY,
+            Alert::Estop => ESTOP,
+            Alert::SelfTestFailed => SELF_TEST_FAILED,
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+use crate::iface::{FourWheeledRobot, MotorPower};
+
+/// Per-wheel correction: `deadband` is the power below which the motor
+/// doesn't turn at all, `scale` compensates for motors faster or slower than
+/// the others.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WheelTrim {
+    pub scale: f32,
+    pub deadband: f32,
+}
+
+impl WheelTrim {
+    pub const IDENTITY: Self = Self {
+        scale: 1.0,
+        deadband: 0.0,
+    };
+
+    pub fn is_valid(&self) -> bool {
+        (0.5..=1.5).contains(&self.scale) && (0.0..0.5).contains(&self.deadband)
+    }
+
+    /// Maps the requested power onto `[deadband, MAX]` (keeping its sign),
+    /// then scales it. Zero stays zero.
+    pub fn apply(&self, power: MotorPower) -> MotorPower {
+        let p = power.inner() / MotorPower::MAX;
+        if p == 0.0 {
+            return power;
+        }
+
+        let magnitude = self.deadband + libm::fabsf(p) * (1.0 - self.deadband);
+        MotorPower::new(libm::copysignf(magnitude * self.scale, p) * MotorPower::MAX)
+    }
+}
+
+impl Default for WheelTrim {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+pub struct CalibratedRobot<R> {
+    robot: R,
+    trims: [WheelTrim; 4],
+}
+
+impl<R> CalibratedRobot<R> {
+    pub fn new(robot: R, trims: [WheelTrim; 4]) -> Self {
+        Self { robot, trims }
+    }
+
+    /// FL-FR-BL-BR.
+    pub fn trims(&self) -> [WheelTrim; 4] {
+        self.trims
+    }
+
+    pub fn set_trims(&mut self, trims: [WheelTrim; 4]) {
+        self.trims = trims;
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.robot
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.robot
+    }
+}
+
+impl<R: FourWheeledRobot> FourWheeledRobot for CalibratedRobot<R> {
+    type Error = R::Error;
+
+    fn drive(
+        &mut self,
+        fl: MotorPower,
+        fr: MotorPower,
+        bl: MotorPower,
+        br: MotorPower,
+    ) -> Result<(), Self::Error> {
+        let [t_fl, t_fr, t_bl, t_br] = self.trims;
+        self.robot.drive(
+            t_fl.apply(fl),
+            t_fr.apply(fr),
+            t_bl.apply(bl),
+            t_br.apply(br),
+        )
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.robot.neutral()
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.robot.brake()
+    }
+}
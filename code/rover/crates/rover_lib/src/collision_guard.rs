@@ -0,0 +1,82 @@
+//! Keeps the robot from driving into what's ahead of it: the forward part of
+//! a command is scaled down as an obstacle gets closer, and cut altogether
+//! near it. Strafing and reversing are left alone, to get away.
+
+use serde::{Deserialize, Serialize};
+use uom::si::{angle::radian, f32::Length, length::millimeter};
+
+use crate::iface::{Angle, MecanumPower};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CollisionGuard {
+    /// No forward motion with an obstacle closer than this.
+    pub stop_mm: u16,
+    /// Forward motion is scaled down from here to `stop_mm`.
+    pub slow_mm: u16,
+}
+
+impl CollisionGuard {
+    /// Never gets in the way.
+    pub const DISABLED: Self = Self {
+        stop_mm: 0,
+        slow_mm: 0,
+    };
+
+    /// Past what the rangers can see.
+    const MAX_MM: u16 = 4_000;
+
+    pub fn is_valid(&self) -> bool {
+        self.stop_mm <= self.slow_mm && self.slow_mm <= Self::MAX_MM
+    }
+
+    /// How much of the forward motion is allowed with an obstacle at
+    /// `distance`, 1 for nothing in range.
+    pub fn forward_scale(&self, distance: Option<Length>) -> f32 {
+        let Some(distance) = distance else {
+            return 1.0;
+        };
+        let distance = distance.get::<millimeter>();
+        let (stop, slow) = (self.stop_mm as f32, self.slow_mm as f32);
+        if distance >= slow {
+            1.0
+        } else if distance <= stop {
+            0.0
+        } else {
+            (distance - stop) / (slow - stop)
+        }
+    }
+
+    /// Scales the forward component of the robot frame direction `theta` for
+    /// an obstacle at `distance`.
+    pub fn apply(
+        &self,
+        power: MecanumPower,
+        theta: Angle,
+        distance: Option<Length>,
+    ) -> (MecanumPower, Angle) {
+        let th = theta.get::<radian>();
+        let x = power.inner() * libm::cosf(th);
+        let y = power.inner() * libm::sinf(th);
+        if y <= 0.0 {
+            return (power, theta);
+        }
+
+        let scale = self.forward_scale(distance);
+        if scale == 1.0 {
+            return (power, theta);
+        }
+        let y = y * scale;
+        let power = MecanumPower::new(libm::hypotf(x, y));
+        // Keep the direction of a stopped robot, for the slew limiter
+        if power.inner() == 0.0 {
+            return (power, theta);
+        }
+        (power, Angle::new::<radian>(libm::atan2f(y, x)))
+    }
+}
+
+impl Default for CollisionGuard {
+    fn default() -> Self {
+        Self::DISABLED
+    }
+}
@@ -0,0 +1,203 @@
+//! Magnetometers, their hard and soft iron calibration, and the heading
+//! from magnetic north they give once compensated for the tilt.
+//!
+//! Fields are in µT, in the robot frame like the [`ImuReading`]s: x right,
+//! y forward, z up.
+//!
+//! [`ImuReading`]: crate::imu::ImuReading
+
+use core::future::Future;
+
+use embedded_hal_async::i2c::I2c;
+use serde::{Deserialize, Serialize};
+use uom::si::angle::radian;
+
+use crate::{fusion::Attitude, iface::Angle, imu::ImuError};
+
+pub trait Magnetometer {
+    type Error: core::error::Error;
+
+    fn init(&mut self) -> impl Future<Output = Result<(), Self::Error>>;
+    fn read(&mut self) -> impl Future<Output = Result<[f32; 3], Self::Error>>;
+}
+
+/// QMC5883L, mounted with its axes along the robot's, configured for ±8 G
+/// at 100 Hz.
+pub struct Qmc5883l<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I> Qmc5883l<I> {
+    pub const DEFAULT_ADDRESS: u8 = 0x0D;
+
+    const DATA_X_LSB: u8 = 0x00;
+    const CONTROL_1: u8 = 0x09;
+    const CONTROL_2: u8 = 0x0A;
+    const SET_RESET_PERIOD: u8 = 0x0B;
+    const CHIP_ID: u8 = 0x0D;
+
+    const LSB_PER_GAUSS: f32 = 3000.0;
+    const UT_PER_GAUSS: f32 = 100.0;
+
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+}
+
+impl<I: I2c> Qmc5883l<I> {
+    async fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), ImuError> {
+        self.i2c
+            .write(self.address, &[reg, value])
+            .await
+            .map_err(|_| ImuError::Bus)
+    }
+
+    async fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), ImuError> {
+        self.i2c
+            .write_read(self.address, &[reg], buf)
+            .await
+            .map_err(|_| ImuError::Bus)
+    }
+}
+
+impl<I: I2c> Magnetometer for Qmc5883l<I> {
+    type Error = ImuError;
+
+    async fn init(&mut self) -> Result<(), Self::Error> {
+        let mut id = [0];
+        self.read_regs(Self::CHIP_ID, &mut id).await?;
+        if id[0] != 0xFF {
+            return Err(ImuError::WrongId(id[0]));
+        }
+
+        // Soft reset
+        self.write_reg(Self::CONTROL_2, 0x80).await?;
+        // As the datasheet recommends
+        self.write_reg(Self::SET_RESET_PERIOD, 0x01).await?;
+        // 512 times oversampling, ±8 G, 100 Hz, continuous
+        self.write_reg(Self::CONTROL_1, 0x19).await
+    }
+
+    async fn read(&mut self) -> Result<[f32; 3], Self::Error> {
+        let mut raw = [0; 6];
+        self.read_regs(Self::DATA_X_LSB, &mut raw).await?;
+        Ok(core::array::from_fn(|i| {
+            i16::from_le_bytes([raw[2 * i], raw[2 * i + 1]]) as f32 / Self::LSB_PER_GAUSS
+                * Self::UT_PER_GAUSS
+        }))
+    }
+}
+
+/// Hard iron offsets, subtracted first, then soft iron scales, along each
+/// axis.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MagCalibration {
+    pub offset: [f32; 3],
+    pub scale: [f32; 3],
+}
+
+impl MagCalibration {
+    pub const IDENTITY: Self = Self {
+        offset: [0.0; 3],
+        scale: [1.0; 3],
+    };
+
+    pub fn is_valid(&self) -> bool {
+        self.offset.iter().all(|o| o.is_finite())
+            && self.scale.iter().all(|s| (0.1..=10.0).contains(s))
+    }
+
+    pub fn apply(&self, raw: [f32; 3]) -> [f32; 3] {
+        core::array::from_fn(|i| (raw[i] - self.offset[i]) * self.scale[i])
+    }
+}
+
+/// Collects the extremes of the field while the robot turns on the spot, to
+/// center and round off the circle it draws.
+///
+/// Turning flat only sweeps x and y, so z is left as is.
+#[derive(Debug, Clone, Copy)]
+pub struct MagCalibrator {
+    /// Of x and y.
+    min: [f32; 2],
+    max: [f32; 2],
+}
+
+impl MagCalibrator {
+    /// The least span of x and y for a calibration, the horizontal field
+    /// being 15 to 40 µT across most of the world.
+    const MIN_SPAN_UT: f32 = 10.0;
+
+    pub fn new() -> Self {
+        Self {
+            min: [f32::MAX; 2],
+            max: [f32::MIN; 2],
+        }
+    }
+
+    pub fn add(&mut self, raw: [f32; 3]) {
+        self.min = core::array::from_fn(|i| self.min[i].min(raw[i]));
+        self.max = core::array::from_fn(|i| self.max[i].max(raw[i]));
+    }
+
+    /// `None` if the robot didn't turn enough.
+    pub fn result(&self) -> Option<MagCalibration> {
+        let span = |i: usize| self.max[i] - self.min[i];
+        if span(0) < Self::MIN_SPAN_UT || span(1) < Self::MIN_SPAN_UT {
+            return None;
+        }
+        let radius = (span(0) + span(1)) / 4.0;
+        let calibration = MagCalibration {
+            offset: [
+                (self.max[0] + self.min[0]) / 2.0,
+                (self.max[1] + self.min[1]) / 2.0,
+                0.0,
+            ],
+            scale: [radius * 2.0 / span(0), radius * 2.0 / span(1), 1.0],
+        };
+        calibration.is_valid().then_some(calibration)
+    }
+}
+
+impl Default for MagCalibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Heading of the robot from magnetic north, counter-clockwise positive
+/// like the odometry one, from the calibrated `field` with the robot tilted
+/// by the roll and pitch of `attitude`.
+pub fn compass_heading(field: [f32; 3], attitude: &Attitude) -> Angle {
+    let (sin_roll, cos_roll) = libm::sincosf(attitude.roll.get::<radian>());
+    let (sin_pitch, cos_pitch) = libm::sincosf(attitude.pitch.get::<radian>());
+    let [x, y, z] = field;
+    // The field along the robot's right and forward axes brought level
+    let right = x * cos_roll + z * sin_roll;
+    let forward = x * sin_roll * sin_pitch + y * cos_pitch - z * cos_roll * sin_pitch;
+    Angle::new::<radian>(libm::atan2f(right, forward))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CompassParams {
+    /// `None` until calibrated, the compass going unused.
+    pub calibration: Option<MagCalibration>,
+    /// How much of the way to the compass heading the gyro one is pulled at
+    /// each reading, 0 leaves the heading to the gyro.
+    pub weight: f32,
+}
+
+impl CompassParams {
+    pub const DEFAULT: Self = Self {
+        calibration: None,
+        weight: 0.02,
+    };
+
+    pub fn is_valid(&self) -> bool {
+        self.calibration
+            .as_ref()
+            .is_none_or(MagCalibration::is_valid)
+            && (0.0..=1.0).contains(&self.weight)
+    }
+}
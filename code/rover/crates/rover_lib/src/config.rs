@@ -0,0 +1,241 @@
+use embedded_storage::nor_flash::NorFlash;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{crc::crc16, wire};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum ConfigError<E> {
+    Flash(E),
+    /// Nothing stored yet (or the sector was erased).
+    Empty,
+    BadVersion(u16),
+    BadCrc,
+    TooLarge,
+    Wire(wire::Error),
+}
+
+impl<E: core::fmt::Debug> core::fmt::Display for ConfigError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl<E: core::fmt::Debug> core::error::Error for ConfigError<E> {}
+
+const MAGIC: u32 = u32::from_le_bytes(*b"RCFG");
+const HEADER_SIZE: usize = 12;
+
+/// A single serde value kept in one erase block of a [`NorFlash`].
+///
+/// Layout: `[magic: u32][version: u16][len: u16][crc16: u16][reserved: u16]`
+/// followed by `len` bytes of [`wire`] payload, all little endian. The
+/// version is the one of the stored type: bump it whenever its layout
+/// changes so stale data is rejected instead of misread. `N` is the size of
+/// the stack buffer used for both, header included.
+pub struct ConfigStore<F, const N: usize = 256> {
+    flash: F,
+    offset: u32,
+    version: u16,
+}
+
+impl<F, const N: usize> ConfigStore<F, N> {
+    const FITS_HEADER: () = assert!(N >= HEADER_SIZE);
+
+    /// Largest payload that fits, to check the largest encoding of the
+    /// stored type against at compile time.
+    pub const CAPACITY: usize = N - HEADER_SIZE;
+
+    /// `offset` must be the start of an erase block.
+    pub fn new(flash: F, offset: u32, version: u16) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::FITS_HEADER;
+        Self {
+            flash,
+            offset,
+            version,
+        }
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.flash
+    }
+
+    pub fn inner_mut(&mut self) -> &mut F {
+        &mut self.flash
+    }
+}
+
+impl<F: NorFlash, const N: usize> ConfigStore<F, N> {
+    pub fn load<T: DeserializeOwned>(&mut self) -> Result<T, ConfigError<F::Error>> {
+        let mut header = [0u8; HEADER_SIZE];
+        self.flash
+            .read(self.offset, &mut header)
+            .map_err(ConfigError::Flash)?;
+
+        let field = |at: usize| u16::from_le_bytes([header[at], header[at + 1]]);
+        if u32::from_le_bytes([header[0], header[1], header[2], header[3]]) != MAGIC {
+            return Err(ConfigError::Empty);
+        }
+        let (version, len, crc) = (field(4), field(6) as usize, field(8));
+        if version != self.version {
+            return Err(ConfigError::BadVersion(version));
+        }
+        if len > Self::CAPACITY {
+            return Err(ConfigError::TooLarge);
+        }
+
+        let mut buf = [0u8; N];
+        let payload = &mut buf[HEADER_SIZE..HEADER_SIZE + len];
+        self.flash
+            .read(self.offset + HEADER_SIZE as u32, payload)
+            .map_err(ConfigError::Flash)?;
+        if crc16(payload) != crc {
+            return Err(ConfigError::BadCrc);
+        }
+
+        wire::from_bytes(payload).map_err(ConfigError::Wire)
+    }
+
+    /// Erases the block and writes `value`. Not atomic: losing power half
+    /// way leaves nothing (or a bad CRC) behind, so the caller falls back to
+    /// defaults.
+    pub fn save<T: Serialize>(&mut self, value: &T) -> Result<(), ConfigError<F::Error>> {
+        let mut buf = [0xFFu8; N];
+        let (header, payload) = buf.split_at_mut(HEADER_SIZE);
+        let len = wire::to_slice(value, payload)
+            .map_err(|e| match e {
+                wire::Error::BufferFull => ConfigError::TooLarge,
+                e => ConfigError::Wire(e),
+            })?
+            .len();
+
+        header[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..6].copy_from_slice(&self.version.to_le_bytes());
+        header[6..8].copy_from_slice(&(len as u16).to_le_bytes());
+        header[8..10].copy_from_slice(&crc16(&payload[..len]).to_le_bytes());
+        header[10..12].copy_from_slice(&[0xFF; 2]);
+
+        // Writes must cover whole words, the padding stays erased
+        let total = (HEADER_SIZE + len).next_multiple_of(F::WRITE_SIZE);
+        let data = buf.get(..total).ok_or(ConfigError::TooLarge)?;
+
+        self.flash
+            .erase(self.offset, self.offset + F::ERASE_SIZE as u32)
+            .map_err(ConfigError::Flash)?;
+        self.flash
+            .write(self.offset, data)
+            .map_err(ConfigError::Flash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::mock::MockFlash;
+
+    const VERSION: u16 = 3;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    struct Sample {
+        id: u32,
+        gain: f32,
+        calibration: Option<[f32; 2]>,
+    }
+
+    /// A `u32` varint, the `f32`, the option tag and both of its `f32`s.
+    const SAMPLE_MAX_SIZE: usize = 5 + 4 + 1 + 8;
+
+    type Store = ConfigStore<MockFlash, 32>;
+
+    const _: () = assert!(HEADER_SIZE + SAMPLE_MAX_SIZE <= 32);
+    const _: () = assert!(Store::CAPACITY >= SAMPLE_MAX_SIZE);
+
+    const LARGEST: Sample = Sample {
+        id: u32::MAX,
+        gain: 1.5,
+        calibration: Some([-2.0, 0.5]),
+    };
+
+    fn store() -> Store {
+        ConfigStore::new(MockFlash::new(2), 4096, VERSION)
+    }
+
+    #[test]
+    fn sample_max_size_is_the_largest_encoding() {
+        let mut buf = [0; 64];
+        assert_eq!(
+            wire::to_slice(&LARGEST, &mut buf).unwrap().len(),
+            SAMPLE_MAX_SIZE
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut store = store();
+        store.save(&LARGEST).unwrap();
+        assert_eq!(store.load::<Sample>(), Ok(LARGEST));
+
+        // Saving again erases what was there first
+        let small = Sample {
+            id: 1,
+            gain: 0.0,
+            calibration: None,
+        };
+        store.save(&small).unwrap();
+        assert_eq!(store.load::<Sample>(), Ok(small));
+        // And leaves the other blocks alone
+        assert!(store.inner().bytes[..4096].iter().all(|&b| b == 0xFF));
+    }
+
+    #[test]
+    fn erased_flash_is_empty() {
+        assert_eq!(store().load::<Sample>(), Err(ConfigError::Empty));
+    }
+
+    #[test]
+    fn another_version_is_refused() {
+        let mut store = store();
+        store.save(&LARGEST).unwrap();
+        let mut newer = ConfigStore::<_, 32>::new(store.inner().clone(), 4096, VERSION + 1);
+        assert_eq!(
+            newer.load::<Sample>(),
+            Err(ConfigError::BadVersion(VERSION))
+        );
+    }
+
+    #[test]
+    fn corruption_fails_the_crc() {
+        let mut store = store();
+        store.save(&LARGEST).unwrap();
+        store.inner_mut().bytes[4096 + HEADER_SIZE + 6] ^= 0x01;
+        assert_eq!(store.load::<Sample>(), Err(ConfigError::BadCrc));
+    }
+
+    #[test]
+    fn too_large_a_value_is_refused() {
+        let mut small = ConfigStore::<_, 16>::new(MockFlash::new(2), 4096, VERSION);
+        assert_eq!(small.save(&LARGEST), Err(ConfigError::TooLarge));
+        // Nothing was erased or written
+        assert_eq!(small.load::<Sample>(), Err(ConfigError::Empty));
+
+        // Nor loaded into too small a buffer
+        let mut store = store();
+        store.save(&LARGEST).unwrap();
+        let mut small = ConfigStore::<_, 16>::new(store.inner().clone(), 4096, VERSION);
+        assert_eq!(small.load::<Sample>(), Err(ConfigError::TooLarge));
+    }
+
+    #[test]
+    fn flash_errors_are_passed_on() {
+        let mut store = ConfigStore::<_, 32>::new(MockFlash::new(1), 4096, VERSION);
+        assert!(matches!(store.load::<Sample>(), Err(ConfigError::Flash(_))));
+        assert!(matches!(store.save(&LARGEST), Err(ConfigError::Flash(_))));
+    }
+}
@@ -0,0 +1,18 @@
+/// CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF), bitwise so it doesn't need
+/// a lookup table in flash.
+pub fn crc16(data: &[u8]) -> u16 {
+    crc16_update(0xFFFF, data)
+}
+
+/// Carries on [`crc16`] over data in several pieces.
+pub fn crc16_update(crc: u16, data: &[u8]) -> u16 {
+    data.iter().fold(crc, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use uom::si::{
+    electric_current::ampere,
+    f32::{ElectricCurrent, Time},
+    time::second,
+};
+
+use crate::iface::{FourWheeledRobot, MotorPower};
+
+/// What to do with a wheel drawing too much current for too long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OvercurrentAction {
+    /// Reduce that wheel's power, it's given back slowly once the current
+    /// drops.
+    #[default]
+    Clamp,
+    /// Put every wheel in neutral until [`CurrentLimited::reset_trip`].
+    Trip,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurrentLimit {
+    pub limit: ElectricCurrent,
+    /// How long the limit may be exceeded before acting.
+    pub duration: Time,
+    pub action: OvercurrentAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OvercurrentEvent {
+    /// FL-FR-BL-BR index.
+    pub wheel: u8,
+    pub current: ElectricCurrent,
+    pub action: OvercurrentAction,
+}
+
+/// Overcurrent protection on top of any [`FourWheeledRobot`], the measured
+/// motor currents are fed through [`CurrentLimited::update`].
+pub struct CurrentLimited<R> {
+    robot: R,
+    limit: CurrentLimit,
+    target: [f32; 4],
+    scale: [f32; 4],
+    over_for: [f32; 4],
+    currents: [f32; 4],
+    active: bool,
+    tripped: bool,
+}
+
+impl<R> CurrentLimited<R> {
+    /// Lowest power scale clamping can reach.
+    const MIN_SCALE: f32 = 0.2;
+    /// Power scale given back per second once under the limit.
+    const RECOVERY_RATE: f32 = 0.5;
+
+    pub fn new(robot: R, limit: CurrentLimit) -> Self {
+        Self {
+            robot,
+            limit,
+            target: [0.0; 4],
+            scale: [1.0; 4],
+            over_for: [0.0; 4],
+            currents: [0.0; 4],
+            active: false,
+            tripped: false,
+        }
+    }
+
+    pub fn limit(&self) -> CurrentLimit {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: CurrentLimit) {
+        self.limit = limit;
+    }
+
+    /// Last measured currents, FL-FR-BL-BR.
+    pub fn currents(&self) -> [ElectricCurrent; 4] {
+        self.currents.map(ElectricCurrent::new::<ampere>)
+    }
+
+    pub fn tripped(&self) -> bool {
+        self.tripped
+    }
+
+    /// Allows driving again after a trip.
+    pub fn reset_trip(&mut self) {
+        self.tripped = false;
+        self.scale = [1.0; 4];
+        self.over_for = [0.0; 4];
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.robot
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.robot
+    }
+}
+
+impl<R: FourWheeledRobot> CurrentLimited<R> {
+    fn apply(&mut self) -> Result<(), R::Error> {
+        let [fl, fr, bl, br] =
+            core::array::from_fn(|i| MotorPower::new(self.target[i] * self.scale[i]));
+        self.robot.drive(fl, fr, bl, br)
+    }
+
+    /// Feeds the currents measured `dt` after the previous ones, returning
+    /// the event if the protection acted.
+    pub fn update(
+        &mut self,
+        currents: [ElectricCurrent; 4],
+        dt: Time,
+    ) -> Result<Option<OvercurrentEvent>, R::Error> {
+        let dt = dt.get::<second>();
+        let limit = self.limit.limit.get::<ampere>();
+        let scale = self.scale;
+        let mut event = None;
+
+        for (i, current) in currents.iter().enumerate() {
+            let current = libm::fabsf(current.get::<ampere>());
+            self.currents[i] = current;
+
+            if current <= limit {
+                self.over_for[i] = 0.0;
+                self.scale[i] = (self.scale[i] + Self::RECOVERY_RATE * dt).min(1.0);
+                continue;
+            }
+
+            self.over_for[i] += dt;
+            if self.over_for[i] < self.limit.duration.get::<second>() {
+                continue;
+            }
+            self.over_for[i] = 0.0;
+            match self.limit.action {
+                OvercurrentAction::Clamp => {
+                    self.scale[i] = (self.scale[i] * limit / current).max(Self::MIN_SCALE)
+                }
+                OvercurrentAction::Trip => self.tripped = true,
+            }
+            event.get_or_insert(OvercurrentEvent {
+                wheel: i as u8,
+                current: ElectricCurrent::new::<ampere>(current),
+                action: self.limit.action,
+            });
+        }
+
+        if self.tripped && self.active {
+            self.active = false;
+            self.robot.neutral()?;
+        } else if self.active && scale != self.scale {
+            self.apply()?;
+        }
+        Ok(event)
+    }
+}
+
+impl<R: FourWheeledRobot> FourWheeledRobot for CurrentLimited<R> {
+    type Error = R::Error;
+
+    fn drive(
+        &mut self,
+        fl: MotorPower,
+        fr: MotorPower,
+        bl: MotorPower,
+        br: MotorPower,
+    ) -> Result<(), Self::Error> {
+        self.target = [fl, fr, bl, br].map(|p| p.inner());
+        if self.tripped {
+            return Ok(());
+        }
+        self.active = true;
+        self.apply()
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.target = [0.0; 4];
+        self.active = false;
+        self.robot.neutral()
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.target = [0.0; 4];
+        self.active = false;
+        self.robot.brake()
+    }
+}
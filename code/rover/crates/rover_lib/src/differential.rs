@@ -0,0 +1,142 @@
+use uom::si::angle::radian;
+
+use crate::iface::{Angle, DriveBase, FourWheeledRobot, MecanumPower, Motor, MotorPower, Turn};
+
+/// Chassis with a left and a right side, each driven as a whole.
+pub trait TwoSidedRobot {
+    type Error: core::error::Error;
+
+    fn drive(&mut self, left: MotorPower, right: MotorPower) -> Result<(), Self::Error>;
+    fn neutral(&mut self) -> Result<(), Self::Error>;
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.neutral()
+    }
+}
+
+/// Four wheel tank drive, both wheels of a side get the same power.
+impl<T: FourWheeledRobot> TwoSidedRobot for T {
+    type Error = T::Error;
+
+    fn drive(&mut self, left: MotorPower, right: MotorPower) -> Result<(), Self::Error> {
+        FourWheeledRobot::drive(self, left, right, left, right)
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        FourWheeledRobot::neutral(self)
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        FourWheeledRobot::brake(self)
+    }
+}
+
+pub struct MyTwoWheelRobot<L, R> {
+    left: L,
+    right: R,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum MyTwoWheelRobotError {
+    Motor(Side),
+}
+
+impl core::fmt::Display for MyTwoWheelRobotError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for MyTwoWheelRobotError {}
+
+impl<L, R> MyTwoWheelRobot<L, R> {
+    pub fn new(left: L, right: R) -> Self {
+        Self { left, right }
+    }
+}
+
+impl<L: Motor, R: Motor> MyTwoWheelRobot<L, R> {
+    /// Applied motor powers, left-right.
+    pub fn powers(&self) -> [MotorPower; 2] {
+        [self.left.power(), self.right.power()]
+    }
+}
+
+impl<L: Motor, R: Motor> TwoSidedRobot for MyTwoWheelRobot<L, R> {
+    type Error = MyTwoWheelRobotError;
+
+    fn drive(&mut self, left: MotorPower, right: MotorPower) -> Result<(), Self::Error> {
+        self.left
+            .drive(left)
+            .map_err(|_| Self::Error::Motor(Side::Left))?;
+        self.right
+            .drive(right)
+            .map_err(|_| Self::Error::Motor(Side::Right))?;
+
+        Ok(())
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.left
+            .neutral()
+            .map_err(|_| Self::Error::Motor(Side::Left))?;
+        self.right
+            .neutral()
+            .map_err(|_| Self::Error::Motor(Side::Right))?;
+
+        Ok(())
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.left
+            .brake()
+            .map_err(|_| Self::Error::Motor(Side::Left))?;
+        self.right
+            .brake()
+            .map_err(|_| Self::Error::Motor(Side::Right))?;
+
+        Ok(())
+    }
+}
+
+/// Tank (skid steer) drive: the forward component of the drive angle moves
+/// the robot, the sideways one is dropped since it can't strafe.
+pub struct DifferentialRobot<R> {
+    robot: R,
+}
+
+impl<R> DifferentialRobot<R> {
+    pub fn new(robot: R) -> Self {
+        Self { robot }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.robot
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.robot
+    }
+}
+
+impl<R: TwoSidedRobot> DriveBase for DifferentialRobot<R> {
+    type Error = R::Error;
+
+    fn drive(&mut self, power: MecanumPower, theta: Angle, turn: Turn) -> Result<(), Self::Error> {
+        let forward = power.inner() * libm::sinf(theta.get::<radian>());
+        let turn = turn.inner();
+
+        self.robot.drive(
+            MotorPower::new(forward + turn),
+            MotorPower::new(forward - turn),
+        )
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.robot.neutral()
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.robot.brake()
+    }
+}
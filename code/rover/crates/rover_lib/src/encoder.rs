@@ -0,0 +1,192 @@
+use core::sync::atomic::{AtomicI32, AtomicU8, Ordering};
+
+use uom::si::{
+    angle::revolution,
+    f32::{Angle, AngularVelocity, Time},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    #[default]
+    Stopped,
+    Forward,
+    Backward,
+}
+
+pub trait Encoder {
+    type Error: core::error::Error;
+
+    /// Samples the underlying counter, `dt` being the time elapsed since the
+    /// previous call. Velocity and direction are computed from this sample.
+    fn update(&mut self, dt: Time) -> Result<(), Self::Error>;
+    fn ticks(&self) -> i32;
+    fn direction(&self) -> Direction;
+    fn angular_velocity(&self) -> AngularVelocity;
+    fn angle(&self) -> Angle;
+}
+
+/// Anything able to report a running, signed quadrature count.
+pub trait TickSource {
+    type Error: core::error::Error;
+
+    fn read_ticks(&mut self) -> Result<i32, Self::Error>;
+}
+
+/// Hardware counters in encoder mode only expose a wrapping 16 bit register.
+pub trait HardwareCounter {
+    fn count(&self) -> u16;
+}
+
+/// Extends a wrapping 16 bit hardware count (e.g. an STM32 timer in encoder
+/// mode) into a 32 bit running count. It must be sampled at least once every
+/// `i16::MAX` ticks.
+pub struct TimerCounter<C> {
+    counter: C,
+    last: u16,
+    ticks: i32,
+}
+
+impl<C: HardwareCounter> TimerCounter<C> {
+    pub fn new(counter: C) -> Self {
+        let last = counter.count();
+        Self {
+            counter,
+            last,
+            ticks: 0,
+        }
+    }
+}
+
+impl<C: HardwareCounter> TickSource for TimerCounter<C> {
+    type Error = core::convert::Infallible;
+
+    fn read_ticks(&mut self) -> Result<i32, Self::Error> {
+        let count = self.counter.count();
+        let delta = count.wrapping_sub(self.last) as i16;
+        self.last = count;
+        self.ticks = self.ticks.wrapping_add(delta as i32);
+        Ok(self.ticks)
+    }
+}
+
+// Indexed by (previous AB state << 2) | new AB state.
+const QUADRATURE_TABLE: [i8; 16] = [0, 1, -1, 0, -1, 0, 0, 1, 1, 0, 0, -1, 0, -1, 1, 0];
+
+/// Software quadrature decoder, fed from EXTI edge interrupts on both
+/// channels. Shared between the interrupt side and the sampling side, hence
+/// the atomics, only loaded and stored as the Cortex-M0+ has nothing else.
+pub struct ExtiCounter {
+    state: AtomicU8,
+    ticks: AtomicI32,
+}
+
+impl ExtiCounter {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(0),
+            ticks: AtomicI32::new(0),
+        }
+    }
+
+    /// To be called on every edge of either channel with the current levels,
+    /// always from the same task or interrupt.
+    pub fn on_edge(&self, a: bool, b: bool) {
+        let new = ((a as u8) << 1) | b as u8;
+        let old = self.state.load(Ordering::Relaxed);
+        self.state.store(new, Ordering::Relaxed);
+        let step = QUADRATURE_TABLE[((old << 2) | new) as usize];
+        if step != 0 {
+            let ticks = self.ticks.load(Ordering::Relaxed);
+            self.ticks.store(ticks + step as i32, Ordering::Relaxed);
+        }
+    }
+
+    pub fn ticks(&self) -> i32 {
+        self.ticks.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for ExtiCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TickSource for &ExtiCounter {
+    type Error = core::convert::Infallible;
+
+    fn read_ticks(&mut self) -> Result<i32, Self::Error> {
+        Ok(self.ticks())
+    }
+}
+
+pub struct QuadratureEncoder<S> {
+    source: S,
+    ticks_per_rev: f32,
+    inverted: bool,
+    ticks: i32,
+    direction: Direction,
+    velocity: AngularVelocity,
+}
+
+impl<S> QuadratureEncoder<S> {
+    /// `ticks_per_rev` counts all four edges of a quadrature cycle, measured
+    /// at the wheel shaft.
+    pub fn new(source: S, ticks_per_rev: u32, inverted: bool) -> Self {
+        Self {
+            source,
+            ticks_per_rev: ticks_per_rev as f32,
+            inverted,
+            ticks: 0,
+            direction: Direction::Stopped,
+            velocity: Default::default(),
+        }
+    }
+}
+
+impl<S: TickSource> Encoder for QuadratureEncoder<S> {
+    type Error = S::Error;
+
+    fn update(&mut self, dt: Time) -> Result<(), Self::Error> {
+        let raw = self.source.read_ticks()?;
+        let ticks = if self.inverted {
+            raw.wrapping_neg()
+        } else {
+            raw
+        };
+        let delta = ticks.wrapping_sub(self.ticks);
+        self.ticks = ticks;
+
+        self.direction = match delta {
+            0 => Direction::Stopped,
+            d if d > 0 => Direction::Forward,
+            _ => Direction::Backward,
+        };
+
+        let dt = dt.get::<uom::si::time::second>();
+        if dt > 0.0 {
+            let revs = delta as f32 / self.ticks_per_rev;
+            self.velocity = AngularVelocity::new::<uom::si::angular_velocity::revolution_per_minute>(
+                revs * 60.0 / dt,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn ticks(&self) -> i32 {
+        self.ticks
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn angular_velocity(&self) -> AngularVelocity {
+        self.velocity
+    }
+
+    fn angle(&self) -> Angle {
+        Angle::new::<revolution>(self.ticks as f32 / self.ticks_per_rev)
+    }
+}
@@ -0,0 +1,170 @@
+//! Brushless wheels on bidirectional (3D mode) ESCs, driven either with RC
+//! servo pulses or with DSHOT frames.
+//!
+//! An ESC only arms after seeing neutral for a while from power up, and
+//! DSHOT ones disarm again when the frames stop: an [`EscMotor`] has to be
+//! [`tick`](EscMotor::tick)ed periodically, which sends neutral until armed
+//! and repeats the last command after.
+
+use uom::si::{f32::Time, time::second};
+
+use crate::iface::{Motor, MotorPower};
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+#[non_exhaustive]
+pub enum EscError<E> {
+    Output(E),
+    /// Driven before the arming time was up.
+    NotArmed,
+}
+
+impl<E: core::fmt::Debug> core::fmt::Display for EscError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl<E: core::fmt::Debug> core::error::Error for EscError<E> {}
+
+/// How the throttle gets to the ESC.
+pub trait EscOutput {
+    type Error: core::fmt::Debug;
+
+    /// `power` full reverse to full forward, zero being neutral.
+    fn throttle(&mut self, power: MotorPower) -> Result<(), Self::Error>;
+}
+
+/// RC servo pulses, 1 ms full reverse, 1.5 ms neutral and 2 ms full
+/// forward, on a PWM running at the ESC's frame rate.
+pub struct RcPwm<P> {
+    pwm: P,
+    period_us: u32,
+}
+
+impl<P> RcPwm<P> {
+    pub const NEUTRAL_US: u32 = 1_500;
+    /// Pulse width from neutral to either end.
+    pub const RANGE_US: u32 = 500;
+
+    /// `period_us` is the period the PWM runs at, 20 000 for 50 Hz.
+    pub fn new(pwm: P, period_us: u32) -> Self {
+        Self { pwm, period_us }
+    }
+}
+
+impl<P: embedded_hal_1::pwm::SetDutyCycle> EscOutput for RcPwm<P> {
+    type Error = P::Error;
+
+    fn throttle(&mut self, power: MotorPower) -> Result<(), Self::Error> {
+        let pulse_us = Self::NEUTRAL_US as f32 + power.inner() * Self::RANGE_US as f32;
+        let duty = pulse_us / self.period_us as f32 * self.pwm.max_duty_cycle() as f32;
+        self.pwm.set_duty_cycle(duty as u16)
+    }
+}
+
+/// Clocks one DSHOT frame out, bit timing and all, usually a timer fed by
+/// DMA.
+pub trait DshotPort {
+    type Error: core::fmt::Debug;
+
+    fn send(&mut self, frame: u16) -> Result<(), Self::Error>;
+}
+
+/// DSHOT in 3D mode, the ESC being set up for it.
+pub struct Dshot<D> {
+    port: D,
+}
+
+impl<D> Dshot<D> {
+    /// Throttle values, 48..=1047 reverse and 1048..=2047 forward.
+    const REVERSE_MIN: u16 = 48;
+    const FORWARD_MIN: u16 = 1048;
+    const STEPS: f32 = 999.0;
+
+    pub fn new(port: D) -> Self {
+        Self { port }
+    }
+
+    /// The value (0 for disarmed or stopped, 1..=47 for commands) followed
+    /// by the telemetry request bit and the 4 bit checksum.
+    pub fn frame(value: u16, telemetry: bool) -> u16 {
+        let packet = (value << 1) | telemetry as u16;
+        let crc = (packet ^ (packet >> 4) ^ (packet >> 8)) & 0x0F;
+        (packet << 4) | crc
+    }
+
+    fn value(power: MotorPower) -> u16 {
+        let power = power.inner();
+        let magnitude = (libm::fabsf(power) * Self::STEPS) as u16;
+        if power > 0.0 {
+            Self::FORWARD_MIN + magnitude
+        } else if power < 0.0 {
+            Self::REVERSE_MIN + magnitude
+        } else {
+            0
+        }
+    }
+}
+
+impl<D: DshotPort> EscOutput for Dshot<D> {
+    type Error = D::Error;
+
+    fn throttle(&mut self, power: MotorPower) -> Result<(), Self::Error> {
+        self.port.send(Self::frame(Self::value(power), false))
+    }
+}
+
+pub struct EscMotor<O> {
+    output: O,
+    /// Neutral left to send before the ESC takes commands.
+    arming_left: Time,
+    power: MotorPower,
+}
+
+impl<O: EscOutput> EscMotor<O> {
+    /// Starts disarmed, armed once [`tick`](Self::tick)ed for `arming_time`.
+    pub fn new(output: O, arming_time: Time) -> Self {
+        Self {
+            output,
+            arming_left: arming_time,
+            power: Default::default(),
+        }
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.arming_left.get::<second>() <= 0.0
+    }
+
+    /// Sends the current command again, neutral while arming, `dt` after
+    /// the last tick.
+    pub fn tick(&mut self, dt: Time) -> Result<(), EscError<O::Error>> {
+        if !self.is_armed() {
+            self.arming_left -= dt;
+        }
+        self.output.throttle(self.power).map_err(EscError::Output)
+    }
+}
+
+impl<O: EscOutput> Motor for EscMotor<O> {
+    type Error = EscError<O::Error>;
+
+    fn drive(&mut self, power: MotorPower) -> Result<(), Self::Error> {
+        if !self.is_armed() {
+            return Err(EscError::NotArmed);
+        }
+        self.output.throttle(power).map_err(EscError::Output)?;
+        self.power = power;
+        Ok(())
+    }
+    /// ESCs brake or not as they're set up to, neutral is all there is.
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.output
+            .throttle(MotorPower::default())
+            .map_err(EscError::Output)?;
+        self.power = Default::default();
+        Ok(())
+    }
+    fn power(&self) -> MotorPower {
+        self.power
+    }
+}
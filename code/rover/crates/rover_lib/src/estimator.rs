@@ -0,0 +1,213 @@
+//! An extended Kalman filter of the pose and velocity of the base, in place
+//! of plain dead reckoning: the wheel odometry slips, the gyro drifts and
+//! the GPS jumps around, each keeping the others in check.
+//!
+//! The state is the [`Pose`] and the [`ChassisVelocity`], the velocities
+//! carried over from one step to the next. The measurements are all of a
+//! single state each, so they're fused one at a time with no inversion.
+
+use serde::{Deserialize, Serialize};
+use uom::si::{
+    angle::{degree, radian},
+    angular_velocity::radian_per_second,
+    f32::{AngularVelocity, Length, Velocity},
+    length::meter,
+    velocity::meter_per_second,
+};
+
+use crate::{
+    iface::Angle,
+    kinematics::ChassisVelocity,
+    odometry::{wrap_angle, Pose},
+};
+
+const N: usize = 6;
+const X: usize = 0;
+const Y: usize = 1;
+const HEADING: usize = 2;
+const VX: usize = 3;
+const VY: usize = 4;
+const OMEGA: usize = 5;
+
+type Matrix = [[f32; N]; N];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EstimatorParams {
+    /// How fast the base may change speed, standard deviation in m/s².
+    pub accel_noise: f32,
+    /// How fast it may change turn rate, in rad/s².
+    pub turn_accel_noise: f32,
+    /// Of the wheel odometry, in m/s.
+    pub odometry_noise: f32,
+    /// Of the turn rate from the wheels, which slip the most turning, in
+    /// rad/s.
+    pub odometry_turn_noise: f32,
+    /// Of the IMU heading.
+    pub heading_noise_deg: f32,
+    /// Of a GPS position.
+    pub gps_noise_m: f32,
+}
+
+impl EstimatorParams {
+    pub const DEFAULT: Self = Self {
+        accel_noise: 2.0,
+        turn_accel_noise: 6.0,
+        odometry_noise: 0.05,
+        odometry_turn_noise: 0.3,
+        heading_noise_deg: 2.0,
+        gps_noise_m: 3.0,
+    };
+
+    pub fn is_valid(&self) -> bool {
+        [
+            self.accel_noise,
+            self.turn_accel_noise,
+            self.odometry_noise,
+            self.odometry_turn_noise,
+            self.heading_noise_deg,
+            self.gps_noise_m,
+        ]
+        .iter()
+        .all(|noise| (0.001..=100.0).contains(noise))
+    }
+}
+
+/// The estimate, with the standard deviations of its pose.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Estimate {
+    pub pose: Pose,
+    pub velocity: ChassisVelocity,
+    pub position_std: Length,
+    pub heading_std: Angle,
+}
+
+pub struct PoseEstimator {
+    params: EstimatorParams,
+    state: [f32; N],
+    covariance: Matrix,
+}
+
+impl PoseEstimator {
+    /// Standing still at the origin, for sure.
+    pub fn new(params: EstimatorParams) -> Self {
+        Self {
+            params,
+            state: [0.0; N],
+            covariance: [[0.0; N]; N],
+        }
+    }
+
+    pub fn set_params(&mut self, params: EstimatorParams) {
+        self.params = params;
+    }
+
+    pub fn pose(&self) -> Pose {
+        Pose {
+            x: Length::new::<meter>(self.state[X]),
+            y: Length::new::<meter>(self.state[Y]),
+            heading: Angle::new::<radian>(self.state[HEADING]),
+        }
+    }
+
+    pub fn velocity(&self) -> ChassisVelocity {
+        ChassisVelocity {
+            vx: Velocity::new::<meter_per_second>(self.state[VX]),
+            vy: Velocity::new::<meter_per_second>(self.state[VY]),
+            omega: AngularVelocity::new::<radian_per_second>(self.state[OMEGA]),
+        }
+    }
+
+    pub fn covariance(&self) -> &[[f32; N]; N] {
+        &self.covariance
+    }
+
+    pub fn estimate(&self) -> Estimate {
+        let p = &self.covariance;
+        Estimate {
+            pose: self.pose(),
+            velocity: self.velocity(),
+            position_std: Length::new::<meter>(libm::sqrtf(p[X][X] + p[Y][Y])),
+            heading_std: Angle::new::<radian>(libm::sqrtf(p[HEADING][HEADING])),
+        }
+    }
+
+    /// Carries the estimate `dt` seconds on at the estimated velocity.
+    pub fn predict(&mut self, dt: f32) {
+        let [x, y, heading, vx, vy, omega] = self.state;
+        // The velocity is in the robot frame, the heading counter-clockwise
+        // from forward
+        let (sin, cos) = libm::sincosf(heading);
+        self.state[X] = x + (vx * cos - vy * sin) * dt;
+        self.state[Y] = y + (vx * sin + vy * cos) * dt;
+        self.state[HEADING] = wrap_angle(heading + omega * dt);
+
+        let mut f: Matrix =
+            core::array::from_fn(|i| core::array::from_fn(|j| (i == j) as u8 as f32));
+        f[X][HEADING] = (-vx * sin - vy * cos) * dt;
+        f[X][VX] = cos * dt;
+        f[X][VY] = -sin * dt;
+        f[Y][HEADING] = (vx * cos - vy * sin) * dt;
+        f[Y][VX] = sin * dt;
+        f[Y][VY] = cos * dt;
+        f[HEADING][OMEGA] = dt;
+
+        let p = &self.covariance;
+        let fp: Matrix = core::array::from_fn(|i| {
+            core::array::from_fn(|j| (0..N).map(|k| f[i][k] * p[k][j]).sum())
+        });
+        self.covariance = core::array::from_fn(|i| {
+            core::array::from_fn(|j| (0..N).map(|k| fp[i][k] * f[j][k]).sum())
+        });
+
+        let accel = self.params.accel_noise * dt;
+        let turn_accel = self.params.turn_accel_noise * dt;
+        self.covariance[VX][VX] += accel * accel;
+        self.covariance[VY][VY] += accel * accel;
+        self.covariance[OMEGA][OMEGA] += turn_accel * turn_accel;
+    }
+
+    /// The velocity the wheels turn at, from [`MecanumGeometry::chassis_velocity`].
+    ///
+    /// [`MecanumGeometry::chassis_velocity`]: crate::odometry::MecanumGeometry::chassis_velocity
+    pub fn update_odometry(&mut self, velocity: ChassisVelocity) {
+        let noise = self.params.odometry_noise;
+        let turn_noise = self.params.odometry_turn_noise;
+        self.update(VX, velocity.vx.get::<meter_per_second>(), noise * noise);
+        self.update(VY, velocity.vy.get::<meter_per_second>(), noise * noise);
+        let omega = velocity.omega.get::<radian_per_second>();
+        self.update(OMEGA, omega, turn_noise * turn_noise);
+    }
+
+    /// The heading of the IMU, in the same frame.
+    pub fn update_heading(&mut self, heading: Angle) {
+        let noise = Angle::new::<degree>(self.params.heading_noise_deg).get::<radian>();
+        self.update(HEADING, heading.get::<radian>(), noise * noise);
+    }
+
+    /// A GPS position, brought into the same frame.
+    pub fn update_position(&mut self, x: Length, y: Length) {
+        let noise = self.params.gps_noise_m;
+        self.update(X, x.get::<meter>(), noise * noise);
+        self.update(Y, y.get::<meter>(), noise * noise);
+    }
+
+    /// Fuses a measurement of state `i` of the given variance.
+    fn update(&mut self, i: usize, measured: f32, variance: f32) {
+        let mut innovation = measured - self.state[i];
+        if i == HEADING {
+            innovation = wrap_angle(innovation);
+        }
+        let row = self.covariance[i];
+        let gain = row.map(|p| p / (row[i] + variance));
+
+        for (state, k) in self.state.iter_mut().zip(gain) {
+            *state += k * innovation;
+        }
+        self.state[HEADING] = wrap_angle(self.state[HEADING]);
+        for (p, k) in self.covariance.iter_mut().zip(gain) {
+            for (p, r) in p.iter_mut().zip(row) {
+                *p -= k * r;
+            }
+        }
+    }
+}
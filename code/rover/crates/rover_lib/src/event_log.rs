@@ -0,0 +1,210 @@
+use embedded_storage::nor_flash::NorFlash;
+use serde::{Deserialize, Serialize};
+
+use crate::{crc::crc16, wire};
+
+/// Faults worth keeping across resets, for post-mortem debugging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Event {
+    /// The host went quiet while driving.
+    SafetyTimeout,
+    Estop,
+    /// FL-FR-BL-BR index.
+    Overcurrent {
+        wheel: u8,
+    },
+    LowBattery,
+    /// Stopping failed and the motor outputs were cut.
+    Fault,
+    /// FL-FR-BL-BR index.
+    Stall {
+        wheel: u8,
+    },
+    WatchdogReset,
+    BrownoutReset,
+    Panic,
+    /// Tilted past the limit, the drive was cut.
+    Tilt,
+    /// The host was lost past the last stage of the failsafe.
+    Failsafe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Counts up across resets, so entries sort even though the uptime
+    /// starts over.
+    pub seq: u32,
+    pub uptime_ms: u32,
+    pub event: Event,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum EventLogError<E> {
+    Flash(E),
+    Wire(wire::Error),
+}
+
+impl<E: core::fmt::Debug> core::fmt::Display for EventLogError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl<E: core::fmt::Debug> core::error::Error for EventLogError<E> {}
+
+/// Layout: `[seq: u32][uptime_ms: u32][event: 6 bytes of wire, 0xFF padded]
+/// [crc16: u16]`, little endian. Erased flash reads as seq `u32::MAX`.
+const RECORD_SIZE: usize = 16;
+const EVENT_AT: usize = 8;
+const CRC_AT: usize = 14;
+
+/// A circular log of [`Entry`]s over whole erase blocks of a [`NorFlash`].
+///
+/// Records are written one after the other; stepping into the next block
+/// erases it first, dropping the oldest entries. With a single block the log
+/// starts over when full. Only the write position is kept in RAM, the flash
+/// is passed to every call so the log can share it with a
+/// [`ConfigStore`](crate::ConfigStore).
+#[derive(Debug, Clone, Copy)]
+pub struct EventLog {
+    offset: u32,
+    size: u32,
+    /// Slot the next entry goes to.
+    next: u32,
+    seq: u32,
+}
+
+impl EventLog {
+    /// Finds where the log left off. `offset` and `size` must cover whole
+    /// erase blocks.
+    pub fn open<F: NorFlash>(
+        flash: &mut F,
+        offset: u32,
+        size: u32,
+    ) -> Result<Self, EventLogError<F::Error>> {
+        const {
+            assert!(
+                RECORD_SIZE.is_multiple_of(F::WRITE_SIZE)
+                    && F::ERASE_SIZE.is_multiple_of(RECORD_SIZE)
+            )
+        };
+        let mut log = Self {
+            offset,
+            size,
+            next: 0,
+            seq: 0,
+        };
+        let mut newest = None;
+        for slot in 0..log.capacity() {
+            if let Some(entry) = log.read_slot(flash, slot)? {
+                if newest.is_none_or(|(seq, _)| entry.seq > seq) {
+                    newest = Some((entry.seq, slot));
+                }
+            }
+        }
+        if let Some((seq, slot)) = newest {
+            log.seq = seq.wrapping_add(1);
+            log.next = (slot + 1) % log.capacity();
+        }
+        Ok(log)
+    }
+
+    /// How many entries fit, the oldest block included.
+    pub fn capacity(&self) -> u32 {
+        self.size / RECORD_SIZE as u32
+    }
+
+    pub fn append<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        uptime_ms: u32,
+        event: Event,
+    ) -> Result<(), EventLogError<F::Error>> {
+        let mut record = [0xFFu8; RECORD_SIZE];
+        wire::to_slice(&event, &mut record[EVENT_AT..CRC_AT]).map_err(EventLogError::Wire)?;
+        record[..4].copy_from_slice(&self.seq.to_le_bytes());
+        record[4..EVENT_AT].copy_from_slice(&uptime_ms.to_le_bytes());
+        let crc = crc16(&record[..CRC_AT]);
+        record[CRC_AT..].copy_from_slice(&crc.to_le_bytes());
+
+        let at = self.address(self.next);
+        let block = F::ERASE_SIZE as u32;
+        if (at - self.offset).is_multiple_of(block) && !self.is_erased(flash, self.next)? {
+            flash.erase(at, at + block).map_err(EventLogError::Flash)?;
+        }
+        flash.write(at, &record).map_err(EventLogError::Flash)?;
+
+        self.next = (self.next + 1) % self.capacity();
+        self.seq = self.seq.wrapping_add(1);
+        Ok(())
+    }
+
+    /// The `index`th entry, oldest first, `None` past the newest or where
+    /// a record is damaged.
+    pub fn read<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        index: u32,
+    ) -> Result<Option<Entry>, EventLogError<F::Error>> {
+        if index >= self.capacity() {
+            return Ok(None);
+        }
+        self.read_slot(flash, (self.next + index) % self.capacity())
+    }
+
+    /// Erases the whole log. Sequence numbers carry on.
+    pub fn clear<F: NorFlash>(&mut self, flash: &mut F) -> Result<(), EventLogError<F::Error>> {
+        flash
+            .erase(self.offset, self.offset + self.size)
+            .map_err(EventLogError::Flash)?;
+        self.next = 0;
+        Ok(())
+    }
+
+    fn address(&self, slot: u32) -> u32 {
+        self.offset + slot * RECORD_SIZE as u32
+    }
+
+    fn read_record<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        slot: u32,
+    ) -> Result<[u8; RECORD_SIZE], EventLogError<F::Error>> {
+        let mut record = [0u8; RECORD_SIZE];
+        flash
+            .read(self.address(slot), &mut record)
+            .map_err(EventLogError::Flash)?;
+        Ok(record)
+    }
+
+    fn is_erased<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        slot: u32,
+    ) -> Result<bool, EventLogError<F::Error>> {
+        Ok(self.read_record(flash, slot)?.iter().all(|&b| b == 0xFF))
+    }
+
+    fn read_slot<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        slot: u32,
+    ) -> Result<Option<Entry>, EventLogError<F::Error>> {
+        let record = self.read_record(flash, slot)?;
+        let crc = u16::from_le_bytes([record[CRC_AT], record[CRC_AT + 1]]);
+        if crc16(&record[..CRC_AT]) != crc {
+            return Ok(None);
+        }
+        let word = |at: usize| {
+            u32::from_le_bytes([record[at], record[at + 1], record[at + 2], record[at + 3]])
+        };
+        Ok(wire::from_bytes(&record[EVENT_AT..CRC_AT])
+            .ok()
+            .map(|event| Entry {
+                seq: word(0),
+                uptime_ms: word(4),
+                event,
+            }))
+    }
+}
@@ -0,0 +1,260 @@
+//! Staged response to losing the host: the longer it stays quiet, the more
+//! the robot is held back, first slowed down, then stopped by the safety
+//! timer, and last faulted until it's armed again.
+//!
+//! The stop is the safety timeout's, the other two stages sit either side
+//! of it and can each be left out.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    defmt::Format,
+)]
+pub enum FailsafeStage {
+    #[default]
+    Linked,
+    /// Drive powers scaled by [`FailsafeParams::slow_power`].
+    Slowed,
+    Stopped,
+    /// Stopped, and driving refused until armed again.
+    Faulted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FailsafeParams {
+    /// Quiet for as long, the robot slows down, 0 skips the stage. Less
+    /// than the safety timeout.
+    pub slow_ms: u32,
+    pub slow_power: f32,
+    /// Quiet for as long, the robot faults, 0 never. More than the safety
+    /// timeout.
+    pub fault_ms: u32,
+}
+
+impl FailsafeParams {
+    pub const DEFAULT: Self = Self {
+        slow_ms: 0,
+        slow_power: 0.5,
+        fault_ms: 0,
+    };
+
+    pub fn is_valid(&self) -> bool {
+        (0.0..=1.0).contains(&self.slow_power)
+            && (self.fault_ms == 0 || self.slow_ms < self.fault_ms)
+    }
+
+    /// Whether the stages fall either side of `safety_timeout_ms`.
+    pub fn fits(&self, safety_timeout_ms: u32) -> bool {
+        self.slow_ms < safety_timeout_ms
+            && (self.fault_ms == 0 || self.fault_ms > safety_timeout_ms)
+    }
+
+    /// When each stage starts, since the host was last heard, `None` for
+    /// those left out.
+    fn starts(&self, safety_timeout_ms: u32) -> [(FailsafeStage, Option<u32>); 3] {
+        [
+            (
+                FailsafeStage::Slowed,
+                (self.slow_ms > 0).then_some(self.slow_ms),
+            ),
+            (FailsafeStage::Stopped, Some(safety_timeout_ms)),
+            (
+                FailsafeStage::Faulted,
+                (self.fault_ms > 0).then_some(self.fault_ms),
+            ),
+        ]
+    }
+}
+
+/// Which stage the robot is at, fed how long the host has been quiet.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Failsafe {
+    stage: FailsafeStage,
+}
+
+impl Failsafe {
+    pub const fn new() -> Self {
+        Self {
+            stage: FailsafeStage::Linked,
+        }
+    }
+
+    pub fn stage(&self) -> FailsafeStage {
+        self.stage
+    }
+
+    /// The stage once the host has been quiet for `quiet_ms`, if it moved
+    /// on. Stages only ever move on, until [`Self::reset`].
+    pub fn update(
+        &mut self,
+        params: &FailsafeParams,
+        safety_timeout_ms: u32,
+        quiet_ms: u64,
+    ) -> Option<FailsafeStage> {
+        let stage = params
+            .starts(safety_timeout_ms)
+            .into_iter()
+            .filter_map(|(stage, start)| Some((stage, start?)))
+            .filter(|&(_, start)| quiet_ms >= start as u64)
+            .map(|(stage, _)| stage)
+            .fold(self.stage, FailsafeStage::max);
+        (stage != self.stage).then(|| {
+            self.stage = stage;
+            stage
+        })
+    }
+
+    /// How long after the host was last heard the next stage starts, `None`
+    /// past the last one.
+    pub fn next_ms(&self, params: &FailsafeParams, safety_timeout_ms: u32) -> Option<u32> {
+        params
+            .starts(safety_timeout_ms)
+            .into_iter()
+            .filter(|&(stage, _)| stage > self.stage)
+            .find_map(|(_, start)| start)
+    }
+
+    /// The host was heard from, returns the stage the robot was at.
+    pub fn reset(&mut self) -> FailsafeStage {
+        core::mem::take(&mut self.stage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT_MS: u32 = 500;
+
+    const STAGED: FailsafeParams = FailsafeParams {
+        slow_ms: 200,
+        slow_power: 0.5,
+        fault_ms: 2_000,
+    };
+
+    /// The stage after each quiet time in turn, `None` where it stayed.
+    fn walk(params: &FailsafeParams, quiet_ms: &[u64]) -> [Option<FailsafeStage>; 8] {
+        let mut failsafe = Failsafe::new();
+        let mut stages = [None; 8];
+        for (stage, &ms) in stages.iter_mut().zip(quiet_ms) {
+            *stage = failsafe.update(params, TIMEOUT_MS, ms);
+        }
+        stages
+    }
+
+    #[test]
+    fn stages_start_exactly_at_their_time() {
+        use FailsafeStage::*;
+        assert_eq!(
+            walk(&STAGED, &[0, 199, 200, 499, 500, 1_999, 2_000, 10_000]),
+            [
+                None,
+                None,
+                Some(Slowed),
+                None,
+                Some(Stopped),
+                None,
+                Some(Faulted),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn stages_left_out_are_skipped() {
+        use FailsafeStage::*;
+        assert_eq!(
+            walk(
+                &FailsafeParams::DEFAULT,
+                &[199, 200, 499, 500, 2_000, 60_000]
+            ),
+            [None, None, None, Some(Stopped), None, None, None, None]
+        );
+    }
+
+    #[test]
+    fn a_long_silence_jumps_to_the_last_stage() {
+        let mut failsafe = Failsafe::new();
+        assert_eq!(
+            failsafe.update(&STAGED, TIMEOUT_MS, 5_000),
+            Some(FailsafeStage::Faulted)
+        );
+    }
+
+    #[test]
+    fn stages_only_move_on() {
+        let mut failsafe = Failsafe::new();
+        failsafe.update(&STAGED, TIMEOUT_MS, 500);
+        assert_eq!(failsafe.update(&STAGED, TIMEOUT_MS, 200), None);
+        assert_eq!(failsafe.stage(), FailsafeStage::Stopped);
+    }
+
+    #[test]
+    fn next_stage_times() {
+        let mut failsafe = Failsafe::new();
+        assert_eq!(failsafe.next_ms(&STAGED, TIMEOUT_MS), Some(200));
+        failsafe.update(&STAGED, TIMEOUT_MS, 200);
+        assert_eq!(failsafe.next_ms(&STAGED, TIMEOUT_MS), Some(500));
+        failsafe.update(&STAGED, TIMEOUT_MS, 500);
+        assert_eq!(failsafe.next_ms(&STAGED, TIMEOUT_MS), Some(2_000));
+        failsafe.update(&STAGED, TIMEOUT_MS, 2_000);
+        assert_eq!(failsafe.next_ms(&STAGED, TIMEOUT_MS), None);
+
+        let mut failsafe = Failsafe::new();
+        let params = FailsafeParams::DEFAULT;
+        assert_eq!(failsafe.next_ms(&params, TIMEOUT_MS), Some(TIMEOUT_MS));
+        failsafe.update(&params, TIMEOUT_MS, 500);
+        assert_eq!(failsafe.next_ms(&params, TIMEOUT_MS), None);
+    }
+
+    #[test]
+    fn the_link_returning_mid_stage_starts_over() {
+        for (quiet_ms, stage) in [
+            (200, FailsafeStage::Slowed),
+            (500, FailsafeStage::Stopped),
+            (2_000, FailsafeStage::Faulted),
+        ] {
+            let mut failsafe = Failsafe::new();
+            failsafe.update(&STAGED, TIMEOUT_MS, quiet_ms);
+            assert_eq!(failsafe.reset(), stage);
+            assert_eq!(failsafe.stage(), FailsafeStage::Linked);
+            // The quiet time counts from the host heard again
+            assert_eq!(failsafe.update(&STAGED, TIMEOUT_MS, 199), None);
+            assert_eq!(failsafe.next_ms(&STAGED, TIMEOUT_MS), Some(200));
+        }
+        assert_eq!(Failsafe::new().reset(), FailsafeStage::Linked);
+    }
+
+    #[test]
+    fn params_fall_either_side_of_the_timeout() {
+        assert!(STAGED.is_valid() && STAGED.fits(TIMEOUT_MS));
+        assert!(FailsafeParams::DEFAULT.is_valid() && FailsafeParams::DEFAULT.fits(TIMEOUT_MS));
+
+        let at_timeout = |slow_ms, fault_ms| FailsafeParams {
+            slow_ms,
+            fault_ms,
+            ..STAGED
+        };
+        assert!(!at_timeout(TIMEOUT_MS, 0).fits(TIMEOUT_MS));
+        assert!(at_timeout(TIMEOUT_MS - 1, 0).fits(TIMEOUT_MS));
+        assert!(!at_timeout(0, TIMEOUT_MS).fits(TIMEOUT_MS));
+        assert!(at_timeout(0, TIMEOUT_MS + 1).fits(TIMEOUT_MS));
+
+        assert!(!at_timeout(300, 300).is_valid());
+        assert!(!FailsafeParams {
+            slow_power: 1.5,
+            ..STAGED
+        }
+        .is_valid());
+    }
+}
@@ -0,0 +1,166 @@
+//! Signed 16.16 fixed point, for the hot paths of the `fixed-point` feature
+//! on cores without an FPU. Arithmetic saturates instead of wrapping.
+
+use core::{
+    f32::consts::{FRAC_PI_2, PI},
+    ops::{Add, Div, Mul, Neg, Sub},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct I16F16(i32);
+
+impl I16F16 {
+    const FRAC_BITS: u32 = 16;
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(1 << Self::FRAC_BITS);
+    pub const MAX: Self = Self(i32::MAX);
+    pub const MIN: Self = Self(i32::MIN);
+
+    const PI: Self = Self::from_f32(PI);
+    const FRAC_PI_2: Self = Self::from_f32(FRAC_PI_2);
+
+    pub const fn from_bits(bits: i32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn to_bits(self) -> i32 {
+        self.0
+    }
+
+    /// Saturates out of range values, NaN is zero.
+    pub const fn from_f32(value: f32) -> Self {
+        // `as` saturates and maps NaN to 0
+        Self((value * (1 << Self::FRAC_BITS) as f32) as i32)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / (1 << Self::FRAC_BITS) as f32
+    }
+
+    pub const fn from_int(value: i16) -> Self {
+        Self((value as i32) << Self::FRAC_BITS)
+    }
+
+    pub fn abs(self) -> Self {
+        Self(self.0.saturating_abs())
+    }
+
+    pub fn clamp(self, min: Self, max: Self) -> Self {
+        Self(self.0.clamp(min.0, max.0))
+    }
+
+    /// `x` in radians, any range.
+    pub fn sin(self) -> Self {
+        // Down to -π..=π, then -π/2..=π/2 by symmetry
+        let two_pi = Self::PI.0 as i64 * 2;
+        let mut x = (self.0 as i64).rem_euclid(two_pi);
+        if x > Self::PI.0 as i64 {
+            x -= two_pi;
+        }
+        let mut x = Self(x as i32);
+        if x > Self::FRAC_PI_2 {
+            x = Self::PI - x;
+        } else if x < -Self::FRAC_PI_2 {
+            x = -Self::PI - x;
+        }
+
+        // Taylor series up to x⁷, within 2e-4 over the quarter wave
+        let x2 = x * x;
+        let term = |coefficient: f32| Self::from_f32(coefficient);
+        x * (Self::ONE
+            - x2 * (term(1.0 / 6.0) - x2 * (term(1.0 / 120.0) - x2 * term(1.0 / 5040.0))))
+    }
+
+    pub fn cos(self) -> Self {
+        (self + Self::FRAC_PI_2).sin()
+    }
+}
+
+impl From<f32> for I16F16 {
+    fn from(value: f32) -> Self {
+        Self::from_f32(value)
+    }
+}
+
+impl From<I16F16> for f32 {
+    fn from(value: I16F16) -> Self {
+        value.to_f32()
+    }
+}
+
+impl Add for I16F16 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl Sub for I16F16 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Neg for I16F16 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(self.0.saturating_neg())
+    }
+}
+
+fn saturate(value: i64) -> I16F16 {
+    I16F16(value.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+}
+
+impl Mul for I16F16 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        saturate((self.0 as i64 * rhs.0 as i64) >> Self::FRAC_BITS)
+    }
+}
+
+impl Div for I16F16 {
+    type Output = Self;
+
+    /// Division by zero saturates towards the sign of `self`.
+    fn div(self, rhs: Self) -> Self {
+        if rhs.0 == 0 {
+            return match self.0.signum() {
+                1 => Self::MAX,
+                -1 => Self::MIN,
+                _ => Self::ZERO,
+            };
+        }
+        saturate(((self.0 as i64) << Self::FRAC_BITS) / rhs.0 as i64)
+    }
+}
+
+/// What the hot paths compute with: [`I16F16`] with the `fixed-point`
+/// feature, `f32` otherwise.
+#[cfg(feature = "fixed-point")]
+pub(crate) type Scalar = I16F16;
+#[cfg(not(feature = "fixed-point"))]
+pub(crate) type Scalar = f32;
+
+#[cfg(feature = "fixed-point")]
+pub(crate) fn scalar(value: f32) -> Scalar {
+    I16F16::from_f32(value)
+}
+#[cfg(not(feature = "fixed-point"))]
+pub(crate) fn scalar(value: f32) -> Scalar {
+    value
+}
+
+#[cfg(feature = "fixed-point")]
+pub(crate) fn to_f32(value: Scalar) -> f32 {
+    value.to_f32()
+}
+#[cfg(not(feature = "fixed-point"))]
+pub(crate) fn to_f32(value: Scalar) -> f32 {
+    value
+}
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use uom::si::{
+    angle::radian,
+    f32::{Angle, Time},
+    time::second,
+};
+
+use crate::{imu::ImuReading, odometry::wrap_angle};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Attitude {
+    pub roll: Angle,
+    pub pitch: Angle,
+    /// Counter-clockwise positive, same convention as the odometry heading.
+    pub heading: Angle,
+}
+
+impl Attitude {
+    /// Between the robot's vertical and gravity, whichever way it leans.
+    pub fn tilt(&self) -> Angle {
+        let cos = libm::cosf(self.roll.get::<radian>()) * libm::cosf(self.pitch.get::<radian>());
+        Angle::new::<radian>(libm::acosf(cos.clamp(-1.0, 1.0)))
+    }
+}
+
+/// Complementary filter: roll and pitch trust the gyro short term and the
+/// gravity vector long term, heading is the integrated (bias corrected) yaw
+/// rate since there's no absolute reference for it.
+pub struct ComplementaryFilter {
+    /// Weight of the gyro integration, in [0, 1].
+    alpha: f32,
+    gyro_bias: [f32; 3],
+    roll: f32,
+    pitch: f32,
+    heading: f32,
+    initialized: bool,
+}
+
+impl ComplementaryFilter {
+    pub fn new(alpha: f32) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 1.0),
+            gyro_bias: [0.0; 3],
+            roll: 0.0,
+            pitch: 0.0,
+            heading: 0.0,
+            initialized: false,
+        }
+    }
+
+    /// Gyro offsets in rad/s, typically the mean of readings taken while the
+    /// robot stands still.
+    pub fn set_gyro_bias(&mut self, bias: [f32; 3]) {
+        self.gyro_bias = bias;
+    }
+
+    pub fn reset_heading(&mut self, heading: Angle) {
+        self.heading = heading.get::<radian>();
+    }
+
+    /// Pulls the heading `weight` of the way to an absolute one, from a
+    /// compass, taking out the drift of the gyro.
+    pub fn correct_heading(&mut self, heading: Angle, weight: f32) {
+        let error = wrap_angle(heading.get::<radian>() - self.heading);
+        self.heading = wrap_angle(self.heading + weight.clamp(0.0, 1.0) * error);
+    }
+
+    pub fn attitude(&self) -> Attitude {
+        Attitude {
+            roll: Angle::new::<radian>(self.roll),
+            pitch: Angle::new::<radian>(self.pitch),
+            heading: Angle::new::<radian>(self.heading),
+        }
+    }
+
+    pub fn update(&mut self, reading: ImuReading, dt: Time) -> Attitude {
+        let [ax, ay, az] = reading.accel;
+        let [gx, gy, gz] = core::array::from_fn(|i| reading.gyro[i] - self.gyro_bias[i]);
+        let dt = dt.get::<second>();
+
+        // Pitch is about the x (right) axis, roll about the y (forward) one
+        let accel_pitch = libm::atan2f(ay, libm::sqrtf(ax * ax + az * az));
+        let accel_roll = libm::atan2f(-ax, az);
+
+        if self.initialized {
+            self.pitch = self.alpha * (self.pitch + gx * dt) + (1.0 - self.alpha) * accel_pitch;
+            self.roll = self.alpha * (self.roll + gy * dt) + (1.0 - self.alpha) * accel_roll;
+        } else {
+            self.pitch = accel_pitch;
+            self.roll = accel_roll;
+            self.initialized = true;
+        }
+        self.heading = wrap_angle(self.heading + gz * dt);
+
+        self.attitude()
+    }
+}
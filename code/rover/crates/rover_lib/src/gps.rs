@@ -0,0 +1,424 @@
+//! GPS receivers speaking NMEA 0183, and the local frame GPS waypoints are
+//! driven in.
+//!
+//! Only RMC, for the position, speed and course, and GGA, for the number
+//! of satellites, are read: every receiver sends both by default.
+
+use serde::{Deserialize, Serialize};
+use uom::si::{
+    angle::{degree, radian},
+    f32::{Length, Velocity},
+    length::meter,
+    velocity::knot,
+};
+
+use crate::iface::Angle;
+
+/// The longest sentence the standard allows, `$` to the checksum.
+const MAX_SENTENCE: usize = 82;
+const EARTH_RADIUS_M: f32 = 6_371_000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum NmeaError {
+    TooLong,
+    BadChecksum,
+    /// A field of a sentence read didn't parse.
+    BadField,
+}
+
+impl core::fmt::Display for NmeaError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for NmeaError {}
+
+/// A position in 1e-7 degrees, north and east positive, about a centimeter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeoPoint {
+    pub lat_e7: i32,
+    pub lon_e7: i32,
+}
+
+impl GeoPoint {
+    pub fn is_valid(&self) -> bool {
+        (-900_000_000..=900_000_000).contains(&self.lat_e7)
+            && (-1_800_000_000..=1_800_000_000).contains(&self.lon_e7)
+    }
+
+    /// Where the point is from `origin`, x right and y forward when facing
+    /// magnetic north, `declination` being how far east of true north that
+    /// is. Flat earth, fine for a few kilometers.
+    pub fn offset_from(&self, origin: &GeoPoint, declination: Angle) -> (Length, Length) {
+        let to_rad = |e7: i64| (e7 as f32 * 1e-7).to_radians();
+        let lat = to_rad(origin.lat_e7 as i64);
+        let north = to_rad(self.lat_e7 as i64 - origin.lat_e7 as i64) * EARTH_RADIUS_M;
+        // Wrapped across the antimeridian
+        let mut dlon = self.lon_e7 as i64 - origin.lon_e7 as i64;
+        if dlon > 1_800_000_000 {
+            dlon -= 3_600_000_000;
+        } else if dlon < -1_800_000_000 {
+            dlon += 3_600_000_000;
+        }
+        let east = to_rad(dlon) * EARTH_RADIUS_M * libm::cosf(lat);
+
+        let (sin, cos) = libm::sincosf(declination.get::<radian>());
+        (
+            Length::new::<meter>(east * cos - north * sin),
+            Length::new::<meter>(east * sin + north * cos),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GpsFix {
+    pub position: GeoPoint,
+    /// Over the ground.
+    pub speed: Velocity,
+    /// Course over the ground from true north, clockwise like a compass.
+    /// `None` when the receiver doesn't tell, standing still.
+    pub course: Option<Angle>,
+    pub satellites: u8,
+}
+
+/// What a receiver reports once a second or so.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GpsReading {
+    Fix(GpsFix),
+    NoFix,
+}
+
+/// Assembles the sentences of the bytes received, a reading at the end of
+/// each RMC one.
+pub struct NmeaParser {
+    sentence: [u8; MAX_SENTENCE],
+    len: usize,
+    /// Dropping bytes until the next `$`.
+    skipping: bool,
+    satellites: u8,
+}
+
+impl NmeaParser {
+    pub fn new() -> Self {
+        Self {
+            sentence: [0; MAX_SENTENCE],
+            len: 0,
+            skipping: true,
+            satellites: 0,
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> Result<Option<GpsReading>, NmeaError> {
+        match byte {
+            b'$' => {
+                self.len = 0;
+                self.skipping = false;
+                Ok(None)
+            }
+            _ if self.skipping => Ok(None),
+            b'\r' | b'\n' => {
+                self.skipping = true;
+                let len = self.len;
+                self.parse(len)
+            }
+            _ if self.len == MAX_SENTENCE => {
+                self.skipping = true;
+                Err(NmeaError::TooLong)
+            }
+            _ => {
+                self.sentence[self.len] = byte;
+                self.len += 1;
+                Ok(None)
+            }
+        }
+    }
+
+    fn parse(&mut self, len: usize) -> Result<Option<GpsReading>, NmeaError> {
+        let sentence =
+            core::str::from_utf8(&self.sentence[..len]).map_err(|_| NmeaError::BadField)?;
+        let (body, checksum) = sentence.split_once('*').ok_or(NmeaError::BadChecksum)?;
+        let checksum = u8::from_str_radix(checksum, 16).map_err(|_| NmeaError::BadChecksum)?;
+        if body.bytes().fold(0, |sum, b| sum ^ b) != checksum {
+            return Err(NmeaError::BadChecksum);
+        }
+
+        // Any talker: GP, GN, GL...
+        let mut fields = body.split(',');
+        let kind = fields.next().and_then(|id| id.get(2..)).unwrap_or_default();
+        let mut field = || fields.next().ok_or(NmeaError::BadField);
+        match kind {
+            "GGA" => {
+                // Time, latitude, N/S, longitude, E/W, quality
+                for _ in 0..6 {
+                    field()?;
+                }
+                self.satellites = parse_or_zero(field()?)?;
+                Ok(None)
+            }
+            "RMC" => {
+                field()?;
+                if field()? != "A" {
+                    return Ok(Some(GpsReading::NoFix));
+                }
+                let position = GeoPoint {
+                    lat_e7: parse_coordinate(field()?, field()?, 'N', 'S')?,
+                    lon_e7: parse_coordinate(field()?, field()?, 'E', 'W')?,
+                };
+                if !position.is_valid() {
+                    return Err(NmeaError::BadField);
+                }
+                let speed: f32 = parse_or_zero(field()?)?;
+                let course = match field()? {
+                    "" => None,
+                    course => Some(Angle::new::<degree>(
+                        course.parse().map_err(|_| NmeaError::BadField)?,
+                    )),
+                };
+                Ok(Some(GpsReading::Fix(GpsFix {
+                    position,
+                    speed: Velocity::new::<knot>(speed),
+                    course,
+                    satellites: self.satellites,
+                })))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Default for NmeaParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_or_zero<T: core::str::FromStr + Default>(field: &str) -> Result<T, NmeaError> {
+    match field {
+        "" => Ok(T::default()),
+        field => field.parse().map_err(|_| NmeaError::BadField),
+    }
+}
+
+/// `dddmm.mmmm` and its hemisphere to 1e-7 degrees, in integers so none of
+/// the precision is lost. The degrees are left to [`GeoPoint::is_valid`].
+fn parse_coordinate(
+    value: &str,
+    hemisphere: &str,
+    positive: char,
+    negative: char,
+) -> Result<i32, NmeaError> {
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let whole: u32 = whole.parse().map_err(|_| NmeaError::BadField)?;
+    if whole % 100 >= 60 {
+        return Err(NmeaError::BadField);
+    }
+    let mut minutes_e7 = (whole % 100) as u64 * 10_000_000;
+    let mut scale = 1_000_000;
+    for digit in fraction.bytes().take(7) {
+        if !digit.is_ascii_digit() {
+            return Err(NmeaError::BadField);
+        }
+        minutes_e7 += (digit - b'0') as u64 * scale;
+        scale /= 10;
+    }
+    let degrees_e7 = i32::try_from((whole / 100) as u64 * 10_000_000 + minutes_e7 / 60)
+        .map_err(|_| NmeaError::BadField)?;
+    match hemisphere.chars().next() {
+        Some(h) if h == positive => Ok(degrees_e7),
+        Some(h) if h == negative => Ok(-degrees_e7),
+        _ => Err(NmeaError::BadField),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GpsParams {
+    /// A GPS waypoint is reached within this distance, the receiver being
+    /// good to a couple of meters at best.
+    pub tolerance_m: f32,
+    /// How far east of true north magnetic north is where the robot drives.
+    pub declination_deg: f32,
+}
+
+impl GpsParams {
+    pub const DEFAULT: Self = Self {
+        tolerance_m: 3.0,
+        declination_deg: 0.0,
+    };
+
+    pub fn is_valid(&self) -> bool {
+        (0.5..=50.0).contains(&self.tolerance_m) && (-180.0..=180.0).contains(&self.declination_deg)
+    }
+
+    pub fn declination(&self) -> Angle {
+        Angle::new::<degree>(self.declination_deg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{format, string::String};
+
+    use uom::si::velocity::knot;
+
+    use super::*;
+
+    /// Feeds `$body*checksum` and the line end, the reading or error at the
+    /// end of it. Errors mid-sentence are returned at once.
+    fn feed(parser: &mut NmeaParser, body: &str) -> Result<Option<GpsReading>, NmeaError> {
+        let checksum = body.bytes().fold(0, |sum, b| sum ^ b);
+        let hex = |nibble: u8| b"0123456789ABCDEF"[nibble as usize];
+        assert_eq!(parser.push(b'$')?, None);
+        let tail = [b'*', hex(checksum >> 4), hex(checksum & 0xF)];
+        for byte in body.bytes().chain(tail) {
+            assert_eq!(parser.push(byte)?, None);
+        }
+        let reading = parser.push(b'\r');
+        assert_eq!(parser.push(b'\n'), Ok(None));
+        reading
+    }
+
+    fn fix(parser: &mut NmeaParser, body: &str) -> GpsFix {
+        match feed(parser, body) {
+            Ok(Some(GpsReading::Fix(fix))) => fix,
+            other => panic!("{other:?} for {body}"),
+        }
+    }
+
+    /// An RMC sentence at the given coordinates.
+    fn rmc(lat: &str, ns: &str, lon: &str, ew: &str) -> String {
+        format!("GPRMC,123519,A,{lat},{ns},{lon},{ew},022.4,084.4,230394,003.1,W")
+    }
+
+    const GGA: &str = "GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,";
+    const RMC: &str = "GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W";
+
+    #[test]
+    fn reads_rmc_with_the_satellites_of_gga() {
+        let mut parser = NmeaParser::new();
+        assert_eq!(feed(&mut parser, GGA), Ok(None));
+        let fix = fix(&mut parser, RMC);
+        assert_eq!(
+            fix.position,
+            GeoPoint {
+                lat_e7: 481_173_000,
+                lon_e7: 115_166_666,
+            }
+        );
+        assert!((fix.speed.get::<knot>() - 22.4).abs() < 1e-4);
+        assert!((fix.course.unwrap().get::<degree>() - 84.4).abs() < 1e-4);
+        assert_eq!(fix.satellites, 8);
+    }
+
+    #[test]
+    fn any_talker_and_no_course() {
+        let mut parser = NmeaParser::new();
+        let fix = fix(
+            &mut parser,
+            "GNRMC,081836,A,3751.65,S,14507.36,E,000.0,,130998,011.3,E",
+        );
+        assert_eq!(fix.course, None);
+        assert_eq!(fix.satellites, 0);
+    }
+
+    #[test]
+    fn no_fix() {
+        let mut parser = NmeaParser::new();
+        assert_eq!(
+            feed(&mut parser, "GPRMC,123519,V,,,,,,,230394,,"),
+            Ok(Some(GpsReading::NoFix))
+        );
+    }
+
+    #[test]
+    fn hemispheres_give_the_signs() {
+        let mut parser = NmeaParser::new();
+        for (lat, lon, expected) in [
+            ("N", "E", (1, 1)),
+            ("S", "E", (-1, 1)),
+            ("N", "W", (1, -1)),
+            ("S", "W", (-1, -1)),
+        ] {
+            let body = rmc("4807.038", lat, "01131.000", lon);
+            let position = fix(&mut parser, &body).position;
+            assert_eq!(
+                (position.lat_e7, position.lon_e7),
+                (expected.0 * 481_173_000, expected.1 * 115_166_666)
+            );
+        }
+    }
+
+    #[test]
+    fn out_of_range_coordinates_are_refused() {
+        let mut parser = NmeaParser::new();
+        for (lat, lon) in [
+            // 60 minutes or more
+            ("4860.000", "01131.000"),
+            ("4807.038", "01175.500"),
+            // Past the poles and the antimeridian
+            ("9100.000", "01131.000"),
+            ("4807.038", "18100.000"),
+            // Past what an i32 holds
+            ("9999959.000", "01131.000"),
+        ] {
+            let body = rmc(lat, "N", lon, "E");
+            assert_eq!(
+                feed(&mut parser, &body),
+                Err(NmeaError::BadField),
+                "{lat} {lon}"
+            );
+        }
+
+        let body = rmc("9000.000", "S", "18000.000", "W");
+        assert_eq!(
+            fix(&mut parser, &body).position,
+            GeoPoint {
+                lat_e7: -900_000_000,
+                lon_e7: -1_800_000_000,
+            }
+        );
+    }
+
+    #[test]
+    fn bad_hemisphere_is_refused() {
+        let mut parser = NmeaParser::new();
+        let body = rmc("4807.038", "E", "01131.000", "E");
+        assert_eq!(feed(&mut parser, &body), Err(NmeaError::BadField));
+    }
+
+    #[test]
+    fn bad_checksum_is_refused() {
+        let mut parser = NmeaParser::new();
+        for &byte in b"$GPRMC,123519,V,,,,,,,230394,,*00" {
+            assert_eq!(parser.push(byte), Ok(None));
+        }
+        assert_eq!(parser.push(b'\r'), Err(NmeaError::BadChecksum));
+    }
+
+    #[test]
+    fn missing_checksum_is_refused() {
+        let mut parser = NmeaParser::new();
+        for &byte in b"$GPRMC,123519,V,,,,,,,230394,," {
+            assert_eq!(parser.push(byte), Ok(None));
+        }
+        assert_eq!(parser.push(b'\r'), Err(NmeaError::BadChecksum));
+    }
+
+    #[test]
+    fn over_long_sentences_are_dropped() {
+        let mut parser = NmeaParser::new();
+        assert_eq!(parser.push(b'$'), Ok(None));
+        for _ in 0..MAX_SENTENCE {
+            assert_eq!(parser.push(b'0'), Ok(None));
+        }
+        assert_eq!(parser.push(b'0'), Err(NmeaError::TooLong));
+        // The rest of it is skipped, up to the next sentence
+        for &byte in b"00*00\r\n" {
+            assert_eq!(parser.push(byte), Ok(None));
+        }
+        fix(&mut parser, RMC);
+    }
+}
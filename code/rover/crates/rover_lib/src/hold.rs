@@ -0,0 +1,51 @@
+//! A parking brake: with nobody driving, the robot is driven back to the
+//! pose it stopped at when pushed off it, instead of only going neutral.
+
+use serde::{Deserialize, Serialize};
+use uom::si::angle::degree;
+
+use crate::fusion::Attitude;
+
+/// Tilt under the slope at which the hold lets go again.
+const SLOPE_HYSTERESIS_DEG: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+pub enum HoldMode {
+    Off,
+    Always,
+    /// Only while the IMU has the robot tilted past the slope.
+    OnSlope,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HoldParams {
+    pub mode: HoldMode,
+    pub slope_deg: f32,
+}
+
+impl HoldParams {
+    pub const DEFAULT: Self = Self {
+        mode: HoldMode::OnSlope,
+        slope_deg: 5.0,
+    };
+
+    pub fn is_valid(&self) -> bool {
+        (SLOPE_HYSTERESIS_DEG..=45.0).contains(&self.slope_deg)
+    }
+
+    /// Whether to hold, `holding` being whether it already does. Never on
+    /// a slope without an `attitude`.
+    pub fn wants_hold(&self, attitude: Option<&Attitude>, holding: bool) -> bool {
+        match self.mode {
+            HoldMode::Off => false,
+            HoldMode::Always => true,
+            HoldMode::OnSlope => attitude.is_some_and(|attitude| {
+                let slope = match holding {
+                    true => self.slope_deg - SLOPE_HYSTERESIS_DEG,
+                    false => self.slope_deg,
+                };
+                attitude.tilt().get::<degree>() > slope
+            }),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! The register map the rover serves as an I2C target, for a Raspberry Pi
+//! or another MCU to drive it without a UART.
+//!
+//! A write sets the register pointer with its first byte, and the registers
+//! from there with the rest. A read goes on from the pointer. Both move it
+//! along, so a block like the odometry is read in one go. Multi-byte values
+//! are little endian, and the read-only ones are latched at the start of
+//! each transaction so they can't tear.
+//!
+//! | Register        | R/W | Content                                           |
+//! |-----------------|-----|---------------------------------------------------|
+//! | `0x00` WHO_AM_I | R   | [`WHO_AM_I`]                                      |
+//! | `0x01` STATUS   | R   | [`Status`] bits                                   |
+//! | `0x02` FAULT    | R   | [`Fault`] bits, any of them refusing drive        |
+//! | `0x03` COMMAND  | W   | a [`TargetCommand`]                               |
+//! | `0x04` KEEPALIVE| W   | anything, feeds the safety timeout                |
+//! | `0x10` POWER    | R/W | `i16`, 0 to 1000                                  |
+//! | `0x12` ANGLE    | R/W | `i16`, hundredths of a degree, 9000 forward       |
+//! | `0x14` TURN     | R/W | `i16`, -1000 to 1000, clockwise                   |
+//! | `0x20` X        | R   | `i32`, odometry in mm, right                      |
+//! | `0x24` Y        | R   | `i32`, odometry in mm, forward                    |
+//! | `0x28` HEADING  | R   | `i16`, hundredths of a degree, counter-clockwise  |
+//! | `0x2A` BATTERY  | R   | `u16`, in mV, 0 unknown                           |
+//!
+//! Writing any of POWER, ANGLE or TURN drives with all three once the write
+//! ends, like a drive command of the host, the safety timeout included.
+
+use uom::si::{
+    angle::{degree, radian},
+    electric_potential::millivolt,
+    f32::ElectricPotential,
+    length::millimeter,
+};
+
+use crate::{
+    iface::{Angle, MecanumPower, Turn},
+    odometry::Pose,
+};
+
+pub const DEFAULT_ADDRESS: u8 = 0x42;
+/// Tells the rover apart from whatever else is on the bus.
+pub const WHO_AM_I: u8 = 0x4D;
+
+const REG_WHO_AM_I: usize = 0x00;
+const REG_STATUS: usize = 0x01;
+const REG_FAULT: usize = 0x02;
+const REG_COMMAND: usize = 0x03;
+const REG_KEEPALIVE: usize = 0x04;
+const REG_POWER: usize = 0x10;
+const REG_ANGLE: usize = 0x12;
+const REG_TURN: usize = 0x14;
+const REG_DRIVE_END: usize = 0x16;
+const REG_X: usize = 0x20;
+const REG_Y: usize = 0x24;
+const REG_HEADING: usize = 0x28;
+const REG_BATTERY: usize = 0x2A;
+const REG_COUNT: usize = 0x2C;
+
+/// Bits of the STATUS register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Status(pub u8);
+
+impl Status {
+    pub const ARMED: u8 = 0x01;
+    pub const DRIVING: u8 = 0x02;
+    /// The safety timeout stopped the rover, nothing having driven it in a
+    /// while.
+    pub const TIMED_OUT: u8 = 0x04;
+    pub const BATTERY_LOW: u8 = 0x08;
+}
+
+/// Bits of the FAULT register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Fault(pub u8);
+
+impl Fault {
+    /// The rover couldn't be stopped, until reset.
+    pub const DRIVE: u8 = 0x01;
+    pub const ESTOP: u8 = 0x02;
+    pub const OVERCURRENT: u8 = 0x04;
+    pub const TILTED: u8 = 0x08;
+}
+
+/// What the COMMAND register takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetCommand {
+    Arm,
+    Disarm,
+    /// All of POWER, ANGLE and TURN to 0.
+    Stop,
+    ClearEstop,
+    ClearOvercurrent,
+}
+
+impl TryFrom<u8> for TargetCommand {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
+        match value {
+            1 => Ok(Self::Arm),
+            2 => Ok(Self::Disarm),
+            3 => Ok(Self::Stop),
+            4 => Ok(Self::ClearEstop),
+            5 => Ok(Self::ClearOvercurrent),
+            other => Err(other),
+        }
+    }
+}
+
+/// What the rover publishes in the read-only registers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Inputs {
+    pub status: Status,
+    pub fault: Fault,
+    pub pose: Pose,
+    pub battery: Option<ElectricPotential>,
+}
+
+/// What the controller wrote since [`RegisterMap::take_writes`] last ran.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Writes {
+    /// From POWER, ANGLE and TURN.
+    pub drive: Option<(MecanumPower, Angle, Turn)>,
+    /// The last one, unknown values dropped.
+    pub command: Option<TargetCommand>,
+    pub keepalive: bool,
+}
+
+impl Writes {
+    pub fn is_empty(&self) -> bool {
+        self.drive.is_none() && self.command.is_none() && !self.keepalive
+    }
+}
+
+/// Served one byte at a time by the board's I2C interrupt.
+pub struct RegisterMap {
+    regs: [u8; REG_COUNT],
+    /// `None` until the rover sets them, the registers reading 0.
+    inputs: Option<Inputs>,
+    pointer: usize,
+    /// The first byte of a write, the pointer, is still to come.
+    expecting_pointer: bool,
+    drive_written: bool,
+    writes: Writes,
+}
+
+impl RegisterMap {
+    pub const fn new() -> Self {
+        let mut regs = [0; REG_COUNT];
+        regs[REG_WHO_AM_I] = WHO_AM_I;
+        // Forward
+        let angle = (9000i16).to_le_bytes();
+        regs[REG_ANGLE] = angle[0];
+        regs[REG_ANGLE + 1] = angle[1];
+        Self {
+            regs,
+            inputs: None,
+            pointer: 0,
+            expecting_pointer: false,
+            drive_written: false,
+            writes: Writes {
+                drive: None,
+                command: None,
+                keepalive: false,
+            },
+        }
+    }
+
+    /// Published from the next transaction on.
+    pub fn set_inputs(&mut self, inputs: Inputs) {
+        self.inputs = Some(inputs);
+    }
+
+    /// A transaction addressed to the rover starts, `read` if the
+    /// controller reads.
+    pub fn start(&mut self, read: bool) {
+        self.expecting_pointer = !read;
+        self.latch();
+    }
+
+    /// A byte the controller wrote.
+    pub fn write(&mut self, byte: u8) {
+        if self.expecting_pointer {
+            self.expecting_pointer = false;
+            self.pointer = byte as usize;
+            return;
+        }
+        match self.pointer {
+            REG_COMMAND => self.writes.command = TargetCommand::try_from(byte).ok(),
+            REG_KEEPALIVE => self.writes.keepalive = true,
+            reg @ REG_POWER..REG_DRIVE_END => {
+                self.regs[reg] = byte;
+                self.drive_written = true;
+            }
+            // Read-only or unused
+            _ => {}
+        }
+        self.pointer = self.pointer.saturating_add(1);
+    }
+
+    /// The next byte for the controller to read.
+    pub fn read(&mut self) -> u8 {
+        let byte = self.regs.get(self.pointer).copied().unwrap_or(0xFF);
+        self.pointer = self.pointer.saturating_add(1);
+        byte
+    }
+
+    /// The transaction ends, true if it wrote anything to act on.
+    pub fn stop(&mut self) -> bool {
+        self.expecting_pointer = false;
+        if core::mem::take(&mut self.drive_written) {
+            self.writes.drive = Some(self.drive());
+        }
+        !self.writes.is_empty()
+    }
+
+    pub fn take_writes(&mut self) -> Writes {
+        let writes = core::mem::take(&mut self.writes);
+        if writes.command == Some(TargetCommand::Stop) {
+            self.regs[REG_POWER..REG_ANGLE].fill(0);
+            self.regs[REG_TURN..REG_TURN + 2].fill(0);
+        }
+        writes
+    }
+
+    fn drive(&self) -> (MecanumPower, Angle, Turn) {
+        let i16_at = |i: usize| i16::from_le_bytes([self.regs[i], self.regs[i + 1]]) as f32;
+        (
+            MecanumPower::new((i16_at(REG_POWER) / 1000.0).clamp(0.0, MecanumPower::MAX)),
+            Angle::new::<degree>(i16_at(REG_ANGLE) / 100.0),
+            Turn::new((i16_at(REG_TURN) / 1000.0).clamp(Turn::MIN, Turn::MAX)),
+        )
+    }
+
+    fn latch(&mut self) {
+        let Some(inputs) = &self.inputs else {
+            return;
+        };
+        self.regs[REG_STATUS] = inputs.status.0;
+        self.regs[REG_FAULT] = inputs.fault.0;
+        let mm = |length: uom::si::f32::Length| libm::roundf(length.get::<millimeter>()) as i32;
+        self.regs[REG_X..REG_X + 4].copy_from_slice(&mm(inputs.pose.x).to_le_bytes());
+        self.regs[REG_Y..REG_Y + 4].copy_from_slice(&mm(inputs.pose.y).to_le_bytes());
+        let heading = libm::roundf(inputs.pose.heading.get::<radian>().to_degrees() * 100.0) as i16;
+        self.regs[REG_HEADING..REG_HEADING + 2].copy_from_slice(&heading.to_le_bytes());
+        let battery = inputs.battery.map_or(0, |v| {
+            libm::roundf(v.get::<millivolt>()).clamp(0.0, u16::MAX as f32) as u16
+        });
+        self.regs[REG_BATTERY..REG_BATTERY + 2].copy_from_slice(&battery.to_le_bytes());
+    }
+}
+
+impl Default for RegisterMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,36 +1,110 @@
 use serde::{Deserialize, Serialize};
+use uom::si::angle::degree;
 pub use uom::si::f32::Angle;
 
-#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// A value given to [`try_new`](MotorPower::try_new) outside of `MIN..=MAX`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutOfRange(pub f32);
+
+impl core::fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for OutOfRange {}
+
+/// The API shared by the bounded newtypes, whose value always lies within
+/// `MIN..=MAX`: out of range results saturate, NaN is taken as zero.
+macro_rules! bounded {
+    ($name:ident) => {
+        impl $name {
+            pub fn new(inner: f32) -> Self {
+                if inner.is_nan() {
+                    return Self(0.0);
+                }
+                Self(inner.clamp(Self::MIN, Self::MAX))
+            }
+
+            pub fn try_new(inner: f32) -> Result<Self, OutOfRange> {
+                if (Self::MIN..=Self::MAX).contains(&inner) {
+                    Ok(Self(inner))
+                } else {
+                    Err(OutOfRange(inner))
+                }
+            }
+
+            pub fn inner(&self) -> f32 {
+                self.0
+            }
+
+            /// Bounds given the wrong way round are swapped rather than
+            /// panicking like [`f32::clamp`].
+            pub fn clamp(self, min: Self, max: Self) -> Self {
+                let (lo, hi) = if min.0 <= max.0 {
+                    (min.0, max.0)
+                } else {
+                    (max.0, min.0)
+                };
+                Self(self.0.clamp(lo, hi))
+            }
+
+            pub fn saturating_add(self, rhs: Self) -> Self {
+                Self::new(self.0 + rhs.0)
+            }
+
+            pub fn saturating_sub(self, rhs: Self) -> Self {
+                Self::new(self.0 - rhs.0)
+            }
+
+            pub fn saturating_scale(self, factor: f32) -> Self {
+                Self::new(self.0 * factor)
+            }
+        }
+
+        impl From<f32> for $name {
+            /// Clamps, like [`new`](Self::new).
+            fn from(inner: f32) -> Self {
+                Self::new(inner)
+            }
+        }
+
+        impl From<$name> for f32 {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default, Serialize, Deserialize)]
 pub struct MotorPower(f32);
 
 impl MotorPower {
     pub const MAX: f32 = 1.0;
     pub const MIN: f32 = -Self::MAX;
-
-    pub fn new(inner: f32) -> Self {
-        Self(inner.clamp(Self::MIN, Self::MAX))
-    }
-
-    pub fn inner(&self) -> f32 {
-        self.0
-    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+bounded!(MotorPower);
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default, Serialize, Deserialize)]
 pub struct MecanumPower(f32);
 
 impl MecanumPower {
     pub const MAX: f32 = 1.0;
     pub const MIN: f32 = 0.0;
+}
 
-    pub fn new(inner: f32) -> Self {
-        Self(inner.clamp(Self::MIN, Self::MAX))
-    }
+bounded!(MecanumPower);
 
-    pub fn inner(&self) -> f32 {
-        self.0
-    }
+/// How a motor is stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum NeutralMode {
+    /// Motor terminals floating, the wheel spins down on its own.
+    #[default]
+    Coast,
+    /// Motor terminals shorted, the wheel stops hard.
+    Brake,
 }
 
 pub trait Motor {
@@ -38,6 +112,12 @@ pub trait Motor {
 
     fn drive(&mut self, power: MotorPower) -> Result<(), Self::Error>;
     fn neutral(&mut self) -> Result<(), Self::Error>;
+    /// Actively stops the motor, drivers that can't brake just go neutral.
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.neutral()
+    }
+    /// Last power actually applied, zero when in neutral.
+    fn power(&self) -> MotorPower;
 }
 
 pub trait FourWheeledRobot {
@@ -51,35 +131,105 @@ pub trait FourWheeledRobot {
         br: MotorPower,
     ) -> Result<(), Self::Error>;
     fn neutral(&mut self) -> Result<(), Self::Error>;
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.neutral()
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default, Serialize, Deserialize)]
 pub struct Turn(f32);
 
 impl Turn {
     pub const MAX: f32 = 1.0;
     pub const MIN: f32 = -Self::MAX;
-
-    pub fn new(turn: f32) -> Self {
-        Self(turn.clamp(Self::MIN, Self::MAX))
-    }
-
-    pub fn inner(&self) -> f32 {
-        self.0
-    }
 }
 
+bounded!(Turn);
+
 pub trait MecanumRobot {
     type Error: core::error::Error;
 
     fn drive(&mut self, power: MecanumPower, theta: Angle, turn: Turn) -> Result<(), Self::Error>;
     fn neutral(&mut self) -> Result<(), Self::Error>;
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.neutral()
+    }
     fn control(&mut self, ctrl: MecanumControl) -> Result<(), Self::Error> {
         match ctrl {
             MecanumControl::Neutral => self.neutral(),
             MecanumControl::Drive(p, th, tu) => self.drive(p, th, tu),
         }
     }
+    /// Straight ahead, backwards for a negative `power`.
+    fn forward(&mut self, power: MotorPower) -> Result<(), Self::Error> {
+        let theta = if power.inner() < 0.0 { 270.0 } else { 90.0 };
+        self.drive(
+            MecanumPower::new(libm::fabsf(power.inner())),
+            Angle::new::<degree>(theta),
+            Turn::default(),
+        )
+    }
+    /// Towards `theta` without turning.
+    fn strafe(&mut self, power: MecanumPower, theta: Angle) -> Result<(), Self::Error> {
+        self.drive(power, theta, Turn::default())
+    }
+    /// Turns in place.
+    fn rotate(&mut self, turn: Turn) -> Result<(), Self::Error> {
+        self.drive(MecanumPower::default(), Angle::new::<degree>(90.0), turn)
+    }
+    /// Zero power, still driven rather than in [`neutral`](Self::neutral).
+    fn stop(&mut self) -> Result<(), Self::Error> {
+        self.rotate(Turn::default())
+    }
+}
+
+/// Any chassis driven with a power, a direction and a turn rate, whatever
+/// its kinematics. Chassis that can't strafe only use the forward component
+/// of the direction.
+pub trait DriveBase {
+    type Error: core::error::Error;
+
+    fn drive(&mut self, power: MecanumPower, theta: Angle, turn: Turn) -> Result<(), Self::Error>;
+    fn neutral(&mut self) -> Result<(), Self::Error>;
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.neutral()
+    }
+}
+
+impl<T: MecanumRobot> DriveBase for T {
+    type Error = T::Error;
+
+    fn drive(&mut self, power: MecanumPower, theta: Angle, turn: Turn) -> Result<(), Self::Error> {
+        MecanumRobot::drive(self, power, theta, turn)
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        MecanumRobot::neutral(self)
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        MecanumRobot::brake(self)
+    }
+}
+
+/// Frame the drive angle is expressed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DriveFrame {
+    /// Relative to the robot body, 90° is always straight ahead.
+    #[default]
+    Robot,
+    /// Relative to the field, 90° is the direction the robot faced when its
+    /// heading was zero.
+    Field,
+}
+
+impl DriveFrame {
+    /// Converts `theta` from this frame to the robot frame, `heading` being
+    /// the current robot heading (counter-clockwise positive).
+    pub fn to_robot(self, theta: Angle, heading: Angle) -> Angle {
+        match self {
+            Self::Robot => theta,
+            Self::Field => theta - heading,
+        }
+    }
 }
 
 pub enum MecanumControl {
@@ -100,24 +250,73 @@ impl<E: core::fmt::Debug> core::fmt::Display for FWRMerror<E> {
 }
 impl<E: core::error::Error> core::error::Error for FWRMerror<E> {}
 
+/// Wheel powers, FL-FR-BL-BR, of a mecanum drive command.
+#[cfg(not(feature = "fixed-point"))]
+pub(crate) fn mix(power: MecanumPower, theta: Angle, turn: Turn) -> [MotorPower; 4] {
+    let power = power.inner();
+    let theta = theta.get::<uom::si::angle::radian>() - core::f32::consts::FRAC_PI_4;
+    let turn = turn.inner();
+
+    let (sin, cos) = (power * libm::sinf(theta), power * libm::cosf(theta));
+    saturate([cos + turn, sin - turn, sin + turn, cos - turn])
+}
+
+#[cfg(feature = "fixed-point")]
+pub(crate) fn mix(power: MecanumPower, theta: Angle, turn: Turn) -> [MotorPower; 4] {
+    use crate::fixed::I16F16;
+
+    let power = I16F16::from_f32(power.inner());
+    let theta = I16F16::from_f32(theta.get::<uom::si::angle::radian>())
+        - I16F16::from_f32(core::f32::consts::FRAC_PI_4);
+    let turn = I16F16::from_f32(turn.inner());
+
+    let (sin, cos) = (power * theta.sin(), power * theta.cos());
+    saturate([cos + turn, sin - turn, sin + turn, cos - turn].map(|p| p.to_f32()))
+}
+
+/// Past full power the wheels are all scaled down alike, keeping the
+/// direction of travel and the turn in proportion, only slower.
+#[cfg(not(feature = "clip-saturation"))]
+fn saturate(powers: [f32; 4]) -> [MotorPower; 4] {
+    let peak = powers
+        .iter()
+        .fold(MotorPower::MAX, |peak, p| peak.max(libm::fabsf(*p)));
+    powers.map(|p| MotorPower::new(p * MotorPower::MAX / peak))
+}
+
+/// Each wheel clipped on its own, which favours the turn over the direction
+/// when both ask for full power.
+#[cfg(feature = "clip-saturation")]
+fn saturate(powers: [f32; 4]) -> [MotorPower; 4] {
+    powers.map(MotorPower::new)
+}
+
 impl<T: FourWheeledRobot> MecanumRobot for T {
     type Error = FWRMerror<T::Error>;
 
     fn drive(&mut self, power: MecanumPower, theta: Angle, turn: Turn) -> Result<(), Self::Error> {
-        let power = power.inner();
-        let theta = theta.get::<uom::si::angle::radian>() - core::f32::consts::FRAC_PI_4;
-        let turn = turn.inner();
-
-        let fl = MotorPower::new(power * libm::cosf(theta) + turn);
-        let fr = MotorPower::new(power * libm::sinf(theta) - turn);
-        let bl = MotorPower::new(power * libm::sinf(theta) + turn);
-        let br = MotorPower::new(power * libm::cosf(theta) - turn);
-
+        let [fl, fr, bl, br] = mix(power, theta, turn);
         FourWheeledRobot::drive(self, fl, fr, bl, br)
-            .map_err(|e| <Self as MecanumRobot>::Error::Internal(e))
+            .map_err(<Self as MecanumRobot>::Error::Internal)
     }
     fn neutral(&mut self) -> Result<(), Self::Error> {
-        self.neutral()
-            .map_err(|e| <Self as MecanumRobot>::Error::Internal(e))
+        FourWheeledRobot::neutral(self).map_err(<Self as MecanumRobot>::Error::Internal)
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        FourWheeledRobot::brake(self).map_err(<Self as MecanumRobot>::Error::Internal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_takes_bounds_either_way_round() {
+        let p = MotorPower::new;
+        assert_eq!(p(0.8).clamp(p(-0.5), p(0.5)), p(0.5));
+        assert_eq!(p(0.8).clamp(p(0.5), p(-0.5)), p(0.5));
+        assert_eq!(p(-0.8).clamp(p(0.5), p(-0.5)), p(-0.5));
+        assert_eq!(p(0.2).clamp(p(0.5), p(-0.5)), p(0.2));
     }
 }
@@ -0,0 +1,197 @@
+use core::future::Future;
+
+use embedded_hal_async::i2c::I2c;
+
+const STANDARD_GRAVITY: f32 = 9.80665;
+
+/// Accelerations in m/s² and angular rates in rad/s, in the robot frame:
+/// x right, y forward, z up.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ImuReading {
+    pub accel: [f32; 3],
+    pub gyro: [f32; 3],
+}
+
+pub trait Imu {
+    type Error: core::error::Error;
+
+    fn init(&mut self) -> impl Future<Output = Result<(), Self::Error>>;
+    fn read(&mut self) -> impl Future<Output = Result<ImuReading, Self::Error>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum ImuError {
+    Bus,
+    WrongId(u8),
+}
+
+impl core::fmt::Display for ImuError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for ImuError {}
+
+fn be_i16(bytes: &[u8]) -> i16 {
+    i16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+/// MPU-6050 configured for ±4 g and ±500 °/s.
+pub struct Mpu6050<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I> Mpu6050<I> {
+    pub const DEFAULT_ADDRESS: u8 = 0x68;
+
+    const WHO_AM_I: u8 = 0x75;
+    const PWR_MGMT_1: u8 = 0x6B;
+    const CONFIG: u8 = 0x1A;
+    const GYRO_CONFIG: u8 = 0x1B;
+    const ACCEL_CONFIG: u8 = 0x1C;
+    const ACCEL_XOUT_H: u8 = 0x3B;
+
+    const ACCEL_LSB_PER_G: f32 = 8192.0;
+    const GYRO_LSB_PER_DPS: f32 = 65.5;
+
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+}
+
+impl<I: I2c> Mpu6050<I> {
+    async fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), ImuError> {
+        self.i2c
+            .write(self.address, &[reg, value])
+            .await
+            .map_err(|_| ImuError::Bus)
+    }
+
+    async fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), ImuError> {
+        self.i2c
+            .write_read(self.address, &[reg], buf)
+            .await
+            .map_err(|_| ImuError::Bus)
+    }
+}
+
+impl<I: I2c> Imu for Mpu6050<I> {
+    type Error = ImuError;
+
+    async fn init(&mut self) -> Result<(), Self::Error> {
+        let mut id = [0];
+        self.read_regs(Self::WHO_AM_I, &mut id).await?;
+        if id[0] != 0x68 {
+            return Err(ImuError::WrongId(id[0]));
+        }
+
+        // Wake up, clocked from the X gyro PLL
+        self.write_reg(Self::PWR_MGMT_1, 0x01).await?;
+        // 44 Hz digital low pass filter
+        self.write_reg(Self::CONFIG, 0x03).await?;
+        self.write_reg(Self::GYRO_CONFIG, 0x08).await?;
+        self.write_reg(Self::ACCEL_CONFIG, 0x08).await
+    }
+
+    async fn read(&mut self) -> Result<ImuReading, Self::Error> {
+        let mut raw = [0; 14];
+        self.read_regs(Self::ACCEL_XOUT_H, &mut raw).await?;
+
+        // Accel at 0..6, temperature at 6..8, gyro at 8..14
+        let accel = core::array::from_fn(|i| {
+            be_i16(&raw[2 * i..]) as f32 / Self::ACCEL_LSB_PER_G * STANDARD_GRAVITY
+        });
+        let gyro = core::array::from_fn(|i| {
+            (be_i16(&raw[8 + 2 * i..]) as f32 / Self::GYRO_LSB_PER_DPS).to_radians()
+        });
+
+        Ok(ImuReading { accel, gyro })
+    }
+}
+
+/// ICM-20948 (accelerometer and gyroscope only) configured for ±4 g and
+/// ±500 °/s.
+pub struct Icm20948<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I> Icm20948<I> {
+    pub const DEFAULT_ADDRESS: u8 = 0x69;
+
+    const REG_BANK_SEL: u8 = 0x7F;
+    // Bank 0
+    const WHO_AM_I: u8 = 0x00;
+    const PWR_MGMT_1: u8 = 0x06;
+    const ACCEL_XOUT_H: u8 = 0x2D;
+    // Bank 2
+    const GYRO_CONFIG_1: u8 = 0x01;
+    const ACCEL_CONFIG: u8 = 0x14;
+
+    const ACCEL_LSB_PER_G: f32 = 8192.0;
+    const GYRO_LSB_PER_DPS: f32 = 65.5;
+
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+}
+
+impl<I: I2c> Icm20948<I> {
+    async fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), ImuError> {
+        self.i2c
+            .write(self.address, &[reg, value])
+            .await
+            .map_err(|_| ImuError::Bus)
+    }
+
+    async fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), ImuError> {
+        self.i2c
+            .write_read(self.address, &[reg], buf)
+            .await
+            .map_err(|_| ImuError::Bus)
+    }
+
+    async fn bank(&mut self, bank: u8) -> Result<(), ImuError> {
+        self.write_reg(Self::REG_BANK_SEL, bank << 4).await
+    }
+}
+
+impl<I: I2c> Imu for Icm20948<I> {
+    type Error = ImuError;
+
+    async fn init(&mut self) -> Result<(), Self::Error> {
+        self.bank(0).await?;
+        let mut id = [0];
+        self.read_regs(Self::WHO_AM_I, &mut id).await?;
+        if id[0] != 0xEA {
+            return Err(ImuError::WrongId(id[0]));
+        }
+
+        // Wake up with automatic clock selection
+        self.write_reg(Self::PWR_MGMT_1, 0x01).await?;
+
+        self.bank(2).await?;
+        // ±500 °/s with the low pass filter enabled
+        self.write_reg(Self::GYRO_CONFIG_1, 0x03).await?;
+        // ±4 g with the low pass filter enabled
+        self.write_reg(Self::ACCEL_CONFIG, 0x03).await?;
+        self.bank(0).await
+    }
+
+    async fn read(&mut self) -> Result<ImuReading, Self::Error> {
+        let mut raw = [0; 12];
+        self.read_regs(Self::ACCEL_XOUT_H, &mut raw).await?;
+
+        let accel = core::array::from_fn(|i| {
+            be_i16(&raw[2 * i..]) as f32 / Self::ACCEL_LSB_PER_G * STANDARD_GRAVITY
+        });
+        let gyro = core::array::from_fn(|i| {
+            (be_i16(&raw[6 + 2 * i..]) as f32 / Self::GYRO_LSB_PER_DPS).to_radians()
+        });
+
+        Ok(ImuReading { accel, gyro })
+    }
+}
@@ -0,0 +1,134 @@
+//! What the rover is up to, shown as blink patterns on a status LED or a
+//! WS2812 (NeoPixel) strip so it can be told without a console.
+//!
+//! A plain LED only has the timing of a [`Pattern`] to go on, so each
+//! [`Status`] blinks differently, and the strip adds a color to it.
+
+/// Most to least urgent, as [`Ord`] goes: when several apply, the greatest
+/// is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum Status {
+    /// Nothing has driven the robot since power up.
+    WaitingForLink,
+    /// A source is in control.
+    Driving,
+    LowBattery,
+    /// The commands stopped and the safety timer put the robot in neutral.
+    SafetyTripped,
+    Estop,
+    /// The robot couldn't be stopped and its motor outputs were cut.
+    Fault,
+}
+
+impl Status {
+    pub fn pattern(self) -> Pattern {
+        match self {
+            Status::WaitingForLink => Pattern::blink(Rgb::BLUE, 100, 2_000),
+            Status::Driving => Pattern::steady(Rgb::GREEN),
+            Status::LowBattery => Pattern::blink(Rgb::ORANGE, 900, 1_000),
+            Status::SafetyTripped => Pattern::blink(Rgb::YELLOW, 500, 1_000),
+            Status::Estop => Pattern::blink(Rgb::MAGENTA, 100, 200),
+            Status::Fault => Pattern::blink(Rgb::RED, 250, 500),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const OFF: Self = Self::new(0, 0, 0);
+    pub const RED: Self = Self::new(255, 0, 0);
+    pub const ORANGE: Self = Self::new(255, 64, 0);
+    pub const YELLOW: Self = Self::new(255, 160, 0);
+    pub const GREEN: Self = Self::new(0, 255, 0);
+    pub const BLUE: Self = Self::new(0, 0, 255);
+    pub const MAGENTA: Self = Self::new(255, 0, 255);
+    pub const WHITE: Self = Self::new(255, 255, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Each channel scaled by `level / 255`, full brightness being too much
+    /// to look at up close.
+    pub fn dimmed(self, level: u8) -> Self {
+        let dim = |c: u8| ((c as u16 * level as u16) / 255) as u8;
+        Self::new(dim(self.r), dim(self.g), dim(self.b))
+    }
+
+    pub fn is_off(self) -> bool {
+        self == Self::OFF
+    }
+}
+
+/// `color` for the first `on_ms` of every `period_ms`, off the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Pattern {
+    pub color: Rgb,
+    pub on_ms: u16,
+    pub period_ms: u16,
+}
+
+impl Pattern {
+    pub const fn steady(color: Rgb) -> Self {
+        Self {
+            color,
+            on_ms: 1,
+            period_ms: 1,
+        }
+    }
+
+    pub const fn blink(color: Rgb, on_ms: u16, period_ms: u16) -> Self {
+        Self {
+            color,
+            on_ms,
+            period_ms,
+        }
+    }
+
+    /// At `t_ms` from any fixed start, uptime usually.
+    pub fn color_at(&self, t_ms: u64) -> Rgb {
+        if t_ms % (self.period_ms.max(1) as u64) < self.on_ms as u64 {
+            self.color
+        } else {
+            Rgb::OFF
+        }
+    }
+}
+
+/// The strip is clocked out of an SPI MOSI at this rate, four SPI bits to a
+/// WS2812 bit: `1000` for a 0 and `1110` for a 1, 0.25 and 0.75 µs high.
+pub const WS2812_SPI_HZ: u32 = 4_000_000;
+
+/// Low bytes after the data, 300 µs at [`WS2812_SPI_HZ`]: the latch of the
+/// newer WS2812B takes 280.
+const WS2812_RESET_LEN: usize = 150;
+
+/// SPI bytes for a strip of `leds`.
+pub const fn ws2812_frame_len(leds: usize) -> usize {
+    leds * 3 * 4 + WS2812_RESET_LEN
+}
+
+/// Fills `frame` with the SPI bytes setting the strip to `colors`, from the
+/// data in end, then the latch. `frame` must be [`ws2812_frame_len`] long.
+pub fn encode_ws2812(colors: &[Rgb], frame: &mut [u8]) {
+    assert_eq!(frame.len(), ws2812_frame_len(colors.len()));
+    let (data, reset) = frame.split_at_mut(colors.len() * 12);
+    let (data, _) = data.as_chunks_mut::<4>();
+    // Green first
+    let bytes = colors.iter().flat_map(|c| [c.g, c.r, c.b]);
+    for (byte, out) in bytes.zip(data) {
+        // Two WS2812 bits per SPI byte, most significant first
+        for (i, out) in out.iter_mut().enumerate() {
+            let pair = byte >> (6 - 2 * i);
+            let symbol = |bit: u8| if bit & 1 == 1 { 0b1110 } else { 0b1000 };
+            *out = (symbol(pair >> 1) << 4) | symbol(pair);
+        }
+    }
+    reset.fill(0);
+}
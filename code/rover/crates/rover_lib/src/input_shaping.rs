@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+use crate::iface::{MecanumPower, Turn};
+
+/// Applies a deadzone, the rest of the travel stretched to make up for it,
+/// then `expo`: 0 is linear, 1 fully cubic for finer control around center.
+pub fn shape(value: f32, deadzone: f32, expo: f32) -> f32 {
+    let value = value.clamp(-1.0, 1.0);
+    if libm::fabsf(value) <= deadzone {
+        return 0.0;
+    }
+    let value = libm::copysignf((libm::fabsf(value) - deadzone) / (1.0 - deadzone), value);
+    (1.0 - expo) * value + expo * value * value * value
+}
+
+/// Response curve of a command input: [`shape`], then scaled down to
+/// `max_output`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct InputShaping {
+    pub deadzone: f32,
+    pub expo: f32,
+    pub max_output: f32,
+}
+
+impl InputShaping {
+    pub const IDENTITY: Self = Self {
+        deadzone: 0.0,
+        expo: 0.0,
+        max_output: 1.0,
+    };
+
+    pub fn is_valid(&self) -> bool {
+        (0.0..=0.5).contains(&self.deadzone)
+            && (0.0..=1.0).contains(&self.expo)
+            && (0.0..=1.0).contains(&self.max_output)
+    }
+
+    /// `value` in -1..=1.
+    pub fn apply(&self, value: f32) -> f32 {
+        shape(value, self.deadzone, self.expo) * self.max_output
+    }
+
+    pub fn apply_power(&self, power: MecanumPower) -> MecanumPower {
+        MecanumPower::new(self.apply(power.inner() / MecanumPower::MAX) * MecanumPower::MAX)
+    }
+
+    pub fn apply_turn(&self, turn: Turn) -> Turn {
+        Turn::new(self.apply(turn.inner() / Turn::MAX) * Turn::MAX)
+    }
+}
+
+impl Default for InputShaping {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
@@ -0,0 +1,260 @@
+//! Mecanum kinematics in real units, from the [`MecanumGeometry`] of the
+//! base, next to the unitless power mixing of [`MecanumRobot`].
+//!
+//! [`MecanumRobot`]: crate::MecanumRobot
+
+use serde::{Deserialize, Serialize};
+use uom::si::{
+    angular_velocity::radian_per_second,
+    f32::{AngularVelocity, Velocity},
+    length::meter,
+    velocity::meter_per_second,
+};
+
+use crate::{iface::MotorPower, odometry::MecanumGeometry};
+
+/// Velocity of the base in its own frame, axes like [`Pose`]'s: `vx` to the
+/// right, `vy` forward and `omega` counter-clockwise.
+///
+/// [`Pose`]: crate::Pose
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct ChassisVelocity {
+    pub vx: Velocity,
+    pub vy: Velocity,
+    pub omega: AngularVelocity,
+}
+
+impl MecanumGeometry {
+    fn lever_arm(&self) -> f32 {
+        (self.half_track + self.half_wheelbase).get::<meter>()
+    }
+
+    /// Inverse kinematics: the wheel speeds, FL-FR-BL-BR and positive when
+    /// the wheel pushes the robot forward, that move the base at `velocity`.
+    pub fn wheel_speeds(&self, velocity: ChassisVelocity) -> [AngularVelocity; 4] {
+        let vx = velocity.vx.get::<meter_per_second>();
+        let vy = velocity.vy.get::<meter_per_second>();
+        let turn = velocity.omega.get::<radian_per_second>() * self.lever_arm();
+        let r = self.wheel_radius.get::<meter>();
+
+        [
+            vy + vx - turn,
+            vy - vx + turn,
+            vy - vx - turn,
+            vy + vx + turn,
+        ]
+        .map(|surface| AngularVelocity::new::<radian_per_second>(surface / r))
+    }
+
+    /// Forward kinematics, the inverse of [`wheel_speeds`](Self::wheel_speeds).
+    pub fn chassis_velocity(&self, wheels: [AngularVelocity; 4]) -> ChassisVelocity {
+        let r = self.wheel_radius.get::<meter>();
+        let [fl, fr, bl, br] = wheels.map(|w| w.get::<radian_per_second>() * r);
+
+        ChassisVelocity {
+            vx: Velocity::new::<meter_per_second>((fl - fr - bl + br) / 4.0),
+            vy: Velocity::new::<meter_per_second>((fl + fr + bl + br) / 4.0),
+            omega: AngularVelocity::new::<radian_per_second>(
+                (-fl + fr - bl + br) / (4.0 * self.lever_arm()),
+            ),
+        }
+    }
+
+    /// [`wheel_speeds`](Self::wheel_speeds) as powers of a wheel turning at
+    /// `max_speed` at full power, for open loop wheels. Past that the speeds
+    /// are all scaled down alike, keeping the direction of travel.
+    pub fn wheel_powers(
+        &self,
+        velocity: ChassisVelocity,
+        max_speed: AngularVelocity,
+    ) -> [MotorPower; 4] {
+        let max = max_speed.get::<radian_per_second>();
+        let ratios = self
+            .wheel_speeds(velocity)
+            .map(|speed| speed.get::<radian_per_second>() / max);
+        let peak = ratios
+            .iter()
+            .fold(1.0f32, |peak, r| peak.max(libm::fabsf(*r)));
+        ratios.map(|r| MotorPower::new(r / peak))
+    }
+}
+
+/// The unitless mixing of [`MecanumRobot`] checked against the kinematics
+/// above, over commands drawn from their whole range.
+///
+/// [`MecanumRobot`]: crate::MecanumRobot
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use uom::si::{angle::degree, f32::Length};
+
+    use super::*;
+    use crate::iface::{mix, Angle, MecanumPower, Turn};
+
+    const EPSILON: f32 = 1e-3;
+
+    /// Wheels of a meter, a meter apart: powers read as speeds.
+    fn geometry() -> MecanumGeometry {
+        MecanumGeometry {
+            wheel_radius: Length::new::<meter>(1.0),
+            half_track: Length::new::<meter>(0.25),
+            half_wheelbase: Length::new::<meter>(0.25),
+        }
+    }
+
+    fn drive(power: f32, theta_deg: f32, turn: f32) -> [f32; 4] {
+        mix(
+            MecanumPower::new(power),
+            Angle::new::<degree>(theta_deg),
+            Turn::new(turn),
+        )
+        .map(|p| p.inner())
+    }
+
+    /// `vx`, `vy` and `omega` of wheels turning at `powers`.
+    fn motion(powers: [f32; 4]) -> [f32; 3] {
+        let wheels = powers.map(AngularVelocity::new::<radian_per_second>);
+        let v = geometry().chassis_velocity(wheels);
+        [
+            v.vx.get::<meter_per_second>(),
+            v.vy.get::<meter_per_second>(),
+            v.omega.get::<radian_per_second>(),
+        ]
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < EPSILON, "{actual:?} != {expected:?}");
+        }
+    }
+
+    /// Any `power`, `theta` and `turn` over their whole range.
+    fn command() -> impl Strategy<Value = (f32, f32, f32)> {
+        (0.0f32..=1.0, 0.0f32..360.0, -1.0f32..=1.0)
+    }
+
+    #[test]
+    fn pure_forward() {
+        let powers = drive(1.0, 90.0, 0.0);
+        let p = core::f32::consts::FRAC_1_SQRT_2;
+        assert_close(&powers, &[p; 4]);
+        assert_close(&motion(powers), &[0.0, p, 0.0]);
+
+        let backwards = drive(1.0, 270.0, 0.0);
+        assert_close(&backwards, &[-p; 4]);
+    }
+
+    #[test]
+    fn pure_strafe() {
+        let p = core::f32::consts::FRAC_1_SQRT_2;
+        let right = drive(1.0, 0.0, 0.0);
+        assert_close(&right, &[p, -p, -p, p]);
+        assert_close(&motion(right), &[p, 0.0, 0.0]);
+
+        let left = drive(1.0, 180.0, 0.0);
+        assert_close(&motion(left), &[-p, 0.0, 0.0]);
+    }
+
+    /// A positive turn is clockwise, the opposite of `omega`.
+    #[test]
+    fn pure_rotation() {
+        let powers = drive(0.0, 90.0, 0.5);
+        assert_close(&powers, &[0.5, -0.5, 0.5, -0.5]);
+        let [vx, vy, omega] = motion(powers);
+        assert_close(&[vx, vy], &[0.0, 0.0]);
+        assert!(omega < 0.0);
+
+        let [.., omega] = motion(drive(0.0, 90.0, -0.5));
+        assert!(omega > 0.0);
+    }
+
+    #[cfg(feature = "clip-saturation")]
+    #[test]
+    fn saturated_wheels_are_clipped() {
+        let p = core::f32::consts::FRAC_1_SQRT_2;
+        let powers = drive(1.0, 90.0, 1.0);
+        assert_close(&powers, &[1.0, p - 1.0, 1.0, p - 1.0]);
+    }
+
+    #[cfg(not(feature = "clip-saturation"))]
+    #[test]
+    fn saturated_wheels_are_scaled() {
+        let p = core::f32::consts::FRAC_1_SQRT_2;
+        let powers = drive(1.0, 90.0, 1.0);
+        let peak = p + 1.0;
+        assert_close(&powers, &[1.0, (p - 1.0) / peak, 1.0, (p - 1.0) / peak]);
+    }
+
+    proptest! {
+        /// Whatever saturates, the robot moves and turns as commanded, only
+        /// slower.
+        #[cfg(not(feature = "clip-saturation"))]
+        #[test]
+        fn saturation_keeps_the_motion((power, theta, turn) in command()) {
+            let (sin, cos) = libm::sincosf(theta.to_radians());
+            let speed = power * core::f32::consts::FRAC_1_SQRT_2;
+            let wanted = [speed * cos, speed * sin, -2.0 * turn];
+            let actual = motion(drive(power, theta, turn));
+
+            // The scale is the same for all three, and never speeds up
+            let scale = actual
+                .iter()
+                .zip(wanted)
+                .find(|(_, w)| w.abs() > 0.1)
+                .map_or(1.0, |(a, w)| a / w);
+            prop_assert!(scale > 0.0 && scale <= 1.0 + EPSILON);
+            assert_close(&actual, &wanted.map(|w| w * scale));
+        }
+
+        #[test]
+        fn no_wheel_past_full_power((power, theta, turn) in command()) {
+            let powers = drive(power, theta, turn);
+            prop_assert!(powers.iter().all(|p| p.abs() <= MotorPower::MAX), "{powers:?}");
+        }
+
+        /// Without turning nothing saturates, so the robot heads for `theta`
+        /// at a speed proportional to `power`.
+        #[test]
+        fn direction_follows_theta((power, theta, _) in command()) {
+            let [vx, vy, omega] = motion(drive(power, theta, 0.0));
+            let (sin, cos) = libm::sincosf(theta.to_radians());
+            let speed = power * core::f32::consts::FRAC_1_SQRT_2;
+            assert_close(&[vx, vy, omega], &[speed * cos, speed * sin, 0.0]);
+        }
+
+        /// Turning alone never moves the robot, the sign of the turn giving
+        /// the way it spins.
+        #[test]
+        fn turn_sign_sets_rotation((_, theta, turn) in command()) {
+            let [vx, vy, omega] = motion(drive(0.0, theta, turn));
+            assert_close(&[vx, vy], &[0.0, 0.0]);
+            assert_close(&[omega], &[-2.0 * turn]);
+        }
+
+        #[test]
+        fn wheel_powers_keep_direction_when_scaled((power, theta, turn) in command()) {
+            let max_speed = AngularVelocity::new::<radian_per_second>(1.0);
+            let (sin, cos) = libm::sincosf(theta.to_radians());
+            // Up to twice what the wheels can do
+            let velocity = ChassisVelocity {
+                vx: Velocity::new::<meter_per_second>(2.0 * power * cos),
+                vy: Velocity::new::<meter_per_second>(2.0 * power * sin),
+                omega: AngularVelocity::new::<radian_per_second>(turn),
+            };
+            let powers = geometry()
+                .wheel_powers(velocity, max_speed)
+                .map(|p| p.inner());
+            prop_assert!(powers.iter().all(|p| p.abs() <= MotorPower::MAX + EPSILON));
+
+            // The same motion, only slower
+            let peak = geometry()
+                .wheel_speeds(velocity)
+                .iter()
+                .fold(1.0f32, |peak, s| {
+                    peak.max(s.get::<radian_per_second>().abs())
+                });
+            let wanted = [2.0 * power * cos, 2.0 * power * sin, turn];
+            assert_close(&motion(powers), &wanted.map(|v| v / peak));
+        }
+    }
+}
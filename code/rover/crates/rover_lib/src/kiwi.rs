@@ -0,0 +1,169 @@
+use core::f32::consts::PI;
+
+use uom::si::angle::radian;
+
+use crate::iface::{Angle, DriveBase, MecanumPower, Motor, MotorPower, Turn};
+
+/// Three omni wheels 120° apart: front, back left and back right. Positive
+/// power spins each wheel so that it pushes the robot counter-clockwise.
+pub trait ThreeWheeledRobot {
+    type Error: core::error::Error;
+
+    fn drive(
+        &mut self,
+        front: MotorPower,
+        back_left: MotorPower,
+        back_right: MotorPower,
+    ) -> Result<(), Self::Error>;
+    fn neutral(&mut self) -> Result<(), Self::Error>;
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.neutral()
+    }
+}
+
+pub struct MyThreeWheelRobot<F, BL, BR> {
+    front: F,
+    back_left: BL,
+    back_right: BR,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KiwiWheel {
+    Front,
+    BackLeft,
+    BackRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum MyThreeWheelRobotError {
+    Motor(KiwiWheel),
+}
+
+impl core::fmt::Display for MyThreeWheelRobotError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for MyThreeWheelRobotError {}
+
+impl<F, BL, BR> MyThreeWheelRobot<F, BL, BR> {
+    pub fn new(front: F, back_left: BL, back_right: BR) -> Self {
+        Self {
+            front,
+            back_left,
+            back_right,
+        }
+    }
+}
+
+impl<F: Motor, BL: Motor, BR: Motor> MyThreeWheelRobot<F, BL, BR> {
+    /// Applied motor powers, front-back left-back right.
+    pub fn powers(&self) -> [MotorPower; 3] {
+        [
+            self.front.power(),
+            self.back_left.power(),
+            self.back_right.power(),
+        ]
+    }
+}
+
+impl<F: Motor, BL: Motor, BR: Motor> ThreeWheeledRobot for MyThreeWheelRobot<F, BL, BR> {
+    type Error = MyThreeWheelRobotError;
+
+    fn drive(
+        &mut self,
+        front: MotorPower,
+        back_left: MotorPower,
+        back_right: MotorPower,
+    ) -> Result<(), Self::Error> {
+        use KiwiWheel::*;
+        self.front
+            .drive(front)
+            .map_err(|_| Self::Error::Motor(Front))?;
+        self.back_left
+            .drive(back_left)
+            .map_err(|_| Self::Error::Motor(BackLeft))?;
+        self.back_right
+            .drive(back_right)
+            .map_err(|_| Self::Error::Motor(BackRight))?;
+
+        Ok(())
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        use KiwiWheel::*;
+        self.front
+            .neutral()
+            .map_err(|_| Self::Error::Motor(Front))?;
+        self.back_left
+            .neutral()
+            .map_err(|_| Self::Error::Motor(BackLeft))?;
+        self.back_right
+            .neutral()
+            .map_err(|_| Self::Error::Motor(BackRight))?;
+
+        Ok(())
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        use KiwiWheel::*;
+        self.front.brake().map_err(|_| Self::Error::Motor(Front))?;
+        self.back_left
+            .brake()
+            .map_err(|_| Self::Error::Motor(BackLeft))?;
+        self.back_right
+            .brake()
+            .map_err(|_| Self::Error::Motor(BackRight))?;
+
+        Ok(())
+    }
+}
+
+/// Kiwi drive kinematics on top of a [`ThreeWheeledRobot`].
+pub struct KiwiRobot<R> {
+    robot: R,
+}
+
+impl<R> KiwiRobot<R> {
+    /// Angular position of the wheels, counter-clockwise from the right.
+    const WHEEL_ANGLES: [f32; 3] = [
+        PI / 2.0,
+        PI / 2.0 + 2.0 * PI / 3.0,
+        PI / 2.0 - 2.0 * PI / 3.0,
+    ];
+
+    pub fn new(robot: R) -> Self {
+        Self { robot }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.robot
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.robot
+    }
+}
+
+impl<R: ThreeWheeledRobot> DriveBase for KiwiRobot<R> {
+    type Error = R::Error;
+
+    fn drive(&mut self, power: MecanumPower, theta: Angle, turn: Turn) -> Result<(), Self::Error> {
+        let power = power.inner();
+        let theta = theta.get::<radian>();
+        // Turn is clockwise positive, wheels push counter-clockwise
+        let turn = turn.inner();
+
+        // Each wheel only sees the velocity component along its tangent
+        let [front, back_left, back_right] = Self::WHEEL_ANGLES
+            .map(|alpha| MotorPower::new(power * libm::sinf(theta - alpha) - turn));
+
+        self.robot.drive(front, back_left, back_right)
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.robot.neutral()
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.robot.brake()
+    }
+}
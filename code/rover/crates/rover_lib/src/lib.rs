@@ -1,7 +1,115 @@
 #![no_std]
 
+pub mod arming;
+pub mod asynch;
+pub mod battery;
+pub mod bts7960;
+pub mod button;
+pub mod buzzer;
+pub mod calibration;
+pub mod collision_guard;
+pub mod compass;
+pub mod config;
+pub mod crc;
+pub mod current;
+pub mod differential;
+pub mod encoder;
+pub mod esc;
+pub mod estimator;
+pub mod event_log;
+pub mod failsafe;
+pub mod fixed;
+pub mod fusion;
+pub mod gps;
+pub mod hold;
+pub mod i2c_target;
 pub mod iface;
+pub mod imu;
+pub mod indicator;
+pub mod input_shaping;
+pub mod kinematics;
+pub mod kiwi;
+pub mod line;
+pub mod link_stats;
+pub mod log;
+pub mod mavlink;
+#[cfg(any(test, feature = "std"))]
+pub mod mock;
+pub mod moves;
+pub mod mux;
 pub mod my_lib;
+pub mod navigator;
+pub mod nrf24;
+pub mod odometry;
+pub mod pca9685;
+pub mod pid;
+pub mod power_budget;
+pub mod profile;
+pub mod radio;
+pub mod rc;
+pub mod sbus;
+pub mod schedule;
+pub mod servo;
+pub mod slew;
+pub mod stabilized;
+pub mod static_cell;
+pub mod tb6612;
+pub mod thermal;
+pub mod tilt;
+pub mod timesync;
+pub mod tof;
+pub mod transport;
+pub mod ultrasonic;
+pub mod velocity;
+pub mod watchdog;
+pub mod wire;
 
-pub use iface::{Angle, FourWheeledRobot, MecanumRobot, Motor, MotorPower, Turn};
-pub use my_lib::{MyFourWheelRobot, MyMotor};
+pub use arming::{Arming, Mode};
+pub use asynch::{AsyncFourWheeledRobot, AsyncMecanum, AsyncMecanumRobot, AsyncMotor};
+pub use battery::{BatteryMonitor, LowVoltageAction};
+pub use bts7960::Bts7960Motor;
+pub use button::{Gesture, GestureDecoder};
+pub use calibration::{CalibratedRobot, WheelTrim};
+pub use collision_guard::CollisionGuard;
+pub use compass::{CompassParams, MagCalibration, Magnetometer, Qmc5883l};
+pub use config::{ConfigError, ConfigStore};
+pub use current::{CurrentLimit, CurrentLimited, OvercurrentAction, OvercurrentEvent};
+pub use differential::{DifferentialRobot, MyTwoWheelRobot, TwoSidedRobot};
+pub use encoder::{Encoder, QuadratureEncoder};
+pub use esc::{Dshot, EscMotor, RcPwm};
+pub use estimator::{Estimate, EstimatorParams, PoseEstimator};
+pub use event_log::EventLog;
+pub use failsafe::{Failsafe, FailsafeParams, FailsafeStage};
+pub use fusion::{Attitude, ComplementaryFilter};
+pub use gps::{GeoPoint, GpsFix, GpsParams};
+pub use hold::{HoldMode, HoldParams};
+pub use iface::{
+    Angle, DriveBase, DriveFrame, FourWheeledRobot, MecanumRobot, Motor, MotorPower, NeutralMode,
+    Turn,
+};
+pub use imu::{Imu, ImuReading};
+pub use indicator::{Pattern, Rgb, Status};
+pub use input_shaping::InputShaping;
+pub use kinematics::ChassisVelocity;
+pub use kiwi::{KiwiRobot, MyThreeWheelRobot, ThreeWheeledRobot};
+pub use link_stats::{LinkMonitor, LinkStats};
+pub use moves::{Move, MoveOutcome};
+pub use mux::CommandMux;
+pub use my_lib::{FourWheelRobotBuilder, L298nMotor, MyFourWheelRobot, MyMotor, MyMotorBuilder};
+pub use odometry::{Odometry, Pose};
+pub use pca9685::{Pca9685, Pca9685Motor};
+pub use pid::{Pid, PidGains};
+pub use power_budget::PowerBudget;
+pub use rc::RcMapping;
+pub use sbus::SbusFrame;
+pub use schedule::Scheduler;
+pub use servo::{Servo, ServoCalibration};
+pub use slew::SlewLimiter;
+pub use stabilized::StabilizedRobot;
+pub use static_cell::StaticCell;
+pub use tb6612::{Tb6612Channel, Tb6612Motor, Tb6612Standby};
+pub use thermal::{ThermalDerating, ThermalParams};
+pub use tilt::{TiltGuard, TiltParams};
+pub use timesync::ClockSync;
+pub use velocity::{StallDetection, VelocityController};
+pub use watchdog::Heartbeats;
@@ -0,0 +1,83 @@
+//! Following a line with a row of reflectance sensors across the front of
+//! the robot.
+
+use serde::{Deserialize, Serialize};
+use uom::si::angle::degree;
+
+use crate::{
+    iface::{Angle, MecanumPower, Turn},
+    pid::{Pid, PidGains},
+};
+
+/// Where the line is under a row of sensors, left to right, each `true`
+/// over the line: -1 under the leftmost, 1 under the rightmost. `None` when
+/// none sees it.
+pub fn line_position(over_line: &[bool]) -> Option<f32> {
+    let last = over_line.len().checked_sub(1)?;
+    let (sum, count) = over_line
+        .iter()
+        .enumerate()
+        .filter(|(_, &over)| over)
+        .fold((0, 0), |(sum, count), (i, _)| (sum + i, count + 1));
+    if count == 0 {
+        return None;
+    }
+    if last == 0 {
+        return Some(0.0);
+    }
+    Some(2.0 * sum as f32 / (count * last) as f32 - 1.0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LineFollowParams {
+    /// Steering from the line position, turn positive clockwise.
+    pub gains: PidGains,
+    /// Forward power on a straight line, less in the curves.
+    pub power: f32,
+}
+
+impl LineFollowParams {
+    pub fn is_valid(&self) -> bool {
+        (0.0..=MecanumPower::MAX).contains(&self.power)
+    }
+}
+
+/// Keeps the line under the middle of the sensors, turning towards it.
+pub struct LineFollower {
+    params: LineFollowParams,
+    pid: Pid,
+}
+
+impl LineFollower {
+    pub fn new(params: LineFollowParams) -> Self {
+        Self {
+            params,
+            pid: Pid::new(params.gains, Turn::MIN, Turn::MAX),
+        }
+    }
+
+    pub fn set_params(&mut self, params: LineFollowParams) {
+        if params != self.params {
+            self.params = params;
+            self.pid.set_gains(params.gains);
+        }
+    }
+
+    /// Call when starting over on a new line.
+    pub fn reset(&mut self) {
+        self.pid.reset();
+    }
+
+    /// Command to stay on the line, `dt` in seconds. `None` once it's lost.
+    pub fn update(&mut self, over_line: &[bool], dt: f32) -> Option<(MecanumPower, Angle, Turn)> {
+        let position = line_position(over_line)?;
+        let turn = self.pid.update(position, dt);
+        // Half speed with the line at the edge, to make the curve
+        let power = self.params.power * (1.0 - libm::fabsf(position) / 2.0);
+        Some((
+            MecanumPower::new(power),
+            Angle::new::<degree>(90.0),
+            Turn::new(turn),
+        ))
+    }
+}
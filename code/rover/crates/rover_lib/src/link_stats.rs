@@ -0,0 +1,142 @@
+//! Health of the host link as the rover sees it, published every
+//! [`PERIOD_MS`] so a host can watch it degrade before the safety timer
+//! trips.
+//!
+//! The rover can't time the link on its own clock alone: the host stamps a
+//! message with its clock, the rover echoes the stamp back in the next
+//! [`LinkStats`] with how long it held it, and the host takes the difference.
+
+use serde::{Deserialize, Serialize};
+
+pub const PERIOD_MS: u64 = 1_000;
+
+/// A host timestamp, echoed back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StampEcho {
+    /// As the host sent it, in its ms.
+    pub host_ms: u32,
+    /// Between the rover receiving the stamp and sending it back.
+    pub held_ms: u32,
+}
+
+impl StampEcho {
+    /// The link's round trip, `now_ms` being the host clock when the echo
+    /// arrived.
+    pub fn round_trip_ms(&self, now_ms: u32) -> u32 {
+        now_ms
+            .wrapping_sub(self.host_ms)
+            .saturating_sub(self.held_ms)
+    }
+}
+
+/// Totals are since boot, on every transport together.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct LinkStats {
+    /// Good frames, decoded into a message.
+    pub frames: u32,
+    /// Frames failing their COBS, length or CRC check.
+    pub crc_errors: u32,
+    /// Frames through their check that aren't a message the rover knows.
+    pub decode_errors: u32,
+    /// Received bytes lost before they were parsed, see [`LinkMonitor::overflow`].
+    pub overflows: u32,
+    /// Drive commands, over the period just ended.
+    pub commands_per_second: f32,
+    /// `None` before the first good frame.
+    pub since_last_frame_ms: Option<u32>,
+    /// The latest stamp from the host, sent back once.
+    pub echo: Option<StampEcho>,
+}
+
+/// Counts what makes up [`LinkStats`] as frames come in.
+#[derive(Debug, Clone, Default)]
+pub struct LinkMonitor {
+    totals: LinkStats,
+    last_frame_ms: Option<u64>,
+    /// Drive commands since `period_start_ms`.
+    commands: u32,
+    period_start_ms: u64,
+    /// Host stamp, and when it came.
+    stamp: Option<(u32, u64)>,
+}
+
+impl LinkMonitor {
+    pub const fn new() -> Self {
+        Self {
+            totals: LinkStats {
+                frames: 0,
+                crc_errors: 0,
+                decode_errors: 0,
+                overflows: 0,
+                commands_per_second: 0.0,
+                since_last_frame_ms: None,
+                echo: None,
+            },
+            last_frame_ms: None,
+            commands: 0,
+            period_start_ms: 0,
+            stamp: None,
+        }
+    }
+
+    pub fn frame(&mut self, now_ms: u64) {
+        self.totals.frames = self.totals.frames.wrapping_add(1);
+        self.last_frame_ms = Some(now_ms);
+    }
+
+    pub fn crc_error(&mut self) {
+        self.totals.crc_errors = self.totals.crc_errors.wrapping_add(1);
+    }
+
+    pub fn decode_error(&mut self) {
+        self.totals.decode_errors = self.totals.decode_errors.wrapping_add(1);
+    }
+
+    /// The transport reported bytes lost, say a DMA ring overrun, or a
+    /// frame outgrew its buffer.
+    pub fn overflow(&mut self) {
+        self.totals.overflows = self.totals.overflows.wrapping_add(1);
+    }
+
+    /// A drive command, on top of its [`Self::frame`].
+    pub fn command(&mut self) {
+        self.commands = self.commands.saturating_add(1);
+    }
+
+    /// The host's clock, to be echoed in the next [`Self::publish`].
+    pub fn stamp(&mut self, host_ms: u32, now_ms: u64) {
+        self.stamp = Some((host_ms, now_ms));
+    }
+
+    pub fn crc_errors(&self) -> u32 {
+        self.totals.crc_errors
+    }
+
+    pub fn overflows(&self) -> u32 {
+        self.totals.overflows
+    }
+
+    /// The stats for the period ending at `now_ms`, starting the next one.
+    pub fn publish(&mut self, now_ms: u64) -> LinkStats {
+        let elapsed_ms = now_ms.saturating_sub(self.period_start_ms);
+        let commands_per_second = if elapsed_ms == 0 {
+            0.0
+        } else {
+            self.commands as f32 * 1000.0 / elapsed_ms as f32
+        };
+        self.commands = 0;
+        self.period_start_ms = now_ms;
+
+        LinkStats {
+            commands_per_second,
+            since_last_frame_ms: self
+                .last_frame_ms
+                .map(|last| now_ms.saturating_sub(last).min(u32::MAX as u64) as u32),
+            echo: self.stamp.take().map(|(host_ms, received_ms)| StampEcho {
+                host_ms,
+                held_ms: now_ms.saturating_sub(received_ms).min(u32::MAX as u64) as u32,
+            }),
+            ..self.totals
+        }
+    }
+}
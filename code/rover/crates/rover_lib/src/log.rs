@@ -0,0 +1,103 @@
+//! Log levels set at runtime per subsystem, on top of the level defmt was
+//! built with, and rate limiting for the logs that would flood the link.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+pub enum Category {
+    /// The host link and the RC receivers.
+    Comms,
+    /// Driving, by hand or autonomously.
+    Drive,
+    /// E-stop, safety timer, arming, current and battery limits.
+    Safety,
+    /// IMU, encoders, rangefinders and the like.
+    Sensors,
+}
+
+impl Category {
+    pub const COUNT: usize = 4;
+    pub const ALL: [Self; Self::COUNT] = [Self::Comms, Self::Drive, Self::Safety, Self::Sensors];
+
+    /// Lowercase, as typed in a shell.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Comms => "comms",
+            Self::Drive => "drive",
+            Self::Safety => "safety",
+            Self::Sensors => "sensors",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+}
+
+/// Least to most severe, as [`Ord`] goes.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, defmt::Format,
+)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    /// Errors are always logged, so this is the quietest.
+    Error,
+}
+
+impl Level {
+    pub const ALL: [Self; 5] = [
+        Self::Trace,
+        Self::Debug,
+        Self::Info,
+        Self::Warn,
+        Self::Error,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Trace => "trace",
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|l| l.name() == name)
+    }
+}
+
+/// Lets through one log per period, counting those dropped meanwhile.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    last_ms: Option<u64>,
+    dropped: u32,
+}
+
+impl RateLimit {
+    pub const fn new() -> Self {
+        Self {
+            last_ms: None,
+            dropped: 0,
+        }
+    }
+
+    /// Whether to log at `now_ms`, with the number dropped since the last
+    /// one logged.
+    pub fn allow(&mut self, now_ms: u64, period_ms: u32) -> Option<u32> {
+        match self.last_ms {
+            Some(last) if now_ms.saturating_sub(last) < period_ms as u64 => {
+                self.dropped = self.dropped.saturating_add(1);
+                None
+            }
+            _ => {
+                self.last_ms = Some(now_ms);
+                Some(core::mem::take(&mut self.dropped))
+            }
+        }
+    }
+}
@@ -0,0 +1,728 @@
+//! The few MAVLink messages a ground control station like QGroundControl
+//! needs to see a rover and drive it: [`Heartbeat`], [`SysStatus`] and
+//! [`AttitudeMsg`] out, [`ManualControl`] and [`CommandLong`] in, each
+//! command answered with a [`CommandAck`].
+//!
+//! Frames go out in MAVLink 2, unsigned. Both versions are read, frames of
+//! messages not listed here being dropped since their CRC can't be checked
+//! without the `CRC_EXTRA` of their definition.
+
+/// The longest MAVLink 2 frame, unsigned.
+pub const MAX_FRAME: usize = 10 + 255 + 2;
+const STX_V1: u8 = 0xFE;
+const STX_V2: u8 = 0xFD;
+/// The signature following a frame flagged signed, not checked.
+const SIGNATURE_LEN: usize = 13;
+const INCOMPAT_SIGNED: u8 = 0x01;
+
+/// `MAV_TYPE_GROUND_ROVER`.
+pub const MAV_TYPE_GROUND_ROVER: u8 = 10;
+/// `MAV_AUTOPILOT_GENERIC`.
+pub const MAV_AUTOPILOT_GENERIC: u8 = 0;
+pub const MAV_MODE_FLAG_SAFETY_ARMED: u8 = 0x80;
+pub const MAV_MODE_FLAG_MANUAL_INPUT_ENABLED: u8 = 0x40;
+/// `MAV_CMD_COMPONENT_ARM_DISARM`.
+pub const MAV_CMD_COMPONENT_ARM_DISARM: u16 = 400;
+
+/// `MAV_STATE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MavState {
+    Standby = 3,
+    Active = 4,
+    Critical = 5,
+    Emergency = 6,
+}
+
+/// `MAV_RESULT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MavResult {
+    Accepted = 0,
+    TemporarilyRejected = 1,
+    Denied = 2,
+    Unsupported = 3,
+    Failed = 4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum MavError {
+    BadCrc,
+    /// A known message shorter than its fields, in MAVLink 1 where they
+    /// can't be truncated.
+    BadLength,
+}
+
+impl core::fmt::Display for MavError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for MavError {}
+
+/// A message of the common set, its fields in wire order: by size, largest
+/// first.
+pub trait MavMessage: Sized {
+    const ID: u32;
+    const LEN: usize;
+    /// Folded into the CRC, so both ends agree on the fields.
+    const CRC_EXTRA: u8;
+
+    fn encode(&self, payload: &mut [u8]);
+    /// From a payload zero padded to [`Self::LEN`].
+    fn decode(payload: &[u8]) -> Self;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Heartbeat {
+    pub custom_mode: u32,
+    pub mav_type: u8,
+    pub autopilot: u8,
+    pub base_mode: u8,
+    pub system_status: MavState,
+}
+
+impl MavMessage for Heartbeat {
+    const ID: u32 = 0;
+    const LEN: usize = 9;
+    const CRC_EXTRA: u8 = 50;
+
+    fn encode(&self, payload: &mut [u8]) {
+        payload[0..4].copy_from_slice(&self.custom_mode.to_le_bytes());
+        payload[4] = self.mav_type;
+        payload[5] = self.autopilot;
+        payload[6] = self.base_mode;
+        payload[7] = self.system_status as u8;
+        // The protocol version, 3 for both 1 and 2
+        payload[8] = 3;
+    }
+
+    fn decode(payload: &[u8]) -> Self {
+        Self {
+            custom_mode: u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]),
+            mav_type: payload[4],
+            autopilot: payload[5],
+            base_mode: payload[6],
+            system_status: match payload[7] {
+                4 => MavState::Active,
+                5 => MavState::Critical,
+                6 => MavState::Emergency,
+                _ => MavState::Standby,
+            },
+        }
+    }
+}
+
+/// Without the extension fields.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SysStatus {
+    pub sensors_present: u32,
+    pub sensors_enabled: u32,
+    pub sensors_health: u32,
+    /// In 0.1% of the time.
+    pub load: u16,
+    /// In mV, `u16::MAX` unknown.
+    pub voltage_battery: u16,
+    /// In 10 mA, -1 unknown.
+    pub current_battery: i16,
+    /// In 0.01%.
+    pub drop_rate_comm: u16,
+    pub errors_comm: u16,
+    /// In %, -1 unknown.
+    pub battery_remaining: i8,
+}
+
+impl MavMessage for SysStatus {
+    const ID: u32 = 1;
+    const LEN: usize = 31;
+    const CRC_EXTRA: u8 = 124;
+
+    fn encode(&self, payload: &mut [u8]) {
+        payload[0..4].copy_from_slice(&self.sensors_present.to_le_bytes());
+        payload[4..8].copy_from_slice(&self.sensors_enabled.to_le_bytes());
+        payload[8..12].copy_from_slice(&self.sensors_health.to_le_bytes());
+        payload[12..14].copy_from_slice(&self.load.to_le_bytes());
+        payload[14..16].copy_from_slice(&self.voltage_battery.to_le_bytes());
+        payload[16..18].copy_from_slice(&self.current_battery.to_le_bytes());
+        payload[18..20].copy_from_slice(&self.drop_rate_comm.to_le_bytes());
+        payload[20..22].copy_from_slice(&self.errors_comm.to_le_bytes());
+        // errors_count1 to 4, autopilot specific
+        payload[22..30].fill(0);
+        payload[30] = self.battery_remaining as u8;
+    }
+
+    fn decode(payload: &[u8]) -> Self {
+        let u16_at = |i: usize| u16::from_le_bytes([payload[i], payload[i + 1]]);
+        let u32_at = |i: usize| {
+            u32::from_le_bytes([payload[i], payload[i + 1], payload[i + 2], payload[i + 3]])
+        };
+        Self {
+            sensors_present: u32_at(0),
+            sensors_enabled: u32_at(4),
+            sensors_health: u32_at(8),
+            load: u16_at(12),
+            voltage_battery: u16_at(14),
+            current_battery: u16_at(16) as i16,
+            drop_rate_comm: u16_at(18),
+            errors_comm: u16_at(20),
+            battery_remaining: payload[30] as i8,
+        }
+    }
+}
+
+/// `ATTITUDE`, in the aircraft convention: roll right side down, pitch nose
+/// up and yaw clockwise from north, all positive, in radians.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AttitudeMsg {
+    pub time_boot_ms: u32,
+    pub roll: f32,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub rollspeed: f32,
+    pub pitchspeed: f32,
+    pub yawspeed: f32,
+}
+
+impl MavMessage for AttitudeMsg {
+    const ID: u32 = 30;
+    const LEN: usize = 28;
+    const CRC_EXTRA: u8 = 39;
+
+    fn encode(&self, payload: &mut [u8]) {
+        payload[0..4].copy_from_slice(&self.time_boot_ms.to_le_bytes());
+        let fields = [
+            self.roll,
+            self.pitch,
+            self.yaw,
+            self.rollspeed,
+            self.pitchspeed,
+            self.yawspeed,
+        ];
+        for (i, field) in fields.iter().enumerate() {
+            payload[4 + i * 4..8 + i * 4].copy_from_slice(&field.to_le_bytes());
+        }
+    }
+
+    fn decode(payload: &[u8]) -> Self {
+        let f32_at = |i: usize| {
+            f32::from_le_bytes([payload[i], payload[i + 1], payload[i + 2], payload[i + 3]])
+        };
+        Self {
+            time_boot_ms: u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]),
+            roll: f32_at(4),
+            pitch: f32_at(8),
+            yaw: f32_at(12),
+            rollspeed: f32_at(16),
+            pitchspeed: f32_at(20),
+            yawspeed: f32_at(24),
+        }
+    }
+}
+
+/// A joystick of the ground station, each axis -1000 to 1000 and `i16::MAX`
+/// when it has none. Without the extension fields.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ManualControl {
+    /// Forward.
+    pub x: i16,
+    /// Right.
+    pub y: i16,
+    /// Thrust, 0 to 1000 on most sticks.
+    pub z: i16,
+    /// Yaw, clockwise.
+    pub r: i16,
+    pub buttons: u16,
+    pub target: u8,
+}
+
+impl ManualControl {
+    /// `axis` from -1 to 1, 0 if the joystick doesn't have it.
+    pub fn normalized(axis: i16) -> f32 {
+        match axis {
+            i16::MAX => 0.0,
+            axis => (axis as f32 / 1000.0).clamp(-1.0, 1.0),
+        }
+    }
+}
+
+impl MavMessage for ManualControl {
+    const ID: u32 = 69;
+    const LEN: usize = 11;
+    const CRC_EXTRA: u8 = 243;
+
+    fn encode(&self, payload: &mut [u8]) {
+        payload[0..2].copy_from_slice(&self.x.to_le_bytes());
+        payload[2..4].copy_from_slice(&self.y.to_le_bytes());
+        payload[4..6].copy_from_slice(&self.z.to_le_bytes());
+        payload[6..8].copy_from_slice(&self.r.to_le_bytes());
+        payload[8..10].copy_from_slice(&self.buttons.to_le_bytes());
+        payload[10] = self.target;
+    }
+
+    fn decode(payload: &[u8]) -> Self {
+        let i16_at = |i: usize| i16::from_le_bytes([payload[i], payload[i + 1]]);
+        Self {
+            x: i16_at(0),
+            y: i16_at(2),
+            z: i16_at(4),
+            r: i16_at(6),
+            buttons: i16_at(8) as u16,
+            target: payload[10],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CommandLong {
+    pub params: [f32; 7],
+    pub command: u16,
+    pub target_system: u8,
+    pub target_component: u8,
+    pub confirmation: u8,
+}
+
+impl MavMessage for CommandLong {
+    const ID: u32 = 76;
+    const LEN: usize = 33;
+    const CRC_EXTRA: u8 = 152;
+
+    fn encode(&self, payload: &mut [u8]) {
+        for (i, param) in self.params.iter().enumerate() {
+            payload[i * 4..i * 4 + 4].copy_from_slice(&param.to_le_bytes());
+        }
+        payload[28..30].copy_from_slice(&self.command.to_le_bytes());
+        payload[30] = self.target_system;
+        payload[31] = self.target_component;
+        payload[32] = self.confirmation;
+    }
+
+    fn decode(payload: &[u8]) -> Self {
+        Self {
+            params: core::array::from_fn(|i| {
+                let i = i * 4;
+                f32::from_le_bytes([payload[i], payload[i + 1], payload[i + 2], payload[i + 3]])
+            }),
+            command: u16::from_le_bytes([payload[28], payload[29]]),
+            target_system: payload[30],
+            target_component: payload[31],
+            confirmation: payload[32],
+        }
+    }
+}
+
+/// Without the extension fields.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommandAck {
+    pub command: u16,
+    pub result: MavResult,
+}
+
+impl MavMessage for CommandAck {
+    const ID: u32 = 77;
+    const LEN: usize = 3;
+    const CRC_EXTRA: u8 = 143;
+
+    fn encode(&self, payload: &mut [u8]) {
+        payload[0..2].copy_from_slice(&self.command.to_le_bytes());
+        payload[2] = self.result as u8;
+    }
+
+    fn decode(payload: &[u8]) -> Self {
+        Self {
+            command: u16::from_le_bytes([payload[0], payload[1]]),
+            result: match payload[2] {
+                0 => MavResult::Accepted,
+                1 => MavResult::TemporarilyRejected,
+                2 => MavResult::Denied,
+                3 => MavResult::Unsupported,
+                _ => MavResult::Failed,
+            },
+        }
+    }
+}
+
+/// The `(LEN, CRC_EXTRA)` of the messages read.
+fn definition(id: u32) -> Option<(usize, u8)> {
+    fn of<M: MavMessage>() -> (usize, u8) {
+        (M::LEN, M::CRC_EXTRA)
+    }
+    match id {
+        Heartbeat::ID => Some(of::<Heartbeat>()),
+        ManualControl::ID => Some(of::<ManualControl>()),
+        CommandLong::ID => Some(of::<CommandLong>()),
+        _ => None,
+    }
+}
+
+/// CRC-16/MCRF4XX, the X.25 one MAVLink uses.
+fn crc_update(crc: u16, data: &[u8]) -> u16 {
+    data.iter().fold(crc, |crc, &byte| {
+        let tmp = byte ^ crc as u8;
+        let tmp = tmp ^ (tmp << 4);
+        (crc >> 8) ^ (tmp as u16) << 8 ^ (tmp as u16) << 3 ^ (tmp as u16) >> 4
+    })
+}
+
+/// A frame received, its payload zero padded to the message's length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub system_id: u8,
+    pub component_id: u8,
+    pub msg_id: u32,
+    payload: [u8; 255],
+}
+
+impl Frame {
+    /// `None` if the frame is another message.
+    pub fn message<M: MavMessage>(&self) -> Option<M> {
+        (self.msg_id == M::ID).then(|| M::decode(&self.payload[..M::LEN]))
+    }
+}
+
+/// Writes frames from one component, numbering them.
+pub struct MavWriter {
+    system_id: u8,
+    component_id: u8,
+    seq: u8,
+}
+
+impl MavWriter {
+    pub const fn new(system_id: u8, component_id: u8) -> Self {
+        Self {
+            system_id,
+            component_id,
+            seq: 0,
+        }
+    }
+
+    /// The frame of `msg` into `out`, trailing zeros of its payload cut off
+    /// like MAVLink 2 wants.
+    pub fn write<'a, M: MavMessage>(&mut self, msg: &M, out: &'a mut [u8; MAX_FRAME]) -> &'a [u8] {
+        let mut payload = [0u8; 255];
+        msg.encode(&mut payload[..M::LEN]);
+        let len = payload[..M::LEN]
+            .iter()
+            .rposition(|&b| b != 0)
+            .map_or(1, |last| last + 1);
+
+        let id = M::ID.to_le_bytes();
+        let header = [
+            STX_V2,
+            len as u8,
+            0,
+            0,
+            self.seq,
+            self.system_id,
+            self.component_id,
+            id[0],
+            id[1],
+            id[2],
+        ];
+        self.seq = self.seq.wrapping_add(1);
+        out[..10].copy_from_slice(&header);
+        out[10..10 + len].copy_from_slice(&payload[..len]);
+        let crc = crc_update(crc_update(0xFFFF, &out[1..10 + len]), &[M::CRC_EXTRA]);
+        out[10 + len..12 + len].copy_from_slice(&crc.to_le_bytes());
+        &out[..12 + len]
+    }
+}
+
+/// Assembles the frames of the bytes received.
+pub struct MavParser {
+    buf: [u8; MAX_FRAME + SIGNATURE_LEN],
+    len: usize,
+}
+
+impl MavParser {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_FRAME + SIGNATURE_LEN],
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> Result<Option<Frame>, MavError> {
+        if self.len == 0 && byte != STX_V1 && byte != STX_V2 {
+            return Ok(None);
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+
+        let v2 = self.buf[0] == STX_V2;
+        let header_len = if v2 { 10 } else { 6 };
+        if self.len < 2 {
+            return Ok(None);
+        }
+        let payload_len = self.buf[1] as usize;
+        let mut frame_len = header_len + payload_len + 2;
+        if v2 && self.len >= 3 && self.buf[2] & INCOMPAT_SIGNED != 0 {
+            frame_len += SIGNATURE_LEN;
+        }
+        if self.len < header_len.max(3) || self.len < frame_len {
+            return Ok(None);
+        }
+        self.len = 0;
+
+        let buf = &self.buf;
+        let (system_id, component_id, msg_id) = if v2 {
+            (
+                buf[5],
+                buf[6],
+                u32::from_le_bytes([buf[7], buf[8], buf[9], 0]),
+            )
+        } else {
+            (buf[3], buf[4], buf[5] as u32)
+        };
+        let Some((len, crc_extra)) = definition(msg_id) else {
+            return Ok(None);
+        };
+        let end = header_len + payload_len;
+        let crc = crc_update(crc_update(0xFFFF, &buf[1..end]), &[crc_extra]);
+        if crc.to_le_bytes() != buf[end..end + 2] {
+            return Err(MavError::BadCrc);
+        }
+        if !v2 && payload_len < len {
+            return Err(MavError::BadLength);
+        }
+
+        let mut payload = [0u8; 255];
+        payload[..payload_len].copy_from_slice(&buf[header_len..end]);
+        Ok(Some(Frame {
+            system_id,
+            component_id,
+            msg_id,
+            payload,
+        }))
+    }
+}
+
+impl Default for MavParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MANUAL_CONTROL as QGroundControl (system 255, component 190) sends
+    /// it: x 500, y -250, z 500, r 0, no buttons, target 1, seq 7.
+    const QGC_MANUAL_CONTROL: [u8; 23] = [
+        0xFD, 0x0B, 0x00, 0x00, 0x07, 0xFF, 0xBE, 0x45, 0x00, 0x00, 0xF4, 0x01, 0x06, 0xFF, 0xF4,
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x01, 0xFE, 0x8A,
+    ];
+    /// The same in MAVLink 1.
+    const QGC_MANUAL_CONTROL_V1: [u8; 19] = [
+        0xFE, 0x0B, 0x07, 0xFF, 0xBE, 0x45, 0xF4, 0x01, 0x06, 0xFF, 0xF4, 0x01, 0x00, 0x00, 0x00,
+        0x00, 0x01, 0xE6, 0x6E,
+    ];
+
+    const STICKS: ManualControl = ManualControl {
+        x: 500,
+        y: -250,
+        z: 500,
+        r: 0,
+        buttons: 0,
+        target: 1,
+    };
+
+    /// The frames completed by `bytes`, or the first error.
+    fn parse(parser: &mut MavParser, bytes: &[u8]) -> Result<Option<Frame>, MavError> {
+        let mut frame = None;
+        for &byte in bytes {
+            if let Some(done) = parser.push(byte)? {
+                assert!(frame.replace(done).is_none(), "two frames");
+            }
+        }
+        Ok(frame)
+    }
+
+    /// A MAVLink 1 frame of `payload`, as long as it is.
+    fn v1_frame(msg_id: u8, crc_extra: u8, payload: &[u8]) -> ([u8; MAX_FRAME], usize) {
+        let mut out = [0; MAX_FRAME];
+        out[..6].copy_from_slice(&[STX_V1, payload.len() as u8, 0, 1, 1, msg_id]);
+        let end = 6 + payload.len();
+        out[6..end].copy_from_slice(payload);
+        let crc = crc_update(crc_update(0xFFFF, &out[1..end]), &[crc_extra]);
+        out[end..end + 2].copy_from_slice(&crc.to_le_bytes());
+        (out, end + 2)
+    }
+
+    fn encoded<M: MavMessage>(msg: &M) -> M {
+        let mut payload = [0u8; 255];
+        msg.encode(&mut payload[..M::LEN]);
+        M::decode(&payload[..M::LEN])
+    }
+
+    #[test]
+    fn crc_is_mcrf4xx() {
+        assert_eq!(crc_update(0xFFFF, b"123456789"), 0x6F91);
+    }
+
+    #[test]
+    fn reads_qgroundcontrol_manual_control() {
+        for bytes in [&QGC_MANUAL_CONTROL[..], &QGC_MANUAL_CONTROL_V1] {
+            let frame = parse(&mut MavParser::new(), bytes).unwrap().unwrap();
+            assert_eq!((frame.system_id, frame.component_id), (255, 190));
+            assert_eq!(frame.message::<ManualControl>(), Some(STICKS));
+            assert_eq!(frame.message::<Heartbeat>(), None);
+        }
+    }
+
+    #[test]
+    fn writes_what_qgroundcontrol_would() {
+        let mut writer = MavWriter::new(255, 190);
+        let mut out = [0; MAX_FRAME];
+        for _ in 0..7 {
+            writer.write(&STICKS, &mut out);
+        }
+        assert_eq!(writer.write(&STICKS, &mut out), QGC_MANUAL_CONTROL);
+    }
+
+    #[test]
+    fn written_frames_parse_back() {
+        let mut writer = MavWriter::new(1, 1);
+        let mut parser = MavParser::new();
+        let mut out = [0; MAX_FRAME];
+
+        let heartbeat = Heartbeat {
+            custom_mode: 0x1234_5678,
+            mav_type: MAV_TYPE_GROUND_ROVER,
+            autopilot: MAV_AUTOPILOT_GENERIC,
+            base_mode: MAV_MODE_FLAG_SAFETY_ARMED,
+            system_status: MavState::Active,
+        };
+        let frame = parse(&mut parser, writer.write(&heartbeat, &mut out))
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.message::<Heartbeat>(), Some(heartbeat));
+
+        let command = CommandLong {
+            params: [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, -2.5],
+            command: MAV_CMD_COMPONENT_ARM_DISARM,
+            target_system: 1,
+            target_component: 1,
+            confirmation: 0,
+        };
+        let frame = parse(&mut parser, writer.write(&command, &mut out))
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.message::<CommandLong>(), Some(command));
+    }
+
+    #[test]
+    fn trailing_zeros_are_cut_and_restored() {
+        let mut writer = MavWriter::new(1, 1);
+        let mut out = [0; MAX_FRAME];
+        let sticks = ManualControl {
+            x: 1,
+            ..ManualControl::default()
+        };
+        let bytes = writer.write(&sticks, &mut out);
+        // Only `x`'s low byte is left
+        assert_eq!(bytes[1], 1);
+        assert_eq!(bytes.len(), 10 + 1 + 2);
+        let frame = parse(&mut MavParser::new(), bytes).unwrap().unwrap();
+        assert_eq!(frame.message::<ManualControl>(), Some(sticks));
+
+        // An all zero payload keeps one byte
+        let bytes = writer.write(&ManualControl::default(), &mut out);
+        assert_eq!(bytes[1], 1);
+        let frame = parse(&mut MavParser::new(), bytes).unwrap().unwrap();
+        assert_eq!(
+            frame.message::<ManualControl>(),
+            Some(ManualControl::default())
+        );
+    }
+
+    #[test]
+    fn messages_decode_what_they_encode() {
+        let status = SysStatus {
+            sensors_present: 1,
+            sensors_enabled: 2,
+            sensors_health: 3,
+            load: 500,
+            voltage_battery: 7_400,
+            current_battery: -1,
+            drop_rate_comm: 10,
+            errors_comm: 2,
+            battery_remaining: -1,
+        };
+        assert_eq!(encoded(&status), status);
+        let attitude = AttitudeMsg {
+            time_boot_ms: 1_000,
+            roll: 0.1,
+            pitch: -0.2,
+            yaw: 3.0,
+            rollspeed: 0.0,
+            pitchspeed: 0.5,
+            yawspeed: -0.5,
+        };
+        assert_eq!(encoded(&attitude), attitude);
+        let ack = CommandAck {
+            command: MAV_CMD_COMPONENT_ARM_DISARM,
+            result: MavResult::TemporarilyRejected,
+        };
+        assert_eq!(encoded(&ack), ack);
+    }
+
+    #[test]
+    fn bad_crc_is_refused_and_the_next_frame_read() {
+        let mut parser = MavParser::new();
+        // Not the length, the flags or the ID, which would make another frame
+        for at in (3..7).chain(10..QGC_MANUAL_CONTROL.len()) {
+            let mut bytes = QGC_MANUAL_CONTROL;
+            bytes[at] ^= 0x01;
+            assert_eq!(parse(&mut parser, &bytes), Err(MavError::BadCrc), "{at}");
+        }
+        let frame = parse(&mut parser, &QGC_MANUAL_CONTROL).unwrap().unwrap();
+        assert_eq!(frame.message::<ManualControl>(), Some(STICKS));
+    }
+
+    #[test]
+    fn short_v1_payloads_are_refused() {
+        let (bytes, len) = v1_frame(ManualControl::ID as u8, ManualControl::CRC_EXTRA, &[1; 10]);
+        assert_eq!(
+            parse(&mut MavParser::new(), &bytes[..len]),
+            Err(MavError::BadLength)
+        );
+        let (bytes, len) = v1_frame(ManualControl::ID as u8, ManualControl::CRC_EXTRA, &[1; 11]);
+        assert!(parse(&mut MavParser::new(), &bytes[..len])
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn signed_frames_skip_the_signature() {
+        let mut bytes = [0; QGC_MANUAL_CONTROL.len() + SIGNATURE_LEN];
+        bytes[..QGC_MANUAL_CONTROL.len()].copy_from_slice(&QGC_MANUAL_CONTROL);
+        bytes[2] = INCOMPAT_SIGNED;
+        let end = QGC_MANUAL_CONTROL.len() - 2;
+        let crc = crc_update(
+            crc_update(0xFFFF, &bytes[1..end]),
+            &[ManualControl::CRC_EXTRA],
+        );
+        bytes[end..end + 2].copy_from_slice(&crc.to_le_bytes());
+        // A signature starting like a frame
+        bytes[end + 2] = STX_V2;
+
+        let mut parser = MavParser::new();
+        let frame = parse(&mut parser, &bytes).unwrap().unwrap();
+        assert_eq!(frame.message::<ManualControl>(), Some(STICKS));
+        assert!(parse(&mut parser, &QGC_MANUAL_CONTROL).unwrap().is_some());
+    }
+
+    #[test]
+    fn unknown_messages_and_noise_are_dropped() {
+        let mut writer = MavWriter::new(1, 1);
+        let mut out = [0; MAX_FRAME];
+        let mut parser = MavParser::new();
+        let status = writer.write(&SysStatus::default(), &mut out);
+        assert_eq!(parse(&mut parser, status), Ok(None));
+        assert_eq!(parse(&mut parser, &[0x00, 0x55, 0xAA]), Ok(None));
+        assert!(parse(&mut parser, &QGC_MANUAL_CONTROL).unwrap().is_some());
+    }
+}
@@ -0,0 +1,263 @@
+//! Stand-ins for the motors that record what they're told, and for the
+//! flash, so the drive logic and the storage can be tested on the host
+//! without hardware.
+
+extern crate std;
+
+use std::{vec, vec::Vec};
+
+use embedded_storage::nor_flash::{
+    check_erase, check_read, check_write, ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash,
+};
+
+use crate::iface::{FourWheeledRobot, Motor, MotorPower};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MotorCall {
+    Drive(MotorPower),
+    Neutral,
+    Brake,
+}
+
+/// A [`RobotCall::Drive`] holds the powers FL-FR-BL-BR.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RobotCall {
+    Drive([MotorPower; 4]),
+    Neutral,
+    Brake,
+}
+
+/// What the mocks return while failing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MockError;
+
+impl core::fmt::Display for MockError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for MockError {}
+
+#[derive(Debug, Default)]
+pub struct MockMotor {
+    /// Every successful call, oldest first.
+    pub calls: Vec<MotorCall>,
+    power: MotorPower,
+    failing: bool,
+}
+
+impl MockMotor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes every following call fail, and go unrecorded.
+    pub fn set_failing(&mut self, failing: bool) {
+        self.failing = failing;
+    }
+
+    pub fn last(&self) -> Option<MotorCall> {
+        self.calls.last().copied()
+    }
+
+    fn record(&mut self, call: MotorCall) -> Result<(), MockError> {
+        if self.failing {
+            return Err(MockError);
+        }
+        self.power = match call {
+            MotorCall::Drive(power) => power,
+            MotorCall::Neutral | MotorCall::Brake => MotorPower::default(),
+        };
+        self.calls.push(call);
+        Ok(())
+    }
+}
+
+impl Motor for MockMotor {
+    type Error = MockError;
+
+    fn drive(&mut self, power: MotorPower) -> Result<(), Self::Error> {
+        self.record(MotorCall::Drive(power))
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.record(MotorCall::Neutral)
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.record(MotorCall::Brake)
+    }
+    fn power(&self) -> MotorPower {
+        self.power
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MockRobot {
+    /// Every successful call, oldest first.
+    pub calls: Vec<RobotCall>,
+    failing: bool,
+}
+
+impl MockRobot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes every following call fail, and go unrecorded.
+    pub fn set_failing(&mut self, failing: bool) {
+        self.failing = failing;
+    }
+
+    pub fn last(&self) -> Option<RobotCall> {
+        self.calls.last().copied()
+    }
+
+    /// Powers applied by the last call, FL-FR-BL-BR, zero when stopped.
+    pub fn powers(&self) -> [MotorPower; 4] {
+        match self.last() {
+            Some(RobotCall::Drive(powers)) => powers,
+            _ => [MotorPower::default(); 4],
+        }
+    }
+
+    fn record(&mut self, call: RobotCall) -> Result<(), MockError> {
+        if self.failing {
+            return Err(MockError);
+        }
+        self.calls.push(call);
+        Ok(())
+    }
+}
+
+impl FourWheeledRobot for MockRobot {
+    type Error = MockError;
+
+    fn drive(
+        &mut self,
+        fl: MotorPower,
+        fr: MotorPower,
+        bl: MotorPower,
+        br: MotorPower,
+    ) -> Result<(), Self::Error> {
+        self.record(RobotCall::Drive([fl, fr, bl, br]))
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.record(RobotCall::Neutral)
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.record(RobotCall::Brake)
+    }
+}
+
+/// NOR flash in RAM: erasing sets bits, writing only clears them, like the
+/// real thing, so a write without an erase first reads back wrong.
+#[derive(Debug, Clone)]
+pub struct MockFlash {
+    pub bytes: Vec<u8>,
+}
+
+impl MockFlash {
+    /// `blocks` erase blocks, all erased.
+    pub fn new(blocks: usize) -> Self {
+        Self {
+            bytes: vec![0xFF; blocks * <Self as NorFlash>::ERASE_SIZE],
+        }
+    }
+}
+
+impl ErrorType for MockFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for MockFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_read(self, offset, bytes.len())?;
+        let at = offset as usize;
+        bytes.copy_from_slice(&self.bytes[at..at + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl NorFlash for MockFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = 4096;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self, from, to)?;
+        self.bytes[from as usize..to as usize].fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write(self, offset, bytes.len())?;
+        let at = offset as usize;
+        for (stored, byte) in self.bytes[at..at + bytes.len()].iter_mut().zip(bytes) {
+            *stored &= byte;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uom::si::{angle::degree, f32::Time, time::second};
+
+    use super::*;
+    use crate::{
+        iface::{Angle, MecanumPower, MecanumRobot, Turn},
+        my_lib::{MyFourWheelRobot, MyFourWheelRobotError, MyMotorKind},
+        slew::SlewLimiter,
+    };
+
+    fn assert_powers(actual: [MotorPower; 4], expected: [f32; 4]) {
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(
+                (actual.inner() - expected).abs() < 1e-3,
+                "{actual:?} != {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn forward_drives_all_wheels_alike() {
+        let mut robot = MockRobot::new();
+        let theta = Angle::new::<degree>(90.0);
+        MecanumRobot::drive(&mut robot, MecanumPower::new(1.0), theta, Turn::new(0.0)).unwrap();
+
+        let p = core::f32::consts::FRAC_1_SQRT_2;
+        assert_powers(robot.powers(), [p; 4]);
+    }
+
+    #[test]
+    fn slew_limiter_ramps_but_stops_at_once() {
+        let mut robot = SlewLimiter::new(MockRobot::new(), 1.0);
+        let full = MotorPower::new(1.0);
+        FourWheeledRobot::drive(&mut robot, full, full, full, full).unwrap();
+        assert!(robot.inner().calls.is_empty());
+
+        robot.update(Time::new::<second>(0.5)).unwrap();
+        assert_powers(robot.inner().powers(), [0.5; 4]);
+
+        FourWheeledRobot::neutral(&mut robot).unwrap();
+        assert_eq!(robot.inner().last(), Some(RobotCall::Neutral));
+    }
+
+    #[test]
+    fn failing_motor_is_reported() {
+        let mut fr = MockMotor::new();
+        fr.set_failing(true);
+        let mut robot =
+            MyFourWheelRobot::new(MockMotor::new(), fr, MockMotor::new(), MockMotor::new());
+
+        assert_eq!(
+            FourWheeledRobot::neutral(&mut robot),
+            Err(MyFourWheelRobotError::Motor(MyMotorKind::Fr))
+        );
+    }
+}
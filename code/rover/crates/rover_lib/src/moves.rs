@@ -0,0 +1,155 @@
+//! Moves of a set distance or angle, driven on odometry until done: the
+//! [`Navigator`] takes the robot to waypoints set from the pose the move
+//! started at.
+
+use core::f32::consts::{FRAC_PI_2, TAU};
+
+use serde::{Deserialize, Serialize};
+use uom::si::{angle::radian, f32::Length, length::meter};
+
+use crate::{
+    asynch::AsyncMecanumRobot,
+    iface::Angle,
+    navigator::{Navigator, Waypoint},
+    odometry::{wrap_angle, Pose},
+};
+
+/// The longest drive of a move.
+pub const MAX_DISTANCE_M: f32 = 100.0;
+/// The most turns of a rotation.
+pub const MAX_TURNS: f32 = 10.0;
+
+/// Rotations are split in steps of at most this, the navigator only
+/// turning the short way to a heading.
+const ROTATE_STEP: f32 = FRAC_PI_2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Move {
+    /// Strafes `distance` towards `angle` of the robot frame as it was at
+    /// the start, keeping the heading. A negative distance goes the other
+    /// way.
+    Drive { distance: Length, angle: Angle },
+    /// Turns on the spot, counter-clockwise positive like the heading.
+    Rotate { angle: Angle },
+}
+
+impl Move {
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Move::Drive { distance, angle } => {
+                libm::fabsf(distance.get::<meter>()) <= MAX_DISTANCE_M
+                    && angle.get::<radian>().is_finite()
+            }
+            Move::Rotate { angle } => libm::fabsf(angle.get::<radian>()) <= MAX_TURNS * TAU,
+        }
+    }
+
+    fn steps(&self) -> usize {
+        match *self {
+            Move::Drive { .. } => 1,
+            Move::Rotate { angle } => {
+                (libm::ceilf(libm::fabsf(angle.get::<radian>()) / ROTATE_STEP) as usize).max(1)
+            }
+        }
+    }
+
+    fn waypoint(&self, start: &Pose, step: usize) -> Waypoint {
+        match *self {
+            Move::Drive { distance, angle } => {
+                // The angle is of the robot frame, 90° forward
+                let direction = (start.heading + angle).get::<radian>();
+                Waypoint {
+                    x: start.x + distance * libm::cosf(direction),
+                    y: start.y + distance * libm::sinf(direction),
+                    heading: start.heading,
+                }
+            }
+            Move::Rotate { angle } => {
+                let turned = angle.get::<radian>() * (step + 1) as f32 / self.steps() as f32;
+                Waypoint {
+                    x: start.x,
+                    y: start.y,
+                    heading: Angle::new::<radian>(wrap_angle(
+                        start.heading.get::<radian>() + turned,
+                    )),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+pub enum MoveOutcome {
+    Done,
+    /// Stopped before the end, by the [`PoseSource`].
+    Aborted,
+}
+
+#[allow(async_fn_in_trait)]
+pub trait PoseSource {
+    /// The next pose of the odometry, `None` to abort the move.
+    async fn next(&mut self) -> Option<Pose>;
+}
+
+/// Drives `mv` to its end, or until `poses` aborts it, then puts the robot
+/// in neutral. `dt` is the period of the poses, in seconds.
+pub async fn run<R: AsyncMecanumRobot>(
+    robot: &mut R,
+    poses: &mut impl PoseSource,
+    navigator: &mut Navigator,
+    mv: Move,
+    dt: f32,
+) -> Result<MoveOutcome, R::Error> {
+    let outcome = drive_to_end(robot, poses, navigator, mv, dt).await?;
+    robot.neutral().await?;
+    Ok(outcome)
+}
+
+async fn drive_to_end<R: AsyncMecanumRobot>(
+    robot: &mut R,
+    poses: &mut impl PoseSource,
+    navigator: &mut Navigator,
+    mv: Move,
+    dt: f32,
+) -> Result<MoveOutcome, R::Error> {
+    let Some(start) = poses.next().await else {
+        return Ok(MoveOutcome::Aborted);
+    };
+    for step in 0..mv.steps() {
+        let target = mv.waypoint(&start, step);
+        navigator.reset();
+        loop {
+            let Some(pose) = poses.next().await else {
+                return Ok(MoveOutcome::Aborted);
+            };
+            let Some((power, theta, turn)) = navigator.update(&pose, &target, dt) else {
+                break;
+            };
+            robot.drive(power, theta, turn).await?;
+        }
+    }
+    Ok(MoveOutcome::Done)
+}
+
+/// [`run`]s a [`Move::Drive`].
+pub async fn drive_distance<R: AsyncMecanumRobot>(
+    robot: &mut R,
+    poses: &mut impl PoseSource,
+    navigator: &mut Navigator,
+    distance: Length,
+    angle: Angle,
+    dt: f32,
+) -> Result<MoveOutcome, R::Error> {
+    run(robot, poses, navigator, Move::Drive { distance, angle }, dt).await
+}
+
+/// [`run`]s a [`Move::Rotate`].
+pub async fn rotate_angle<R: AsyncMecanumRobot>(
+    robot: &mut R,
+    poses: &mut impl PoseSource,
+    navigator: &mut Navigator,
+    angle: Angle,
+    dt: f32,
+) -> Result<MoveOutcome, R::Error> {
+    run(robot, poses, navigator, Move::Rotate { angle }, dt).await
+}
@@ -0,0 +1,139 @@
+//! Arbitration between everything that wants to drive the robot: the highest
+//! priority source with a live claim is in control, the others get refused.
+//! A source going quiet is ignored once its claim runs out, and one sending
+//! commands faster than its rate limit has the extra ones dropped.
+
+use serde::{Deserialize, Serialize};
+
+/// Highest priority first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Source {
+    /// Latched e-stop, so nothing drives.
+    Estop,
+    /// The RC link died while the pilot was in control.
+    RcFailsafe,
+    RcManual,
+    /// Bench shell, ahead of the host as they share the UART tier.
+    Shell,
+    Host,
+    Autonomous,
+    /// Holding the position, when nothing else drives.
+    Hold,
+}
+
+impl Source {
+    pub const ALL: [Self; 7] = [
+        Self::Estop,
+        Self::RcFailsafe,
+        Self::RcManual,
+        Self::Shell,
+        Self::Host,
+        Self::Autonomous,
+        Self::Hold,
+    ];
+}
+
+/// Why a command was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MuxError {
+    /// Another source is in control.
+    NotSelected,
+    /// Too soon after the last command of the source.
+    RateLimited,
+}
+
+impl core::fmt::Display for MuxError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for MuxError {}
+
+/// Claim expiries in ms, `u64::MAX` for the held ones.
+#[derive(Debug, Clone, Default)]
+pub struct CommandMux {
+    claims: [Option<u64>; Source::ALL.len()],
+    /// When each source last had a command let through, in ms.
+    last_command: [Option<u64>; Source::ALL.len()],
+    /// 0 for no rate limit.
+    min_interval_ms: [u64; Source::ALL.len()],
+}
+
+impl CommandMux {
+    pub const fn new() -> Self {
+        Self {
+            claims: [None; Source::ALL.len()],
+            last_command: [None; Source::ALL.len()],
+            min_interval_ms: [0; Source::ALL.len()],
+        }
+    }
+
+    /// Lets through at most `max_hz` commands a second from `source`, 0 for
+    /// any number.
+    pub fn set_rate_limit(&mut self, source: Source, max_hz: u32) {
+        self.min_interval_ms[source as usize] = match max_hz {
+            0 => 0,
+            hz => 1000 / hz as u64,
+        };
+    }
+
+    /// Claims control like [`claim`](Self::claim) for a command of
+    /// `source`, and checks it may be applied. A rate limited command still
+    /// renews the claim.
+    pub fn command(&mut self, source: Source, now_ms: u64, lease_ms: u64) -> Result<(), MuxError> {
+        if !self.claim(source, now_ms, lease_ms) {
+            return Err(MuxError::NotSelected);
+        }
+        let last = &mut self.last_command[source as usize];
+        let min_interval = self.min_interval_ms[source as usize];
+        if last.is_some_and(|last| now_ms < last.saturating_add(min_interval)) {
+            return Err(MuxError::RateLimited);
+        }
+        *last = Some(now_ms);
+        Ok(())
+    }
+
+    /// How long since `source` last had a command let through, `None` if
+    /// it never had.
+    pub fn staleness(&self, source: Source, now_ms: u64) -> Option<u64> {
+        self.last_command[source as usize].map(|last| now_ms.saturating_sub(last))
+    }
+
+    /// Claims control for `lease_ms`, returns whether `source` has it. A
+    /// refused claim still counts, so a source keeps asking and takes over
+    /// once the ones above it let go.
+    pub fn claim(&mut self, source: Source, now_ms: u64, lease_ms: u64) -> bool {
+        let claim = &mut self.claims[source as usize];
+        if *claim != Some(u64::MAX) {
+            *claim = Some(now_ms.saturating_add(lease_ms));
+        }
+        self.active(now_ms) == Some(source)
+    }
+
+    /// Extends the claim of `source` by `lease_ms` if it hasn't run out,
+    /// without making one. Returns whether `source` has control.
+    pub fn renew(&mut self, source: Source, now_ms: u64, lease_ms: u64) -> bool {
+        let claim = &mut self.claims[source as usize];
+        if claim.is_some_and(|expiry| now_ms < expiry && expiry != u64::MAX) {
+            *claim = Some(now_ms.saturating_add(lease_ms));
+        }
+        self.active(now_ms) == Some(source)
+    }
+
+    /// Claims control until [`release`](Self::release)d.
+    pub fn hold(&mut self, source: Source) {
+        self.claims[source as usize] = Some(u64::MAX);
+    }
+
+    pub fn release(&mut self, source: Source) {
+        self.claims[source as usize] = None;
+    }
+
+    pub fn active(&self, now_ms: u64) -> Option<Source> {
+        Source::ALL
+            .into_iter()
+            .find(|&source| self.claims[source as usize].is_some_and(|expiry| now_ms < expiry))
+    }
+}
@@ -3,8 +3,14 @@ use embedded_hal_1::{
     pwm::SetDutyCycle,
 };
 // use uom::si::f32::Angle;
+use uom::si::f32::{AngularVelocity, Time};
 
-use crate::iface::{FourWheeledRobot, Motor, MotorPower};
+use crate::{
+    asynch::{AsyncFourWheeledRobot, AsyncMotor},
+    iface::{FourWheeledRobot, Motor, MotorPower, NeutralMode},
+    pid::PidGains,
+    velocity::{StallDetection, VelocityController},
+};
 
 pub struct MyMotor<P, O0, O1> {
     pwm: P,
@@ -12,23 +18,38 @@ pub struct MyMotor<P, O0, O1> {
     dir_1: O1,
     dir_active: PinState,
     dir_passive: PinState,
+    neutral_mode: NeutralMode,
     power: MotorPower,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// Keeps the error of whichever peripheral failed, generic over the HAL
+/// error types.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
 #[non_exhaustive]
-pub enum MyMotorError {
-    Pwm,
-    Dir,
+pub enum MyMotorError<P, D0, D1> {
+    Pwm(P),
+    Dir0(D0),
+    Dir1(D1),
 }
 
-impl core::fmt::Display for MyMotorError {
+impl<P: core::fmt::Debug, D0: core::fmt::Debug, D1: core::fmt::Debug> core::fmt::Display
+    for MyMotorError<P, D0, D1>
+{
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         core::fmt::Debug::fmt(&self, f)
     }
 }
 
-impl core::error::Error for MyMotorError {}
+impl<P: core::fmt::Debug, D0: core::fmt::Debug, D1: core::fmt::Debug> core::error::Error
+    for MyMotorError<P, D0, D1>
+{
+}
+
+type MyMotorErrorOf<P, O0, O1> = MyMotorError<
+    <P as embedded_hal_1::pwm::ErrorType>::Error,
+    <O0 as embedded_hal_1::digital::ErrorType>::Error,
+    <O1 as embedded_hal_1::digital::ErrorType>::Error,
+>;
 
 trait Opposite {
     fn opposite(&self) -> Self;
@@ -43,7 +64,17 @@ impl Opposite for PinState {
     }
 }
 
+/// An L298N channel: EN on the PWM, IN1 and IN2 the direction pins, active
+/// high. EN low lets the motor coast and both inputs high brake it, as
+/// [`MyMotor`] expects.
+pub type L298nMotor<P, I1, I2> = MyMotor<P, I1, I2>;
+
 impl<P, O0, O1> MyMotor<P, O0, O1> {
+    /// An [`L298nMotor`], forward with IN1 high.
+    pub fn l298n(en: P, in1: O0, in2: O1) -> Self {
+        Self::new(en, in1, in2, PinState::High)
+    }
+
     pub fn new(pwm: P, dir_0: O0, dir_1: O1, dir_active: PinState) -> Self {
         Self {
             pwm,
@@ -51,13 +82,133 @@ impl<P, O0, O1> MyMotor<P, O0, O1> {
             dir_1,
             dir_active,
             dir_passive: dir_active.opposite(),
+            neutral_mode: Default::default(),
             power: Default::default(),
         }
     }
+
+    pub fn neutral_mode(&self) -> NeutralMode {
+        self.neutral_mode
+    }
+
+    /// What [`Motor::neutral`] does, [`Motor::brake`] always brakes.
+    pub fn set_neutral_mode(&mut self, mode: NeutralMode) {
+        self.neutral_mode = mode;
+    }
+}
+
+/// Builds a [`MyMotor`] from named parts. Leaving out the PWM or the
+/// direction pins doesn't compile.
+pub struct MyMotorBuilder<P, O0, O1> {
+    pwm: P,
+    dir_0: O0,
+    dir_1: O1,
+    dir_active: PinState,
+    neutral_mode: NeutralMode,
+}
+
+impl MyMotorBuilder<(), (), ()> {
+    /// Direction pins active high and coasting in neutral, unless told
+    /// otherwise.
+    pub fn new() -> Self {
+        Self {
+            pwm: (),
+            dir_0: (),
+            dir_1: (),
+            dir_active: PinState::High,
+            neutral_mode: Default::default(),
+        }
+    }
+}
+
+impl Default for MyMotorBuilder<(), (), ()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P, O0, O1> MyMotorBuilder<P, O0, O1> {
+    /// The enable input of the driver.
+    pub fn pwm<Q>(self, pwm: Q) -> MyMotorBuilder<Q, O0, O1> {
+        MyMotorBuilder {
+            pwm,
+            dir_0: self.dir_0,
+            dir_1: self.dir_1,
+            dir_active: self.dir_active,
+            neutral_mode: self.neutral_mode,
+        }
+    }
+
+    /// The two direction inputs of the driver, `dir_0` active when driving
+    /// forward.
+    pub fn dir_pins<A, B>(self, dir_0: A, dir_1: B) -> MyMotorBuilder<P, A, B> {
+        MyMotorBuilder {
+            pwm: self.pwm,
+            dir_0,
+            dir_1,
+            dir_active: self.dir_active,
+            neutral_mode: self.neutral_mode,
+        }
+    }
+
+    pub fn dir_active(self, dir_active: PinState) -> Self {
+        Self { dir_active, ..self }
+    }
+
+    pub fn neutral_mode(self, neutral_mode: NeutralMode) -> Self {
+        Self {
+            neutral_mode,
+            ..self
+        }
+    }
+}
+
+impl<P: SetDutyCycle, O0: OutputPin, O1: OutputPin> MyMotorBuilder<P, O0, O1> {
+    pub fn build(self) -> MyMotor<P, O0, O1> {
+        let mut motor = MyMotor::new(self.pwm, self.dir_0, self.dir_1, self.dir_active);
+        motor.set_neutral_mode(self.neutral_mode);
+        motor
+    }
+}
+
+impl<P: SetDutyCycle, O0: OutputPin, O1: OutputPin> MyMotor<P, O0, O1> {
+    /// Both inputs low, enable off.
+    fn coast(&mut self) -> Result<(), MyMotorErrorOf<P, O0, O1>> {
+        self.pwm
+            .set_duty_cycle_fully_off()
+            .map_err(MyMotorError::Pwm)?;
+        self.dir_0
+            .set_state(PinState::Low)
+            .map_err(MyMotorError::Dir0)?;
+        self.dir_1
+            .set_state(PinState::Low)
+            .map_err(MyMotorError::Dir1)?;
+
+        self.power = Default::default();
+
+        Ok(())
+    }
+
+    /// Both inputs high, enable on.
+    fn short(&mut self) -> Result<(), MyMotorErrorOf<P, O0, O1>> {
+        self.dir_0
+            .set_state(PinState::High)
+            .map_err(MyMotorError::Dir0)?;
+        self.dir_1
+            .set_state(PinState::High)
+            .map_err(MyMotorError::Dir1)?;
+        self.pwm
+            .set_duty_cycle_fully_on()
+            .map_err(MyMotorError::Pwm)?;
+
+        self.power = Default::default();
+
+        Ok(())
+    }
 }
 
 impl<P: SetDutyCycle, O0: OutputPin, O1: OutputPin> Motor for MyMotor<P, O0, O1> {
-    type Error = MyMotorError;
+    type Error = MyMotorErrorOf<P, O0, O1>;
 
     fn drive(&mut self, power: MotorPower) -> Result<(), Self::Error> {
         let inner_power = power.inner();
@@ -70,28 +221,27 @@ impl<P: SetDutyCycle, O0: OutputPin, O1: OutputPin> Motor for MyMotor<P, O0, O1>
 
         let duty_percent = ((libm::fabsf(inner_power) / MotorPower::MAX) * 100.0) as u8;
 
-        self.dir_0.set_state(dirs.0).map_err(|_| Self::Error::Dir)?;
-        self.dir_1.set_state(dirs.1).map_err(|_| Self::Error::Dir)?;
+        self.dir_0.set_state(dirs.0).map_err(MyMotorError::Dir0)?;
+        self.dir_1.set_state(dirs.1).map_err(MyMotorError::Dir1)?;
         self.pwm
             .set_duty_cycle_percent(duty_percent)
-            .map_err(|_| Self::Error::Pwm)?;
+            .map_err(MyMotorError::Pwm)?;
 
         self.power = power;
 
         Ok(())
     }
     fn neutral(&mut self) -> Result<(), Self::Error> {
-        self.pwm
-            .set_duty_cycle_fully_off()
-            .map_err(|_| Self::Error::Pwm)?;
-        self.dir_0
-            .set_state(self.dir_passive)
-            .map_err(|_| Self::Error::Dir)?;
-        self.dir_1
-            .set_state(self.dir_passive)
-            .map_err(|_| Self::Error::Dir)?;
-
-        Ok(())
+        match self.neutral_mode {
+            NeutralMode::Coast => self.coast(),
+            NeutralMode::Brake => self.short(),
+        }
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.short()
+    }
+    fn power(&self) -> MotorPower {
+        self.power
     }
 }
 
@@ -100,8 +250,11 @@ pub struct MyFourWheelRobot<FL, FR, BL, BR> {
     fr: FR,
     bl: BL,
     br: BR,
+    /// FL-FR-BL-BR.
+    inverted: [bool; 4],
 }
 
+/// A wheel, in FL-FR-BL-BR order.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MyMotorKind {
     Fl,
@@ -127,7 +280,133 @@ impl core::error::Error for MyFourWheelRobotError {}
 
 impl<FL, FR, BL, BR> MyFourWheelRobot<FL, FR, BL, BR> {
     pub fn new(fl: FL, fr: FR, bl: BL, br: BR) -> Self {
-        Self { fl, fr, bl, br }
+        Self {
+            fl,
+            fr,
+            bl,
+            br,
+            inverted: [false; 4],
+        }
+    }
+
+    /// Wheels spinning the wrong way, FL-FR-BL-BR.
+    pub fn inverted(&self) -> [bool; 4] {
+        self.inverted
+    }
+
+    /// Flips the powers of the wheels spinning the wrong way, FL-FR-BL-BR,
+    /// for a motor wired or mounted backwards. The motors and anything
+    /// between them and this, a velocity loop included, keep their own
+    /// direction: an encoder on an inverted wheel must count the same way as
+    /// its motor drives.
+    pub fn set_inverted(&mut self, inverted: [bool; 4]) {
+        self.inverted = inverted;
+    }
+
+    fn apply_inversion(&self, powers: [MotorPower; 4]) -> [MotorPower; 4] {
+        let mut powers = powers;
+        for (power, inverted) in powers.iter_mut().zip(self.inverted) {
+            if inverted {
+                *power = MotorPower::new(-power.inner());
+            }
+        }
+        powers
+    }
+}
+
+/// Builds a [`MyFourWheelRobot`] with the wheels named instead of in
+/// order. Leaving one out doesn't compile.
+pub struct FourWheelRobotBuilder<FL, FR, BL, BR> {
+    fl: FL,
+    fr: FR,
+    bl: BL,
+    br: BR,
+    inverted: [bool; 4],
+}
+
+impl FourWheelRobotBuilder<(), (), (), ()> {
+    pub fn new() -> Self {
+        Self {
+            fl: (),
+            fr: (),
+            bl: (),
+            br: (),
+            inverted: [false; 4],
+        }
+    }
+}
+
+impl Default for FourWheelRobotBuilder<(), (), (), ()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<FL, FR, BL, BR> FourWheelRobotBuilder<FL, FR, BL, BR> {
+    pub fn front_left<M>(self, motor: M) -> FourWheelRobotBuilder<M, FR, BL, BR> {
+        FourWheelRobotBuilder {
+            fl: motor,
+            fr: self.fr,
+            bl: self.bl,
+            br: self.br,
+            inverted: self.inverted,
+        }
+    }
+
+    pub fn front_right<M>(self, motor: M) -> FourWheelRobotBuilder<FL, M, BL, BR> {
+        FourWheelRobotBuilder {
+            fl: self.fl,
+            fr: motor,
+            bl: self.bl,
+            br: self.br,
+            inverted: self.inverted,
+        }
+    }
+
+    pub fn back_left<M>(self, motor: M) -> FourWheelRobotBuilder<FL, FR, M, BR> {
+        FourWheelRobotBuilder {
+            fl: self.fl,
+            fr: self.fr,
+            bl: motor,
+            br: self.br,
+            inverted: self.inverted,
+        }
+    }
+
+    pub fn back_right<M>(self, motor: M) -> FourWheelRobotBuilder<FL, FR, BL, M> {
+        FourWheelRobotBuilder {
+            fl: self.fl,
+            fr: self.fr,
+            bl: self.bl,
+            br: motor,
+            inverted: self.inverted,
+        }
+    }
+
+    /// See [`MyFourWheelRobot::set_inverted`].
+    pub fn inverted(mut self, wheel: MyMotorKind, inverted: bool) -> Self {
+        self.inverted[wheel as usize] = inverted;
+        self
+    }
+}
+
+impl<FL: Motor, FR: Motor, BL: Motor, BR: Motor> FourWheelRobotBuilder<FL, FR, BL, BR> {
+    pub fn build(self) -> MyFourWheelRobot<FL, FR, BL, BR> {
+        let mut robot = MyFourWheelRobot::new(self.fl, self.fr, self.bl, self.br);
+        robot.set_inverted(self.inverted);
+        robot
+    }
+}
+
+impl<FL: Motor, FR: Motor, BL: Motor, BR: Motor> MyFourWheelRobot<FL, FR, BL, BR> {
+    /// Applied motor powers, FL-FR-BL-BR.
+    pub fn powers(&self) -> [MotorPower; 4] {
+        [
+            self.fl.power(),
+            self.fr.power(),
+            self.bl.power(),
+            self.br.power(),
+        ]
     }
 }
 
@@ -143,6 +422,7 @@ impl<FL: Motor, FR: Motor, BL: Motor, BR: Motor> FourWheeledRobot
         bl: MotorPower,
         br: MotorPower,
     ) -> Result<(), Self::Error> {
+        let [fl, fr, bl, br] = self.apply_inversion([fl, fr, bl, br]);
         self.fl
             .drive(fl)
             .map_err(|_| Self::Error::Motor(MyMotorKind::Fl))?;
@@ -167,4 +447,154 @@ impl<FL: Motor, FR: Motor, BL: Motor, BR: Motor> FourWheeledRobot
 
         Ok(())
     }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        use MyMotorKind::*;
+        self.fl.brake().map_err(|_| Self::Error::Motor(Fl))?;
+        self.fr.brake().map_err(|_| Self::Error::Motor(Fr))?;
+        self.bl.brake().map_err(|_| Self::Error::Motor(Bl))?;
+        self.br.brake().map_err(|_| Self::Error::Motor(Br))?;
+
+        Ok(())
+    }
+}
+
+impl<FL: AsyncMotor, FR: AsyncMotor, BL: AsyncMotor, BR: AsyncMotor> AsyncFourWheeledRobot
+    for MyFourWheelRobot<FL, FR, BL, BR>
+{
+    type Error = MyFourWheelRobotError;
+
+    async fn drive(
+        &mut self,
+        fl: MotorPower,
+        fr: MotorPower,
+        bl: MotorPower,
+        br: MotorPower,
+    ) -> Result<(), Self::Error> {
+        use MyMotorKind::*;
+        let [fl, fr, bl, br] = self.apply_inversion([fl, fr, bl, br]);
+        AsyncMotor::drive(&mut self.fl, fl)
+            .await
+            .map_err(|_| Self::Error::Motor(Fl))?;
+        AsyncMotor::drive(&mut self.fr, fr)
+            .await
+            .map_err(|_| Self::Error::Motor(Fr))?;
+        AsyncMotor::drive(&mut self.bl, bl)
+            .await
+            .map_err(|_| Self::Error::Motor(Bl))?;
+        AsyncMotor::drive(&mut self.br, br)
+            .await
+            .map_err(|_| Self::Error::Motor(Br))?;
+
+        Ok(())
+    }
+    async fn neutral(&mut self) -> Result<(), Self::Error> {
+        use MyMotorKind::*;
+        AsyncMotor::neutral(&mut self.fl)
+            .await
+            .map_err(|_| Self::Error::Motor(Fl))?;
+        AsyncMotor::neutral(&mut self.fr)
+            .await
+            .map_err(|_| Self::Error::Motor(Fr))?;
+        AsyncMotor::neutral(&mut self.bl)
+            .await
+            .map_err(|_| Self::Error::Motor(Bl))?;
+        AsyncMotor::neutral(&mut self.br)
+            .await
+            .map_err(|_| Self::Error::Motor(Br))?;
+
+        Ok(())
+    }
+    async fn brake(&mut self) -> Result<(), Self::Error> {
+        use MyMotorKind::*;
+        AsyncMotor::brake(&mut self.fl)
+            .await
+            .map_err(|_| Self::Error::Motor(Fl))?;
+        AsyncMotor::brake(&mut self.fr)
+            .await
+            .map_err(|_| Self::Error::Motor(Fr))?;
+        AsyncMotor::brake(&mut self.bl)
+            .await
+            .map_err(|_| Self::Error::Motor(Bl))?;
+        AsyncMotor::brake(&mut self.br)
+            .await
+            .map_err(|_| Self::Error::Motor(Br))?;
+
+        Ok(())
+    }
+}
+
+impl<FL: Motor, FR: Motor, BL: Motor, BR: Motor>
+    MyFourWheelRobot<
+        VelocityController<FL>,
+        VelocityController<FR>,
+        VelocityController<BL>,
+        VelocityController<BR>,
+    >
+{
+    /// Runs one step of every wheel's velocity loop, `measured` being in
+    /// FL-FR-BL-BR order.
+    pub fn update(
+        &mut self,
+        measured: [AngularVelocity; 4],
+        dt: Time,
+    ) -> Result<(), MyFourWheelRobotError> {
+        use MyMotorKind::*;
+        let [fl, fr, bl, br] = measured;
+        self.fl
+            .update(fl, dt)
+            .map_err(|_| MyFourWheelRobotError::Motor(Fl))?;
+        self.fr
+            .update(fr, dt)
+            .map_err(|_| MyFourWheelRobotError::Motor(Fr))?;
+        self.bl
+            .update(bl, dt)
+            .map_err(|_| MyFourWheelRobotError::Motor(Bl))?;
+        self.br
+            .update(br, dt)
+            .map_err(|_| MyFourWheelRobotError::Motor(Br))?;
+
+        Ok(())
+    }
+
+    /// Wheel speed targets, FL-FR-BL-BR, e.g. from
+    /// [`MecanumGeometry::wheel_speeds`](crate::odometry::MecanumGeometry::wheel_speeds).
+    /// Inverted wheels are flipped like powers are.
+    pub fn drive_speeds(&mut self, speeds: [AngularVelocity; 4]) {
+        let [fl, fr, bl, br] = core::array::from_fn(|i| {
+            if self.inverted[i] {
+                -speeds[i]
+            } else {
+                speeds[i]
+            }
+        });
+        self.fl.drive_speed(fl);
+        self.fr.drive_speed(fr);
+        self.bl.drive_speed(bl);
+        self.br.drive_speed(br);
+    }
+
+    /// Stalled wheels, FL-FR-BL-BR.
+    pub fn stalls(&self) -> [bool; 4] {
+        [
+            self.fl.stalled(),
+            self.fr.stalled(),
+            self.bl.stalled(),
+            self.br.stalled(),
+        ]
+    }
+
+    pub fn set_stall_detection(&mut self, stall_detection: Option<StallDetection>) {
+        self.fl.set_stall_detection(stall_detection);
+        self.fr.set_stall_detection(stall_detection);
+        self.bl.set_stall_detection(stall_detection);
+        self.br.set_stall_detection(stall_detection);
+    }
+
+    pub fn set_gains(&mut self, gains: [PidGains; 4]) {
+        let [fl, fr, bl, br] = gains;
+        self.fl.set_gains(fl);
+        self.fr.set_gains(fr);
+        self.bl.set_gains(bl);
+        self.br.set_gains(br);
+    }
 }
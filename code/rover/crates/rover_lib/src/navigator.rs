@@ -0,0 +1,114 @@
+//! Driving to a pose of the odometry frame: the mecanum base strafes
+//! straight at the point while turning to the heading, both under PID.
+
+use serde::{Deserialize, Serialize};
+use uom::si::{
+    angle::{degree, radian},
+    f32::Length,
+    length::{meter, millimeter},
+};
+
+use crate::{
+    iface::{Angle, MecanumPower, Turn},
+    odometry::{wrap_angle, Pose},
+    pid::{Pid, PidGains},
+};
+
+/// A pose to reach, in the odometry frame.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Waypoint {
+    pub x: Length,
+    pub y: Length,
+    pub heading: Angle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NavParams {
+    /// Power from the distance left, in m.
+    pub position_gains: PidGains,
+    /// Turn from the heading error, in rad.
+    pub heading_gains: PidGains,
+    pub max_power: f32,
+    /// A waypoint is reached within this distance and heading of it.
+    pub tolerance_mm: u16,
+    pub heading_tolerance_deg: f32,
+}
+
+impl NavParams {
+    pub fn is_valid(&self) -> bool {
+        (0.0..=MecanumPower::MAX).contains(&self.max_power)
+            && self.tolerance_mm > 0
+            && (0.0..=90.0).contains(&self.heading_tolerance_deg)
+    }
+}
+
+pub struct Navigator {
+    params: NavParams,
+    position_pid: Pid,
+    heading_pid: Pid,
+}
+
+impl Navigator {
+    pub fn new(params: NavParams) -> Self {
+        Self {
+            params,
+            position_pid: Pid::new(params.position_gains, 0.0, params.max_power),
+            heading_pid: Pid::new(params.heading_gains, Turn::MIN, Turn::MAX),
+        }
+    }
+
+    pub fn set_params(&mut self, params: NavParams) {
+        if params != self.params {
+            *self = Self::new(params);
+        }
+    }
+
+    /// Call when heading for a new waypoint.
+    pub fn reset(&mut self) {
+        self.position_pid.reset();
+        self.heading_pid.reset();
+    }
+
+    pub fn distance(pose: &Pose, target: &Waypoint) -> Length {
+        let dx = (target.x - pose.x).get::<meter>();
+        let dy = (target.y - pose.y).get::<meter>();
+        Length::new::<meter>(libm::hypotf(dx, dy))
+    }
+
+    /// Command towards `target` from `pose`, `dt` in seconds. `None` once
+    /// it's reached.
+    pub fn update(
+        &mut self,
+        pose: &Pose,
+        target: &Waypoint,
+        dt: f32,
+    ) -> Option<(MecanumPower, Angle, Turn)> {
+        let dx = (target.x - pose.x).get::<meter>();
+        let dy = (target.y - pose.y).get::<meter>();
+        let distance = libm::hypotf(dx, dy);
+        let heading = pose.heading.get::<radian>();
+        let heading_error = wrap_angle(target.heading.get::<radian>() - heading);
+
+        let tolerance = Length::new::<millimeter>(self.params.tolerance_mm as f32).get::<meter>();
+        let heading_tolerance =
+            Angle::new::<degree>(self.params.heading_tolerance_deg).get::<radian>();
+        if distance <= tolerance && libm::fabsf(heading_error) <= heading_tolerance {
+            return None;
+        }
+
+        let power = if distance > tolerance {
+            self.position_pid.update(distance, dt)
+        } else {
+            0.0
+        };
+        // The direction of the target in the robot frame, the heading being
+        // counter-clockwise and the turn clockwise
+        let theta = libm::atan2f(dy, dx) - heading;
+        let turn = -self.heading_pid.update(heading_error, dt);
+        Some((
+            MecanumPower::new(power),
+            Angle::new::<radian>(wrap_angle(theta)),
+            Turn::new(turn),
+        ))
+    }
+}
@@ -0,0 +1,270 @@
+//! nRF24L01+ 2.4 GHz transceivers, set up for the [`radio`] link: 250 kbps,
+//! 16 bit CRC, auto acknowledgment with payloads in them, and dynamic
+//! payload lengths, all on pipe 0.
+//!
+//! [`radio`]: crate::radio
+
+use embedded_hal_1::digital::OutputPin;
+use embedded_hal_async::{
+    delay::DelayNs,
+    spi::{Operation, SpiDevice},
+};
+
+/// Longest payload the chip takes.
+pub const MAX_PAYLOAD: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Nrf24Error {
+    Spi,
+    /// Registers don't read back what was written: no chip, or a bad
+    /// wiring.
+    NotFound,
+    PayloadTooLong(usize),
+}
+
+impl core::fmt::Display for Nrf24Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for Nrf24Error {}
+
+const R_REGISTER: u8 = 0x00;
+const W_REGISTER: u8 = 0x20;
+const R_RX_PAYLOAD: u8 = 0x61;
+const W_TX_PAYLOAD: u8 = 0xA0;
+const FLUSH_TX: u8 = 0xE1;
+const FLUSH_RX: u8 = 0xE2;
+const R_RX_PL_WID: u8 = 0x60;
+const W_ACK_PAYLOAD: u8 = 0xA8;
+
+const CONFIG: u8 = 0x00;
+const EN_AA: u8 = 0x01;
+const EN_RXADDR: u8 = 0x02;
+const SETUP_AW: u8 = 0x03;
+const SETUP_RETR: u8 = 0x04;
+const RF_CH: u8 = 0x05;
+const RF_SETUP: u8 = 0x06;
+const STATUS: u8 = 0x07;
+const RX_ADDR_P0: u8 = 0x0A;
+const TX_ADDR: u8 = 0x10;
+const FIFO_STATUS: u8 = 0x17;
+const DYNPD: u8 = 0x1C;
+const FEATURE: u8 = 0x1D;
+
+const CONFIG_EN_CRC: u8 = 0x08;
+const CONFIG_CRCO: u8 = 0x04;
+const CONFIG_PWR_UP: u8 = 0x02;
+const CONFIG_PRIM_RX: u8 = 0x01;
+const STATUS_RX_DR: u8 = 0x40;
+const STATUS_TX_DS: u8 = 0x20;
+const STATUS_MAX_RT: u8 = 0x10;
+const FIFO_RX_EMPTY: u8 = 0x01;
+const FEATURE_EN_DPL: u8 = 0x04;
+const FEATURE_EN_ACK_PAY: u8 = 0x02;
+
+/// 250 kbps for the range, 0 dBm.
+const RF_SETUP_250K: u8 = 0x26;
+/// 5 byte addresses.
+const SETUP_AW_5: u8 = 0x03;
+/// Three retries 750 µs apart, room for an acknowledgment with a payload
+/// at 250 kbps.
+const SETUP_RETR_750US_3: u8 = 0x23;
+
+/// An nRF24L01+ on `spi`, with its CE on `ce`.
+pub struct Nrf24<S, P> {
+    spi: S,
+    ce: P,
+    config: u8,
+}
+
+impl<S: SpiDevice, P: OutputPin> Nrf24<S, P> {
+    pub fn new(spi: S, ce: P) -> Self {
+        Self {
+            spi,
+            ce,
+            config: CONFIG_EN_CRC | CONFIG_CRCO,
+        }
+    }
+
+    /// Sets the chip up, powered down, for [`Self::listen`] or
+    /// [`Self::send`].
+    pub async fn init(&mut self, address: [u8; 5], channel: u8) -> Result<(), Nrf24Error> {
+        self.ce.set_low().map_err(|_| Nrf24Error::Spi)?;
+        self.write_reg(CONFIG, self.config).await?;
+        self.write_reg(SETUP_AW, SETUP_AW_5).await?;
+        if self.read_reg(SETUP_AW).await? != SETUP_AW_5 {
+            return Err(Nrf24Error::NotFound);
+        }
+        self.write_regs(RX_ADDR_P0, &address).await?;
+        self.write_regs(TX_ADDR, &address).await?;
+        self.write_reg(EN_RXADDR, 0x01).await?;
+        self.write_reg(EN_AA, 0x01).await?;
+        self.write_reg(SETUP_RETR, SETUP_RETR_750US_3).await?;
+        self.write_reg(RF_SETUP, RF_SETUP_250K).await?;
+        self.write_reg(FEATURE, FEATURE_EN_DPL | FEATURE_EN_ACK_PAY)
+            .await?;
+        self.write_reg(DYNPD, 0x01).await?;
+        self.write_reg(RF_CH, channel).await?;
+        self.command(FLUSH_RX).await?;
+        self.command(FLUSH_TX).await?;
+        self.write_reg(STATUS, STATUS_RX_DR | STATUS_TX_DS | STATUS_MAX_RT)
+            .await
+    }
+
+    /// Powers up as a receiver, and starts listening.
+    pub async fn listen(&mut self, delay: &mut impl DelayNs) -> Result<(), Nrf24Error> {
+        self.config |= CONFIG_PWR_UP | CONFIG_PRIM_RX;
+        self.write_reg(CONFIG, self.config).await?;
+        // Oscillator start up
+        delay.delay_us(1_500).await;
+        self.ce.set_high().map_err(|_| Nrf24Error::Spi)
+    }
+
+    /// Takes effect at once, listening or not.
+    pub async fn set_channel(
+        &mut self,
+        channel: u8,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Nrf24Error> {
+        let listening = self.config & CONFIG_PRIM_RX != 0;
+        self.ce.set_low().map_err(|_| Nrf24Error::Spi)?;
+        self.write_reg(RF_CH, channel).await?;
+        if listening {
+            self.ce.set_high().map_err(|_| Nrf24Error::Spi)?;
+            // PLL settling
+            delay.delay_us(130).await;
+        }
+        Ok(())
+    }
+
+    /// The oldest payload received, its length in `buf`, if any.
+    pub async fn receive(
+        &mut self,
+        buf: &mut [u8; MAX_PAYLOAD],
+    ) -> Result<Option<usize>, Nrf24Error> {
+        if self.read_reg(FIFO_STATUS).await? & FIFO_RX_EMPTY != 0 {
+            return Ok(None);
+        }
+        let mut width = [R_RX_PL_WID, 0];
+        self.transfer(&mut width).await?;
+        let len = width[1] as usize;
+        if len == 0 || len > MAX_PAYLOAD {
+            // Corrupt, as the datasheet says
+            self.command(FLUSH_RX).await?;
+            return Ok(None);
+        }
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[R_RX_PAYLOAD]),
+                Operation::Read(&mut buf[..len]),
+            ])
+            .await
+            .map_err(|_| Nrf24Error::Spi)?;
+        self.write_reg(STATUS, STATUS_RX_DR).await?;
+        Ok(Some(len))
+    }
+
+    /// Goes back in the acknowledgment of the next packet received, in
+    /// place of any not sent yet.
+    pub async fn set_ack_payload(&mut self, payload: &[u8]) -> Result<(), Nrf24Error> {
+        if payload.len() > MAX_PAYLOAD {
+            return Err(Nrf24Error::PayloadTooLong(payload.len()));
+        }
+        self.command(FLUSH_TX).await?;
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[W_ACK_PAYLOAD]),
+                Operation::Write(payload),
+            ])
+            .await
+            .map_err(|_| Nrf24Error::Spi)
+    }
+
+    /// Sends `payload` as a transmitter, powering up as one first if need
+    /// be. The length of the payload acknowledging it in `ack`, 0 for none,
+    /// or `None` if it went unacknowledged.
+    pub async fn send(
+        &mut self,
+        payload: &[u8],
+        ack: &mut [u8; MAX_PAYLOAD],
+        delay: &mut impl DelayNs,
+    ) -> Result<Option<usize>, Nrf24Error> {
+        if payload.len() > MAX_PAYLOAD {
+            return Err(Nrf24Error::PayloadTooLong(payload.len()));
+        }
+        if self.config & (CONFIG_PWR_UP | CONFIG_PRIM_RX) != CONFIG_PWR_UP {
+            self.ce.set_low().map_err(|_| Nrf24Error::Spi)?;
+            self.config = (self.config | CONFIG_PWR_UP) & !CONFIG_PRIM_RX;
+            self.write_reg(CONFIG, self.config).await?;
+            delay.delay_us(1_500).await;
+        }
+        self.command(FLUSH_TX).await?;
+        self.spi
+            .transaction(&mut [Operation::Write(&[W_TX_PAYLOAD]), Operation::Write(payload)])
+            .await
+            .map_err(|_| Nrf24Error::Spi)?;
+
+        // A pulse of 10 µs at least sends it
+        self.ce.set_high().map_err(|_| Nrf24Error::Spi)?;
+        delay.delay_us(15).await;
+        self.ce.set_low().map_err(|_| Nrf24Error::Spi)?;
+
+        let status = loop {
+            let status = self.status().await?;
+            if status & (STATUS_TX_DS | STATUS_MAX_RT) != 0 {
+                break status;
+            }
+            delay.delay_us(100).await;
+        };
+        self.write_reg(STATUS, STATUS_TX_DS | STATUS_MAX_RT).await?;
+        if status & STATUS_MAX_RT != 0 {
+            self.command(FLUSH_TX).await?;
+            return Ok(None);
+        }
+        Ok(Some(self.receive(ack).await?.unwrap_or(0)))
+    }
+
+    async fn status(&mut self) -> Result<u8, Nrf24Error> {
+        // Clocked out with any command
+        let mut buf = [0xFF];
+        self.transfer(&mut buf).await?;
+        Ok(buf[0])
+    }
+
+    async fn command(&mut self, command: u8) -> Result<(), Nrf24Error> {
+        self.spi
+            .write(&[command])
+            .await
+            .map_err(|_| Nrf24Error::Spi)
+    }
+
+    async fn transfer(&mut self, buf: &mut [u8]) -> Result<(), Nrf24Error> {
+        self.spi
+            .transfer_in_place(buf)
+            .await
+            .map_err(|_| Nrf24Error::Spi)
+    }
+
+    async fn read_reg(&mut self, reg: u8) -> Result<u8, Nrf24Error> {
+        let mut buf = [R_REGISTER | reg, 0];
+        self.transfer(&mut buf).await?;
+        Ok(buf[1])
+    }
+
+    async fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), Nrf24Error> {
+        self.write_regs(reg, &[value]).await
+    }
+
+    async fn write_regs(&mut self, reg: u8, values: &[u8]) -> Result<(), Nrf24Error> {
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[W_REGISTER | reg]),
+                Operation::Write(values),
+            ])
+            .await
+            .map_err(|_| Nrf24Error::Spi)
+    }
+}
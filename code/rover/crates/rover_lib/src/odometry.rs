@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use uom::si::{
+    angle::radian,
+    f32::{Angle, Length},
+    length::meter,
+};
+
+/// Robot pose in the frame the robot started in: x points right, y forward
+/// and heading is counter-clockwise positive, like the drive angle.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Pose {
+    pub x: Length,
+    pub y: Length,
+    pub heading: Angle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MecanumGeometry {
+    pub wheel_radius: Length,
+    /// Half the distance between left and right wheels.
+    pub half_track: Length,
+    /// Half the distance between front and rear axles.
+    pub half_wheelbase: Length,
+}
+
+/// Dead reckoning from the wheel angles, integrated with the forward
+/// kinematics of an X-configured mecanum base.
+pub struct Odometry {
+    geometry: MecanumGeometry,
+    pose: Pose,
+    last: Option<[Angle; 4]>,
+}
+
+impl Odometry {
+    pub fn new(geometry: MecanumGeometry) -> Self {
+        Self {
+            geometry,
+            pose: Default::default(),
+            last: None,
+        }
+    }
+
+    pub fn pose(&self) -> Pose {
+        self.pose
+    }
+
+    pub fn reset(&mut self, pose: Pose) {
+        self.pose = pose;
+    }
+
+    /// `wheels` are the absolute wheel angles in FL-FR-BL-BR order, positive
+    /// when the wheel pushes the robot forward.
+    pub fn update(&mut self, wheels: [Angle; 4]) -> Pose {
+        let Some(last) = self.last.replace(wheels) else {
+            return self.pose;
+        };
+
+        let r = self.geometry.wheel_radius.get::<meter>();
+        let [fl, fr, bl, br] = core::array::from_fn(|i| (wheels[i] - last[i]).get::<radian>() * r);
+
+        let dx = (fl - fr - bl + br) / 4.0;
+        let dy = (fl + fr + bl + br) / 4.0;
+        let k = self.geometry.half_track + self.geometry.half_wheelbase;
+        let dth = (-fl + fr - bl + br) / (4.0 * k.get::<meter>());
+
+        // Midpoint heading is a good enough approximation of the arc
+        let heading = self.pose.heading.get::<radian>() + dth / 2.0;
+        let (sin, cos) = (libm::sinf(heading), libm::cosf(heading));
+
+        self.pose.x += Length::new::<meter>(dx * cos - dy * sin);
+        self.pose.y += Length::new::<meter>(dx * sin + dy * cos);
+        self.pose.heading =
+            Angle::new::<radian>(wrap_angle(self.pose.heading.get::<radian>() + dth));
+
+        self.pose
+    }
+}
+
+/// Wraps an angle in radians to (-pi, pi].
+pub fn wrap_angle(theta: f32) -> f32 {
+    use core::f32::consts::PI;
+
+    let wrapped = libm::remainderf(theta, 2.0 * PI);
+    if wrapped <= -PI {
+        wrapped + 2.0 * PI
+    } else {
+        wrapped
+    }
+}
@@ -0,0 +1,216 @@
+//! PCA9685 16 channel PWM chip on I2C, driving H-bridges for chassis
+//! short of timer channels. A channel held fully on or off makes a direction
+//! line, so four motors take twelve channels of one chip and the MCU only
+//! gives up the bus.
+//!
+//! Each [`Pca9685Motor`] has its own handle on the chip: with more than one
+//! motor, `I` is a device on a shared bus, like embassy-embedded-hal's
+//! `I2cDevice`. Four of them make a [`MyFourWheelRobot`] driven through
+//! [`AsyncMecanum`].
+//!
+//! [`MyFourWheelRobot`]: crate::MyFourWheelRobot
+//! [`AsyncMecanum`]: crate::AsyncMecanum
+
+use embedded_hal_1::digital::PinState;
+use embedded_hal_async::i2c::I2c;
+
+use crate::{
+    asynch::AsyncMotor,
+    iface::{MotorPower, NeutralMode},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Pca9685Error {
+    Bus,
+}
+
+impl core::fmt::Display for Pca9685Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for Pca9685Error {}
+
+pub struct Pca9685<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I> Pca9685<I> {
+    pub const DEFAULT_ADDRESS: u8 = 0x40;
+    pub const CHANNELS: u8 = 16;
+    /// Steps of a PWM period, a duty of this much is fully on.
+    pub const STEPS: u16 = 4096;
+
+    const MODE1: u8 = 0x00;
+    const MODE2: u8 = 0x01;
+    const LED0_ON_L: u8 = 0x06;
+    const PRE_SCALE: u8 = 0xFE;
+
+    const MODE1_AI: u8 = 0x20;
+    const MODE1_SLEEP: u8 = 0x10;
+    const MODE2_OUTDRV: u8 = 0x04;
+    /// Bit 4 of the high byte of `ON` or `OFF`, overriding the count.
+    const FULL: u16 = 0x1000;
+
+    const OSCILLATOR_HZ: f32 = 25_000_000.0;
+
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+}
+
+impl<I: I2c> Pca9685<I> {
+    /// Sets the PWM frequency of every channel and wakes the chip up with
+    /// all of them off, once for the chip whichever handle does it.
+    pub async fn init(&mut self, pwm_hz: u32) -> Result<(), Pca9685Error> {
+        let prescale =
+            libm::roundf(Self::OSCILLATOR_HZ / (Self::STEPS as f32 * pwm_hz as f32)) as i32 - 1;
+        // The prescaler only takes a new value while asleep
+        self.write(&[Self::MODE1, Self::MODE1_SLEEP]).await?;
+        self.write(&[Self::PRE_SCALE, prescale.clamp(3, 255) as u8])
+            .await?;
+        self.write(&[Self::MODE2, Self::MODE2_OUTDRV]).await?;
+        // Outputs stay off until the oscillator settles, some 500 µs, there's
+        // no need to wait for it
+        self.write(&[Self::MODE1, Self::MODE1_AI]).await?;
+        for channel in 0..Self::CHANNELS {
+            self.set_duty(channel, 0).await?;
+        }
+        Ok(())
+    }
+
+    /// `duty` out of [`STEPS`](Self::STEPS), saturating.
+    pub async fn set_duty(&mut self, channel: u8, duty: u16) -> Result<(), Pca9685Error> {
+        let (on, off) = match duty {
+            0 => (0, Self::FULL),
+            duty if duty >= Self::STEPS => (Self::FULL, 0),
+            duty => (0, duty),
+        };
+        let [on_l, on_h] = on.to_le_bytes();
+        let [off_l, off_h] = off.to_le_bytes();
+        self.write(&[Self::LED0_ON_L + 4 * channel, on_l, on_h, off_l, off_h])
+            .await
+    }
+
+    /// A channel as a digital output.
+    pub async fn set_state(&mut self, channel: u8, state: PinState) -> Result<(), Pca9685Error> {
+        let duty = match state {
+            PinState::Low => 0,
+            PinState::High => Self::STEPS,
+        };
+        self.set_duty(channel, duty).await
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), Pca9685Error> {
+        self.i2c
+            .write(self.address, bytes)
+            .await
+            .map_err(|_| Pca9685Error::Bus)
+    }
+}
+
+/// An H-bridge on three channels of a [`Pca9685`], wired like a
+/// [`MyMotor`](crate::MyMotor): enable and two direction inputs.
+pub struct Pca9685Motor<I> {
+    chip: Pca9685<I>,
+    pwm: u8,
+    dir_0: u8,
+    dir_1: u8,
+    dir_active: PinState,
+    neutral_mode: NeutralMode,
+    power: MotorPower,
+}
+
+impl<I> Pca9685Motor<I> {
+    /// `dir_0` is active when driving forward.
+    ///
+    /// # Panics
+    ///
+    /// If a channel is past the chip's sixteen.
+    pub fn new(chip: Pca9685<I>, pwm: u8, dir_0: u8, dir_1: u8, dir_active: PinState) -> Self {
+        assert!([pwm, dir_0, dir_1]
+            .iter()
+            .all(|&channel| channel < Pca9685::<I>::CHANNELS));
+        Self {
+            chip,
+            pwm,
+            dir_0,
+            dir_1,
+            dir_active,
+            neutral_mode: Default::default(),
+            power: Default::default(),
+        }
+    }
+
+    pub fn neutral_mode(&self) -> NeutralMode {
+        self.neutral_mode
+    }
+
+    /// What [`AsyncMotor::neutral`] does, [`AsyncMotor::brake`] always
+    /// brakes.
+    pub fn set_neutral_mode(&mut self, mode: NeutralMode) {
+        self.neutral_mode = mode;
+    }
+}
+
+impl<I: I2c> Pca9685Motor<I> {
+    async fn set(&mut self, dirs: (PinState, PinState), duty: u16) -> Result<(), Pca9685Error> {
+        self.chip.set_state(self.dir_0, dirs.0).await?;
+        self.chip.set_state(self.dir_1, dirs.1).await?;
+        self.chip.set_duty(self.pwm, duty).await
+    }
+
+    /// Both inputs low, enable off.
+    async fn coast(&mut self) -> Result<(), Pca9685Error> {
+        self.chip.set_duty(self.pwm, 0).await?;
+        self.chip.set_state(self.dir_0, PinState::Low).await?;
+        self.chip.set_state(self.dir_1, PinState::Low).await?;
+        self.power = Default::default();
+        Ok(())
+    }
+
+    /// Both inputs high, enable on.
+    async fn short(&mut self) -> Result<(), Pca9685Error> {
+        self.set((PinState::High, PinState::High), Pca9685::<I>::STEPS)
+            .await?;
+        self.power = Default::default();
+        Ok(())
+    }
+}
+
+impl<I: I2c> AsyncMotor for Pca9685Motor<I> {
+    type Error = Pca9685Error;
+
+    async fn drive(&mut self, power: MotorPower) -> Result<(), Self::Error> {
+        let inner_power = power.inner();
+        let passive = match self.dir_active {
+            PinState::High => PinState::Low,
+            PinState::Low => PinState::High,
+        };
+        let dirs = if inner_power >= 0.0 {
+            (self.dir_active, passive)
+        } else {
+            (passive, self.dir_active)
+        };
+        let duty = libm::fabsf(inner_power) / MotorPower::MAX * Pca9685::<I>::STEPS as f32;
+
+        self.set(dirs, duty as u16).await?;
+        self.power = power;
+        Ok(())
+    }
+    async fn neutral(&mut self) -> Result<(), Self::Error> {
+        match self.neutral_mode {
+            NeutralMode::Coast => self.coast().await,
+            NeutralMode::Brake => self.short().await,
+        }
+    }
+    async fn brake(&mut self) -> Result<(), Self::Error> {
+        self.short().await
+    }
+    fn power(&self) -> MotorPower {
+        self.power
+    }
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+
+use crate::fixed::{scalar, to_f32, Scalar};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct PidGains {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+impl PidGains {
+    pub const fn new(kp: f32, ki: f32, kd: f32) -> Self {
+        Self { kp, ki, kd }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pid {
+    gains: PidGains,
+    kp: Scalar,
+    ki: Scalar,
+    kd: Scalar,
+    out_min: Scalar,
+    out_max: Scalar,
+    integral: Scalar,
+    last_error: Option<Scalar>,
+}
+
+impl Pid {
+    pub fn new(gains: PidGains, out_min: f32, out_max: f32) -> Self {
+        Self {
+            gains,
+            kp: scalar(gains.kp),
+            ki: scalar(gains.ki),
+            kd: scalar(gains.kd),
+            out_min: scalar(out_min),
+            out_max: scalar(out_max),
+            integral: Scalar::default(),
+            last_error: None,
+        }
+    }
+
+    pub fn gains(&self) -> PidGains {
+        self.gains
+    }
+
+    pub fn set_gains(&mut self, gains: PidGains) {
+        self.gains = gains;
+        (self.kp, self.ki, self.kd) = (scalar(gains.kp), scalar(gains.ki), scalar(gains.kd));
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = Scalar::default();
+        self.last_error = None;
+    }
+
+    /// `dt` is in seconds. The integral term is clamped to the output range
+    /// so it can't wind up while the output saturates.
+    pub fn update(&mut self, error: f32, dt: f32) -> f32 {
+        if dt <= 0.0 {
+            return 0.0;
+        }
+        let (error, dt) = (scalar(error), scalar(dt));
+
+        self.integral = (self.integral + self.ki * error * dt).clamp(self.out_min, self.out_max);
+        let derivative = self
+            .last_error
+            .map_or(Scalar::default(), |last| (error - last) / dt);
+        self.last_error = Some(error);
+
+        to_f32(
+            (self.kp * error + self.integral + self.kd * derivative)
+                .clamp(self.out_min, self.out_max),
+        )
+    }
+}
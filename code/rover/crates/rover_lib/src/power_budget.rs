@@ -0,0 +1,100 @@
+//! A cap on the sum of the wheel duties, for a battery or regulator too
+//! small for all four motors at full power: past it, every wheel is scaled
+//! down alike so the robot still goes where it was told, only slower.
+
+use crate::iface::{FourWheeledRobot, MotorPower};
+
+/// All four wheels at full power, which disables the budget.
+pub const UNLIMITED: f32 = 4.0 * MotorPower::MAX;
+
+pub struct PowerBudget<R> {
+    robot: R,
+    budget: f32,
+    scale: f32,
+}
+
+impl<R> PowerBudget<R> {
+    /// `budget` is the most the absolute wheel powers may add up to.
+    pub fn new(robot: R, budget: f32) -> Self {
+        Self {
+            robot,
+            budget,
+            scale: 1.0,
+        }
+    }
+
+    pub fn budget(&self) -> f32 {
+        self.budget
+    }
+
+    /// Takes effect at the next drive.
+    pub fn set_budget(&mut self, budget: f32) {
+        self.budget = budget;
+    }
+
+    /// What the last powers driven were scaled by, 1 when within budget.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.robot
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.robot
+    }
+}
+
+impl<R: FourWheeledRobot> FourWheeledRobot for PowerBudget<R> {
+    type Error = R::Error;
+
+    fn drive(
+        &mut self,
+        fl: MotorPower,
+        fr: MotorPower,
+        bl: MotorPower,
+        br: MotorPower,
+    ) -> Result<(), Self::Error> {
+        let powers = [fl, fr, bl, br];
+        let total: f32 = powers.iter().map(|p| libm::fabsf(p.inner())).sum();
+        self.scale = if total > self.budget {
+            self.budget.max(0.0) / total
+        } else {
+            1.0
+        };
+        let [fl, fr, bl, br] = powers.map(|p| p.saturating_scale(self.scale));
+        self.robot.drive(fl, fr, bl, br)
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.scale = 1.0;
+        self.robot.neutral()
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.scale = 1.0;
+        self.robot.brake()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockRobot;
+
+    fn powers(robot: &PowerBudget<MockRobot>) -> [f32; 4] {
+        robot.inner().powers().map(|p| p.inner())
+    }
+
+    #[test]
+    fn power_budget_scales_all_wheels_alike() {
+        let mut robot = PowerBudget::new(MockRobot::new(), 2.0);
+        let p = |power| MotorPower::new(power);
+        robot.drive(p(1.0), p(-0.5), p(0.5), p(0.0)).unwrap();
+        assert_eq!(powers(&robot), [1.0, -0.5, 0.5, 0.0]);
+        assert_eq!(robot.scale(), 1.0);
+
+        robot.drive(p(1.0), p(-1.0), p(1.0), p(-1.0)).unwrap();
+        assert_eq!(powers(&robot), [0.5, -0.5, 0.5, -0.5]);
+        assert_eq!(robot.scale(), 0.5);
+    }
+}
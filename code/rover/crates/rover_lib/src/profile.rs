@@ -0,0 +1,24 @@
+//! Time-parameterized motion: a profile is a list of segments, each holding
+//! one drive command for a while.
+
+use serde::{Deserialize, Serialize};
+
+use crate::iface::{Angle, MecanumPower, Turn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    pub duration_ms: u32,
+    pub power: MecanumPower,
+    /// In the robot frame.
+    pub angle: Angle,
+    pub turn: Turn,
+}
+
+impl Segment {
+    /// A minute, longer is a typo.
+    pub const MAX_DURATION_MS: u32 = 60_000;
+
+    pub fn is_valid(&self) -> bool {
+        self.duration_ms <= Self::MAX_DURATION_MS
+    }
+}
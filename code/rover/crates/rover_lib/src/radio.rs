@@ -0,0 +1,229 @@
+//! The 2.4 GHz radio link between a handheld transmitter and the rover, over
+//! a pair of [`Nrf24`]s: the packets, the channel hopping and the link
+//! quality, for both ends.
+//!
+//! The handheld sends a [`ControlPacket`] every [`PACKET_PERIOD_MS`], each on
+//! the next channel of [`HOPS`], and the rover answers in the acknowledgment
+//! with a [`StatusPacket`], one packet late. The rover follows the hops by
+//! the sequence number, and parks on the first channel once it lost the
+//! handheld, for it to come by again.
+//!
+//! [`Nrf24`]: crate::nrf24::Nrf24
+
+use crate::arming::Mode;
+
+/// Shared by both ends, the nRF24L01+'s 5 byte address, least significant
+/// byte first.
+pub const DEFAULT_ADDRESS: [u8; 5] = *b"rovr0";
+
+pub const PACKET_PERIOD_MS: u64 = 10;
+
+/// Spread across the band, clear of the busiest Wi-Fi channels' centers.
+pub const HOPS: [u8; 16] = [2, 42, 12, 52, 22, 62, 32, 72, 7, 47, 17, 57, 27, 67, 37, 77];
+
+/// Without a packet for as long, the handheld is lost.
+pub const FAILSAFE_MS: u64 = 200;
+
+const CONTROL_KIND: u8 = 0xC1;
+const STATUS_KIND: u8 = 0x5A;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RadioError {
+    BadLength(usize),
+    /// Not one of ours, or a version we don't know.
+    BadKind(u8),
+}
+
+impl core::fmt::Display for RadioError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for RadioError {}
+
+/// The sticks, from the handheld.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ControlPacket {
+    /// Counts up by one every period, sent or not: its hop is
+    /// `seq % HOPS.len()`.
+    pub seq: u8,
+    /// In thousandths, -1000 to 1000, in the channel order of an RC
+    /// transmitter, see [`RcMapping`].
+    ///
+    /// [`RcMapping`]: crate::rc::RcMapping
+    pub axes: [i16; 4],
+}
+
+impl ControlPacket {
+    pub const LEN: usize = 10;
+
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let mut out = [0; Self::LEN];
+        out[0] = CONTROL_KIND;
+        out[1] = self.seq;
+        for (chunk, axis) in out[2..].chunks_mut(2).zip(self.axes) {
+            chunk.copy_from_slice(&axis.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn decode(packet: &[u8]) -> Result<Self, RadioError> {
+        let packet: &[u8; Self::LEN] = packet
+            .try_into()
+            .map_err(|_| RadioError::BadLength(packet.len()))?;
+        if packet[0] != CONTROL_KIND {
+            return Err(RadioError::BadKind(packet[0]));
+        }
+        Ok(Self {
+            seq: packet[1],
+            axes: core::array::from_fn(|i| {
+                i16::from_le_bytes([packet[2 + 2 * i], packet[3 + 2 * i]])
+            }),
+        })
+    }
+
+    /// The hop this packet goes out on.
+    pub fn channel(&self) -> u8 {
+        HOPS[self.seq as usize % HOPS.len()]
+    }
+
+    /// Normalized to -1..=1, as [`RcMapping::command`] takes them.
+    ///
+    /// [`RcMapping::command`]: crate::rc::RcMapping::command
+    pub fn normalized(&self) -> [f32; 4] {
+        self.axes
+            .map(|axis| (axis as f32 / 1000.0).clamp(-1.0, 1.0))
+    }
+}
+
+/// The rover, back to the handheld.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StatusPacket {
+    /// Of the last [`ControlPacket`] the rover got.
+    pub seq: u8,
+    /// Of the handheld's packets, in percent, see [`RadioLink::quality`].
+    pub quality: u8,
+    pub mode: Mode,
+    /// In mV, 0 unknown.
+    pub battery_mv: u16,
+}
+
+impl StatusPacket {
+    pub const LEN: usize = 6;
+
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let battery = self.battery_mv.to_le_bytes();
+        [
+            STATUS_KIND,
+            self.seq,
+            self.quality,
+            self.mode as u8,
+            battery[0],
+            battery[1],
+        ]
+    }
+
+    pub fn decode(packet: &[u8]) -> Result<Self, RadioError> {
+        let packet: &[u8; Self::LEN] = packet
+            .try_into()
+            .map_err(|_| RadioError::BadLength(packet.len()))?;
+        if packet[0] != STATUS_KIND {
+            return Err(RadioError::BadKind(packet[0]));
+        }
+        let mode = match packet[3] {
+            1 => Mode::Armed,
+            2 => Mode::Driving,
+            3 => Mode::Fault,
+            4 => Mode::Estop,
+            5 => Mode::Failsafe,
+            _ => Mode::Disarmed,
+        };
+        Ok(Self {
+            seq: packet[1],
+            quality: packet[2],
+            mode,
+            battery_mv: u16::from_le_bytes([packet[4], packet[5]]),
+        })
+    }
+}
+
+/// The rover's end of the link: which channel to listen on, and how well
+/// packets get through.
+#[derive(Debug, Clone)]
+pub struct RadioLink {
+    /// Into [`HOPS`].
+    hop: usize,
+    /// When the packet of this hop is late, `None` while parked.
+    deadline_ms: Option<u64>,
+    last_packet_ms: Option<u64>,
+    /// One bit per period gone by, the latest lowest, set if its packet
+    /// came.
+    history: u32,
+}
+
+impl RadioLink {
+    pub const fn new() -> Self {
+        Self {
+            hop: 0,
+            deadline_ms: None,
+            last_packet_ms: None,
+            history: 0,
+        }
+    }
+
+    /// The channel to listen on.
+    pub fn channel(&self) -> u8 {
+        HOPS[self.hop]
+    }
+
+    /// A packet came, on [`Self::channel`]. Hops to the next channel.
+    pub fn received(&mut self, packet: &ControlPacket, now_ms: u64) {
+        self.history = self.history << 1 | 1;
+        self.last_packet_ms = Some(now_ms);
+        self.hop = (packet.seq as usize + 1) % HOPS.len();
+        // Half a period of slack either way
+        self.deadline_ms = Some(now_ms + PACKET_PERIOD_MS * 3 / 2);
+    }
+
+    /// Moves on to the next hop when its packet is late, or parks once the
+    /// handheld is lost. True if the channel changed.
+    pub fn poll(&mut self, now_ms: u64) -> bool {
+        let Some(deadline) = self.deadline_ms else {
+            return false;
+        };
+        if now_ms < deadline {
+            return false;
+        }
+        // A period gone by without its packet
+        self.history <<= 1;
+        // Parked, still counting periods for the quality to run down
+        self.deadline_ms = (self.history != 0).then_some(deadline + PACKET_PERIOD_MS);
+
+        let hop = self.hop;
+        self.hop = if self.is_lost(now_ms) {
+            0
+        } else {
+            (self.hop + 1) % HOPS.len()
+        };
+        self.hop != hop
+    }
+
+    /// No packet for [`FAILSAFE_MS`], or none yet.
+    pub fn is_lost(&self, now_ms: u64) -> bool {
+        self.last_packet_ms
+            .is_none_or(|last| now_ms.saturating_sub(last) > FAILSAFE_MS)
+    }
+
+    /// Of the last 32 periods, the share whose packet came, in percent.
+    pub fn quality(&self) -> u8 {
+        (self.history.count_ones() * 100 / u32::BITS) as u8
+    }
+}
+
+impl Default for RadioLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,62 @@
+//! Drive commands from stick axes: the channels of an RC receiver, whatever
+//! link they came over, or a gamepad on the host.
+
+use uom::si::{angle::radian, f32::Angle};
+
+use crate::{
+    iface::{MecanumPower, Turn},
+    input_shaping::shape,
+};
+
+/// Servo pulse width of the stick center, and its throw either way.
+const PULSE_MID_US: f32 = 1500.0;
+const PULSE_HALF_TRAVEL_US: f32 = 500.0;
+
+/// Normalizes a servo pulse to -1..=1, 1000 to 2000 µs end to end.
+pub fn pulse_to_axis(width_us: u16) -> f32 {
+    ((width_us as f32 - PULSE_MID_US) / PULSE_HALF_TRAVEL_US).clamp(-1.0, 1.0)
+}
+
+/// Strafe and forward become the power and angle of a mecanum drive
+/// command, `rot` its turn.
+pub fn mix(x: f32, y: f32, rot: f32) -> (MecanumPower, Angle, Turn) {
+    (
+        MecanumPower::new(libm::hypotf(x, y)),
+        Angle::new::<radian>(libm::atan2f(y, x)),
+        Turn::new(rot),
+    )
+}
+
+/// Which channels drive the rover, indices from 0, see [`mix`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RcMapping {
+    pub strafe: usize,
+    pub forward: usize,
+    pub turn: usize,
+    /// Around the stick center, in normalized units, read as zero.
+    pub deadband: f32,
+}
+
+impl RcMapping {
+    /// Mode 2 AETR: right stick translates, left stick sideways turns.
+    pub const AETR: Self = Self {
+        strafe: 0,
+        forward: 1,
+        turn: 3,
+        deadband: 0.05,
+    };
+
+    /// `axes` are the channels normalized to -1..=1, missing ones read as
+    /// centered.
+    pub fn command(&self, axes: &[f32]) -> (MecanumPower, Angle, Turn) {
+        let axis =
+            |index: usize| shape(axes.get(index).copied().unwrap_or(0.0), self.deadband, 0.0);
+        mix(axis(self.strafe), axis(self.forward), axis(self.turn))
+    }
+}
+
+impl Default for RcMapping {
+    fn default() -> Self {
+        Self::AETR
+    }
+}
@@ -0,0 +1,183 @@
+//! Futaba SBUS, as output by most hobby RC receivers: 100000 baud 8E2,
+//! inverted, a 25 byte frame every 7 or 14 ms carrying 16 proportional
+//! channels of 11 bits and two digital ones.
+//!
+//! Frames are told apart by the gap between them, not by their content, so
+//! [`SbusFrame::decode`] expects exactly one.
+
+pub const FRAME_LEN: usize = 25;
+const HEADER: u8 = 0x0F;
+
+/// Channel value range as sent by FrSky and Futaba receivers at 100% travel.
+pub const CHANNEL_MIN: u16 = 172;
+pub const CHANNEL_MID: u16 = 992;
+pub const CHANNEL_MAX: u16 = 1811;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    BadLength(usize),
+    BadHeader,
+    BadFooter,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for Error {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SbusFrame {
+    pub channels: [u16; 16],
+    pub ch17: bool,
+    pub ch18: bool,
+    /// This frame didn't make it from the transmitter, the receiver repeated
+    /// the last good one.
+    pub frame_lost: bool,
+    /// The receiver lost the transmitter for good, the channels hold its
+    /// failsafe values.
+    pub failsafe: bool,
+}
+
+impl SbusFrame {
+    pub fn decode(frame: &[u8]) -> Result<Self, Error> {
+        let frame: &[u8; FRAME_LEN] = frame
+            .try_into()
+            .map_err(|_| Error::BadLength(frame.len()))?;
+        if frame[0] != HEADER {
+            return Err(Error::BadHeader);
+        }
+        // SBUS2 receivers cycle the footer through telemetry slots
+        let footer = frame[24];
+        if footer != 0x00 && footer & 0x0F != 0x04 {
+            return Err(Error::BadFooter);
+        }
+
+        // Channels are packed LSB first, 11 bits each
+        let data = &frame[1..23];
+        let mut channels = [0; 16];
+        for (i, channel) in channels.iter_mut().enumerate() {
+            let bit = i * 11;
+            let byte = bit / 8;
+            let mut raw = data[byte] as u32 | (data[byte + 1] as u32) << 8;
+            if let Some(&third) = data.get(byte + 2) {
+                raw |= (third as u32) << 16;
+            }
+            *channel = (raw >> (bit % 8)) as u16 & 0x07FF;
+        }
+
+        let flags = frame[23];
+        Ok(Self {
+            channels,
+            ch17: flags & 0x01 != 0,
+            ch18: flags & 0x02 != 0,
+            frame_lost: flags & 0x04 != 0,
+            failsafe: flags & 0x08 != 0,
+        })
+    }
+
+    /// Channel `index` (from 0) in -1..=1, zero at the stick center.
+    pub fn normalized(&self, index: usize) -> f32 {
+        let value = self.channels[index] as f32 - CHANNEL_MID as f32;
+        let half_travel = (CHANNEL_MAX - CHANNEL_MIN) as f32 / 2.0;
+        (value / half_travel).clamp(-1.0, 1.0)
+    }
+
+    /// Every channel [`normalized`](Self::normalized), for
+    /// [`RcMapping`](crate::rc::RcMapping).
+    pub fn axes(&self) -> [f32; 16] {
+        core::array::from_fn(|index| self.normalized(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Channels cycling through min, mid and max from channel 1, no flags.
+    const FRAME: [u8; FRAME_LEN] = [
+        0x0F, 0xAC, 0x00, 0xDF, 0xC4, 0x59, 0x01, 0xBE, 0x89, 0xB3, 0x02, 0x7C, 0x13, 0x67, 0x05,
+        0xF8, 0x26, 0xCE, 0x0A, 0xF0, 0x4D, 0x9C, 0x15, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn unpacks_every_channel() {
+        let frame = SbusFrame::decode(&FRAME).unwrap();
+        let expected: [u16; 16] =
+            core::array::from_fn(|i| [CHANNEL_MIN, CHANNEL_MID, CHANNEL_MAX][i % 3]);
+        assert_eq!(frame.channels, expected);
+        assert!(!frame.ch17 && !frame.ch18 && !frame.frame_lost && !frame.failsafe);
+
+        // The travel has no exact middle, so the ends land a hair off
+        for (axis, expected) in frame.axes().iter().zip([-1.0, 0.0, 1.0]) {
+            assert!((axis - expected).abs() < 2e-3, "{axis} != {expected}");
+        }
+    }
+
+    #[test]
+    fn channels_take_all_eleven_bits() {
+        let mut bytes = [0xFF; FRAME_LEN];
+        bytes[0] = HEADER;
+        bytes[23] = 0;
+        bytes[24] = 0;
+        let frame = SbusFrame::decode(&bytes).unwrap();
+        assert_eq!(frame.channels, [0x07FF; 16]);
+        // Past the travel, clamped
+        assert_eq!(frame.normalized(15), 1.0);
+    }
+
+    #[test]
+    fn reads_the_flags() {
+        for (flags, expected) in [
+            (0x01, [true, false, false, false]),
+            (0x02, [false, true, false, false]),
+            (0x04, [false, false, true, false]),
+            (0x08, [false, false, false, true]),
+            (0x0C, [false, false, true, true]),
+        ] {
+            let mut bytes = FRAME;
+            bytes[23] = flags;
+            let frame = SbusFrame::decode(&bytes).unwrap();
+            assert_eq!(
+                [frame.ch17, frame.ch18, frame.frame_lost, frame.failsafe],
+                expected,
+                "{flags:#04x}"
+            );
+            // The flags don't spill into the channels
+            assert_eq!(frame.channels[15], CHANNEL_MIN);
+        }
+    }
+
+    #[test]
+    fn bad_header_is_refused() {
+        let mut bytes = FRAME;
+        bytes[0] = 0x0E;
+        assert_eq!(SbusFrame::decode(&bytes), Err(Error::BadHeader));
+    }
+
+    #[test]
+    fn footers_of_sbus_and_sbus2_are_taken() {
+        for footer in [0x00, 0x04, 0x14, 0x24, 0x34] {
+            let mut bytes = FRAME;
+            bytes[24] = footer;
+            assert!(SbusFrame::decode(&bytes).is_ok(), "{footer:#04x}");
+        }
+        for footer in [0x01, 0x08, 0x0F, 0xFF] {
+            let mut bytes = FRAME;
+            bytes[24] = footer;
+            assert_eq!(SbusFrame::decode(&bytes), Err(Error::BadFooter));
+        }
+    }
+
+    #[test]
+    fn only_whole_frames_are_taken() {
+        assert_eq!(SbusFrame::decode(&FRAME[..24]), Err(Error::BadLength(24)));
+        let mut long = [0; FRAME_LEN + 1];
+        long[..FRAME_LEN].copy_from_slice(&FRAME);
+        assert_eq!(SbusFrame::decode(&long), Err(Error::BadLength(26)));
+        assert_eq!(SbusFrame::decode(&[]), Err(Error::BadLength(0)));
+    }
+}
@@ -0,0 +1,176 @@
+//! Messages held until a set time, for several robots sharing a clock with
+//! the host, see [`crate::timesync`], to carry out their commands together.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScheduleError {
+    Full,
+}
+
+impl core::fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for ScheduleError {}
+
+/// Up to `N` items, each due at a time in ms, handed back in that order,
+/// those due together in the order they came.
+pub struct Scheduler<T, const N: usize> {
+    slots: [Option<(u64, u32, T)>; N],
+    /// Orders the items due together.
+    next_seq: u32,
+}
+
+impl<T, const N: usize> Scheduler<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { None }; N],
+            next_seq: 0,
+        }
+    }
+
+    pub fn push(&mut self, at_ms: u64, item: T) -> Result<(), ScheduleError> {
+        let slot = self
+            .slots
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(ScheduleError::Full)?;
+        *slot = Some((at_ms, self.next_seq, item));
+        self.next_seq = self.next_seq.wrapping_add(1);
+        Ok(())
+    }
+
+    /// When the next item is due.
+    pub fn next_ms(&self) -> Option<u64> {
+        self.slots.iter().flatten().map(|&(at_ms, ..)| at_ms).min()
+    }
+
+    /// The next item, if due by `now_ms`.
+    pub fn pop_due(&mut self, now_ms: u64) -> Option<T> {
+        let slot = self
+            .slots
+            .iter_mut()
+            .filter(|slot| slot.as_ref().is_some_and(|&(at_ms, ..)| at_ms <= now_ms))
+            .min_by_key(|slot| slot.as_ref().map(|&(at_ms, seq, _)| (at_ms, seq)))?;
+        slot.take().map(|(.., item)| item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| *slot = None);
+    }
+}
+
+impl<T, const N: usize> Default for Scheduler<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Everything due by `now_ms`, in the order handed back.
+    fn drain<const N: usize>(scheduler: &mut Scheduler<char, N>, now_ms: u64) -> [Option<char>; 8] {
+        let mut items = [None; 8];
+        for item in &mut items {
+            *item = scheduler.pop_due(now_ms);
+        }
+        items
+    }
+
+    #[test]
+    fn items_come_back_by_time() {
+        let mut scheduler = Scheduler::<char, 4>::new();
+        scheduler.push(300, 'c').unwrap();
+        scheduler.push(100, 'a').unwrap();
+        scheduler.push(200, 'b').unwrap();
+        assert_eq!(scheduler.next_ms(), Some(100));
+
+        assert_eq!(scheduler.pop_due(99), None);
+        assert_eq!(scheduler.pop_due(100), Some('a'));
+        assert_eq!(scheduler.next_ms(), Some(200));
+        assert_eq!(
+            drain(&mut scheduler, 1_000)[..3],
+            [Some('b'), Some('c'), None]
+        );
+        assert_eq!(scheduler.next_ms(), None);
+    }
+
+    #[test]
+    fn items_due_together_keep_their_order() {
+        let mut scheduler = Scheduler::<char, 4>::new();
+        for item in ['a', 'b', 'c', 'd'] {
+            scheduler.push(100, item).unwrap();
+        }
+        assert_eq!(
+            drain(&mut scheduler, 100)[..5],
+            [Some('a'), Some('b'), Some('c'), Some('d'), None]
+        );
+
+        // Slots freed out of order don't reorder the next ones
+        scheduler.push(200, 'x').unwrap();
+        scheduler.push(100, 'y').unwrap();
+        scheduler.pop_due(100);
+        scheduler.push(200, 'z').unwrap();
+        assert_eq!(
+            drain(&mut scheduler, 200)[..3],
+            [Some('x'), Some('z'), None]
+        );
+    }
+
+    #[test]
+    fn items_already_due_come_back_at_once() {
+        let mut scheduler = Scheduler::<char, 4>::new();
+        scheduler.push(5_000, 'z').unwrap();
+        scheduler.push(0, 'a').unwrap();
+        scheduler.push(900, 'b').unwrap();
+        assert_eq!(scheduler.next_ms(), Some(0));
+        assert_eq!(
+            drain(&mut scheduler, 1_000)[..3],
+            [Some('a'), Some('b'), None]
+        );
+        assert_eq!(scheduler.len(), 1);
+    }
+
+    #[test]
+    fn a_full_schedule_refuses_more() {
+        let mut scheduler = Scheduler::<char, 2>::new();
+        scheduler.push(100, 'a').unwrap();
+        scheduler.push(200, 'b').unwrap();
+        assert_eq!(scheduler.push(50, 'c'), Err(ScheduleError::Full));
+        assert_eq!(scheduler.len(), 2);
+        // The refused item isn't there
+        assert_eq!(scheduler.next_ms(), Some(100));
+
+        scheduler.pop_due(100);
+        scheduler.push(50, 'c').unwrap();
+        assert_eq!(
+            drain(&mut scheduler, 200)[..3],
+            [Some('c'), Some('b'), None]
+        );
+    }
+
+    #[test]
+    fn clear_drops_everything() {
+        let mut scheduler = Scheduler::<char, 2>::new();
+        scheduler.push(100, 'a').unwrap();
+        scheduler.push(200, 'b').unwrap();
+        scheduler.clear();
+        assert!(scheduler.is_empty());
+        assert_eq!(scheduler.next_ms(), None);
+        assert_eq!(scheduler.pop_due(u64::MAX), None);
+        scheduler.push(100, 'c').unwrap();
+        assert_eq!(scheduler.len(), 1);
+    }
+}
@@ -0,0 +1,107 @@
+//! Hobby servos, for a camera pan/tilt or a gripper, on a PWM running at
+//! the servo frame rate.
+
+use embedded_hal_1::pwm::SetDutyCycle;
+use serde::{Deserialize, Serialize};
+use uom::si::{angle::degree, f32::Angle};
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+#[non_exhaustive]
+pub enum ServoError<E> {
+    Pwm(E),
+    /// Past the travel of the [`ServoCalibration`].
+    OutOfRange,
+}
+
+impl<E: core::fmt::Debug> core::fmt::Display for ServoError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl<E: core::fmt::Debug> core::error::Error for ServoError<E> {}
+
+/// Pulse widths at both ends of the travel, the angle being zero halfway.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ServoCalibration {
+    /// At `-travel / 2`.
+    pub min_pulse_us: u16,
+    /// At `travel / 2`.
+    pub max_pulse_us: u16,
+    pub travel: Angle,
+}
+
+impl Default for ServoCalibration {
+    /// The nominal 1 to 2 ms over 90° of most hobby servos, which go
+    /// further but not all alike.
+    fn default() -> Self {
+        Self {
+            min_pulse_us: 1_000,
+            max_pulse_us: 2_000,
+            travel: Angle::new::<degree>(90.0),
+        }
+    }
+}
+
+pub struct Servo<P> {
+    pwm: P,
+    period_us: u32,
+    calibration: ServoCalibration,
+    angle: Option<Angle>,
+}
+
+impl<P: SetDutyCycle> Servo<P> {
+    /// Limp until told an angle. `period_us` is the period the PWM runs at,
+    /// 20 000 for 50 Hz.
+    pub fn new(pwm: P, period_us: u32, calibration: ServoCalibration) -> Self {
+        Self {
+            pwm,
+            period_us,
+            calibration,
+            angle: None,
+        }
+    }
+
+    pub fn calibration(&self) -> ServoCalibration {
+        self.calibration
+    }
+
+    /// Takes effect at the next [`set_angle`](Self::set_angle).
+    pub fn set_calibration(&mut self, calibration: ServoCalibration) {
+        self.calibration = calibration;
+    }
+
+    /// Last angle set, `None` while limp.
+    pub fn angle(&self) -> Option<Angle> {
+        self.angle
+    }
+
+    pub fn set_angle(&mut self, angle: Angle) -> Result<(), ServoError<P::Error>> {
+        let ServoCalibration {
+            min_pulse_us,
+            max_pulse_us,
+            travel,
+        } = self.calibration;
+        // From 0 at one end to 1 at the other
+        let position = angle.get::<degree>() / travel.get::<degree>() + 0.5;
+        if !(0.0..=1.0).contains(&position) {
+            return Err(ServoError::OutOfRange);
+        }
+        let pulse_us = min_pulse_us as f32 + position * (max_pulse_us as f32 - min_pulse_us as f32);
+        let duty = pulse_us / self.period_us as f32 * self.pwm.max_duty_cycle() as f32;
+        self.pwm
+            .set_duty_cycle(duty as u16)
+            .map_err(ServoError::Pwm)?;
+        self.angle = Some(angle);
+        Ok(())
+    }
+
+    /// Stops the pulses, most servos then stop holding their position.
+    pub fn release(&mut self) -> Result<(), ServoError<P::Error>> {
+        self.pwm
+            .set_duty_cycle_fully_off()
+            .map_err(ServoError::Pwm)?;
+        self.angle = None;
+        Ok(())
+    }
+}
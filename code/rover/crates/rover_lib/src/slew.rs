@@ -0,0 +1,182 @@
+use uom::si::{f32::Time, time::second};
+
+use crate::{
+    fixed::{scalar, to_f32, Scalar},
+    iface::{FourWheeledRobot, MotorPower},
+};
+
+/// Ramps wheel powers towards the commanded ones at a bounded rate, so a
+/// full power reversal (whether it comes from power, angle or turn) takes
+/// `2 / rate` seconds instead of happening instantly.
+///
+/// `drive()` only records the target: [`SlewLimiter::update`] must be called
+/// periodically to actually move the wheels. `neutral()` is never delayed.
+pub struct SlewLimiter<R> {
+    robot: R,
+    /// Maximum change in [`MotorPower`] per second, non positive disables the
+    /// limiting.
+    rate: f32,
+    /// Used instead of `rate` while soft starting, when lower.
+    soft_start_rate: f32,
+    soft_starting: bool,
+    /// Set by [`SlewLimiter::ramp_down`], used instead of the other rates.
+    ramp_down_rate: Option<f32>,
+    /// Of the commands, into `target`.
+    scale: f32,
+    command: [Scalar; 4],
+    target: [Scalar; 4],
+    current: [Scalar; 4],
+    active: bool,
+}
+
+impl<R> SlewLimiter<R> {
+    pub fn new(robot: R, rate: f32) -> Self {
+        Self {
+            robot,
+            rate,
+            soft_start_rate: 0.0,
+            soft_starting: false,
+            ramp_down_rate: None,
+            scale: 1.0,
+            command: [Scalar::default(); 4],
+            target: [Scalar::default(); 4],
+            current: [Scalar::default(); 4],
+            active: false,
+        }
+    }
+
+    pub fn rate(&self) -> f32 {
+        self.rate
+    }
+
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    /// Non positive disables soft starts.
+    pub fn set_soft_start_rate(&mut self, rate: f32) {
+        self.soft_start_rate = rate;
+    }
+
+    /// Ramps the next commands at the soft start rate until the wheels reach
+    /// their target, so the robot doesn't lurch when commands come back after
+    /// an unplanned stop.
+    pub fn soft_start(&mut self) {
+        self.soft_starting = true;
+    }
+
+    /// Brings the wheels to a stop over `duration`, from whatever power they
+    /// are at, ignoring commands until [`SlewLimiter::resume`], `neutral()`
+    /// or `brake()`.
+    pub fn ramp_down(&mut self, duration: Time) {
+        let fastest = self
+            .current
+            .iter()
+            .map(|p| to_f32(*p).abs())
+            .fold(0.0, f32::max);
+        self.target = [Scalar::default(); 4];
+        self.ramp_down_rate = Some(fastest / duration.get::<second>());
+    }
+
+    /// Takes commands again after [`SlewLimiter::ramp_down`].
+    pub fn resume(&mut self) {
+        self.ramp_down_rate = None;
+    }
+
+    /// Scales the commands, the last one included, the wheels ramping to
+    /// the new powers.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+        if self.ramp_down_rate.is_none() {
+            self.target = self.command.map(|p| p * scalar(scale));
+        }
+    }
+
+    fn effective_rate(&self) -> f32 {
+        if let Some(rate) = self.ramp_down_rate {
+            return rate;
+        }
+        let soft = self.soft_starting && self.soft_start_rate > 0.0;
+        if soft && (self.rate <= 0.0 || self.soft_start_rate < self.rate) {
+            self.soft_start_rate
+        } else {
+            self.rate
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.robot
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.robot
+    }
+
+    /// Whether the wheels have reached the commanded powers.
+    pub fn settled(&self) -> bool {
+        self.current == self.target
+    }
+}
+
+impl<R: FourWheeledRobot> SlewLimiter<R> {
+    pub fn update(&mut self, dt: Time) -> Result<(), R::Error> {
+        if !self.active || self.settled() {
+            return Ok(());
+        }
+
+        let rate = self.effective_rate();
+        let max_step = scalar(rate * dt.get::<second>());
+        for (current, target) in self.current.iter_mut().zip(self.target) {
+            *current = if rate <= 0.0 {
+                target
+            } else {
+                *current + (target - *current).clamp(-max_step, max_step)
+            };
+        }
+        if self.settled() {
+            self.soft_starting = false;
+        }
+
+        let [fl, fr, bl, br] = self.current.map(|p| MotorPower::new(to_f32(p)));
+        self.robot.drive(fl, fr, bl, br)
+    }
+}
+
+impl<R: FourWheeledRobot> FourWheeledRobot for SlewLimiter<R> {
+    type Error = R::Error;
+
+    fn drive(
+        &mut self,
+        fl: MotorPower,
+        fr: MotorPower,
+        bl: MotorPower,
+        br: MotorPower,
+    ) -> Result<(), Self::Error> {
+        if self.ramp_down_rate.is_some() {
+            return Ok(());
+        }
+        self.command = [fl, fr, bl, br].map(|p| scalar(p.inner()));
+        self.target = self.command.map(|p| p * scalar(self.scale));
+        self.active = true;
+
+        Ok(())
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.command = [Scalar::default(); 4];
+        self.target = [Scalar::default(); 4];
+        self.current = [Scalar::default(); 4];
+        self.active = false;
+        self.ramp_down_rate = None;
+
+        self.robot.neutral()
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.command = [Scalar::default(); 4];
+        self.target = [Scalar::default(); 4];
+        self.current = [Scalar::default(); 4];
+        self.active = false;
+        self.ramp_down_rate = None;
+
+        self.robot.brake()
+    }
+}
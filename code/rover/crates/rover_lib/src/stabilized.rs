@@ -0,0 +1,113 @@
+use uom::si::{
+    angle::radian,
+    f32::{Angle, Time},
+    time::second,
+};
+
+use crate::{
+    iface::{DriveBase, MecanumPower, Turn},
+    odometry::wrap_angle,
+    pid::{Pid, PidGains},
+};
+
+/// Heading hold on top of any [`DriveBase`].
+///
+/// While the commanded turn is zero the heading at that moment is latched
+/// and yaw drift is corrected with a PID on the heading error; any non zero
+/// turn is passed through and releases the latch. The measured heading is
+/// fed through [`StabilizedRobot::update_heading`].
+pub struct StabilizedRobot<R> {
+    robot: R,
+    pid: Pid,
+    heading: Option<f32>,
+    target: Option<f32>,
+    command: Option<(MecanumPower, Angle)>,
+    correction: f32,
+}
+
+impl<R> StabilizedRobot<R> {
+    pub fn new(robot: R, gains: PidGains) -> Self {
+        Self {
+            robot,
+            pid: Pid::new(gains, Turn::MIN, Turn::MAX),
+            heading: None,
+            target: None,
+            command: None,
+            correction: 0.0,
+        }
+    }
+
+    pub fn gains(&self) -> PidGains {
+        self.pid.gains()
+    }
+
+    pub fn set_gains(&mut self, gains: PidGains) {
+        self.pid.set_gains(gains);
+    }
+
+    /// Heading being held, if any.
+    pub fn target(&self) -> Option<Angle> {
+        self.target.map(Angle::new::<radian>)
+    }
+
+    fn release(&mut self) {
+        self.target = None;
+        self.command = None;
+        self.correction = 0.0;
+        self.pid.reset();
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.robot
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.robot
+    }
+}
+
+impl<R: DriveBase> StabilizedRobot<R> {
+    /// Feeds a new heading measurement, `dt` after the previous one, and
+    /// applies the resulting correction when holding.
+    pub fn update_heading(&mut self, heading: Angle, dt: Time) -> Result<(), R::Error> {
+        let heading = heading.get::<radian>();
+        self.heading = Some(heading);
+
+        let (Some((power, theta)), Some(target)) = (self.command, self.target) else {
+            return Ok(());
+        };
+
+        // Heading is counter-clockwise positive, turn is clockwise positive
+        let error = wrap_angle(target - heading);
+        self.correction = -self.pid.update(error, dt.get::<second>());
+        self.robot.drive(power, theta, Turn::new(self.correction))
+    }
+}
+
+impl<R: DriveBase> DriveBase for StabilizedRobot<R> {
+    type Error = R::Error;
+
+    fn drive(&mut self, power: MecanumPower, theta: Angle, turn: Turn) -> Result<(), Self::Error> {
+        if turn.inner() != 0.0 {
+            self.release();
+            return self.robot.drive(power, theta, turn);
+        }
+
+        if self.target.is_none() {
+            self.target = self.heading;
+            self.correction = 0.0;
+            self.pid.reset();
+        }
+        self.command = Some((power, theta));
+
+        self.robot.drive(power, theta, Turn::new(self.correction))
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.release();
+        self.robot.neutral()
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.release();
+        self.robot.brake()
+    }
+}
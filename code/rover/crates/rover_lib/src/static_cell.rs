@@ -0,0 +1,50 @@
+use core::{
+    cell::{Cell, UnsafeCell},
+    mem::MaybeUninit,
+};
+
+use critical_section::Mutex;
+
+/// Room in a `static` for a value only known at runtime, handed out once as
+/// a `&'static mut`: what would otherwise be leaked from the heap.
+pub struct StaticCell<T> {
+    taken: Mutex<Cell<bool>>,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// The value is only ever reachable through the one reference `init` hands
+// out, on whichever thread took it, hence `Send`.
+unsafe impl<T: Send> Sync for StaticCell<T> {}
+
+impl<T> StaticCell<T> {
+    pub const fn new() -> Self {
+        Self {
+            taken: Mutex::new(Cell::new(false)),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Moves `value` in. `None` if it was already done.
+    #[allow(clippy::mut_from_ref)]
+    pub fn try_init(&'static self, value: T) -> Option<&'static mut T> {
+        let taken = critical_section::with(|cs| self.taken.borrow(cs).replace(true));
+        if taken {
+            return None;
+        }
+        // SAFETY: first and only time through, nobody else has a reference
+        let slot = unsafe { &mut *self.value.get() };
+        Some(slot.write(value))
+    }
+
+    /// Panics if it was already done.
+    #[allow(clippy::mut_from_ref)]
+    pub fn init(&'static self, value: T) -> &'static mut T {
+        self.try_init(value).expect("StaticCell initialized twice")
+    }
+}
+
+impl<T> Default for StaticCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,205 @@
+//! TB6612FNG dual H-bridge: two motors sharing the STBY line of the chip.
+//!
+//! The chip only sleeps once both of its motors coast, so the channels keep
+//! track of each other through the [`Tb6612Standby`] they borrow. Unlike a
+//! [`MyMotor`](crate::MyMotor) driver, PWM low shorts the motor rather than
+//! letting it float: coasting takes both inputs low, and the duty cycle
+//! switches between driving and braking.
+
+use core::cell::RefCell;
+
+use embedded_hal_1::{
+    digital::{OutputPin, PinState},
+    pwm::SetDutyCycle,
+};
+
+use crate::iface::{Motor, MotorPower, NeutralMode};
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+#[non_exhaustive]
+pub enum Tb6612Error<P, I1, I2, S> {
+    Pwm(P),
+    In1(I1),
+    In2(I2),
+    Standby(S),
+}
+
+impl<P: core::fmt::Debug, I1: core::fmt::Debug, I2: core::fmt::Debug, S: core::fmt::Debug>
+    core::fmt::Display for Tb6612Error<P, I1, I2, S>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl<P: core::fmt::Debug, I1: core::fmt::Debug, I2: core::fmt::Debug, S: core::fmt::Debug>
+    core::error::Error for Tb6612Error<P, I1, I2, S>
+{
+}
+
+type Tb6612ErrorOf<P, I1, I2, S> = Tb6612Error<
+    <P as embedded_hal_1::pwm::ErrorType>::Error,
+    <I1 as embedded_hal_1::digital::ErrorType>::Error,
+    <I2 as embedded_hal_1::digital::ErrorType>::Error,
+    <S as embedded_hal_1::digital::ErrorType>::Error,
+>;
+
+/// A side of the chip, motor A or B.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Tb6612Channel {
+    A,
+    B,
+}
+
+struct Standby<S> {
+    pin: S,
+    /// Channels not coasting, A then B.
+    awake: [bool; 2],
+}
+
+/// The STBY line of one chip, shared by its two [`Tb6612Motor`]s. The chip
+/// starts in standby.
+pub struct Tb6612Standby<S> {
+    inner: RefCell<Standby<S>>,
+}
+
+impl<S: OutputPin> Tb6612Standby<S> {
+    pub fn new(mut pin: S) -> Result<Self, S::Error> {
+        pin.set_low()?;
+        Ok(Self {
+            inner: RefCell::new(Standby {
+                pin,
+                awake: [false; 2],
+            }),
+        })
+    }
+
+    /// Whether the chip is out of standby.
+    pub fn is_awake(&self) -> bool {
+        self.inner.borrow().awake.contains(&true)
+    }
+
+    /// The motor on `channel`, with its enable and two inputs.
+    pub fn motor<P, I1, I2>(
+        &self,
+        channel: Tb6612Channel,
+        pwm: P,
+        in1: I1,
+        in2: I2,
+    ) -> Tb6612Motor<'_, P, I1, I2, S> {
+        Tb6612Motor {
+            standby: self,
+            channel,
+            pwm,
+            in1,
+            in2,
+            neutral_mode: Default::default(),
+            power: Default::default(),
+        }
+    }
+
+    /// Takes the chip out of standby for `channel`, or lets it sleep once
+    /// neither channel needs it.
+    fn set_awake(&self, channel: Tb6612Channel, awake: bool) -> Result<(), S::Error> {
+        let mut standby = self.inner.borrow_mut();
+        standby.awake[channel as usize] = awake;
+        let state = PinState::from(standby.awake.contains(&true));
+        standby.pin.set_state(state)
+    }
+}
+
+/// One motor of a TB6612FNG, forward with IN1 high.
+pub struct Tb6612Motor<'a, P, I1, I2, S> {
+    standby: &'a Tb6612Standby<S>,
+    channel: Tb6612Channel,
+    pwm: P,
+    in1: I1,
+    in2: I2,
+    neutral_mode: NeutralMode,
+    power: MotorPower,
+}
+
+impl<P, I1, I2, S> Tb6612Motor<'_, P, I1, I2, S> {
+    pub fn channel(&self) -> Tb6612Channel {
+        self.channel
+    }
+
+    pub fn neutral_mode(&self) -> NeutralMode {
+        self.neutral_mode
+    }
+
+    /// What [`Motor::neutral`] does, [`Motor::brake`] always brakes.
+    pub fn set_neutral_mode(&mut self, mode: NeutralMode) {
+        self.neutral_mode = mode;
+    }
+}
+
+impl<P: SetDutyCycle, I1: OutputPin, I2: OutputPin, S: OutputPin> Tb6612Motor<'_, P, I1, I2, S> {
+    fn set_inputs(
+        &mut self,
+        in1: PinState,
+        in2: PinState,
+    ) -> Result<(), Tb6612ErrorOf<P, I1, I2, S>> {
+        self.in1.set_state(in1).map_err(Tb6612Error::In1)?;
+        self.in2.set_state(in2).map_err(Tb6612Error::In2)
+    }
+
+    fn wake(&mut self, awake: bool) -> Result<(), Tb6612ErrorOf<P, I1, I2, S>> {
+        self.standby
+            .set_awake(self.channel, awake)
+            .map_err(Tb6612Error::Standby)
+    }
+
+    /// Both inputs low, outputs floating, and the chip in standby if the
+    /// other motor coasts too.
+    fn coast(&mut self) -> Result<(), Tb6612ErrorOf<P, I1, I2, S>> {
+        self.set_inputs(PinState::Low, PinState::Low)?;
+        self.pwm
+            .set_duty_cycle_fully_off()
+            .map_err(Tb6612Error::Pwm)?;
+        self.power = Default::default();
+        self.wake(false)
+    }
+
+    /// Both inputs high, short brake whatever the PWM.
+    fn short(&mut self) -> Result<(), Tb6612ErrorOf<P, I1, I2, S>> {
+        self.wake(true)?;
+        self.set_inputs(PinState::High, PinState::High)?;
+        self.power = Default::default();
+        Ok(())
+    }
+}
+
+impl<P: SetDutyCycle, I1: OutputPin, I2: OutputPin, S: OutputPin> Motor
+    for Tb6612Motor<'_, P, I1, I2, S>
+{
+    type Error = Tb6612ErrorOf<P, I1, I2, S>;
+
+    fn drive(&mut self, power: MotorPower) -> Result<(), Self::Error> {
+        let inner_power = power.inner();
+        let forward = inner_power >= 0.0;
+        let duty_percent = ((libm::fabsf(inner_power) / MotorPower::MAX) * 100.0) as u8;
+
+        self.wake(true)?;
+        self.set_inputs(PinState::from(forward), PinState::from(!forward))?;
+        self.pwm
+            .set_duty_cycle_percent(duty_percent)
+            .map_err(Tb6612Error::Pwm)?;
+
+        self.power = power;
+
+        Ok(())
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        match self.neutral_mode {
+            NeutralMode::Coast => self.coast(),
+            NeutralMode::Brake => self.short(),
+        }
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.short()
+    }
+    fn power(&self) -> MotorPower {
+        self.power
+    }
+}
@@ -0,0 +1,206 @@
+//! An I²t estimate of how hot each motor and its driver run, lowering the
+//! power it may be driven at as it heats up and giving it back as it cools,
+//! so long sessions of stalls and pushing don't burn the drivers.
+//!
+//! The heat follows the square of the load, the duty driven or the measured
+//! current over the rated one, through a first order lag: 1 is where a
+//! motor ends up after a long time at full load.
+
+use serde::{Deserialize, Serialize};
+use uom::si::{f32::Time, time::second};
+
+use crate::iface::{FourWheeledRobot, MotorPower};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ThermalParams {
+    /// The heat gets about two thirds of the way to a new load in this
+    /// long.
+    pub time_constant_s: f32,
+    /// Heat from which the power is derated, 1 disables it.
+    pub derate_start: f32,
+    /// Heat at which the power is down to `min_power`.
+    pub derate_end: f32,
+    /// Enough to still get the robot home.
+    pub min_power: f32,
+}
+
+impl ThermalParams {
+    /// A minute or so at full power before derating kicks in.
+    pub const DEFAULT: Self = Self {
+        time_constant_s: 60.0,
+        derate_start: 0.7,
+        derate_end: 0.95,
+        min_power: 0.3,
+    };
+
+    pub fn is_valid(&self) -> bool {
+        (1.0..=600.0).contains(&self.time_constant_s)
+            && (0.1..=1.0).contains(&self.derate_start)
+            && (self.derate_start == 1.0
+                || (self.derate_start..=1.0).contains(&self.derate_end)
+                    && self.derate_end > self.derate_start)
+            && (0.0..=1.0).contains(&self.min_power)
+    }
+
+    /// The most a motor at `heat` may be driven at.
+    pub fn ceiling(&self, heat: f32) -> f32 {
+        if heat <= self.derate_start || self.derate_start >= 1.0 {
+            return MotorPower::MAX;
+        }
+        let t = ((heat - self.derate_start) / (self.derate_end - self.derate_start)).min(1.0);
+        (MotorPower::MAX - t * (MotorPower::MAX - self.min_power)).max(self.min_power)
+    }
+}
+
+pub struct ThermalDerating<R> {
+    robot: R,
+    params: ThermalParams,
+    /// FL-FR-BL-BR.
+    heat: [f32; 4],
+    ceilings: [f32; 4],
+    /// What was asked for, driven within the ceilings.
+    requested: [MotorPower; 4],
+    active: bool,
+}
+
+impl<R> ThermalDerating<R> {
+    /// Starts cold.
+    pub fn new(robot: R, params: ThermalParams) -> Self {
+        Self {
+            robot,
+            params,
+            heat: [0.0; 4],
+            ceilings: [MotorPower::MAX; 4],
+            requested: [MotorPower::default(); 4],
+            active: false,
+        }
+    }
+
+    pub fn params(&self) -> ThermalParams {
+        self.params
+    }
+
+    /// Takes effect at the next update.
+    pub fn set_params(&mut self, params: ThermalParams) {
+        self.params = params;
+    }
+
+    /// FL-FR-BL-BR.
+    pub fn heat(&self) -> [f32; 4] {
+        self.heat
+    }
+
+    /// The most each motor may be driven at, FL-FR-BL-BR.
+    pub fn ceilings(&self) -> [f32; 4] {
+        self.ceilings
+    }
+
+    pub fn derating(&self) -> bool {
+        self.ceilings.iter().any(|c| *c < MotorPower::MAX)
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.robot
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.robot
+    }
+
+    fn applied(&self) -> [MotorPower; 4] {
+        if !self.active {
+            return [MotorPower::default(); 4];
+        }
+        let mut applied = self.requested;
+        for (power, ceiling) in applied.iter_mut().zip(self.ceilings) {
+            *power = MotorPower::new(power.inner().clamp(-ceiling, ceiling));
+        }
+        applied
+    }
+}
+
+impl<R: FourWheeledRobot> ThermalDerating<R> {
+    /// Heats each motor with the duty it was driven at over `dt`.
+    pub fn update(&mut self, dt: Time) -> Result<(), R::Error> {
+        let loads = self.applied().map(|p| p.inner() / MotorPower::MAX);
+        self.update_with_loads(dt, loads)
+    }
+
+    /// Like [`update`](Self::update), with the measured current over the
+    /// rated one as the loads: a stalled motor draws more than its duty
+    /// says.
+    pub fn update_with_loads(&mut self, dt: Time, loads: [f32; 4]) -> Result<(), R::Error> {
+        let dt = dt.get::<second>();
+        let alpha = dt / (self.params.time_constant_s + dt);
+        for (heat, load) in self.heat.iter_mut().zip(loads) {
+            *heat += (load * load - *heat) * alpha;
+        }
+
+        let ceilings = self.heat.map(|heat| self.params.ceiling(heat));
+        let changed = ceilings != self.ceilings;
+        self.ceilings = ceilings;
+        if changed && self.active {
+            let [fl, fr, bl, br] = self.applied();
+            self.robot.drive(fl, fr, bl, br)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<R: FourWheeledRobot> FourWheeledRobot for ThermalDerating<R> {
+    type Error = R::Error;
+
+    fn drive(
+        &mut self,
+        fl: MotorPower,
+        fr: MotorPower,
+        bl: MotorPower,
+        br: MotorPower,
+    ) -> Result<(), Self::Error> {
+        self.requested = [fl, fr, bl, br];
+        self.active = true;
+        let [fl, fr, bl, br] = self.applied();
+        self.robot.drive(fl, fr, bl, br)
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.active = false;
+        self.robot.neutral()
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.active = false;
+        self.robot.brake()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockRobot;
+
+    #[test]
+    fn thermal_derating_follows_the_heat() {
+        let mut robot = ThermalDerating::new(MockRobot::new(), ThermalParams::DEFAULT);
+        let p = |power| MotorPower::new(power);
+        robot.drive(p(1.0), p(-1.0), p(0.2), p(0.0)).unwrap();
+        for _ in 0..600 {
+            robot.update(Time::new::<second>(1.0)).unwrap();
+        }
+        assert!(robot.derating());
+        let [fl, fr, bl, br] = robot.inner().powers().map(|p| p.inner());
+        assert!(fl < 0.9 && fl > ThermalParams::DEFAULT.min_power);
+        assert_eq!(fr, -fl);
+        assert_eq!([bl, br], [0.2, 0.0]);
+
+        robot.neutral().unwrap();
+        for _ in 0..600 {
+            robot.update(Time::new::<second>(1.0)).unwrap();
+        }
+        assert!(!robot.derating());
+        robot.drive(p(1.0), p(-1.0), p(0.2), p(0.0)).unwrap();
+        assert_eq!(
+            robot.inner().powers().map(|p| p.inner()),
+            [1.0, -1.0, 0.2, 0.0]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Rollover protection: the drive is cut when the robot tilts far enough to
+//! be about to tip over, and given back once it's level again.
+//!
+//! The tilt has to stay past the limit for a while to trip, so hard
+//! accelerations and bumps pitching the attitude estimate for a moment
+//! don't, and has to come back under the limit by the hysteresis to clear.
+
+use serde::{Deserialize, Serialize};
+use uom::si::angle::degree;
+
+use crate::iface::Angle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TiltParams {
+    /// Tilt from vertical past which the drive is cut, 0 disables it.
+    pub limit_deg: f32,
+    pub hysteresis_deg: f32,
+    /// How long the tilt has to stay past the limit.
+    pub trip_ms: u16,
+}
+
+impl TiltParams {
+    pub const DEFAULT: Self = Self {
+        limit_deg: 35.0,
+        hysteresis_deg: 5.0,
+        trip_ms: 200,
+    };
+
+    pub fn is_valid(&self) -> bool {
+        (self.limit_deg == 0.0 || (10.0..=80.0).contains(&self.limit_deg))
+            && (0.0..=self.limit_deg).contains(&self.hysteresis_deg)
+            && self.trip_ms <= 2_000
+    }
+}
+
+pub struct TiltGuard {
+    params: TiltParams,
+    over_ms: u32,
+    tripped: bool,
+}
+
+impl TiltGuard {
+    pub fn new(params: TiltParams) -> Self {
+        Self {
+            params,
+            over_ms: 0,
+            tripped: false,
+        }
+    }
+
+    pub fn set_params(&mut self, params: TiltParams) {
+        self.params = params;
+    }
+
+    pub fn tripped(&self) -> bool {
+        self.tripped
+    }
+
+    /// Feeds the tilt measured `dt_ms` after the last one. Returns whether
+    /// it's tripped when that changes.
+    pub fn update(&mut self, tilt: Angle, dt_ms: u32) -> Option<bool> {
+        let tilt = tilt.get::<degree>();
+        let limit = self.params.limit_deg;
+        self.over_ms = match tilt > limit {
+            true => self.over_ms.saturating_add(dt_ms),
+            false => 0,
+        };
+        let tripped = if limit == 0.0 {
+            false
+        } else if self.tripped {
+            tilt > limit - self.params.hysteresis_deg
+        } else {
+            tilt > limit && self.over_ms >= self.params.trip_ms as u32
+        };
+        (tripped != self.tripped).then(|| {
+            self.tripped = tripped;
+            tripped
+        })
+    }
+}
@@ -0,0 +1,70 @@
+//! Lines the host's clock up with the rover's uptime, the way NTP does: the
+//! host sends its time, the rover answers with its own, and the host takes
+//! the rover's to have been read halfway through the round trip.
+//!
+//! Exchanges with the shortest round trip are the least skewed by queueing
+//! on either end, so [`ClockSync`] keeps the best one.
+
+/// One exchange, as the host sees it once the answer is back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncSample {
+    /// Rover uptime minus host time.
+    pub offset_ms: i64,
+    pub round_trip_ms: u64,
+}
+
+impl SyncSample {
+    /// `sent_ms` and `received_ms` by the host clock, around the rover
+    /// answering `rover_ms`.
+    pub fn new(sent_ms: u64, rover_ms: u64, received_ms: u64) -> Self {
+        let round_trip_ms = received_ms.saturating_sub(sent_ms);
+        let midpoint_ms = sent_ms + round_trip_ms / 2;
+        Self {
+            offset_ms: rover_ms as i64 - midpoint_ms as i64,
+            round_trip_ms,
+        }
+    }
+}
+
+/// The host's end, converting between the two clocks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClockSync {
+    best: Option<SyncSample>,
+}
+
+impl ClockSync {
+    pub const fn new() -> Self {
+        Self { best: None }
+    }
+
+    /// Kept if its round trip is the shortest yet.
+    pub fn add(&mut self, sample: SyncSample) {
+        if self
+            .best
+            .is_none_or(|best| sample.round_trip_ms <= best.round_trip_ms)
+        {
+            self.best = Some(sample);
+        }
+    }
+
+    pub fn best(&self) -> Option<SyncSample> {
+        self.best
+    }
+
+    /// Forgets the samples, say after the rover reset.
+    pub fn reset(&mut self) {
+        self.best = None;
+    }
+
+    /// Within half the best round trip, `None` before any sample or before
+    /// the rover booted.
+    pub fn to_rover(&self, host_ms: u64) -> Option<u64> {
+        let offset = self.best?.offset_ms;
+        u64::try_from(host_ms as i64 + offset).ok()
+    }
+
+    pub fn to_host(&self, rover_ms: u64) -> Option<u64> {
+        let offset = self.best?.offset_ms;
+        u64::try_from(rover_ms as i64 - offset).ok()
+    }
+}
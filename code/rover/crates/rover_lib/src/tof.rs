@@ -0,0 +1,273 @@
+//! VL53L0X time-of-flight rangefinder, after ST's API as condensed by
+//! Pololu: ranging continuously, back to back, with the default timing
+//! budget of about 30 ms a measurement.
+
+use embedded_hal_async::i2c::I2c;
+use uom::si::{f32::Length, length::millimeter};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum TofError {
+    Bus,
+    WrongId(u8),
+    /// The sensor didn't finish a calibration or measurement in time.
+    Timeout,
+    /// No measurement since the last read.
+    NotReady,
+}
+
+impl core::fmt::Display for TofError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for TofError {}
+
+/// Register writes of ST's default tuning, as is.
+#[rustfmt::skip]
+const TUNING: [(u8, u8); 80] = [
+    (0xFF, 0x01), (0x00, 0x00), (0xFF, 0x00), (0x09, 0x00), (0x10, 0x00), (0x11, 0x00),
+    (0x24, 0x01), (0x25, 0xFF), (0x75, 0x00), (0xFF, 0x01), (0x4E, 0x2C), (0x48, 0x00),
+    (0x30, 0x20), (0xFF, 0x00), (0x30, 0x09), (0x54, 0x00), (0x31, 0x04), (0x32, 0x03),
+    (0x40, 0x83), (0x46, 0x25), (0x60, 0x00), (0x27, 0x00), (0x50, 0x06), (0x51, 0x00),
+    (0x52, 0x96), (0x56, 0x08), (0x57, 0x30), (0x61, 0x00), (0x62, 0x00), (0x64, 0x00),
+    (0x65, 0x00), (0x66, 0xA0), (0xFF, 0x01), (0x22, 0x32), (0x47, 0x14), (0x49, 0xFF),
+    (0x4A, 0x00), (0xFF, 0x00), (0x7A, 0x0A), (0x7B, 0x00), (0x78, 0x21), (0xFF, 0x01),
+    (0x23, 0x34), (0x42, 0x00), (0x44, 0xFF), (0x45, 0x26), (0x46, 0x05), (0x40, 0x40),
+    (0x0E, 0x06), (0x20, 0x1A), (0x43, 0x40), (0xFF, 0x00), (0x34, 0x03), (0x35, 0x44),
+    (0xFF, 0x01), (0x31, 0x04), (0x4B, 0x09), (0x4C, 0x05), (0x4D, 0x04), (0xFF, 0x00),
+    (0x44, 0x00), (0x45, 0x20), (0x47, 0x08), (0x48, 0x28), (0x67, 0x00), (0x70, 0x04),
+    (0x71, 0x01), (0x72, 0xFE), (0x76, 0x00), (0x77, 0x00), (0xFF, 0x01), (0x0D, 0x01),
+    (0xFF, 0x00), (0x80, 0x01), (0x01, 0xF8), (0xFF, 0x01), (0x8E, 0x01), (0x00, 0x01),
+    (0xFF, 0x00), (0x80, 0x00),
+];
+
+/// Status polls before giving up, some 150 ms at 400 kHz.
+const POLL_ATTEMPTS: u32 = 2_000;
+
+pub struct Vl53l0x<I> {
+    i2c: I,
+    address: u8,
+    /// Read from the sensor at init, written back to start ranging.
+    stop_variable: u8,
+}
+
+impl<I> Vl53l0x<I> {
+    pub const DEFAULT_ADDRESS: u8 = 0x29;
+
+    const SYSRANGE_START: u8 = 0x00;
+    const SYSTEM_SEQUENCE_CONFIG: u8 = 0x01;
+    const SYSTEM_INTERRUPT_CONFIG_GPIO: u8 = 0x0A;
+    const SYSTEM_INTERRUPT_CLEAR: u8 = 0x0B;
+    const RESULT_INTERRUPT_STATUS: u8 = 0x13;
+    const RESULT_RANGE_MM: u8 = 0x1E;
+    const FINAL_RANGE_MIN_COUNT_RATE_RTN_LIMIT: u8 = 0x44;
+    const MSRC_CONFIG_CONTROL: u8 = 0x60;
+    const GPIO_HV_MUX_ACTIVE_HIGH: u8 = 0x84;
+    const SPAD_ENABLES_REF_0: u8 = 0xB0;
+    const REF_EN_START_SELECT: u8 = 0xB6;
+    const IDENTIFICATION_MODEL_ID: u8 = 0xC0;
+
+    const MODEL_ID: u8 = 0xEE;
+    /// 0.25 MCPS in 9.7 fixed point, the least return signal that counts.
+    const SIGNAL_RATE_LIMIT: u16 = 32;
+    /// Past about 2 m the sensor reports 8190 or 8191 for nothing seen.
+    const MAX_RANGE_MM: u16 = 2_000;
+
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self {
+            i2c,
+            address,
+            stop_variable: 0,
+        }
+    }
+}
+
+impl<I: I2c> Vl53l0x<I> {
+    async fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), TofError> {
+        self.i2c
+            .write(self.address, &[reg, value])
+            .await
+            .map_err(|_| TofError::Bus)
+    }
+
+    async fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), TofError> {
+        self.i2c
+            .write_read(self.address, &[reg], buf)
+            .await
+            .map_err(|_| TofError::Bus)
+    }
+
+    async fn read_reg(&mut self, reg: u8) -> Result<u8, TofError> {
+        let mut buf = [0];
+        self.read_regs(reg, &mut buf).await?;
+        Ok(buf[0])
+    }
+
+    async fn write_regs(&mut self, regs: &[(u8, u8)]) -> Result<(), TofError> {
+        for &(reg, value) in regs {
+            self.write_reg(reg, value).await?;
+        }
+        Ok(())
+    }
+
+    /// Polls the interrupt status until a measurement or calibration is done.
+    async fn wait_ready(&mut self) -> Result<(), TofError> {
+        for _ in 0..POLL_ATTEMPTS {
+            if self.read_reg(Self::RESULT_INTERRUPT_STATUS).await? & 0x07 != 0 {
+                return Ok(());
+            }
+        }
+        Err(TofError::Timeout)
+    }
+
+    /// Number of reference SPADs to enable and whether they're the aperture
+    /// kind, from the sensor's NVM.
+    async fn spad_info(&mut self) -> Result<(u8, bool), TofError> {
+        self.write_regs(&[(0x80, 0x01), (0xFF, 0x01), (0x00, 0x00), (0xFF, 0x06)])
+            .await?;
+        let value = self.read_reg(0x83).await?;
+        self.write_reg(0x83, value | 0x04).await?;
+        self.write_regs(&[
+            (0xFF, 0x07),
+            (0x81, 0x01),
+            (0x80, 0x01),
+            (0x94, 0x6B),
+            (0x83, 0x00),
+        ])
+        .await?;
+
+        let mut ready = false;
+        for _ in 0..POLL_ATTEMPTS {
+            if self.read_reg(0x83).await? != 0 {
+                ready = true;
+                break;
+            }
+        }
+        if !ready {
+            return Err(TofError::Timeout);
+        }
+
+        self.write_reg(0x83, 0x01).await?;
+        let info = self.read_reg(0x92).await?;
+        self.write_regs(&[(0x81, 0x00), (0xFF, 0x06)]).await?;
+        let value = self.read_reg(0x83).await?;
+        self.write_reg(0x83, value & !0x04).await?;
+        self.write_regs(&[(0xFF, 0x01), (0x00, 0x01), (0xFF, 0x00), (0x80, 0x00)])
+            .await?;
+
+        Ok((info & 0x7F, info & 0x80 != 0))
+    }
+
+    async fn single_ref_calibration(&mut self, vhv_init: u8) -> Result<(), TofError> {
+        self.write_reg(Self::SYSRANGE_START, 0x01 | vhv_init)
+            .await?;
+        self.wait_ready().await?;
+        self.write_reg(Self::SYSTEM_INTERRUPT_CLEAR, 0x01).await?;
+        self.write_reg(Self::SYSRANGE_START, 0x00).await
+    }
+
+    /// Checks the sensor is there, sets it up and starts ranging.
+    pub async fn init(&mut self) -> Result<(), TofError> {
+        let id = self.read_reg(Self::IDENTIFICATION_MODEL_ID).await?;
+        if id != Self::MODEL_ID {
+            return Err(TofError::WrongId(id));
+        }
+
+        // Standard I2C mode, then fetch the stop variable
+        self.write_regs(&[(0x88, 0x00), (0x80, 0x01), (0xFF, 0x01), (0x00, 0x00)])
+            .await?;
+        self.stop_variable = self.read_reg(0x91).await?;
+        self.write_regs(&[(0x00, 0x01), (0xFF, 0x00), (0x80, 0x00)])
+            .await?;
+
+        // No minimum signal rate checks but the final one
+        let value = self.read_reg(Self::MSRC_CONFIG_CONTROL).await?;
+        self.write_reg(Self::MSRC_CONFIG_CONTROL, value | 0x12)
+            .await?;
+        let limit = Self::SIGNAL_RATE_LIMIT.to_be_bytes();
+        self.i2c
+            .write(
+                self.address,
+                &[
+                    Self::FINAL_RANGE_MIN_COUNT_RATE_RTN_LIMIT,
+                    limit[0],
+                    limit[1],
+                ],
+            )
+            .await
+            .map_err(|_| TofError::Bus)?;
+        self.write_reg(Self::SYSTEM_SEQUENCE_CONFIG, 0xFF).await?;
+
+        // Enable the first `count` good reference SPADs of the right kind
+        let (count, aperture) = self.spad_info().await?;
+        let mut map = [0u8; 6];
+        self.read_regs(Self::SPAD_ENABLES_REF_0, &mut map).await?;
+        self.write_regs(&[(0xFF, 0x01), (0x4F, 0x00), (0x4E, 0x2C), (0xFF, 0x00)])
+            .await?;
+        self.write_reg(Self::REF_EN_START_SELECT, 0xB4).await?;
+        let first = if aperture { 12 } else { 0 };
+        let mut enabled = 0;
+        for spad in 0..48 {
+            let bit = 1 << (spad % 8);
+            if spad < first || enabled == count {
+                map[spad / 8] &= !bit;
+            } else if map[spad / 8] & bit != 0 {
+                enabled += 1;
+            }
+        }
+        let mut buf = [0; 7];
+        buf[0] = Self::SPAD_ENABLES_REF_0;
+        buf[1..].copy_from_slice(&map);
+        self.i2c
+            .write(self.address, &buf)
+            .await
+            .map_err(|_| TofError::Bus)?;
+
+        self.write_regs(&TUNING).await?;
+
+        // Interrupt on new sample, active low
+        self.write_reg(Self::SYSTEM_INTERRUPT_CONFIG_GPIO, 0x04)
+            .await?;
+        let value = self.read_reg(Self::GPIO_HV_MUX_ACTIVE_HIGH).await?;
+        self.write_reg(Self::GPIO_HV_MUX_ACTIVE_HIGH, value & !0x10)
+            .await?;
+        self.write_reg(Self::SYSTEM_INTERRUPT_CLEAR, 0x01).await?;
+
+        // VHV and phase calibrations, then the usual sequence without MSRC
+        // and TCC
+        self.write_reg(Self::SYSTEM_SEQUENCE_CONFIG, 0x01).await?;
+        self.single_ref_calibration(0x40).await?;
+        self.write_reg(Self::SYSTEM_SEQUENCE_CONFIG, 0x02).await?;
+        self.single_ref_calibration(0x00).await?;
+        self.write_reg(Self::SYSTEM_SEQUENCE_CONFIG, 0xE8).await?;
+
+        // Back to back continuous ranging
+        let stop_variable = self.stop_variable;
+        self.write_regs(&[
+            (0x80, 0x01),
+            (0xFF, 0x01),
+            (0x00, 0x00),
+            (0x91, stop_variable),
+            (0x00, 0x01),
+            (0xFF, 0x00),
+            (0x80, 0x00),
+        ])
+        .await?;
+        self.write_reg(Self::SYSRANGE_START, 0x02).await
+    }
+
+    /// Latest measurement, `None` for nothing in range.
+    pub async fn read(&mut self) -> Result<Option<Length>, TofError> {
+        if self.read_reg(Self::RESULT_INTERRUPT_STATUS).await? & 0x07 == 0 {
+            return Err(TofError::NotReady);
+        }
+        let mut buf = [0; 2];
+        self.read_regs(Self::RESULT_RANGE_MM, &mut buf).await?;
+        self.write_reg(Self::SYSTEM_INTERRUPT_CLEAR, 0x01).await?;
+
+        let range = u16::from_be_bytes(buf);
+        Ok((range <= Self::MAX_RANGE_MM).then(|| Length::new::<millimeter>(range as f32)))
+    }
+}
@@ -0,0 +1,71 @@
+//! Which of the links the host talks to the rover over, when several carry
+//! the same framed protocol.
+//!
+//! Each transport keeps its own keepalive: it's live while good frames keep
+//! coming on it, and replies go to the live ones only. With none live, say
+//! before the host sent anything, they go to every transport that could
+//! carry them.
+
+/// Where frames come from and go to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Uart,
+    Bluetooth,
+    Usb,
+}
+
+impl Transport {
+    pub const ALL: [Self; 3] = [Self::Uart, Self::Bluetooth, Self::Usb];
+}
+
+/// One flag per [`Transport`], in the order of [`Transport::ALL`].
+pub type TransportSet = [bool; Transport::ALL.len()];
+
+/// A host quiet on a transport for longer is taken to have left it.
+pub const KEEPALIVE_MS: u64 = 3_000;
+
+#[derive(Debug, Clone)]
+pub struct TransportTracker {
+    /// When each transport last had a good frame.
+    heard: [Option<u64>; Transport::ALL.len()],
+    keepalive_ms: u64,
+}
+
+impl TransportTracker {
+    pub const fn new(keepalive_ms: u64) -> Self {
+        Self {
+            heard: [None; Transport::ALL.len()],
+            keepalive_ms,
+        }
+    }
+
+    /// A good frame came in on `transport`. True if it wasn't live before,
+    /// the host having just turned up on it.
+    pub fn heard(&mut self, transport: Transport, now_ms: u64) -> bool {
+        let was_live = self.is_live(transport, now_ms);
+        self.heard[transport as usize] = Some(now_ms);
+        !was_live
+    }
+
+    pub fn is_live(&self, transport: Transport, now_ms: u64) -> bool {
+        self.heard[transport as usize]
+            .is_some_and(|heard| now_ms.saturating_sub(heard) <= self.keepalive_ms)
+    }
+
+    /// Where to send: the live transports, or all the `connected` ones when
+    /// none is.
+    pub fn destinations(&self, now_ms: u64, connected: TransportSet) -> TransportSet {
+        let live = Transport::ALL.map(|transport| self.is_live(transport, now_ms));
+        if live.contains(&true) {
+            live
+        } else {
+            connected
+        }
+    }
+}
+
+impl Default for TransportTracker {
+    fn default() -> Self {
+        Self::new(KEEPALIVE_MS)
+    }
+}
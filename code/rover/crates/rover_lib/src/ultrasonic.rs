@@ -0,0 +1,24 @@
+//! HC-SR04 style ultrasonic rangers: a pulse on TRIG sends a ping, then ECHO
+//! stays high for the round trip time of the sound.
+
+use uom::si::{f32::Length, length::meter};
+
+/// The datasheet asks for 10 µs at least.
+pub const TRIGGER_PULSE_US: u64 = 10;
+
+/// In air at 20 °C.
+const SPEED_OF_SOUND_M_PER_S: f32 = 343.0;
+
+/// Closer than 2 cm the echo comes back while the sensor is still pinging.
+const MIN_ECHO_US: u64 = 120;
+/// About 4 m, past which readings are mostly noise. Echoes of nothing in
+/// range last 38 ms.
+pub const MAX_ECHO_US: u64 = 23_000;
+
+/// Distance to the obstacle for an echo of `echo_us`, `None` when out of
+/// range.
+pub fn echo_to_distance(echo_us: u64) -> Option<Length> {
+    (MIN_ECHO_US..=MAX_ECHO_US)
+        .contains(&echo_us)
+        .then(|| Length::new::<meter>(echo_us as f32 * 1e-6 * SPEED_OF_SOUND_M_PER_S / 2.0))
+}
@@ -0,0 +1,180 @@
+use uom::si::{
+    angular_velocity::revolution_per_minute,
+    f32::{AngularVelocity, Time},
+    time::second,
+};
+
+use crate::{
+    iface::{Motor, MotorPower},
+    pid::{Pid, PidGains},
+};
+
+/// A wheel commanded at least `min_power` but turning slower than
+/// `min_speed` for `timeout` is considered stalled: its power is capped to
+/// `stalled_power` until it moves again or the command goes back to zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StallDetection {
+    pub min_power: f32,
+    pub min_speed: AngularVelocity,
+    pub timeout: Time,
+    pub stalled_power: f32,
+}
+
+/// Closed loop speed control for a single wheel.
+///
+/// `drive()` sets a target speed proportional to `max_speed` instead of a PWM
+/// duty; the duty is then computed by [`VelocityController::update`], which
+/// must be called periodically with the speed measured by the wheel encoder.
+pub struct VelocityController<M> {
+    motor: M,
+    pid: Pid,
+    max_speed: AngularVelocity,
+    target: AngularVelocity,
+    measured: AngularVelocity,
+    active: bool,
+    stall_detection: Option<StallDetection>,
+    still_for: f32,
+    stalled: bool,
+}
+
+impl<M> VelocityController<M> {
+    pub fn new(motor: M, gains: PidGains, max_speed: AngularVelocity) -> Self {
+        Self {
+            motor,
+            pid: Pid::new(gains, MotorPower::MIN, MotorPower::MAX),
+            max_speed,
+            target: Default::default(),
+            measured: Default::default(),
+            active: false,
+            stall_detection: None,
+            still_for: 0.0,
+            stalled: false,
+        }
+    }
+
+    pub fn gains(&self) -> PidGains {
+        self.pid.gains()
+    }
+
+    pub fn set_gains(&mut self, gains: PidGains) {
+        self.pid.set_gains(gains);
+    }
+
+    pub fn target(&self) -> AngularVelocity {
+        self.target
+    }
+
+    pub fn measured(&self) -> AngularVelocity {
+        self.measured
+    }
+
+    /// `None` disables it.
+    pub fn set_stall_detection(&mut self, stall_detection: Option<StallDetection>) {
+        self.stall_detection = stall_detection;
+        self.clear_stall();
+    }
+
+    pub fn stalled(&self) -> bool {
+        self.stalled
+    }
+
+    fn clear_stall(&mut self) {
+        self.still_for = 0.0;
+        self.stalled = false;
+    }
+
+    /// Tracks how long the wheel has been pushed without moving and caps
+    /// `power` once stalled.
+    fn check_stall(&mut self, power: f32, dt: f32) -> f32 {
+        let Some(stall) = self.stall_detection else {
+            return power;
+        };
+        let moving = self.measured.abs() >= stall.min_speed;
+
+        if self.stalled {
+            if moving || self.target == AngularVelocity::default() {
+                self.clear_stall();
+            }
+        } else if libm::fabsf(power) >= stall.min_power && !moving {
+            self.still_for += dt;
+            if self.still_for >= stall.timeout.get::<second>() {
+                self.stalled = true;
+                self.pid.reset();
+            }
+        } else {
+            self.still_for = 0.0;
+        }
+
+        if self.stalled {
+            power.clamp(-stall.stalled_power, stall.stalled_power)
+        } else {
+            power
+        }
+    }
+
+    pub fn inner(&self) -> &M {
+        &self.motor
+    }
+
+    pub fn inner_mut(&mut self) -> &mut M {
+        &mut self.motor
+    }
+}
+
+impl<M: Motor> VelocityController<M> {
+    /// Targets `speed` itself rather than a share of the top speed, clamped
+    /// to it.
+    pub fn drive_speed(&mut self, speed: AngularVelocity) {
+        self.target = speed.max(-self.max_speed).min(self.max_speed);
+        self.active = true;
+    }
+
+    pub fn update(&mut self, measured: AngularVelocity, dt: Time) -> Result<(), M::Error> {
+        self.measured = measured;
+
+        if !self.active {
+            return Ok(());
+        }
+
+        let max = self.max_speed.get::<revolution_per_minute>();
+        let target = self.target.get::<revolution_per_minute>() / max;
+        let error = target - measured.get::<revolution_per_minute>() / max;
+
+        // The target itself is the feed-forward term, the PID only corrects
+        // what's left.
+        let dt = dt.get::<second>();
+        let correction = self.pid.update(error, dt);
+        let power = self.check_stall(target + correction, dt);
+        self.motor.drive(MotorPower::new(power))
+    }
+}
+
+impl<M: Motor> Motor for VelocityController<M> {
+    type Error = M::Error;
+
+    fn drive(&mut self, power: MotorPower) -> Result<(), Self::Error> {
+        self.target = self.max_speed * (power.inner() / MotorPower::MAX);
+        self.active = true;
+
+        Ok(())
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.target = Default::default();
+        self.active = false;
+        self.pid.reset();
+        self.clear_stall();
+
+        self.motor.neutral()
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.target = Default::default();
+        self.active = false;
+        self.pid.reset();
+        self.clear_stall();
+
+        self.motor.brake()
+    }
+    fn power(&self) -> MotorPower {
+        self.motor.power()
+    }
+}
@@ -0,0 +1,53 @@
+use core::cell::Cell;
+
+use critical_section::Mutex;
+
+/// Liveness of up to 32 periodic tasks, for whoever feeds the hardware
+/// watchdog: it should only be fed while [`Heartbeats::stale`] is empty, so
+/// a single hung task is enough to get a reset.
+pub struct Heartbeats {
+    registered: Mutex<Cell<u32>>,
+    beats: Mutex<Cell<u32>>,
+}
+
+impl Heartbeats {
+    pub const fn new() -> Self {
+        Self {
+            registered: Mutex::new(Cell::new(0)),
+            beats: Mutex::new(Cell::new(0)),
+        }
+    }
+
+    /// Starts expecting beats from `task`, in 0..32.
+    pub fn register(&self, task: u8) {
+        let bit = 1 << task;
+        critical_section::with(|cs| {
+            let beats = self.beats.borrow(cs);
+            beats.set(beats.get() | bit);
+            let registered = self.registered.borrow(cs);
+            registered.set(registered.get() | bit);
+        });
+    }
+
+    pub fn beat(&self, task: u8) {
+        critical_section::with(|cs| {
+            let beats = self.beats.borrow(cs);
+            beats.set(beats.get() | 1 << task);
+        });
+    }
+
+    /// Bitmap of the registered tasks that didn't beat since the previous
+    /// call.
+    pub fn stale(&self) -> u32 {
+        critical_section::with(|cs| {
+            let beats = self.beats.borrow(cs).replace(0);
+            self.registered.borrow(cs).get() & !beats
+        })
+    }
+}
+
+impl Default for Heartbeats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,873 @@
+//! Compact, non self-describing binary serde format in the spirit of
+//! postcard: integers are LEB128 varints (zigzag for signed ones), floats are
+//! little endian, enum variants are varint indices and structs are their
+//! fields in order.
+//!
+//! Frames add a leading protocol version byte and a trailing little endian
+//! CRC16 of everything before it.
+
+use serde::{de, ser, Deserialize, Serialize};
+
+use crate::crc::crc16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    BufferFull,
+    UnexpectedEnd,
+    BadVarint,
+    BadBool,
+    BadChar,
+    BadUtf8,
+    BadOption,
+    UnknownLength,
+    AnyUnsupported,
+    BadVersion(u8),
+    BadCrc,
+    Custom,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: core::fmt::Display>(_msg: T) -> Self {
+        Self::Custom
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: core::fmt::Display>(_msg: T) -> Self {
+        Self::Custom
+    }
+}
+
+/// Serializes `value` at the start of `buf`, returning the used part.
+pub fn to_slice<'a, T: Serialize + ?Sized>(
+    value: &T,
+    buf: &'a mut [u8],
+) -> Result<&'a mut [u8], Error> {
+    let mut ser = Serializer { buf, pos: 0 };
+    value.serialize(&mut ser)?;
+    let pos = ser.pos;
+    Ok(&mut ser.buf[..pos])
+}
+
+/// Deserializes a `T` from the start of `bytes`, returning it together with
+/// the unused remainder.
+pub fn take_from_bytes<'de, T: Deserialize<'de>>(
+    bytes: &'de [u8],
+) -> Result<(T, &'de [u8]), Error> {
+    let mut de = Deserializer { input: bytes };
+    let value = T::deserialize(&mut de)?;
+    Ok((value, de.input))
+}
+
+pub fn from_bytes<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> Result<T, Error> {
+    take_from_bytes(bytes).map(|(value, _)| value)
+}
+
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Serializes `value` into a version + payload + CRC16 frame.
+pub fn to_frame<'a, T: Serialize + ?Sized>(
+    value: &T,
+    buf: &'a mut [u8],
+) -> Result<&'a mut [u8], Error> {
+    let (version, rest) = buf.split_first_mut().ok_or(Error::BufferFull)?;
+    *version = PROTOCOL_VERSION;
+    let len = 1 + to_slice(value, rest)?.len();
+
+    let crc = crc16(&buf[..len]);
+    buf.get_mut(len..len + 2)
+        .ok_or(Error::BufferFull)?
+        .copy_from_slice(&crc.to_le_bytes());
+    Ok(&mut buf[..len + 2])
+}
+
+pub fn from_frame<'de, T: Deserialize<'de>>(frame: &'de [u8]) -> Result<T, Error> {
+    if frame.len() < 3 {
+        return Err(Error::UnexpectedEnd);
+    }
+    let (body, crc) = frame.split_at(frame.len() - 2);
+    if crc16(body) != u16::from_le_bytes([crc[0], crc[1]]) {
+        return Err(Error::BadCrc);
+    }
+    match body[0] {
+        PROTOCOL_VERSION => from_bytes(&body[1..]),
+        v => Err(Error::BadVersion(v)),
+    }
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+pub struct Serializer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Serializer<'_> {
+    fn push(&mut self, byte: u8) -> Result<(), Error> {
+        let slot = self.buf.get_mut(self.pos).ok_or(Error::BufferFull)?;
+        *slot = byte;
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn extend(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let end = self.pos + bytes.len();
+        self.buf
+            .get_mut(self.pos..end)
+            .ok_or(Error::BufferFull)?
+            .copy_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+
+    fn varint(&mut self, mut v: u64) -> Result<(), Error> {
+        loop {
+            let byte = (v & 0x7f) as u8;
+            v >>= 7;
+            if v == 0 {
+                return self.push(byte);
+            }
+            self.push(byte | 0x80)?;
+        }
+    }
+}
+
+impl<'a> ser::Serializer for &mut Serializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.push(v as u8)
+    }
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.push(v as u8)
+    }
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.varint(zigzag(v as i64))
+    }
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.varint(zigzag(v as i64))
+    }
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.varint(zigzag(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.push(v)
+    }
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.varint(v as u64)
+    }
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.varint(v as u64)
+    }
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.varint(v)
+    }
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.extend(&v.to_le_bytes())
+    }
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.extend(&v.to_le_bytes())
+    }
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.serialize_bytes(v.as_bytes())
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        self.varint(v.len() as u64)?;
+        self.extend(v)
+    }
+    fn serialize_none(self) -> Result<(), Error> {
+        self.push(0)
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        self.push(1)?;
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        Ok(())
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), Error> {
+        self.varint(variant_index as u64)
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.varint(variant_index as u64)?;
+        value.serialize(self)
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, Error> {
+        self.varint(len.ok_or(Error::UnknownLength)? as u64)?;
+        Ok(self)
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, Error> {
+        self.varint(variant_index as u64)?;
+        Ok(self)
+    }
+    fn serialize_map(self, len: Option<usize>) -> Result<Self, Error> {
+        self.varint(len.ok_or(Error::UnknownLength)? as u64)?;
+        Ok(self)
+    }
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, Error> {
+        self.varint(variant_index as u64)?;
+        Ok(self)
+    }
+    fn collect_str<T: core::fmt::Display + ?Sized>(self, _value: &T) -> Result<(), Error> {
+        Err(Error::Custom)
+    }
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+macro_rules! impl_compound {
+    ($($tr:ident :: $method:ident),*) => {
+        $(
+            impl ser::$tr for &mut Serializer<'_> {
+                type Ok = ();
+                type Error = Error;
+
+                fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+                    value.serialize(&mut **self)
+                }
+                fn end(self) -> Result<(), Error> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_compound!(
+    SerializeSeq::serialize_element,
+    SerializeTuple::serialize_element,
+    SerializeTupleStruct::serialize_field,
+    SerializeTupleVariant::serialize_field
+);
+
+impl ser::SerializeMap for &mut Serializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        key.serialize(&mut **self)
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut Serializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut Serializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+pub struct Deserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> Deserializer<'de> {
+    fn pop(&mut self) -> Result<u8, Error> {
+        let (&first, rest) = self.input.split_first().ok_or(Error::UnexpectedEnd)?;
+        self.input = rest;
+        Ok(first)
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'de [u8], Error> {
+        if self.input.len() < n {
+            return Err(Error::UnexpectedEnd);
+        }
+        let (taken, rest) = self.input.split_at(n);
+        self.input = rest;
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.pop()?;
+            v |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(Error::BadVarint)
+    }
+
+    fn varint_as<T: TryFrom<u64>>(&mut self) -> Result<T, Error> {
+        T::try_from(self.varint()?).map_err(|_| Error::BadVarint)
+    }
+
+    fn signed_as<T: TryFrom<i64>>(&mut self) -> Result<T, Error> {
+        T::try_from(unzigzag(self.varint()?)).map_err(|_| Error::BadVarint)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut out = [0; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    fn bytes(&mut self) -> Result<&'de [u8], Error> {
+        let len = self.varint_as::<usize>()?;
+        self.take(len)
+    }
+
+    fn str(&mut self) -> Result<&'de str, Error> {
+        core::str::from_utf8(self.bytes()?).map_err(|_| Error::BadUtf8)
+    }
+}
+
+struct Counted<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    left: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for Counted<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Counted<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let index = self.varint_as::<u32>()?;
+        let value = seed.deserialize(de::IntoDeserializer::<Error>::into_deserializer(index))?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+    fn tuple_variant<V: de::Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(Error::AnyUnsupported)
+    }
+    fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.pop()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            _ => Err(Error::BadBool),
+        }
+    }
+    fn deserialize_i8<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i8(self.pop()? as i8)
+    }
+    fn deserialize_i16<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i16(self.signed_as()?)
+    }
+    fn deserialize_i32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i32(self.signed_as()?)
+    }
+    fn deserialize_i64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(unzigzag(self.varint()?))
+    }
+    fn deserialize_u8<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u8(self.pop()?)
+    }
+    fn deserialize_u16<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u16(self.varint_as()?)
+    }
+    fn deserialize_u32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u32(self.varint_as()?)
+    }
+    fn deserialize_u64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(self.varint()?)
+    }
+    fn deserialize_f32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f32(f32::from_le_bytes(self.array()?))
+    }
+    fn deserialize_f64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f64(f64::from_le_bytes(self.array()?))
+    }
+    fn deserialize_char<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut chars = self.str()?.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(Error::BadChar),
+        }
+    }
+    fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_borrowed_str(self.str()?)
+    }
+    fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+    fn deserialize_bytes<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_borrowed_bytes(self.bytes()?)
+    }
+    fn deserialize_byte_buf<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.pop()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            _ => Err(Error::BadOption),
+        }
+    }
+    fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+    fn deserialize_unit_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+    fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let left = self.varint_as()?;
+        visitor.visit_seq(Counted { de: self, left })
+    }
+    fn deserialize_tuple<V: de::Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_seq(Counted {
+            de: self,
+            left: len,
+        })
+    }
+    fn deserialize_tuple_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+    fn deserialize_map<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let left = self.varint_as()?;
+        visitor.visit_map(Counted { de: self, left })
+    }
+    fn deserialize_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(self)
+    }
+    fn deserialize_identifier<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u32(visitor)
+    }
+    fn deserialize_ignored_any<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(Error::AnyUnsupported)
+    }
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+
+    fn encode<'a, T: Serialize + ?Sized>(value: &T, buf: &'a mut [u8]) -> &'a [u8] {
+        to_slice(value, buf).unwrap()
+    }
+
+    fn round_trip<T: Serialize + DeserializeOwned + PartialEq + core::fmt::Debug>(value: T) {
+        let mut buf = [0; 64];
+        let bytes = encode(&value, &mut buf);
+        assert_eq!(take_from_bytes::<T>(bytes), Ok((value, &[][..])));
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Unit,
+        Newtype(i16),
+        Tuple(u8, bool),
+        Struct { a: Option<u32>, b: f64 },
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        id: u16,
+        shape: Shape,
+        pair: (char, i64),
+        bytes: [u8; 3],
+    }
+
+    #[test]
+    fn varints_are_leb128() {
+        let mut buf = [0; 16];
+        assert_eq!(encode(&0u32, &mut buf), [0x00]);
+        assert_eq!(encode(&127u32, &mut buf), [0x7F]);
+        assert_eq!(encode(&128u32, &mut buf), [0x80, 0x01]);
+        assert_eq!(encode(&300u16, &mut buf), [0xAC, 0x02]);
+        assert_eq!(encode(&u32::MAX, &mut buf), [0xFF, 0xFF, 0xFF, 0xFF, 0x0F]);
+        assert_eq!(encode(&u64::MAX, &mut buf).len(), 10);
+        // Zigzag: small magnitudes of either sign stay short
+        assert_eq!(encode(&0i32, &mut buf), [0x00]);
+        assert_eq!(encode(&-1i32, &mut buf), [0x01]);
+        assert_eq!(encode(&1i32, &mut buf), [0x02]);
+        assert_eq!(encode(&-64i32, &mut buf), [0x7F]);
+        assert_eq!(encode(&64i32, &mut buf), [0x80, 0x01]);
+    }
+
+    #[test]
+    fn primitives_round_trip() {
+        for v in [0, 1, 127, 128, u64::MAX] {
+            round_trip(v);
+        }
+        for v in [i64::MIN, -1, 0, 1, i64::MAX] {
+            round_trip(v);
+        }
+        for v in [i32::MIN, i32::MAX] {
+            round_trip(v);
+        }
+        for v in [i16::MIN, i16::MAX] {
+            round_trip(v);
+        }
+        round_trip(i8::MIN);
+        round_trip(u8::MAX);
+        round_trip(u16::MAX);
+        round_trip(u32::MAX);
+        round_trip(f32::MIN_POSITIVE);
+        round_trip(-1.5f64);
+        round_trip(true);
+        round_trip('é');
+        round_trip(());
+        round_trip(None::<u8>);
+        round_trip(Some(Some(0u8)));
+    }
+
+    #[test]
+    fn compound_values_round_trip() {
+        for shape in [
+            Shape::Unit,
+            Shape::Newtype(-300),
+            Shape::Tuple(7, false),
+            Shape::Struct {
+                a: Some(u32::MAX),
+                b: 2.5,
+            },
+            Shape::Struct { a: None, b: 0.0 },
+        ] {
+            round_trip(shape);
+            round_trip(Record {
+                id: 1_000,
+                shape,
+                pair: ('x', i64::MIN),
+                bytes: [1, 2, 3],
+            });
+        }
+    }
+
+    #[test]
+    fn strings_and_sequences_are_counted() {
+        let mut buf = [0; 16];
+        let bytes = encode("héllo", &mut buf);
+        assert_eq!(bytes[0], 6);
+        assert_eq!(from_bytes::<&str>(bytes), Ok("héllo"));
+
+        assert_eq!(encode(&[0xFFu8, 0x00][..], &mut buf), [2, 0xFF, 0x00]);
+    }
+
+    #[test]
+    fn take_from_bytes_leaves_the_rest() {
+        let mut buf = [0; 16];
+        let len = encode(&(300u16, true), &mut buf).len();
+        buf[len] = 0xAA;
+        assert_eq!(
+            take_from_bytes::<(u16, bool)>(&buf[..len + 1]),
+            Ok(((300, true), &[0xAA][..]))
+        );
+    }
+
+    #[test]
+    fn truncated_input_runs_out() {
+        let record = Record {
+            id: u16::MAX,
+            shape: Shape::Struct {
+                a: Some(u32::MAX),
+                b: 1.0,
+            },
+            pair: ('ü', -1),
+            bytes: [9; 3],
+        };
+        let mut buf = [0; 64];
+        let bytes = encode(&record, &mut buf);
+        for len in 0..bytes.len() {
+            assert_eq!(
+                from_bytes::<Record>(&bytes[..len]),
+                Err(Error::UnexpectedEnd),
+                "cut to {len} bytes"
+            );
+        }
+        // A string longer than what's left
+        assert_eq!(from_bytes::<&str>(&[5, b'a']), Err(Error::UnexpectedEnd));
+    }
+
+    #[test]
+    fn over_long_varints_are_refused() {
+        // Eleven bytes all continuing: past 64 bits
+        assert_eq!(from_bytes::<u64>(&[0x80; 11]), Err(Error::BadVarint));
+        // Fits a u64, not the type read
+        let mut buf = [0; 16];
+        assert_eq!(
+            from_bytes::<u16>(encode(&65_536u32, &mut buf)),
+            Err(Error::BadVarint)
+        );
+        assert_eq!(
+            from_bytes::<i16>(encode(&-32_769i32, &mut buf)),
+            Err(Error::BadVarint)
+        );
+    }
+
+    #[test]
+    fn bad_tags_are_refused() {
+        assert_eq!(from_bytes::<Option<u8>>(&[2, 0]), Err(Error::BadOption));
+        assert_eq!(from_bytes::<bool>(&[2]), Err(Error::BadBool));
+        assert_eq!(from_bytes::<char>(&[2, b'a', b'b']), Err(Error::BadChar));
+        assert_eq!(from_bytes::<&str>(&[1, 0xFF]), Err(Error::BadUtf8));
+        // Past the last variant
+        assert!(from_bytes::<Shape>(&[4]).is_err());
+    }
+
+    #[test]
+    fn full_buffers_are_refused() {
+        let mut buf = [0; 4];
+        assert_eq!(to_slice(&u32::MAX, &mut buf), Err(Error::BufferFull));
+        assert_eq!(to_slice(&1.0f64, &mut buf), Err(Error::BufferFull));
+        assert_eq!(to_slice("hello", &mut buf), Err(Error::BufferFull));
+        assert_eq!(to_frame(&0u8, &mut []), Err(Error::BufferFull));
+        // No room left for the CRC
+        assert_eq!(to_frame(&0u16, &mut buf[..3]), Err(Error::BufferFull));
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut buf = [0; 64];
+        let shape = Shape::Struct {
+            a: Some(42),
+            b: -0.5,
+        };
+        let frame = to_frame(&shape, &mut buf).unwrap();
+        assert_eq!(frame[0], PROTOCOL_VERSION);
+        let (body, crc) = frame.split_at(frame.len() - 2);
+        assert_eq!(crc, crc16(body).to_le_bytes());
+        assert_eq!(from_frame::<Shape>(frame), Ok(shape));
+    }
+
+    #[test]
+    fn corrupted_frames_fail_the_crc() {
+        let mut buf = [0; 64];
+        let len = to_frame(&Shape::Newtype(5), &mut buf).unwrap().len();
+        for at in 0..len {
+            let mut frame = buf;
+            frame[at] ^= 0x10;
+            assert_eq!(
+                from_frame::<Shape>(&frame[..len]),
+                Err(Error::BadCrc),
+                "byte {at} flipped"
+            );
+        }
+        assert_eq!(
+            from_frame::<u8>(&[PROTOCOL_VERSION, 0]),
+            Err(Error::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let mut buf = [0; 64];
+        let len = to_frame(&7u8, &mut buf).unwrap().len();
+        buf[0] = PROTOCOL_VERSION + 1;
+        let crc = crc16(&buf[..len - 2]);
+        buf[len - 2..len].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(
+            from_frame::<u8>(&buf[..len]),
+            Err(Error::BadVersion(PROTOCOL_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn self_describing_reads_are_unsupported() {
+        struct Any;
+        impl<'de> Deserialize<'de> for Any {
+            fn deserialize<D: de::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                d.deserialize_any(de::IgnoredAny).map(|_| Any)
+            }
+        }
+        assert_eq!(
+            from_bytes::<Any>(&[0]).map(|_| ()),
+            Err(Error::AnyUnsupported)
+        );
+    }
+}
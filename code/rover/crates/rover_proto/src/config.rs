@@ -0,0 +1,379 @@
+//! The runtime configuration of the rover, and the messages changing it.
+
+use serde::{Deserialize, Serialize};
+use uom::si::{
+    electric_current::milliampere,
+    f32::{ElectricCurrent, Time},
+    time::millisecond,
+};
+
+use rover_lib::{
+    line::LineFollowParams, navigator::NavParams, power_budget, CollisionGuard, CompassParams,
+    CurrentLimit, EstimatorParams, FailsafeParams, GpsParams, HoldParams, InputShaping,
+    LowVoltageAction, NeutralMode, OvercurrentAction, PidGains, ThermalParams, TiltParams,
+    WheelTrim,
+};
+
+use crate::{framing, AckCode};
+
+pub const DEFAULT_GAINS: PidGains = PidGains::new(0.8, 2.0, 0.0);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ConfigMessage {
+    SafetyTimeoutMs(u32),
+    TelemetryPeriodMs(u32),
+    /// Maximum wheel power change per second, 0 disables slew limiting.
+    SlewRate(f32),
+    /// All zero disables heading hold.
+    HeadingGains(PidGains),
+    /// How the wheels are stopped when the safety timer fires, at once or
+    /// at the end of [`ConfigMessage::SafetyRampMs`].
+    SafetyStop(NeutralMode),
+    BatteryLowMv(u32),
+    LowVoltageAction(LowVoltageAction),
+    OvercurrentMa(u32),
+    OvercurrentMs(u32),
+    OvercurrentAction(OvercurrentAction),
+    StickDeadzone(f32),
+    StickExpo(f32),
+    PowerShaping(InputShaping),
+    TurnShaping(InputShaping),
+    CollisionGuard(CollisionGuard),
+    LineFollow(LineFollowParams),
+    Navigation(NavParams),
+    MaxCommandRateHz(u32),
+    /// Maximum wheel power change per second after the safety timer or the
+    /// e-stop stopped the robot, 0 disables it.
+    SoftStartRate(f32),
+    /// FL-FR-BL-BR.
+    WheelInverted([bool; 4]),
+    /// Most the absolute wheel powers may add up to, 4 disables it.
+    PowerBudget(f32),
+    Thermal(ThermalParams),
+    /// The parking brake, with nobody driving.
+    Hold(HoldParams),
+    Tilt(TiltParams),
+    Compass(CompassParams),
+    Gps(GpsParams),
+    Estimator(EstimatorParams),
+    /// Time to ramp the wheels down when the safety timer fires, before the
+    /// [`ConfigMessage::SafetyStop`], 0 stops them at once.
+    SafetyRampMs(u16),
+    /// The stages either side of the safety timeout.
+    Failsafe(FailsafeParams),
+    /// Anything but [`framing::BROADCAST`], taken on the next boot.
+    RoverId(u8),
+}
+
+/// Encoding of outgoing messages, incoming ones are accepted in both. JSON
+/// takes the `alloc` feature of the firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ProtocolMode {
+    Json,
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    pub safety_timeout_ms: u32,
+    /// 0 disables telemetry.
+    pub telemetry_period_ms: u32,
+    pub slew_rate: f32,
+    pub heading_gains: PidGains,
+    /// FL-FR-BL-BR.
+    pub wheel_trims: [WheelTrim; 4],
+    /// FL-FR-BL-BR, only used with closed loop wheels.
+    pub wheel_gains: [PidGains; 4],
+    pub protocol: ProtocolMode,
+    pub safety_stop: NeutralMode,
+    pub battery_low_mv: u32,
+    pub low_voltage_action: LowVoltageAction,
+    /// Per motor.
+    pub overcurrent_ma: u32,
+    pub overcurrent_ms: u32,
+    pub overcurrent_action: OvercurrentAction,
+    /// Shaping of the axes of [`RxBody::Sticks`](crate::RxBody::Sticks).
+    pub stick_deadzone: f32,
+    /// 0 is linear, 1 fully cubic.
+    pub stick_expo: f32,
+    /// Applied to every drive command, whatever its source.
+    pub power_shaping: InputShaping,
+    pub turn_shaping: InputShaping,
+    /// Slows forward motion near obstacles seen by the front rangefinders.
+    pub collision_guard: CollisionGuard,
+    pub line_follow: LineFollowParams,
+    pub navigation: NavParams,
+    /// Drive commands from the host coming faster are dropped, 0 lets them
+    /// all through.
+    pub max_command_rate_hz: u32,
+    /// Slew rate, when lower, from a stop by the safety timer or the e-stop
+    /// until the wheels catch up with the commands.
+    pub soft_start_rate: f32,
+    /// Wheels mounted or wired backwards, FL-FR-BL-BR.
+    pub wheel_inverted: [bool; 4],
+    /// Sum of the absolute wheel powers past which all are scaled down, for
+    /// a small battery or regulator. [`power_budget::UNLIMITED`] disables
+    /// it.
+    pub power_budget: f32,
+    /// Derating of the motors as they heat up.
+    pub thermal: ThermalParams,
+    /// When the position is held against pushes with nobody driving.
+    pub hold: HoldParams,
+    /// Past which the drive is cut, the robot about to tip over.
+    pub tilt: TiltParams,
+    /// The magnetometer and how much its heading is trusted.
+    pub compass: CompassParams,
+    /// Driving GPS waypoints.
+    pub gps: GpsParams,
+    /// How much each sensor of the pose estimate is trusted, with `ekf`.
+    pub estimator: EstimatorParams,
+    /// Deceleration on link loss, so the robot doesn't skid at speed, 0
+    /// goes straight to `safety_stop`.
+    pub safety_ramp_ms: u16,
+    /// Slowing down before the safety timeout, faulting after it.
+    pub failsafe: FailsafeParams,
+    /// The address of the rover on a shared bus, read on boot so it doesn't
+    /// drop off the bus mid-session: set it, save and restart.
+    pub rover_id: u8,
+}
+
+impl Config {
+    /// Longest [`rover_lib::wire`] encoding of a config, every integer at
+    /// its widest and the compass calibrated, for sizing its storage.
+    pub const MAX_WIRE_SIZE: usize = 342;
+    /// The buffer of the [`rover_lib::ConfigStore`] keeping it on the
+    /// rover, header included.
+    pub const STORE_SIZE: usize = 512;
+
+    const SAFETY_TIMEOUT_MS: core::ops::RangeInclusive<u32> = 100..=5_000;
+    pub const TELEMETRY_PERIOD_MS: core::ops::RangeInclusive<u32> = 20..=10_000;
+    const SLEW_RATE: core::ops::RangeInclusive<f32> = 0.0..=100.0;
+    const BATTERY_LOW_MV: core::ops::RangeInclusive<u32> = 3_000..=30_000;
+    const OVERCURRENT_MA: core::ops::RangeInclusive<u32> = 100..=20_000;
+    const OVERCURRENT_MS: core::ops::RangeInclusive<u32> = 0..=5_000;
+    const STICK_DEADZONE: core::ops::RangeInclusive<f32> = 0.0..=0.5;
+    const STICK_EXPO: core::ops::RangeInclusive<f32> = 0.0..=1.0;
+    const SAFETY_RAMP_MS: core::ops::RangeInclusive<u16> = 0..=2_000;
+    const MAX_COMMAND_RATE_HZ: core::ops::RangeInclusive<u32> = 5..=1_000;
+    /// A single wheel at half power, or less, can't get the robot going.
+    const POWER_BUDGET: core::ops::RangeInclusive<f32> = 0.5..=power_budget::UNLIMITED;
+
+    /// The defaults, `protocol` depending on the firmware build.
+    pub const fn new(protocol: ProtocolMode) -> Self {
+        Self {
+            safety_timeout_ms: 500,
+            telemetry_period_ms: 200,
+            slew_rate: 4.0,
+            heading_gains: PidGains::new(1.5, 0.2, 0.05),
+            wheel_trims: [WheelTrim::IDENTITY; 4],
+            wheel_gains: [DEFAULT_GAINS; 4],
+            protocol,
+            safety_stop: NeutralMode::Brake,
+            // 3.4 V per cell on a 2S pack
+            battery_low_mv: 6_800,
+            low_voltage_action: LowVoltageAction::LimitPower,
+            overcurrent_ma: 2_500,
+            overcurrent_ms: 300,
+            overcurrent_action: OvercurrentAction::Clamp,
+            stick_deadzone: 0.05,
+            stick_expo: 0.3,
+            power_shaping: InputShaping::IDENTITY,
+            turn_shaping: InputShaping::IDENTITY,
+            collision_guard: CollisionGuard {
+                stop_mm: 150,
+                slow_mm: 500,
+            },
+            line_follow: LineFollowParams {
+                gains: PidGains::new(0.6, 0.0, 0.05),
+                power: 0.3,
+            },
+            navigation: NavParams {
+                position_gains: PidGains::new(2.0, 0.1, 0.0),
+                heading_gains: PidGains::new(1.0, 0.0, 0.05),
+                max_power: 0.5,
+                tolerance_mm: 30,
+                heading_tolerance_deg: 5.0,
+            },
+            max_command_rate_hz: 50,
+            soft_start_rate: 1.0,
+            wheel_inverted: [false; 4],
+            power_budget: power_budget::UNLIMITED,
+            thermal: ThermalParams::DEFAULT,
+            hold: HoldParams::DEFAULT,
+            tilt: TiltParams::DEFAULT,
+            compass: CompassParams::DEFAULT,
+            gps: GpsParams::DEFAULT,
+            estimator: EstimatorParams::DEFAULT,
+            safety_ramp_ms: 0,
+            failsafe: FailsafeParams::DEFAULT,
+            rover_id: 0,
+        }
+    }
+
+    pub fn current_limit(&self) -> CurrentLimit {
+        CurrentLimit {
+            limit: ElectricCurrent::new::<milliampere>(self.overcurrent_ma as f32),
+            duration: Time::new::<millisecond>(self.overcurrent_ms as f32),
+            action: self.overcurrent_action,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        Self::SAFETY_TIMEOUT_MS.contains(&self.safety_timeout_ms)
+            && (self.telemetry_period_ms == 0
+                || Self::TELEMETRY_PERIOD_MS.contains(&self.telemetry_period_ms))
+            && Self::SLEW_RATE.contains(&self.slew_rate)
+            && Self::SLEW_RATE.contains(&self.soft_start_rate)
+            && Self::BATTERY_LOW_MV.contains(&self.battery_low_mv)
+            && Self::OVERCURRENT_MA.contains(&self.overcurrent_ma)
+            && Self::OVERCURRENT_MS.contains(&self.overcurrent_ms)
+            && Self::STICK_DEADZONE.contains(&self.stick_deadzone)
+            && Self::STICK_EXPO.contains(&self.stick_expo)
+            && self.power_shaping.is_valid()
+            && self.turn_shaping.is_valid()
+            && self.collision_guard.is_valid()
+            && self.line_follow.is_valid()
+            && self.navigation.is_valid()
+            && self.wheel_trims.iter().all(WheelTrim::is_valid)
+            && (self.max_command_rate_hz == 0
+                || Self::MAX_COMMAND_RATE_HZ.contains(&self.max_command_rate_hz))
+            && Self::POWER_BUDGET.contains(&self.power_budget)
+            && self.thermal.is_valid()
+            && self.hold.is_valid()
+            && self.tilt.is_valid()
+            && self.compass.is_valid()
+            && self.gps.is_valid()
+            && self.estimator.is_valid()
+            && Self::SAFETY_RAMP_MS.contains(&self.safety_ramp_ms)
+            && self.failsafe.is_valid()
+            && self.failsafe.fits(self.safety_timeout_ms)
+            && self.rover_id != framing::BROADCAST
+    }
+
+    /// Applies `msg` if the new value is within bounds.
+    pub fn apply(&mut self, msg: ConfigMessage) -> Result<(), AckCode> {
+        match msg {
+            ConfigMessage::SafetyTimeoutMs(ms)
+                if Self::SAFETY_TIMEOUT_MS.contains(&ms) && self.failsafe.fits(ms) =>
+            {
+                self.safety_timeout_ms = ms
+            }
+            ConfigMessage::TelemetryPeriodMs(ms)
+                if ms == 0 || Self::TELEMETRY_PERIOD_MS.contains(&ms) =>
+            {
+                self.telemetry_period_ms = ms
+            }
+            ConfigMessage::SlewRate(rate) if Self::SLEW_RATE.contains(&rate) => {
+                self.slew_rate = rate
+            }
+            ConfigMessage::HeadingGains(gains) => self.heading_gains = gains,
+            ConfigMessage::SafetyStop(mode) => self.safety_stop = mode,
+            ConfigMessage::BatteryLowMv(mv) if Self::BATTERY_LOW_MV.contains(&mv) => {
+                self.battery_low_mv = mv
+            }
+            ConfigMessage::LowVoltageAction(action) => self.low_voltage_action = action,
+            ConfigMessage::OvercurrentMa(ma) if Self::OVERCURRENT_MA.contains(&ma) => {
+                self.overcurrent_ma = ma
+            }
+            ConfigMessage::OvercurrentMs(ms) if Self::OVERCURRENT_MS.contains(&ms) => {
+                self.overcurrent_ms = ms
+            }
+            ConfigMessage::OvercurrentAction(action) => self.overcurrent_action = action,
+            ConfigMessage::StickDeadzone(deadzone) if Self::STICK_DEADZONE.contains(&deadzone) => {
+                self.stick_deadzone = deadzone
+            }
+            ConfigMessage::StickExpo(expo) if Self::STICK_EXPO.contains(&expo) => {
+                self.stick_expo = expo
+            }
+            ConfigMessage::PowerShaping(shaping) if shaping.is_valid() => {
+                self.power_shaping = shaping
+            }
+            ConfigMessage::TurnShaping(shaping) if shaping.is_valid() => {
+                self.turn_shaping = shaping
+            }
+            ConfigMessage::CollisionGuard(guard) if guard.is_valid() => {
+                self.collision_guard = guard
+            }
+            ConfigMessage::LineFollow(params) if params.is_valid() => self.line_follow = params,
+            ConfigMessage::Navigation(params) if params.is_valid() => self.navigation = params,
+            ConfigMessage::MaxCommandRateHz(hz)
+                if hz == 0 || Self::MAX_COMMAND_RATE_HZ.contains(&hz) =>
+            {
+                self.max_command_rate_hz = hz
+            }
+            ConfigMessage::SoftStartRate(rate) if Self::SLEW_RATE.contains(&rate) => {
+                self.soft_start_rate = rate
+            }
+            ConfigMessage::WheelInverted(inverted) => self.wheel_inverted = inverted,
+            ConfigMessage::PowerBudget(budget) if Self::POWER_BUDGET.contains(&budget) => {
+                self.power_budget = budget
+            }
+            ConfigMessage::Thermal(params) if params.is_valid() => self.thermal = params,
+            ConfigMessage::Hold(params) if params.is_valid() => self.hold = params,
+            ConfigMessage::Tilt(params) if params.is_valid() => self.tilt = params,
+            ConfigMessage::Compass(params) if params.is_valid() => self.compass = params,
+            ConfigMessage::Gps(params) if params.is_valid() => self.gps = params,
+            ConfigMessage::Estimator(params) if params.is_valid() => self.estimator = params,
+            ConfigMessage::SafetyRampMs(ms) if Self::SAFETY_RAMP_MS.contains(&ms) => {
+                self.safety_ramp_ms = ms
+            }
+            ConfigMessage::Failsafe(params)
+                if params.is_valid() && params.fits(self.safety_timeout_ms) =>
+            {
+                self.failsafe = params
+            }
+            ConfigMessage::RoverId(id) if id != framing::BROADCAST => self.rover_id = id,
+            _ => return Err(AckCode::OutOfRange),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rover_lib::{compass::MagCalibration, mock::MockFlash, wire, ConfigStore};
+
+    use super::*;
+
+    /// The defaults with the encoding of every field at its longest.
+    fn largest() -> Config {
+        let mut config = Config::new(ProtocolMode::Binary);
+        config.safety_timeout_ms = u32::MAX;
+        config.telemetry_period_ms = u32::MAX;
+        config.battery_low_mv = u32::MAX;
+        config.overcurrent_ma = u32::MAX;
+        config.overcurrent_ms = u32::MAX;
+        config.collision_guard = CollisionGuard {
+            stop_mm: u16::MAX,
+            slow_mm: u16::MAX,
+        };
+        config.navigation.tolerance_mm = u16::MAX;
+        config.max_command_rate_hz = u32::MAX;
+        config.tilt.trip_ms = u16::MAX;
+        config.compass.calibration = Some(MagCalibration {
+            offset: [0.0; 3],
+            scale: [1.0; 3],
+        });
+        config.safety_ramp_ms = u16::MAX;
+        config.failsafe.slow_ms = u32::MAX;
+        config.failsafe.fault_ms = u32::MAX;
+        config.rover_id = u8::MAX;
+        config
+    }
+
+    #[test]
+    fn max_wire_size_is_the_largest_encoding() {
+        let mut buf = [0; 1024];
+        let len = wire::to_slice(&largest(), &mut buf).unwrap().len();
+        assert_eq!(len, Config::MAX_WIRE_SIZE);
+    }
+
+    #[test]
+    fn round_trips_through_the_store() {
+        let mut store = ConfigStore::<_, { Config::STORE_SIZE }>::new(MockFlash::new(1), 0, 1);
+        for config in [Config::new(ProtocolMode::Json), largest()] {
+            store.save(&config).unwrap();
+            assert_eq!(store.load::<Config>(), Ok(config));
+        }
+    }
+}
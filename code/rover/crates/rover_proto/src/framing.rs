@@ -0,0 +1,362 @@
+//! Packets on the link: an address byte, the payload, its length as a little
+//! endian `u16` and a little endian CRC16 of all three, COBS encoded and
+//! terminated by a zero.
+//!
+//! The length catches packets run together when a terminator is lost, the
+//! CRC anything else the line garbled, so neither reaches serde.
+//!
+//! The address lets several rovers share a bus, RS-485 or a radio: the host
+//! puts the ID of the rover it's talking to, or [`BROADCAST`], and rovers
+//! put their own on what they send.
+
+use cobs::CobsEncoder;
+use rover_lib::crc::{crc16, crc16_update};
+
+/// The address.
+pub const HEADER_LEN: usize = 1;
+/// Length and CRC.
+pub const TRAILER_LEN: usize = 4;
+
+/// Every rover on the bus, none of them answering.
+pub const BROADCAST: u8 = 0xFF;
+
+/// Room for a packet of up to `payload` bytes, terminator included.
+pub const fn max_packet_len(payload: usize) -> usize {
+    let raw = HEADER_LEN + payload + TRAILER_LEN;
+    raw + raw / 254 + 2
+}
+
+/// A checked packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet<'a> {
+    pub address: u8,
+    pub payload: &'a [u8],
+}
+
+/// Whether a packet sent to `address` is for the rover `id`, itself or all
+/// of them.
+pub fn addressed_to(address: u8, id: u8) -> bool {
+    address == id || address == BROADCAST
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    BufferFull,
+    /// Not valid COBS.
+    Cobs,
+    /// Shorter than its trailer, or not the length it says.
+    BadLength,
+    BadCrc,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// Packs `payload` for `address` at the start of `out`, returning the length
+/// of the packet, terminator included.
+pub fn encode(address: u8, payload: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+    let len = u16::try_from(payload.len()).map_err(|_| Error::BufferFull)?;
+    let mut trailer = [0u8; TRAILER_LEN];
+    trailer[..2].copy_from_slice(&len.to_le_bytes());
+    let crc = crc16_update(crc16_update(crc16(&[address]), payload), &trailer[..2]);
+    trailer[2..].copy_from_slice(&crc.to_le_bytes());
+
+    // Keeping the last byte for the terminator
+    let body = out.len().checked_sub(1).ok_or(Error::BufferFull)?;
+    let mut encoder = CobsEncoder::new(&mut out[..body]);
+    encoder.push(&[address]).map_err(|()| Error::BufferFull)?;
+    encoder.push(payload).map_err(|()| Error::BufferFull)?;
+    encoder.push(&trailer).map_err(|()| Error::BufferFull)?;
+    let n = encoder.finalize().map_err(|()| Error::BufferFull)?;
+    out[n] = 0;
+    Ok(n + 1)
+}
+
+/// Decodes a packet without its terminator into `out`.
+pub fn decode<'a>(packet: &[u8], out: &'a mut [u8]) -> Result<Packet<'a>, Error> {
+    let n = cobs::decode(packet, out).map_err(|()| Error::Cobs)?;
+    check(&out[..n])
+}
+
+/// Checks the header and trailer of an already COBS decoded packet.
+pub fn check(raw: &[u8]) -> Result<Packet<'_>, Error> {
+    let Some(split) = raw.len().checked_sub(TRAILER_LEN) else {
+        return Err(Error::BadLength);
+    };
+    let (framed, trailer) = raw.split_at(split);
+    let Some((&address, payload)) = framed.split_first() else {
+        return Err(Error::BadLength);
+    };
+    if u16::from_le_bytes([trailer[0], trailer[1]]) as usize != payload.len() {
+        return Err(Error::BadLength);
+    }
+    let crc = crc16_update(crc16(framed), &trailer[..2]);
+    if u16::from_le_bytes([trailer[2], trailer[3]]) != crc {
+        return Err(Error::BadCrc);
+    }
+    Ok(Packet { address, payload })
+}
+
+/// Cuts a byte stream into packets at their terminators, for links that hand
+/// over bytes in bursts, like a UART read by DMA until the line goes idle.
+#[derive(Debug, Clone)]
+pub struct Splitter<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    /// The packet being collected outgrew `buf`, and is dropped at its
+    /// terminator.
+    overflowed: bool,
+}
+
+impl<const N: usize> Splitter<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Takes bytes off `data` up to and including the next terminator,
+    /// returning how many it took and, if that ended a packet, the packet
+    /// without its terminator. [`Error::BufferFull`] for one longer than `N`.
+    pub fn push(&mut self, data: &[u8]) -> (usize, Option<Result<&[u8], Error>>) {
+        let (taken, ended) = match data.iter().position(|&byte| byte == 0) {
+            Some(i) => (i + 1, true),
+            None => (data.len(), false),
+        };
+        let bytes = &data[..taken - ended as usize];
+        if !self.overflowed {
+            match self.buf.get_mut(self.len..self.len + bytes.len()) {
+                Some(room) => {
+                    room.copy_from_slice(bytes);
+                    self.len += bytes.len();
+                }
+                None => self.overflowed = true,
+            }
+        }
+        if !ended {
+            return (taken, None);
+        }
+
+        let len = core::mem::take(&mut self.len);
+        let packet = if core::mem::take(&mut self.overflowed) {
+            Some(Err(Error::BufferFull))
+        } else {
+            // Back to back terminators
+            (len > 0).then(|| Ok(&self.buf[..len]))
+        };
+        (taken, packet)
+    }
+
+    /// Drops the packet being collected, when the link lost bytes. What's
+    /// left of it still comes out, to fail [`check`].
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.overflowed = false;
+    }
+}
+
+impl<const N: usize> Default for Splitter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `payload` through [`encode`] and [`decode`], the terminator checked
+    /// and stripped in between.
+    fn round_trip(address: u8, payload: &[u8]) -> Result<(u8, heapless::Vec<u8, 600>), Error> {
+        let mut packet = [0u8; max_packet_len(512)];
+        let n = encode(address, payload, &mut packet)?;
+        assert_eq!(packet[n - 1], 0);
+        assert!(!packet[..n - 1].contains(&0));
+        let mut raw = [0u8; HEADER_LEN + 512 + TRAILER_LEN];
+        let Packet { address, payload } = decode(&packet[..n - 1], &mut raw)?;
+        Ok((address, heapless::Vec::from_slice(payload).unwrap()))
+    }
+
+    /// `payload` packed and COBS decoded, for [`check`] to take once
+    /// tampered with.
+    fn raw(address: u8, payload: &[u8]) -> heapless::Vec<u8, 600> {
+        let mut packet = [0u8; max_packet_len(512)];
+        let n = encode(address, payload, &mut packet).unwrap();
+        let mut raw = [0u8; 600];
+        let len = cobs::decode(&packet[..n - 1], &mut raw).unwrap();
+        heapless::Vec::from_slice(&raw[..len]).unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let long: heapless::Vec<u8, 512> = (0..512).map(|i| (i % 7) as u8).collect();
+        for payload in [&b""[..], b"{\"Ping\":1}", &[0, 0, 1, 0], &long] {
+            for address in [0, 1, 0x7F, BROADCAST] {
+                let (to, decoded) = round_trip(address, payload).unwrap();
+                assert_eq!(to, address);
+                assert_eq!(&decoded[..], payload);
+            }
+        }
+    }
+
+    #[test]
+    fn too_small_a_buffer_is_refused() {
+        let mut packet = [0u8; 8];
+        assert_eq!(encode(0, &[1; 8], &mut packet), Err(Error::BufferFull));
+        assert_eq!(encode(0, &[], &mut []), Err(Error::BufferFull));
+    }
+
+    #[test]
+    fn truncated_packets_are_refused() {
+        let raw = raw(3, b"drive");
+        for len in 0..HEADER_LEN + TRAILER_LEN {
+            assert_eq!(check(&raw[..len]), Err(Error::BadLength));
+        }
+        // Losing a byte off either end shifts what's read as the trailer
+        assert!(check(&raw[..raw.len() - 1]).is_err());
+        assert!(check(&raw[1..]).is_err());
+    }
+
+    #[test]
+    fn packets_run_together_are_refused() {
+        let mut joined = raw(3, b"first");
+        joined.extend_from_slice(&raw(3, b"second")).unwrap();
+        assert_eq!(check(&joined), Err(Error::BadLength));
+    }
+
+    #[test]
+    fn corruption_fails_the_crc() {
+        let raw = raw(3, b"drive");
+        // The length is covered by its own check first
+        for i in (0..raw.len()).filter(|&i| !(raw.len() - 4..raw.len() - 2).contains(&i)) {
+            let mut corrupted = raw.clone();
+            corrupted[i] ^= 0x10;
+            assert_eq!(check(&corrupted), Err(Error::BadCrc), "byte {i}");
+        }
+    }
+
+    #[test]
+    fn bad_cobs_is_refused() {
+        let mut raw = [0u8; 16];
+        // A code byte pointing past the end
+        assert_eq!(decode(&[9, 1, 2], &mut raw), Err(Error::Cobs));
+    }
+
+    /// Every packet in `stream`, through a splitter of `N` bytes, and then
+    /// [`decode`].
+    fn split<const N: usize>(
+        stream: &[u8],
+    ) -> heapless::Vec<Result<(u8, heapless::Vec<u8, 64>), Error>, 8> {
+        let mut splitter = Splitter::<N>::new();
+        let mut packets = heapless::Vec::new();
+        let mut data = stream;
+        while !data.is_empty() {
+            let (taken, packet) = splitter.push(data);
+            data = &data[taken..];
+            if let Some(packet) = packet {
+                let mut raw = [0u8; 64];
+                let decoded = packet.and_then(|packet| decode(packet, &mut raw)).map(
+                    |Packet { address, payload }| {
+                        (address, heapless::Vec::from_slice(payload).unwrap())
+                    },
+                );
+                packets.push(decoded).unwrap();
+            }
+        }
+        packets
+    }
+
+    fn packet(address: u8, payload: &[u8]) -> heapless::Vec<u8, 64> {
+        let mut packet = [0u8; 64];
+        let n = encode(address, payload, &mut packet).unwrap();
+        heapless::Vec::from_slice(&packet[..n]).unwrap()
+    }
+
+    #[test]
+    fn splitter_cuts_at_terminators() {
+        let mut stream: heapless::Vec<u8, 128> = heapless::Vec::new();
+        stream.extend_from_slice(&packet(1, b"one")).unwrap();
+        // Back to back terminators are skipped
+        stream.extend_from_slice(&[0, 0]).unwrap();
+        stream.extend_from_slice(&packet(2, b"two")).unwrap();
+
+        let packets = split::<64>(&stream);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(
+            packets[0].as_ref().unwrap(),
+            &(1, heapless::Vec::from_slice(b"one").unwrap())
+        );
+        assert_eq!(
+            packets[1].as_ref().unwrap(),
+            &(2, heapless::Vec::from_slice(b"two").unwrap())
+        );
+    }
+
+    #[test]
+    fn splitter_resyncs_after_garbage() {
+        let mut stream: heapless::Vec<u8, 128> = heapless::Vec::new();
+        // Line noise, then the tail of a packet whose start was lost
+        stream.extend_from_slice(&[0x55, 0xAA, 0x13]).unwrap();
+        stream.extend_from_slice(&packet(1, b"lost")[4..]).unwrap();
+        stream.extend_from_slice(&packet(1, b"good")).unwrap();
+
+        let packets = split::<64>(&stream);
+        assert_eq!(packets.len(), 2);
+        assert!(packets[0].is_err());
+        assert_eq!(
+            packets[1].as_ref().unwrap(),
+            &(1, heapless::Vec::from_slice(b"good").unwrap())
+        );
+    }
+
+    #[test]
+    fn splitter_drops_what_outgrows_it() {
+        let mut stream: heapless::Vec<u8, 128> = heapless::Vec::new();
+        stream.extend_from_slice(&[0x55; 20]).unwrap();
+        stream.push(0).unwrap();
+        stream.extend_from_slice(&packet(1, b"ok")).unwrap();
+
+        let packets = split::<16>(&stream);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0], Err(Error::BufferFull));
+        assert_eq!(
+            packets[1].as_ref().unwrap(),
+            &(1, heapless::Vec::from_slice(b"ok").unwrap())
+        );
+    }
+
+    #[test]
+    fn splitter_reset_drops_the_partial_packet() {
+        let mut splitter = Splitter::<64>::new();
+        let good = packet(1, b"ok");
+        assert_eq!(splitter.push(&good[..3]), (3, None));
+        splitter.reset();
+        let (taken, packet) = splitter.push(&good);
+        assert_eq!(taken, good.len());
+        let mut raw = [0u8; 64];
+        assert_eq!(
+            decode(packet.unwrap().unwrap(), &mut raw),
+            Ok(Packet {
+                address: 1,
+                payload: b"ok"
+            })
+        );
+    }
+
+    #[test]
+    fn addressing() {
+        assert!(addressed_to(3, 3));
+        assert!(addressed_to(BROADCAST, 3));
+        assert!(addressed_to(BROADCAST, 0));
+        assert!(!addressed_to(4, 3));
+        assert!(!addressed_to(0, 3));
+    }
+}
@@ -0,0 +1,881 @@
+//! The host link protocol: everything the rover and the host exchange, for
+//! the firmware and the host tools alike so they can't drift apart.
+//!
+//! Messages travel as [`framing`] packets, each either JSON or a
+//! [`rover_lib::wire`] frame. The binary encoding goes by the order of
+//! variants and fields: add to the end, never reorder.
+
+#![no_std]
+
+pub mod config;
+pub mod framing;
+pub mod params;
+
+use heapless::{String, Vec};
+use serde::{Deserialize, Serialize};
+use uom::si::f32::{ElectricCurrent, ElectricPotential, Length};
+
+use rover_lib::{
+    arming::Mode,
+    compass::MagCalibration,
+    estimator::Estimate,
+    event_log::Entry,
+    failsafe::FailsafeStage,
+    gps::{GeoPoint, GpsFix},
+    iface::{MecanumPower, MotorPower},
+    link_stats::LinkStats,
+    log::{Category, Level},
+    moves::{Move, MoveOutcome},
+    mux::Source,
+    navigator::Waypoint,
+    profile::Segment,
+    Angle, Attitude, DriveFrame, OvercurrentEvent, Pose, Turn, WheelTrim,
+};
+
+pub use config::{Config, ConfigMessage, ProtocolMode};
+pub use params::{Param, ParamName, ParamValue};
+
+/// Version of the messages below, exchanged in [`Hello`]. Bumped on any
+/// change older hosts can't cope with.
+pub const PROTOCOL_VERSION: u16 = 3;
+
+/// Largest decoded message the rover takes: room for a few segments of a
+/// profile or macro, in JSON.
+pub const RX_SIZE: usize = 512;
+/// Largest message the rover sends, before framing: room for the whole config
+/// in JSON.
+pub const TX_SIZE: usize = 1024;
+
+/// Longer routes don't decode.
+pub const MAX_WAYPOINTS: usize = 16;
+
+pub type Route = Vec<Waypoint, MAX_WAYPOINTS>;
+
+pub type GpsRoute = Vec<GeoPoint, MAX_WAYPOINTS>;
+
+/// Longer profiles don't decode.
+pub const MAX_SEGMENTS: usize = 32;
+
+pub type Segments = Vec<Segment, MAX_SEGMENTS>;
+
+/// The rover storage only fits a few full length ones anyway.
+pub const MAX_MACROS: usize = 4;
+pub const MAX_NAME_LEN: usize = 16;
+
+pub type MacroName = String<MAX_NAME_LEN>;
+
+/// A named profile, stored on the rover.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    pub name: MacroName,
+    pub segments: Segments,
+}
+
+impl Macro {
+    pub fn is_valid(&self) -> bool {
+        !self.name.is_empty()
+            && !self.segments.is_empty()
+            && self.segments.iter().all(Segment::is_valid)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Command {
+    pub p: MecanumPower,
+    pub th: Angle,
+    pub tu: Turn,
+}
+
+/// Where the route is at, for telemetry.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NavProgress {
+    pub waypoint: u8,
+    pub waypoints: u8,
+    pub distance: Length,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Telemetry {
+    pub uptime_ms: u64,
+    pub command: Command,
+    pub powers: [MotorPower; 4],
+    pub safety_tripped: bool,
+    pub estop: bool,
+    pub battery: Option<ElectricPotential>,
+    /// Motor currents, FL-FR-BL-BR, when sensed.
+    pub currents: Option<[ElectricCurrent; 4]>,
+    pub overcurrent_tripped: bool,
+    pub fault: bool,
+    pub attitude: Option<Attitude>,
+    /// In control of the robot, if anyone.
+    pub source: Option<Source>,
+    /// Ultrasonic distances front, right, back, left, when fitted. `None`
+    /// for nothing in range.
+    pub ranges: Option<[Option<Length>; 4]>,
+    /// Progress along the route, while navigating.
+    pub navigation: Option<NavProgress>,
+    /// Nearest obstacle ahead, ultrasonic or time-of-flight.
+    pub front_distance: Option<Length>,
+    /// Packets received but dropped as corrupted, since boot.
+    pub framing_errors: u32,
+    pub reset_cause: ResetCause,
+    /// AUX outputs that are on, bit `n` for the board's `n`th.
+    pub aux: u8,
+    pub mode: Mode,
+    /// What the power budget scaled the wheel powers by, 1 when within it.
+    pub power_scale: f32,
+    /// Tilted past the limit, the drive cut until level again.
+    pub tilted: bool,
+    /// The last GPS fix, with `gps`, kept while the receiver loses it.
+    pub gps: Option<GpsFix>,
+    /// The fused pose and velocity, with `ekf`.
+    pub estimate: Option<Estimate>,
+    /// What navigation drives on: the odometry, or the estimate with `ekf`.
+    pub pose: Option<Pose>,
+    /// The handheld's packets getting through, in percent, with `nrf24`.
+    pub radio_quality: Option<u8>,
+    /// Received bytes lost before they were parsed, the UART's DMA ring
+    /// overrunning or a packet too long to take, since boot.
+    pub rx_overflows: u32,
+    /// How far the failsafe went with the host quiet.
+    pub failsafe: FailsafeStage,
+}
+
+pub type AuxName = String<16>;
+
+/// What [`RxBody::Aux`] does to the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuxAction {
+    Set,
+    Clear,
+    Toggle,
+}
+
+/// What the last reset came from, as far as the chip's reset flags tell.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResetCause {
+    #[default]
+    Unknown,
+    PowerOn,
+    /// The reset pin: the button, or a probe.
+    Pin,
+    /// Requested by the firmware, e.g. after a panic.
+    Software,
+    Watchdog,
+    /// The supply sagged below the brownout threshold.
+    Brownout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AckCode {
+    Ok,
+    DriveFailed,
+    OutOfRange,
+    StorageFailed,
+    /// Refused while the e-stop is latched or still active.
+    Estopped,
+    /// Refused because the battery is low.
+    LowBattery,
+    /// Refused until the overcurrent trip is cleared.
+    Overcurrent,
+    /// Refused since the motor outputs were cut, until reset.
+    Fault,
+    /// Refused because a higher priority source is in control.
+    NotSelected,
+    /// Refused because the firmware was built without what it takes.
+    Unsupported,
+    /// No macro of that name is stored, or no parameter of that name.
+    NotFound,
+    /// The host speaks another [`PROTOCOL_VERSION`].
+    Incompatible,
+    /// Dropped for coming faster than
+    /// [`Config::max_command_rate_hz`](crate::Config::max_command_rate_hz).
+    RateLimited,
+    /// Refused until armed with [`RxBody::Arm`].
+    NotArmed,
+    /// Refused while tilted past the limit, about to tip over.
+    Tilted,
+    /// Refused until the GPS has a fix and the compass the heading.
+    NoFix,
+    /// Too many messages held for later already.
+    ScheduleFull,
+}
+
+/// Reply to a sequenced message; anything but [`AckCode::Ok`] is a NACK.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Ack {
+    pub seq: u32,
+    pub code: AckCode,
+}
+
+/// Reply to [`RxBody::Ping`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pong {
+    /// The ping's, for the host to time the round trip.
+    pub host_ms: u32,
+    pub uptime_ms: u64,
+    pub mode: Mode,
+    /// In control of the robot, if anyone.
+    pub source: Option<Source>,
+    pub safety_tripped: bool,
+    pub estop: bool,
+    pub fault: bool,
+}
+
+/// Reply to [`RxBody::TimeSync`], see [`rover_lib::timesync`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeSync {
+    /// The request's.
+    pub host_ms: u64,
+    /// The rover's uptime as it answered.
+    pub rover_ms: u64,
+}
+
+/// Longer defmt frames are dropped.
+pub const MAX_LOG_FRAME: usize = 96;
+
+/// One encoded defmt log frame, decoded on the host against the firmware
+/// ELF.
+pub type LogFrame = Vec<u8, MAX_LOG_FRAME>;
+
+pub type FirmwareVersion = String<16>;
+
+/// What the rover is and has, for the host to adapt to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hello {
+    pub firmware: FirmwareVersion,
+    pub protocol: u16,
+    pub capabilities: Capabilities,
+    pub reset_cause: ResetCause,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Chassis {
+    #[default]
+    Mecanum,
+    Differential,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub chassis: Chassis,
+    pub encoders: bool,
+    /// An IMU answered.
+    pub imu: bool,
+    pub current_sense: bool,
+    /// Ultrasonic rangers or a time-of-flight sensor.
+    pub ranging: bool,
+    /// Takes and can send JSON, not just binary frames.
+    pub json: bool,
+    /// Auxiliary servos, numbered from 0 for [`RxBody::SetServo`].
+    pub servos: u8,
+}
+
+/// Per-subsystem pass/fail bitmap of the boot self-test.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SelfTestReport {
+    /// Checks that actually ran.
+    pub tested: u16,
+    /// The ones among `tested` that passed.
+    pub passed: u16,
+}
+
+impl SelfTestReport {
+    pub fn record(&mut self, check: u16, passed: bool) {
+        self.tested |= check;
+        if passed {
+            self.passed |= check;
+        }
+    }
+
+    pub fn ok(&self) -> bool {
+        self.passed == self.tested
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum NavEvent {
+    Reached {
+        waypoint: u8,
+    },
+    Done,
+    /// Replaced by another route, or stopped.
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ProfileEvent {
+    Segment {
+        index: u16,
+    },
+    Paused,
+    Resumed,
+    Done,
+    /// Aborted, or replaced by another profile.
+    Aborted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TxMessage {
+    Telemetry(Telemetry),
+    Ack(Ack),
+    Overcurrent(OvercurrentEvent),
+    /// A wheel, FL-FR-BL-BR index, is pushed but doesn't turn.
+    Stall {
+        wheel: u8,
+    },
+    SelfTest(SelfTestReport),
+    Navigation(NavEvent),
+    Profile(ProfileEvent),
+    /// Reply to [`RxBody::ListMacros`].
+    Macros(Vec<MacroName, MAX_MACROS>),
+    /// Reply to [`RxBody::GetConfig`].
+    Config(Config),
+    /// Reply to [`RxBody::Hello`].
+    Hello(Hello),
+    /// A firmware log line, with `uart_log`.
+    Log(LogFrame),
+    /// One per stored entry, oldest first, in reply to
+    /// [`RxBody::DumpEvents`].
+    Event(Entry),
+    /// Reply to [`RxBody::GetParam`], or one per parameter in reply to
+    /// [`RxBody::ListParams`].
+    Param(Param),
+    /// The end of an [`RxBody::Move`].
+    Move(MoveOutcome),
+    /// The end of an [`RxBody::CalibrateCompass`], `None` if the robot
+    /// didn't turn enough.
+    CompassCalibration(Option<MagCalibration>),
+    /// Every [`rover_lib::link_stats::PERIOD_MS`].
+    LinkStats(LinkStats),
+    Pong(Pong),
+    TimeSync(TimeSync),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RxMessage {
+    /// When present the rover answers with an [`Ack`] carrying it back.
+    #[serde(default)]
+    pub seq: Option<u32>,
+    pub body: RxBody,
+    /// The rover uptime to handle the message at, ACKed as it's held, see
+    /// [`RxBody::TimeSync`]. `None`, or a time gone by, is at once.
+    #[serde(default)]
+    pub at_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RxBody {
+    Drive(DriveMessage),
+    /// Raw gamepad axes in -1..=1, shaped and mixed on the rover: `x` strafes
+    /// right, `y` goes forward, `rot` turns clockwise.
+    Sticks {
+        x: f32,
+        y: f32,
+        rot: f32,
+    },
+    Config(ConfigMessage),
+    SetDriveFrame(DriveFrame),
+    /// Wheel trims, FL-FR-BL-BR, applied and stored in flash.
+    Calibrate([WheelTrim; 4]),
+    /// Replaces the whole configuration, in RAM only.
+    SetConfig(Config),
+    /// Stores the current configuration in flash.
+    SaveConfig,
+    /// Releases a latched e-stop once its input is back to normal.
+    ClearEstop,
+    /// Allows driving again after an overcurrent trip.
+    ClearOvercurrent,
+    /// Starts or stops following a line, with `line_sensor`.
+    LineFollow(bool),
+    /// Drives through the waypoints in turn, in the odometry frame. An empty
+    /// route stops.
+    Navigate(Route),
+    /// Plays the segments in turn, replacing any profile playing.
+    Profile(Segments),
+    PauseProfile,
+    ResumeProfile,
+    AbortProfile,
+    /// Stores a macro in flash, replacing the one of the same name.
+    StoreMacro(Macro),
+    /// Plays a stored macro, like [`RxBody::Profile`].
+    RunMacro(MacroName),
+    DeleteMacro(MacroName),
+    ListMacros,
+    /// Answered with the configuration in use.
+    GetConfig,
+    /// Sent by a host first thing, with its [`PROTOCOL_VERSION`]. Answered
+    /// with the rover's [`Hello`], then NACKed with
+    /// [`AckCode::Incompatible`] if the versions differ.
+    Hello {
+        protocol: u16,
+    },
+    /// Sends the whole event log, then the ACK.
+    DumpEvents,
+    ClearEvents,
+    GetParam(ParamName),
+    /// Changes a single parameter, in RAM only. NACKed with
+    /// [`AckCode::OutOfRange`] if the value is of the wrong type or out of
+    /// bounds.
+    SetParam(Param),
+    /// Sends every parameter, then the ACK.
+    ListParams,
+    /// Moves an auxiliary servo, `angle` from its center. NACKed with
+    /// [`AckCode::NotFound`] for a servo that isn't there and
+    /// [`AckCode::OutOfRange`] past its travel.
+    SetServo {
+        index: u8,
+        angle: Angle,
+    },
+    /// Switches an AUX output of the board, lights or a relay. NACKed with
+    /// [`AckCode::NotFound`] for a name the board doesn't have.
+    Aux {
+        name: AuxName,
+        action: AuxAction,
+    },
+    /// Arms with `true`, drive commands being refused with
+    /// [`AckCode::NotArmed`] until then, and disarms with `false`.
+    Arm(bool),
+    /// Until reset, errors being logged whatever the level.
+    SetLogLevel {
+        category: Category,
+        level: Level,
+    },
+    /// Drives a set distance or angle on odometry, replacing any move
+    /// going. Its end is reported with [`TxMessage::Move`].
+    Move(Move),
+    AbortMove,
+    /// Turns on the spot for a while, or waits to be turned by hand when it
+    /// can't drive, to calibrate the magnetometer, with `magnetometer`. The
+    /// calibration is applied in RAM and reported with
+    /// [`TxMessage::CompassCalibration`].
+    CalibrateCompass,
+    /// Drives through the points in turn like [`RxBody::Navigate`], on GPS
+    /// and the compass, with `gps`. NACKed with [`AckCode::NoFix`] until
+    /// both are there. An empty route stops.
+    NavigateGps(GpsRoute),
+    /// The host's clock in ms, any epoch, echoed back in the next
+    /// [`TxMessage::LinkStats`] for the host to time the round trip.
+    Stamp(u32),
+    /// Keeps the link alive without driving: feeds the safety timer and
+    /// renews the host's control, if it has it, for the last drive command
+    /// to carry on. Answered at once with a [`TxMessage::Pong`] echoing the
+    /// host's clock in ms, any epoch. Usually sent without a `seq`.
+    Ping(u32),
+    /// The host's clock in ms, any epoch, answered at once with a
+    /// [`TxMessage::TimeSync`] for the host to map it to the rover's
+    /// uptime, which the telemetry, the event log and timed commands go by.
+    TimeSync(u64),
+    /// Drops the messages held for later.
+    ClearSchedule,
+    /// Latches the e-stop as if its input tripped, until
+    /// [`RxBody::ClearEstop`]. The one message rovers take sent to
+    /// [`framing::BROADCAST`], to stop them all.
+    Estop,
+}
+
+/// Only the values present change.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct DriveMessage {
+    pub p: Option<MecanumPower>,
+    pub th: Option<Angle>,
+    pub tu: Option<Turn>,
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+
+    use serde::de::DeserializeOwned;
+    use uom::si::{
+        angle::degree, electric_current::ampere, electric_potential::volt, f32::Velocity,
+        length::meter, velocity::meter_per_second,
+    };
+
+    use rover_lib::{
+        estimator::Estimate, event_log::Event, link_stats::StampEcho, wire, ChassisVelocity,
+        CollisionGuard, CompassParams, EstimatorParams, FailsafeParams, GpsParams, HoldParams,
+        InputShaping, LowVoltageAction, NeutralMode, OvercurrentAction, PidGains, ThermalParams,
+        TiltParams,
+    };
+
+    use super::*;
+
+    /// Through a frame and back, then encoded again to the same bytes.
+    /// Every shorter payload runs out.
+    fn round_trip<T: Serialize + DeserializeOwned + core::fmt::Debug>(value: &T) {
+        let mut buf = [0; TX_SIZE];
+        let frame = wire::to_frame(value, &mut buf).unwrap();
+        let decoded: T = wire::from_frame(frame).unwrap();
+        assert_eq!(format!("{decoded:?}"), format!("{value:?}"));
+        let mut again = [0; TX_SIZE];
+        assert_eq!(wire::to_frame(&decoded, &mut again).unwrap(), frame);
+
+        let payload = &frame[1..frame.len() - 2];
+        for len in 0..payload.len() {
+            assert_eq!(
+                wire::from_bytes::<T>(&payload[..len]).map(|_| ()),
+                Err(wire::Error::UnexpectedEnd),
+                "{value:?} cut to {len} bytes"
+            );
+        }
+    }
+
+    fn angle(deg: f32) -> Angle {
+        Angle::new::<degree>(deg)
+    }
+
+    fn length(m: f32) -> Length {
+        Length::new::<meter>(m)
+    }
+
+    fn pose() -> Pose {
+        Pose {
+            x: length(1.5),
+            y: length(-0.25),
+            heading: angle(-30.0),
+        }
+    }
+
+    fn gps_fix() -> GpsFix {
+        GpsFix {
+            position: GeoPoint {
+                lat_e7: 481_173_000,
+                lon_e7: -115_166_666,
+            },
+            speed: Velocity::new::<meter_per_second>(1.2),
+            course: Some(angle(84.4)),
+            satellites: 8,
+        }
+    }
+
+    fn segment() -> Segment {
+        Segment {
+            duration_ms: 1_500,
+            power: MecanumPower::new(0.5),
+            angle: angle(90.0),
+            turn: Turn::new(-0.2),
+        }
+    }
+
+    fn name(name: &str) -> MacroName {
+        MacroName::try_from(name).unwrap()
+    }
+
+    fn rx(seq: Option<u32>, body: RxBody, at_ms: Option<u64>) -> RxMessage {
+        RxMessage { seq, body, at_ms }
+    }
+
+    #[test]
+    fn rx_messages_round_trip() {
+        let config = Config::new(ProtocolMode::Binary);
+        let bodies = [
+            RxBody::Drive(DriveMessage {
+                p: Some(MecanumPower::new(0.75)),
+                th: None,
+                tu: Some(Turn::new(-1.0)),
+            }),
+            RxBody::Drive(DriveMessage::default()),
+            RxBody::Sticks {
+                x: -1.0,
+                y: 0.5,
+                rot: 0.0,
+            },
+            RxBody::Config(ConfigMessage::SafetyTimeoutMs(750)),
+            RxBody::SetDriveFrame(DriveFrame::Field),
+            RxBody::Calibrate([WheelTrim::IDENTITY; 4]),
+            RxBody::SetConfig(config),
+            RxBody::SaveConfig,
+            RxBody::ClearEstop,
+            RxBody::ClearOvercurrent,
+            RxBody::LineFollow(true),
+            RxBody::Navigate(
+                [Waypoint {
+                    x: length(2.0),
+                    y: length(-1.0),
+                    heading: angle(45.0),
+                }; MAX_WAYPOINTS]
+                    .into_iter()
+                    .collect(),
+            ),
+            RxBody::Navigate(Route::new()),
+            RxBody::Profile([segment(); MAX_SEGMENTS].into_iter().collect()),
+            RxBody::PauseProfile,
+            RxBody::ResumeProfile,
+            RxBody::AbortProfile,
+            RxBody::StoreMacro(Macro {
+                name: name("square"),
+                segments: [segment(); 4].into_iter().collect(),
+            }),
+            RxBody::RunMacro(name("square")),
+            RxBody::DeleteMacro(name("")),
+            RxBody::ListMacros,
+            RxBody::GetConfig,
+            RxBody::Hello {
+                protocol: PROTOCOL_VERSION,
+            },
+            RxBody::DumpEvents,
+            RxBody::ClearEvents,
+            RxBody::GetParam(ParamName::try_from("slew_rate").unwrap()),
+            RxBody::SetParam(Param {
+                name: ParamName::try_from("safety_ramp_ms").unwrap(),
+                value: ParamValue::U16(u16::MAX),
+            }),
+            RxBody::ListParams,
+            RxBody::SetServo {
+                index: 1,
+                angle: angle(-45.0),
+            },
+            RxBody::Aux {
+                name: AuxName::try_from("lights").unwrap(),
+                action: AuxAction::Toggle,
+            },
+            RxBody::Arm(false),
+            RxBody::SetLogLevel {
+                category: Category::Sensors,
+                level: Level::Trace,
+            },
+            RxBody::Move(Move::Drive {
+                distance: length(-0.5),
+                angle: angle(180.0),
+            }),
+            RxBody::Move(Move::Rotate { angle: angle(90.0) }),
+            RxBody::AbortMove,
+            RxBody::CalibrateCompass,
+            RxBody::NavigateGps([gps_fix().position; 3].into_iter().collect()),
+            RxBody::Stamp(u32::MAX),
+            RxBody::Ping(0),
+            RxBody::TimeSync(u64::MAX),
+            RxBody::ClearSchedule,
+            RxBody::Estop,
+        ];
+        for body in bodies {
+            round_trip(&rx(None, body.clone(), None));
+            round_trip(&rx(Some(u32::MAX), body, Some(123_456_789)));
+        }
+    }
+
+    #[test]
+    fn config_messages_round_trip() {
+        let gains = PidGains::new(1.0, -0.5, 0.0);
+        let messages = [
+            ConfigMessage::SafetyTimeoutMs(500),
+            ConfigMessage::TelemetryPeriodMs(0),
+            ConfigMessage::SlewRate(4.0),
+            ConfigMessage::HeadingGains(gains),
+            ConfigMessage::SafetyStop(NeutralMode::Coast),
+            ConfigMessage::BatteryLowMv(6_800),
+            ConfigMessage::LowVoltageAction(LowVoltageAction::LimitPower),
+            ConfigMessage::OvercurrentMa(20_000),
+            ConfigMessage::OvercurrentMs(300),
+            ConfigMessage::OvercurrentAction(OvercurrentAction::Trip),
+            ConfigMessage::StickDeadzone(0.05),
+            ConfigMessage::StickExpo(0.3),
+            ConfigMessage::PowerShaping(InputShaping::IDENTITY),
+            ConfigMessage::TurnShaping(InputShaping {
+                deadzone: 0.1,
+                expo: 0.5,
+                max_output: 0.8,
+            }),
+            ConfigMessage::CollisionGuard(CollisionGuard {
+                stop_mm: 150,
+                slow_mm: 500,
+            }),
+            ConfigMessage::LineFollow(config::Config::new(ProtocolMode::Json).line_follow),
+            ConfigMessage::Navigation(config::Config::new(ProtocolMode::Json).navigation),
+            ConfigMessage::MaxCommandRateHz(50),
+            ConfigMessage::SoftStartRate(1.0),
+            ConfigMessage::WheelInverted([true, false, false, true]),
+            ConfigMessage::PowerBudget(2.0),
+            ConfigMessage::Thermal(ThermalParams::DEFAULT),
+            ConfigMessage::Hold(HoldParams::DEFAULT),
+            ConfigMessage::Tilt(TiltParams::DEFAULT),
+            ConfigMessage::Compass(CompassParams {
+                calibration: Some(MagCalibration {
+                    offset: [0.1, -0.2, 0.3],
+                    scale: [1.0, 1.1, 0.9],
+                }),
+                ..CompassParams::DEFAULT
+            }),
+            ConfigMessage::Compass(CompassParams::DEFAULT),
+            ConfigMessage::Gps(GpsParams::DEFAULT),
+            ConfigMessage::Estimator(EstimatorParams::DEFAULT),
+            ConfigMessage::SafetyRampMs(2_000),
+            ConfigMessage::Failsafe(FailsafeParams::DEFAULT),
+            ConfigMessage::RoverId(7),
+        ];
+        for message in messages {
+            round_trip(&rx(Some(1), RxBody::Config(message), None));
+        }
+    }
+
+    #[test]
+    fn tx_messages_round_trip() {
+        let telemetry = Telemetry {
+            uptime_ms: u64::MAX,
+            command: Command {
+                p: MecanumPower::new(1.0),
+                th: angle(270.0),
+                tu: Turn::new(0.5),
+            },
+            powers: [-1.0, -0.5, 0.5, 1.0].map(MotorPower::new),
+            safety_tripped: true,
+            estop: false,
+            battery: Some(ElectricPotential::new::<volt>(7.4)),
+            currents: Some([0.5, 1.0, 1.5, 2.0].map(ElectricCurrent::new::<ampere>)),
+            overcurrent_tripped: false,
+            fault: true,
+            attitude: Some(Attitude {
+                roll: angle(1.0),
+                pitch: angle(-2.0),
+                heading: angle(3.0),
+            }),
+            source: Some(Source::Hold),
+            ranges: Some([Some(length(0.3)), None, Some(length(4.0)), None]),
+            navigation: Some(NavProgress {
+                waypoint: 2,
+                waypoints: 5,
+                distance: length(1.25),
+            }),
+            front_distance: Some(length(0.15)),
+            framing_errors: 42,
+            reset_cause: ResetCause::Brownout,
+            aux: 0b101,
+            mode: Mode::Failsafe,
+            power_scale: 0.5,
+            tilted: true,
+            gps: Some(gps_fix()),
+            estimate: Some(Estimate {
+                pose: pose(),
+                velocity: ChassisVelocity {
+                    vx: Velocity::new::<meter_per_second>(0.1),
+                    ..ChassisVelocity::default()
+                },
+                position_std: length(0.05),
+                heading_std: angle(2.0),
+            }),
+            pose: Some(pose()),
+            radio_quality: Some(100),
+            rx_overflows: u32::MAX,
+            failsafe: FailsafeStage::Faulted,
+        };
+        let empty_telemetry = Telemetry {
+            battery: None,
+            currents: None,
+            attitude: None,
+            source: None,
+            ranges: None,
+            navigation: None,
+            front_distance: None,
+            gps: None,
+            estimate: None,
+            pose: None,
+            radio_quality: None,
+            ..telemetry
+        };
+        let pong = Pong {
+            host_ms: 12_345,
+            uptime_ms: 67_890,
+            mode: Mode::Driving,
+            source: Some(Source::Host),
+            safety_tripped: false,
+            estop: true,
+            fault: false,
+        };
+        let messages = [
+            TxMessage::Telemetry(telemetry),
+            TxMessage::Telemetry(empty_telemetry),
+            TxMessage::Ack(Ack {
+                seq: 9,
+                code: AckCode::ScheduleFull,
+            }),
+            TxMessage::Overcurrent(OvercurrentEvent {
+                wheel: 3,
+                current: ElectricCurrent::new::<ampere>(3.5),
+                action: OvercurrentAction::Clamp,
+            }),
+            TxMessage::Stall { wheel: 0 },
+            TxMessage::SelfTest(SelfTestReport {
+                tested: 0b1111,
+                passed: 0b1011,
+            }),
+            TxMessage::Navigation(NavEvent::Reached { waypoint: 4 }),
+            TxMessage::Navigation(NavEvent::Done),
+            TxMessage::Navigation(NavEvent::Cancelled),
+            TxMessage::Profile(ProfileEvent::Segment { index: 31 }),
+            TxMessage::Profile(ProfileEvent::Paused),
+            TxMessage::Profile(ProfileEvent::Resumed),
+            TxMessage::Profile(ProfileEvent::Done),
+            TxMessage::Profile(ProfileEvent::Aborted),
+            TxMessage::Macros([name("a"), name("sixteen-chars-xx")].into_iter().collect()),
+            TxMessage::Macros(Vec::new()),
+            TxMessage::Config(Config::new(ProtocolMode::Json)),
+            TxMessage::Hello(Hello {
+                firmware: FirmwareVersion::try_from("0.1.0").unwrap(),
+                protocol: PROTOCOL_VERSION,
+                capabilities: Capabilities {
+                    chassis: Chassis::Differential,
+                    encoders: true,
+                    imu: false,
+                    current_sense: true,
+                    ranging: false,
+                    json: true,
+                    servos: 2,
+                },
+                reset_cause: ResetCause::Watchdog,
+            }),
+            TxMessage::Log([0xFF; MAX_LOG_FRAME].into_iter().collect()),
+            TxMessage::Event(Entry {
+                seq: 1,
+                uptime_ms: 2,
+                event: Event::Overcurrent { wheel: 2 },
+            }),
+            TxMessage::Event(Entry {
+                seq: u32::MAX,
+                uptime_ms: u32::MAX,
+                event: Event::Failsafe,
+            }),
+            TxMessage::Param(Param {
+                name: ParamName::try_from("tilt.limit_deg").unwrap(),
+                value: ParamValue::F32(-0.0),
+            }),
+            TxMessage::Param(Param {
+                name: ParamName::try_from("hold").unwrap(),
+                value: ParamValue::Bool(true),
+            }),
+            TxMessage::Move(MoveOutcome::Aborted),
+            TxMessage::CompassCalibration(None),
+            TxMessage::CompassCalibration(Some(MagCalibration {
+                offset: [1.0, 2.0, 3.0],
+                scale: [0.5; 3],
+            })),
+            TxMessage::LinkStats(LinkStats {
+                frames: 1_000,
+                crc_errors: 1,
+                decode_errors: 2,
+                overflows: 3,
+                commands_per_second: 49.5,
+                since_last_frame_ms: Some(20),
+                echo: Some(StampEcho {
+                    host_ms: u32::MAX,
+                    held_ms: 3,
+                }),
+            }),
+            TxMessage::LinkStats(LinkStats::default()),
+            TxMessage::Pong(pong),
+            TxMessage::Pong(Pong {
+                source: None,
+                ..pong
+            }),
+            TxMessage::TimeSync(TimeSync {
+                host_ms: u64::MAX,
+                rover_ms: 0,
+            }),
+        ];
+        for message in messages {
+            round_trip(&message);
+        }
+    }
+}
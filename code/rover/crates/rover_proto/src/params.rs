@@ -0,0 +1,185 @@
+//! The numeric values of the [`Config`], each under a name of its own, to
+//! be read and changed one at a time without knowing the whole layout.
+
+use heapless::String;
+use serde::{Deserialize, Serialize};
+
+use crate::{AckCode, Config};
+
+pub type ParamName = String<32>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ParamValue {
+    U16(u16),
+    U32(u32),
+    F32(f32),
+    Bool(bool),
+}
+
+impl ParamValue {
+    /// `text` parsed as a value of the same type, for text interfaces.
+    pub fn parse_as(self, text: &str) -> Option<Self> {
+        Some(match self {
+            Self::U16(_) => Self::U16(text.parse().ok()?),
+            Self::U32(_) => Self::U32(text.parse().ok()?),
+            Self::F32(_) => Self::F32(text.parse().ok()?),
+            Self::Bool(_) => Self::Bool(text.parse().ok()?),
+        })
+    }
+}
+
+impl core::fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::U16(value) => value.fmt(f),
+            Self::U32(value) => value.fmt(f),
+            Self::F32(value) => value.fmt(f),
+            Self::Bool(value) => value.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Param {
+    pub name: ParamName,
+    pub value: ParamValue,
+}
+
+/// How to reach one value of the [`Config`].
+pub struct ParamDef {
+    pub name: &'static str,
+    pub get: fn(&Config) -> ParamValue,
+    /// `None` if `value` isn't of the right type.
+    pub set: fn(&mut Config, ParamValue) -> Option<()>,
+}
+
+impl ParamDef {
+    pub fn param(&self, config: &Config) -> Param {
+        Param {
+            // Names are checked to fit by `PARAMS`
+            name: ParamName::try_from(self.name).unwrap_or_default(),
+            value: (self.get)(config),
+        }
+    }
+}
+
+macro_rules! params {
+    ($($name:literal => $ty:ident($($field:tt)+),)*) => {
+        /// Every parameter, in a stable order.
+        pub const PARAMS: &[ParamDef] = &[$(
+            ParamDef {
+                name: {
+                    assert!($name.len() <= 32);
+                    $name
+                },
+                get: |config| ParamValue::$ty(config.$($field)+),
+                set: |config, value| match value {
+                    ParamValue::$ty(value) => {
+                        config.$($field)+ = value;
+                        Some(())
+                    }
+                    _ => None,
+                },
+            },
+        )*];
+    };
+}
+
+params! {
+    "safety_timeout_ms" => U32(safety_timeout_ms),
+    "telemetry_period_ms" => U32(telemetry_period_ms),
+    "slew_rate" => F32(slew_rate),
+    "heading.kp" => F32(heading_gains.kp),
+    "heading.ki" => F32(heading_gains.ki),
+    "heading.kd" => F32(heading_gains.kd),
+    "trim.fl.scale" => F32(wheel_trims[0].scale),
+    "trim.fl.deadband" => F32(wheel_trims[0].deadband),
+    "trim.fr.scale" => F32(wheel_trims[1].scale),
+    "trim.fr.deadband" => F32(wheel_trims[1].deadband),
+    "trim.bl.scale" => F32(wheel_trims[2].scale),
+    "trim.bl.deadband" => F32(wheel_trims[2].deadband),
+    "trim.br.scale" => F32(wheel_trims[3].scale),
+    "trim.br.deadband" => F32(wheel_trims[3].deadband),
+    "wheel.fl.kp" => F32(wheel_gains[0].kp),
+    "wheel.fl.ki" => F32(wheel_gains[0].ki),
+    "wheel.fl.kd" => F32(wheel_gains[0].kd),
+    "wheel.fr.kp" => F32(wheel_gains[1].kp),
+    "wheel.fr.ki" => F32(wheel_gains[1].ki),
+    "wheel.fr.kd" => F32(wheel_gains[1].kd),
+    "wheel.bl.kp" => F32(wheel_gains[2].kp),
+    "wheel.bl.ki" => F32(wheel_gains[2].ki),
+    "wheel.bl.kd" => F32(wheel_gains[2].kd),
+    "wheel.br.kp" => F32(wheel_gains[3].kp),
+    "wheel.br.ki" => F32(wheel_gains[3].ki),
+    "wheel.br.kd" => F32(wheel_gains[3].kd),
+    "battery_low_mv" => U32(battery_low_mv),
+    "overcurrent_ma" => U32(overcurrent_ma),
+    "overcurrent_ms" => U32(overcurrent_ms),
+    "stick_deadzone" => F32(stick_deadzone),
+    "stick_expo" => F32(stick_expo),
+    "power_shaping.deadzone" => F32(power_shaping.deadzone),
+    "power_shaping.expo" => F32(power_shaping.expo),
+    "power_shaping.max_output" => F32(power_shaping.max_output),
+    "turn_shaping.deadzone" => F32(turn_shaping.deadzone),
+    "turn_shaping.expo" => F32(turn_shaping.expo),
+    "turn_shaping.max_output" => F32(turn_shaping.max_output),
+    "collision_guard.stop_mm" => U16(collision_guard.stop_mm),
+    "collision_guard.slow_mm" => U16(collision_guard.slow_mm),
+    "line_follow.kp" => F32(line_follow.gains.kp),
+    "line_follow.ki" => F32(line_follow.gains.ki),
+    "line_follow.kd" => F32(line_follow.gains.kd),
+    "line_follow.power" => F32(line_follow.power),
+    "navigation.position.kp" => F32(navigation.position_gains.kp),
+    "navigation.position.ki" => F32(navigation.position_gains.ki),
+    "navigation.position.kd" => F32(navigation.position_gains.kd),
+    "navigation.heading.kp" => F32(navigation.heading_gains.kp),
+    "navigation.heading.ki" => F32(navigation.heading_gains.ki),
+    "navigation.heading.kd" => F32(navigation.heading_gains.kd),
+    "navigation.max_power" => F32(navigation.max_power),
+    "navigation.tolerance_mm" => U16(navigation.tolerance_mm),
+    "navigation.heading_tolerance" => F32(navigation.heading_tolerance_deg),
+    "max_command_rate_hz" => U32(max_command_rate_hz),
+    "soft_start_rate" => F32(soft_start_rate),
+    "invert.fl" => Bool(wheel_inverted[0]),
+    "invert.fr" => Bool(wheel_inverted[1]),
+    "invert.bl" => Bool(wheel_inverted[2]),
+    "invert.br" => Bool(wheel_inverted[3]),
+    "power_budget" => F32(power_budget),
+    "thermal.time_constant_s" => F32(thermal.time_constant_s),
+    "thermal.derate_start" => F32(thermal.derate_start),
+    "thermal.derate_end" => F32(thermal.derate_end),
+    "thermal.min_power" => F32(thermal.min_power),
+    "hold.slope_deg" => F32(hold.slope_deg),
+    "tilt.limit_deg" => F32(tilt.limit_deg),
+    "tilt.hysteresis_deg" => F32(tilt.hysteresis_deg),
+    "tilt.trip_ms" => U16(tilt.trip_ms),
+    "compass.weight" => F32(compass.weight),
+    "gps.tolerance_m" => F32(gps.tolerance_m),
+    "gps.declination_deg" => F32(gps.declination_deg),
+    "ekf.accel_noise" => F32(estimator.accel_noise),
+    "ekf.turn_accel_noise" => F32(estimator.turn_accel_noise),
+    "ekf.odometry_noise" => F32(estimator.odometry_noise),
+    "ekf.odometry_turn_noise" => F32(estimator.odometry_turn_noise),
+    "ekf.heading_noise_deg" => F32(estimator.heading_noise_deg),
+    "ekf.gps_noise_m" => F32(estimator.gps_noise_m),
+    "safety_ramp_ms" => U16(safety_ramp_ms),
+    "failsafe.slow_ms" => U32(failsafe.slow_ms),
+    "failsafe.slow_power" => F32(failsafe.slow_power),
+    "failsafe.fault_ms" => U32(failsafe.fault_ms),
+}
+
+pub fn find(name: &str) -> Option<&'static ParamDef> {
+    PARAMS.iter().find(|def| def.name == name)
+}
+
+/// The `config` with the parameter changed, if it exists, `value` is of its
+/// type and the result is valid.
+pub fn apply(config: &Config, name: &str, value: ParamValue) -> Result<Config, AckCode> {
+    let def = find(name).ok_or(AckCode::NotFound)?;
+    let mut new = *config;
+    (def.set)(&mut new, value).ok_or(AckCode::OutOfRange)?;
+    if !new.is_valid() {
+        return Err(AckCode::OutOfRange);
+    }
+    Ok(new)
+}
@@ -0,0 +1,200 @@
+//! Stands in for the rover on a TCP socket, speaking the host link protocol
+//! with simulated kinematics and odometry, for developing host software
+//! without the hardware.
+//!
+//! `rover_sim [address] [--binary] [--id <rover id>]`, listening on
+//! `127.0.0.1:7878` by default. Like the firmware both encodings are
+//! understood, replies are in JSON unless `--binary`, and packets for
+//! another rover ID than 0 unless `--id` are dropped. One client at a time.
+
+mod protocol;
+mod sim;
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use uom::si::{angle::degree, f32::Time, length::meter, time::second};
+
+use rover_lib::link_stats;
+use rover_proto::{framing, Ack, RxBody, RxMessage, TxMessage};
+
+use protocol::Encoding;
+use sim::SimRover;
+
+const STEP: Duration = Duration::from_millis(10);
+/// Same as the firmware default.
+const TELEMETRY_PERIOD: Duration = Duration::from_millis(200);
+
+fn main() {
+    let mut address = String::from("127.0.0.1:7878");
+    let mut encoding = Encoding::Json;
+    let mut id = 0;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--binary" => encoding = Encoding::Binary,
+            "--id" => match args.next().and_then(|id| id.parse().ok()) {
+                Some(arg) if arg != framing::BROADCAST => id = arg,
+                _ => {
+                    eprintln!("--id takes a rover ID below {}", framing::BROADCAST);
+                    return;
+                }
+            },
+            _ => address = arg,
+        }
+    }
+
+    let listener = TcpListener::bind(&address).expect("failed to bind");
+    eprintln!("listening on {address}");
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                eprintln!("client connected");
+                if let Err(e) = serve(stream, encoding, id) {
+                    eprintln!("client gone: {e}");
+                }
+            }
+            Err(e) => eprintln!("failed to accept: {e}"),
+        }
+    }
+}
+
+/// Runs a fresh simulation for as long as the client stays connected.
+fn serve(mut stream: TcpStream, encoding: Encoding, id: u8) -> std::io::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let reader = stream.try_clone()?;
+    thread::spawn(move || receive(reader, tx));
+
+    let mut rover = SimRover::new();
+    let mut last_telemetry = Instant::now();
+    let mut last_link_stats = Instant::now();
+    let mut last_log = Instant::now();
+    loop {
+        loop {
+            match rx.try_recv() {
+                Ok(Err(_)) => rover.framing_error(),
+                Ok(Ok((address, _))) if !framing::addressed_to(address, id) => {}
+                // Unanswered, like the firmware
+                Ok(Ok((framing::BROADCAST, msg))) => {
+                    if let RxBody::Estop = msg.body {
+                        rover.handle(msg.body);
+                    }
+                }
+                Ok(Ok((_, msg))) => {
+                    let RxMessage { seq, body, at_ms } = msg;
+                    match body {
+                        RxBody::Hello { .. } => {
+                            send(&mut stream, id, &TxMessage::Hello(rover.hello()), encoding)?
+                        }
+                        RxBody::Ping(host_ms) => send(
+                            &mut stream,
+                            id,
+                            &TxMessage::Pong(rover.pong(host_ms)),
+                            encoding,
+                        )?,
+                        RxBody::TimeSync(host_ms) => send(
+                            &mut stream,
+                            id,
+                            &TxMessage::TimeSync(rover.time_sync(host_ms)),
+                            encoding,
+                        )?,
+                        _ => {}
+                    }
+                    let code = match at_ms {
+                        Some(at_ms) if at_ms > rover.uptime_ms() => rover.hold(at_ms, body),
+                        _ => rover.handle(body),
+                    };
+                    if let Some(seq) = seq {
+                        send(
+                            &mut stream,
+                            id,
+                            &TxMessage::Ack(Ack { seq, code }),
+                            encoding,
+                        )?;
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    return Err(std::io::ErrorKind::ConnectionAborted.into())
+                }
+            }
+        }
+
+        rover.step(Time::new::<second>(STEP.as_secs_f32()));
+
+        if last_telemetry.elapsed() >= TELEMETRY_PERIOD {
+            last_telemetry = Instant::now();
+            send(
+                &mut stream,
+                id,
+                &TxMessage::Telemetry(rover.telemetry()),
+                encoding,
+            )?;
+        }
+        if last_link_stats.elapsed() >= Duration::from_millis(link_stats::PERIOD_MS) {
+            last_link_stats = Instant::now();
+            send(
+                &mut stream,
+                id,
+                &TxMessage::LinkStats(rover.link_stats()),
+                encoding,
+            )?;
+        }
+        if last_log.elapsed() >= Duration::from_secs(1) {
+            last_log = Instant::now();
+            let pose = rover.pose();
+            eprintln!(
+                "pose: x {:.3} m, y {:.3} m, heading {:.1}°",
+                pose.x.get::<meter>(),
+                pose.y.get::<meter>(),
+                pose.heading.get::<degree>()
+            );
+        }
+
+        thread::sleep(STEP);
+    }
+}
+
+fn send(
+    stream: &mut TcpStream,
+    id: u8,
+    msg: &TxMessage,
+    encoding: Encoding,
+) -> std::io::Result<()> {
+    match protocol::encode(id, msg, encoding) {
+        Some(packet) => stream.write_all(&packet),
+        None => {
+            eprintln!("failed to encode tx message");
+            Ok(())
+        }
+    }
+}
+
+/// Splits the stream into packets on the zero terminators, until it closes.
+fn receive(mut stream: TcpStream, tx: mpsc::Sender<Result<(u8, RxMessage), framing::Error>>) {
+    let mut packet = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let n = match stream.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        for &byte in &buf[..n] {
+            if byte != 0 {
+                packet.push(byte);
+                continue;
+            }
+            if let Some(msg) = protocol::decode(&packet).transpose() {
+                if tx.send(msg).is_err() {
+                    return;
+                }
+            }
+            packet.clear();
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Packing of the [`rover_proto`] messages for the TCP link, the same way
+//! the firmware does over its UART.
+
+use rover_proto::{
+    framing::{self, Packet},
+    RxMessage, TxMessage, RX_SIZE, TX_SIZE,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    Binary,
+}
+
+/// A packet, without its terminating zero, and the address it went to. Like
+/// the firmware, a payload starting with `{` is JSON and anything else a
+/// binary frame. An error for a corrupted packet, `None` for a payload that
+/// doesn't decode.
+pub fn decode(packet: &[u8]) -> Result<Option<(u8, RxMessage)>, framing::Error> {
+    let mut raw = [0u8; framing::HEADER_LEN + RX_SIZE + framing::TRAILER_LEN];
+    let Packet { address, payload } =
+        framing::decode(packet, &mut raw).inspect_err(|e| eprintln!("dropping packet: {e}"))?;
+    let msg = if payload.first() == Some(&b'{') {
+        serde_json::from_slice(payload)
+            .inspect_err(|e| eprintln!("error decoding JSON: {e}"))
+            .ok()
+    } else {
+        rover_lib::wire::from_frame(payload)
+            .inspect_err(|e| eprintln!("error decoding binary frame: {e}"))
+            .ok()
+    };
+    Ok(msg.map(|msg| (address, msg)))
+}
+
+/// A whole packet from the rover at `address`, terminating zero included.
+pub fn encode(address: u8, msg: &TxMessage, encoding: Encoding) -> Option<Vec<u8>> {
+    let raw = match encoding {
+        Encoding::Json => serde_json::to_vec(msg).ok()?,
+        Encoding::Binary => {
+            let mut raw = [0u8; TX_SIZE];
+            rover_lib::wire::to_frame(msg, &mut raw).ok()?.to_vec()
+        }
+    };
+    let mut packet = vec![0u8; framing::max_packet_len(raw.len())];
+    let n = framing::encode(address, &raw, &mut packet).ok()?;
+    packet.truncate(n);
+    Some(packet)
+}
@@ -0,0 +1,328 @@
+//! The simulated rover: ideal motors spinning the wheels in proportion to
+//! their power, and the odometry of the firmware integrating them.
+
+use core::convert::Infallible;
+
+use uom::si::{
+    angle::radian,
+    angular_velocity::{radian_per_second, revolution_per_minute},
+    f32::{Angle, AngularVelocity, Length, Time},
+    length::millimeter,
+    time::second,
+};
+
+use rover_lib::{
+    arming::{ArmingError, ModeEvent},
+    failsafe::FailsafeStage,
+    iface::{FourWheeledRobot, MecanumRobot, MotorPower},
+    input_shaping,
+    link_stats::{LinkMonitor, LinkStats},
+    mux::Source,
+    odometry::{MecanumGeometry, Odometry},
+    rc,
+    schedule::Scheduler,
+    Arming, Attitude, CommandMux, DriveFrame, Mode, Pose,
+};
+use rover_proto::{
+    AckCode, Capabilities, Command, DriveMessage, FirmwareVersion, Hello, Pong, ResetCause, RxBody,
+    Telemetry, TimeSync, PROTOCOL_VERSION,
+};
+
+/// Same as the firmware.
+const MAX_WHEEL_RPM: f32 = 330.0;
+const SAFETY_TIMEOUT_MS: u64 = 500;
+const STICK_DEADZONE: f32 = 0.05;
+const STICK_EXPO: f32 = 0.3;
+const SCHEDULE_SIZE: usize = 8;
+
+fn geometry() -> MecanumGeometry {
+    MecanumGeometry {
+        wheel_radius: Length::new::<millimeter>(40.0),
+        half_track: Length::new::<millimeter>(95.0),
+        half_wheelbase: Length::new::<millimeter>(80.0),
+    }
+}
+
+/// Applies wheel powers instantly and exactly.
+#[derive(Default)]
+struct Wheels {
+    powers: [MotorPower; 4],
+}
+
+impl FourWheeledRobot for Wheels {
+    type Error = Infallible;
+
+    fn drive(
+        &mut self,
+        fl: MotorPower,
+        fr: MotorPower,
+        bl: MotorPower,
+        br: MotorPower,
+    ) -> Result<(), Self::Error> {
+        self.powers = [fl, fr, bl, br];
+        Ok(())
+    }
+    fn neutral(&mut self) -> Result<(), Self::Error> {
+        self.powers = Default::default();
+        Ok(())
+    }
+}
+
+pub struct SimRover {
+    wheels: Wheels,
+    angles: [Angle; 4],
+    odometry: Odometry,
+    mux: CommandMux,
+    arming: Arming,
+    command: Command,
+    frame: DriveFrame,
+    uptime_ms: u64,
+    link: LinkMonitor,
+    schedule: Scheduler<RxBody, SCHEDULE_SIZE>,
+    /// Only ever latched by the host, there's no switch.
+    estop: bool,
+}
+
+impl SimRover {
+    pub fn new() -> Self {
+        Self {
+            wheels: Wheels::default(),
+            angles: Default::default(),
+            odometry: Odometry::new(geometry()),
+            mux: CommandMux::new(),
+            arming: Arming::new(),
+            command: Command::default(),
+            frame: DriveFrame::default(),
+            uptime_ms: 0,
+            link: LinkMonitor::new(),
+            schedule: Scheduler::new(),
+            estop: false,
+        }
+    }
+
+    pub fn pose(&self) -> Pose {
+        self.odometry.pose()
+    }
+
+    pub fn uptime_ms(&self) -> u64 {
+        self.uptime_ms
+    }
+
+    /// Holds `body` for [`Self::step`] to handle at `at_ms`.
+    pub fn hold(&mut self, at_ms: u64, body: RxBody) -> AckCode {
+        self.link.frame(self.uptime_ms);
+        match self.schedule.push(at_ms, body) {
+            Ok(()) => AckCode::Ok,
+            Err(_) => AckCode::ScheduleFull,
+        }
+    }
+
+    /// Counts a corrupted packet, for telemetry.
+    pub fn framing_error(&mut self) {
+        self.link.crc_error();
+    }
+
+    /// For the period since the last call.
+    pub fn link_stats(&mut self) -> LinkStats {
+        self.link.publish(self.uptime_ms)
+    }
+
+    /// Moves the simulation `dt` ahead, handling the held messages falling
+    /// due, and stopping when the host lease ran out like the firmware
+    /// safety timer.
+    pub fn step(&mut self, dt: Time) {
+        self.uptime_ms += (dt.get::<second>() * 1000.0) as u64;
+        while let Some(body) = self.schedule.pop_due(self.uptime_ms) {
+            self.handle(body);
+        }
+        if self.mux.active(self.uptime_ms).is_none()
+            && self.wheels.powers != [MotorPower::default(); 4]
+        {
+            self.command = Command::default();
+            _ = FourWheeledRobot::neutral(&mut self.wheels);
+            _ = self.arming.handle(ModeEvent::Stopped);
+        }
+
+        let max_speed = AngularVelocity::new::<revolution_per_minute>(MAX_WHEEL_RPM);
+        for (angle, power) in self.angles.iter_mut().zip(self.wheels.powers) {
+            let speed = max_speed.get::<radian_per_second>() * power.inner();
+            *angle += Angle::new::<radian>(speed * dt.get::<second>());
+        }
+        self.odometry.update(self.angles);
+    }
+
+    pub fn handle(&mut self, body: RxBody) -> AckCode {
+        self.link.frame(self.uptime_ms);
+        if let RxBody::Drive(_) | RxBody::Sticks { .. } = body {
+            self.link.command();
+        }
+        match body {
+            RxBody::Drive(_) | RxBody::Sticks { .. }
+                if !self
+                    .mux
+                    .claim(Source::Host, self.uptime_ms, SAFETY_TIMEOUT_MS) =>
+            {
+                AckCode::NotSelected
+            }
+            RxBody::Drive(DriveMessage { p, th, tu }) => {
+                let Command {
+                    p: last_p,
+                    th: last_th,
+                    tu: last_tu,
+                } = self.command;
+                self.drive(Command {
+                    p: p.unwrap_or(last_p),
+                    th: th.unwrap_or(last_th),
+                    tu: tu.unwrap_or(last_tu),
+                })
+            }
+            RxBody::Sticks { x, y, rot } => {
+                let shape = |axis| input_shaping::shape(axis, STICK_DEADZONE, STICK_EXPO);
+                let (p, th, tu) = rc::mix(shape(x), shape(y), shape(rot));
+                self.drive(Command { p, th, tu })
+            }
+            RxBody::SetDriveFrame(frame) => {
+                self.frame = frame;
+                AckCode::Ok
+            }
+            RxBody::Arm(arm) => {
+                let event = if arm {
+                    ModeEvent::Arm
+                } else {
+                    ModeEvent::Disarm
+                };
+                match self.arming.handle(event) {
+                    Ok(_) if !arm => {
+                        self.command = Command::default();
+                        _ = FourWheeledRobot::neutral(&mut self.wheels);
+                        AckCode::Ok
+                    }
+                    result => ack(result),
+                }
+            }
+            RxBody::Estop => {
+                self.estop = true;
+                self.command = Command::default();
+                _ = FourWheeledRobot::neutral(&mut self.wheels);
+                _ = self.arming.handle(ModeEvent::Estop);
+                AckCode::Ok
+            }
+            RxBody::ClearEstop => {
+                if core::mem::take(&mut self.estop) {
+                    _ = self.arming.handle(ModeEvent::EstopCleared);
+                }
+                AckCode::Ok
+            }
+            RxBody::ClearOvercurrent => AckCode::Ok,
+            RxBody::RunMacro(_) | RxBody::DeleteMacro(_) => AckCode::NotFound,
+            RxBody::Hello { protocol } if protocol != PROTOCOL_VERSION => AckCode::Incompatible,
+            RxBody::Hello { .. } => AckCode::Ok,
+            // Nothing goes wrong in simulation, so the event log stays empty
+            RxBody::DumpEvents | RxBody::ClearEvents => AckCode::Ok,
+            RxBody::Ping(_) => {
+                self.mux
+                    .renew(Source::Host, self.uptime_ms, SAFETY_TIMEOUT_MS);
+                AckCode::Ok
+            }
+            RxBody::Stamp(host_ms) => {
+                self.link.stamp(host_ms, self.uptime_ms);
+                AckCode::Ok
+            }
+            RxBody::TimeSync(_) => AckCode::Ok,
+            RxBody::ClearSchedule => {
+                self.schedule.clear();
+                AckCode::Ok
+            }
+            _ => AckCode::Unsupported,
+        }
+    }
+
+    fn drive(&mut self, command: Command) -> AckCode {
+        if let Err(e) = self.arming.handle(ModeEvent::Drive) {
+            return ack(Err(e));
+        }
+        self.command = command;
+        let th = self.frame.to_robot(command.th, self.pose().heading);
+        match MecanumRobot::drive(&mut self.wheels, command.p, th, command.tu) {
+            Ok(()) => AckCode::Ok,
+            Err(_) => AckCode::DriveFailed,
+        }
+    }
+
+    pub fn pong(&self, host_ms: u32) -> Pong {
+        Pong {
+            host_ms,
+            uptime_ms: self.uptime_ms,
+            mode: self.arming.mode(),
+            source: self.mux.active(self.uptime_ms),
+            safety_tripped: false,
+            estop: self.estop,
+            fault: false,
+        }
+    }
+
+    pub fn time_sync(&self, host_ms: u64) -> TimeSync {
+        TimeSync {
+            host_ms,
+            rover_ms: self.uptime_ms,
+        }
+    }
+
+    pub fn hello(&self) -> Hello {
+        Hello {
+            firmware: FirmwareVersion::try_from(env!("CARGO_PKG_VERSION")).unwrap_or_default(),
+            protocol: PROTOCOL_VERSION,
+            capabilities: Capabilities {
+                encoders: true,
+                imu: true,
+                json: true,
+                ..Default::default()
+            },
+            reset_cause: ResetCause::PowerOn,
+        }
+    }
+
+    pub fn telemetry(&self) -> Telemetry {
+        Telemetry {
+            uptime_ms: self.uptime_ms,
+            command: self.command,
+            powers: self.wheels.powers,
+            safety_tripped: false,
+            estop: self.estop,
+            battery: None,
+            currents: None,
+            overcurrent_tripped: false,
+            fault: false,
+            // A perfect IMU, agreeing with the odometry
+            attitude: Some(Attitude {
+                heading: self.pose().heading,
+                ..Default::default()
+            }),
+            source: self.mux.active(self.uptime_ms),
+            ranges: None,
+            navigation: None,
+            front_distance: None,
+            framing_errors: self.link.crc_errors(),
+            reset_cause: ResetCause::PowerOn,
+            aux: 0,
+            mode: self.arming.mode(),
+            power_scale: 1.0,
+            tilted: false,
+            gps: None,
+            estimate: None,
+            pose: Some(self.pose()),
+            radio_quality: None,
+            rx_overflows: 0,
+            failsafe: FailsafeStage::Linked,
+        }
+    }
+}
+
+fn ack(result: Result<Mode, ArmingError>) -> AckCode {
+    match result {
+        Ok(_) => AckCode::Ok,
+        Err(ArmingError::NotArmed) => AckCode::NotArmed,
+        Err(ArmingError::Estopped) => AckCode::Estopped,
+        Err(_) => AckCode::Fault,
+    }
+}
@@ -0,0 +1,72 @@
+//! The robot [`Mode`], which drive commands go through in [`apply_drive`].
+//! Armed by the host or the user button, it follows the e-stop, the safety
+//! timer and the failsafe from the event bus.
+//!
+//! [`apply_drive`]: crate::comms::apply_drive
+
+use core::cell::Cell;
+
+use embassy_executor::task;
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+
+use rover_lib::{
+    arming::{ArmingError, ModeEvent},
+    Arming, FailsafeStage, Mode,
+};
+use rover_proto::AckCode;
+
+use crate::{
+    events::{self, BusEvent},
+    log::{info, warn},
+};
+
+static ARMING: Mutex<CriticalSectionRawMutex, Cell<Arming>> = Mutex::new(Cell::new(Arming::new()));
+
+pub fn mode() -> Mode {
+    ARMING.lock(|arming| arming.get().mode())
+}
+
+/// Publishes [`BusEvent::Disarmed`] when the robot could drive before.
+pub fn handle(event: ModeEvent) -> Result<Mode, ArmingError> {
+    let (old, result) = ARMING.lock(|cell| {
+        let mut arming = cell.get();
+        let old = arming.mode();
+        let result = arming.handle(event);
+        cell.set(arming);
+        (old, result)
+    });
+    match result {
+        Ok(new) if new != old => {
+            info!(Safety, "mode {} -> {}", old, new);
+            if new == Mode::Disarmed && old.may_drive() {
+                events::publish(BusEvent::Disarmed);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => warn!(Safety, "{} refused in mode {}: {}", event, old, e),
+    }
+    result
+}
+
+pub fn ack(result: Result<Mode, ArmingError>) -> AckCode {
+    match result {
+        Ok(_) => AckCode::Ok,
+        Err(ArmingError::NotArmed) => AckCode::NotArmed,
+        Err(ArmingError::Estopped) => AckCode::Estopped,
+        Err(_) => AckCode::Fault,
+    }
+}
+
+#[task]
+pub async fn arming_task() {
+    let mut events = events::subscribe();
+    loop {
+        let event = match events.next_message_pure().await {
+            BusEvent::EstopPressed => ModeEvent::Estop,
+            BusEvent::LinkLost => ModeEvent::Stopped,
+            BusEvent::Failsafe(FailsafeStage::Faulted) => ModeEvent::Failsafe,
+            _ => continue,
+        };
+        _ = handle(event);
+    }
+}
@@ -0,0 +1,51 @@
+//! Named digital outputs, for lights, a relay or an electromagnet, switched
+//! by the host. Which ones there are is up to the board's [`AUX_NAMES`].
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use rover_proto::{AckCode, AuxAction};
+
+use crate::board::{LedPin, AUX_NAMES};
+
+const AUX_COUNT: usize = AUX_NAMES.len();
+// A bit each in the telemetry
+const _: () = assert!(AUX_COUNT <= 8);
+
+static OUTPUTS: Mutex<CriticalSectionRawMutex, RefCell<Option<[LedPin; AUX_COUNT]>>> =
+    Mutex::new(RefCell::new(None));
+
+pub fn init(pins: [LedPin; AUX_COUNT]) {
+    OUTPUTS.lock(|cell| cell.replace(Some(pins)));
+}
+
+pub fn apply(name: &str, action: AuxAction) -> AckCode {
+    let Some(index) = AUX_NAMES.iter().position(|aux| *aux == name) else {
+        return AckCode::NotFound;
+    };
+    OUTPUTS.lock(|cell| {
+        let mut outputs = cell.borrow_mut();
+        let Some(pin) = outputs.as_mut().map(|outputs| &mut outputs[index]) else {
+            return AckCode::NotFound;
+        };
+        match action {
+            AuxAction::Set => pin.set_high(),
+            AuxAction::Clear => pin.set_low(),
+            AuxAction::Toggle => pin.toggle(),
+        }
+        AckCode::Ok
+    })
+}
+
+/// Bit `n` set if the `n`th output is on, for telemetry.
+pub fn states() -> u8 {
+    OUTPUTS.lock(|cell| {
+        cell.borrow().as_ref().map_or(0, |outputs| {
+            outputs
+                .iter()
+                .enumerate()
+                .filter(|(_, pin)| pin.is_set_high())
+                .fold(0, |bits, (index, _)| bits | 1 << index)
+        })
+    })
+}
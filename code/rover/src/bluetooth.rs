@@ -0,0 +1,127 @@
+//! Serial Bluetooth module, an HC-05 or with `hm10` an HM-10: set up with AT
+//! commands at boot, a transparent link for [`crate::comms`] afterwards.
+//!
+//! Both come out of the box at 9600 baud and keep their settings, so the
+//! module is looked for at [`BAUDRATE`] first and only reconfigured when
+//! found at the factory rate.
+
+use defmt::{info, warn};
+use embassy_time::{with_timeout, Duration, Timer};
+use embedded_io_async::{Read, Write};
+
+use crate::board::BluetoothSerial;
+
+pub const BAUDRATE: u32 = 115_200;
+const FACTORY_BAUDRATE: u32 = 9_600;
+/// Also spelled out in the `at` commands.
+const NAME: &str = "rover";
+
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+/// Until the module takes commands after power-up or a reset.
+const BOOT_TIME: Duration = Duration::from_millis(800);
+
+#[cfg(not(feature = "hm10"))]
+mod at {
+    pub const PING: &str = "AT\r\n";
+    pub const NAME: &str = "AT+NAME=rover\r\n";
+    pub const BAUD: &str = "AT+UART=115200,0,0\r\n";
+    pub const RESET: &str = "AT+RESET\r\n";
+}
+
+#[cfg(feature = "hm10")]
+mod at {
+    // No line endings: the HM-10 takes whatever arrived once the line goes
+    // quiet
+    pub const PING: &str = "AT";
+    pub const NAME: &str = "AT+NAMErover";
+    /// Baud rate index 4, 115200.
+    pub const BAUD: &str = "AT+BAUD4";
+    pub const RESET: &str = "AT+RESET";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum AtError {
+    /// Not at either baud rate, or not in command mode.
+    NoReply,
+    Rejected,
+    Serial,
+}
+
+impl core::fmt::Display for AtError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Leaves the UART at [`BAUDRATE`] whatever happens, so the link may still
+/// work after an error.
+pub async fn configure(serial: &mut BluetoothSerial) -> Result<(), AtError> {
+    serial.set_command_mode(true);
+    Timer::after(BOOT_TIME).await;
+
+    let result = setup(serial).await;
+    if result.is_ok() {
+        // Comes back in data mode, at the new rate if it changed
+        let _ = command(serial, at::RESET).await;
+    }
+    serial.set_command_mode(false);
+    serial.set_baudrate(BAUDRATE);
+    Timer::after(BOOT_TIME).await;
+
+    if result.is_ok() {
+        info!("bluetooth module ready as {}", NAME);
+    }
+    result
+}
+
+async fn setup(serial: &mut BluetoothSerial) -> Result<(), AtError> {
+    let mut baudrate = None;
+    for candidate in [BAUDRATE, FACTORY_BAUDRATE] {
+        serial.set_baudrate(candidate);
+        if command(serial, at::PING).await.is_ok() {
+            baudrate = Some(candidate);
+            break;
+        }
+    }
+    let baudrate = baudrate.ok_or(AtError::NoReply)?;
+
+    command(serial, at::NAME).await?;
+    if baudrate != BAUDRATE {
+        warn!(
+            "bluetooth module at {} baud, moving it to {}",
+            baudrate, BAUDRATE
+        );
+        command(serial, at::BAUD).await?;
+    }
+    Ok(())
+}
+
+/// Sends `cmd` and waits for an `OK`, the start of every positive reply in
+/// both dialects.
+async fn command(serial: &mut BluetoothSerial, cmd: &str) -> Result<(), AtError> {
+    serial
+        .write_all(cmd.as_bytes())
+        .await
+        .map_err(|_| AtError::Serial)?;
+
+    let mut reply = [0u8; 32];
+    let mut len = 0;
+    loop {
+        let n = match with_timeout(REPLY_TIMEOUT, serial.read(&mut reply[len..])).await {
+            Ok(Ok(n)) => n,
+            Ok(Err(_)) => return Err(AtError::Serial),
+            Err(_) => return Err(AtError::NoReply),
+        };
+        len += n;
+
+        let full = len == reply.len();
+        let reply = &reply[..len];
+        if reply.windows(2).any(|w| w == b"OK") {
+            return Ok(());
+        }
+        if reply.windows(5).any(|w| w == b"ERROR") || full {
+            return Err(AtError::Rejected);
+        }
+    }
+}
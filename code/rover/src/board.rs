@@ -0,0 +1,229 @@
+//! The robot type and what doesn't depend on the chip, plus the bring-up of
+//! the chip picked by feature: STM32F411RE by default, RP2040 with
+//! `rp2040`.
+//!
+//! Each chip module provides the same items, for the rest of the firmware
+//! to use without naming a HAL:
+//! - [`Board`], with `init` taking the peripherals over and setting them up
+//! - [`Pwm`] and [`DirPin`], what a [`MyMotor`] is made of
+//! - [`EdgeInput`] and [`LedPin`], plain GPIOs
+//! - [`StatusLight`], a [`LedPin`] or, with `neopixel` on the STM32, a WS2812
+//!   strip
+//! - [`AUX_NAMES`], naming the `aux` outputs of the [`Board`]
+//! - [`BoardImu`], [`Analog`], [`Watchdog`] and [`ConfigFlash`]
+//! - [`BoardTof`] with `vl53l0x`, on the IMU bus
+//! - [`BoardMag`] with `magnetometer`, on the IMU bus too
+//! - [`HostUart`], split into [`HostTx`] and [`HostRx`]
+//! - [`kill_motor_outputs`], [`safe_motors`] and [`take_reset_cause`]
+
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex};
+use embedded_hal_async::i2c::{ErrorType, I2c, Operation, SevenBitAddress};
+
+#[cfg(feature = "closed_loop")]
+use uom::si::{
+    f32::{AngularVelocity, Time},
+    time::millisecond,
+};
+
+#[cfg(not(feature = "neopixel"))]
+use rover_lib::indicator::Rgb;
+#[cfg(feature = "differential")]
+use rover_lib::DifferentialRobot;
+use rover_lib::{
+    encoder::Encoder, odometry::MecanumGeometry, CalibratedRobot, CurrentLimited, MyFourWheelRobot,
+    MyMotor, PowerBudget, SlewLimiter, StabilizedRobot, ThermalDerating,
+};
+#[cfg(feature = "closed_loop")]
+use rover_lib::{StallDetection, VelocityController};
+#[cfg(feature = "closed_loop")]
+use rover_proto::config::DEFAULT_GAINS;
+
+#[cfg(feature = "rp2040")]
+mod rp2040;
+#[cfg(not(feature = "rp2040"))]
+mod stm32;
+
+#[cfg(feature = "rp2040")]
+pub use rp2040::*;
+#[cfg(not(feature = "rp2040"))]
+pub use stm32::*;
+
+/// The status LED, lit for any color.
+#[cfg(not(feature = "neopixel"))]
+pub struct StatusLight(pub LedPin);
+
+#[cfg(not(feature = "neopixel"))]
+impl StatusLight {
+    pub async fn show(&mut self, color: Rgb) {
+        if color.is_off() {
+            self.0.set_low();
+        } else {
+            self.0.set_high();
+        }
+    }
+}
+
+// 11 pulses per motor revolution, 1:30 gearbox, counting all four edges.
+pub const ENCODER_TICKS_PER_REV: u32 = 11 * 30 * 4;
+
+pub type WheelEncoder = &'static mut dyn Encoder<Error = core::convert::Infallible>;
+
+type Wheel = MyMotor<Pwm, DirPin, DirPin>;
+#[cfg(feature = "closed_loop")]
+type RobotWheel = VelocityController<Wheel>;
+#[cfg(not(feature = "closed_loop"))]
+type RobotWheel = Wheel;
+pub type Wheels = MyFourWheelRobot<RobotWheel, RobotWheel, RobotWheel, RobotWheel>;
+type Limited = PowerBudget<ThermalDerating<CurrentLimited<Wheels>>>;
+type Drivetrain = SlewLimiter<CalibratedRobot<Limited>>;
+#[cfg(not(feature = "differential"))]
+pub type Robot = StabilizedRobot<Drivetrain>;
+#[cfg(feature = "differential")]
+pub type Robot = StabilizedRobot<DifferentialRobot<Drivetrain>>;
+
+#[cfg(not(feature = "differential"))]
+fn drivetrain(robot: &Robot) -> &Drivetrain {
+    robot.inner()
+}
+#[cfg(feature = "differential")]
+fn drivetrain(robot: &Robot) -> &Drivetrain {
+    robot.inner().inner()
+}
+
+#[cfg(not(feature = "differential"))]
+pub fn drivetrain_mut(robot: &mut Robot) -> &mut Drivetrain {
+    robot.inner_mut()
+}
+#[cfg(feature = "differential")]
+pub fn drivetrain_mut(robot: &mut Robot) -> &mut Drivetrain {
+    robot.inner_mut().inner_mut()
+}
+
+pub fn wheels(robot: &Robot) -> &Wheels {
+    current_limiter(robot).inner()
+}
+
+pub fn wheels_mut(robot: &mut Robot) -> &mut Wheels {
+    current_limiter_mut(robot).inner_mut()
+}
+
+pub fn current_limiter(robot: &Robot) -> &CurrentLimited<Wheels> {
+    power_budget(robot).inner().inner()
+}
+
+pub fn current_limiter_mut(robot: &mut Robot) -> &mut CurrentLimited<Wheels> {
+    thermal_mut(robot).inner_mut()
+}
+
+pub fn thermal_mut(robot: &mut Robot) -> &mut ThermalDerating<CurrentLimited<Wheels>> {
+    power_budget_mut(robot).inner_mut()
+}
+
+pub fn power_budget(robot: &Robot) -> &Limited {
+    drivetrain(robot).inner().inner()
+}
+
+pub fn power_budget_mut(robot: &mut Robot) -> &mut Limited {
+    calibration_mut(robot).inner_mut()
+}
+
+pub fn calibration_mut(robot: &mut Robot) -> &mut CalibratedRobot<Limited> {
+    drivetrain_mut(robot).inner_mut()
+}
+
+#[cfg(feature = "closed_loop")]
+const MAX_WHEEL_RPM: f32 = 330.0;
+
+#[cfg(feature = "closed_loop")]
+fn stall_detection() -> StallDetection {
+    use uom::si::angular_velocity::revolution_per_minute;
+
+    StallDetection {
+        min_power: 0.3,
+        min_speed: AngularVelocity::new::<revolution_per_minute>(5.0),
+        timeout: Time::new::<millisecond>(300.0),
+        stalled_power: 0.15,
+    }
+}
+
+#[cfg(feature = "closed_loop")]
+fn wheel(motor: Wheel) -> RobotWheel {
+    let mut wheel = VelocityController::new(
+        motor,
+        DEFAULT_GAINS,
+        AngularVelocity::new::<uom::si::angular_velocity::revolution_per_minute>(MAX_WHEEL_RPM),
+    );
+    wheel.set_stall_detection(Some(stall_detection()));
+    wheel
+}
+#[cfg(not(feature = "closed_loop"))]
+fn wheel(motor: Wheel) -> RobotWheel {
+    motor
+}
+
+/// One device on an I2C bus shared between tasks, which get it a
+/// transaction at a time.
+pub struct I2cDevice<I: 'static> {
+    bus: &'static Mutex<NoopRawMutex, I>,
+}
+
+impl<I: 'static> I2cDevice<I> {
+    fn new(bus: &'static Mutex<NoopRawMutex, I>) -> Self {
+        Self { bus }
+    }
+}
+
+impl<I: ErrorType + 'static> ErrorType for I2cDevice<I> {
+    type Error = I::Error;
+}
+
+/// Forwards each call as is: the STM32 HAL has no `transaction`.
+impl<I: I2c + 'static> I2c for I2cDevice<I> {
+    async fn read(&mut self, address: SevenBitAddress, read: &mut [u8]) -> Result<(), I::Error> {
+        self.bus.lock().await.read(address, read).await
+    }
+
+    async fn write(&mut self, address: SevenBitAddress, write: &[u8]) -> Result<(), I::Error> {
+        self.bus.lock().await.write(address, write).await
+    }
+
+    async fn write_read(
+        &mut self,
+        address: SevenBitAddress,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), I::Error> {
+        self.bus.lock().await.write_read(address, write, read).await
+    }
+
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), I::Error> {
+        self.bus.lock().await.transaction(address, operations).await
+    }
+}
+
+pub fn geometry() -> MecanumGeometry {
+    use uom::si::{f32::Length, length::millimeter};
+
+    MecanumGeometry {
+        wheel_radius: Length::new::<millimeter>(40.0),
+        half_track: Length::new::<millimeter>(95.0),
+        half_wheelbase: Length::new::<millimeter>(80.0),
+    }
+}
+
+// 100k over 10k, on both boards
+pub const BATTERY_DIVIDER: f32 = 11.0;
+const ADC_VREF: f32 = 3.3;
+const ADC_MAX: f32 = 4095.0;
+
+/// Output of the current sense amplifiers.
+#[cfg(feature = "current_sense")]
+pub const CURRENT_SENSE_V_PER_A: f32 = 0.5;
+
+pub fn adc_volts(raw: u16) -> f32 {
+    raw as f32 / ADC_MAX * ADC_VREF
+}
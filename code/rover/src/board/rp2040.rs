@@ -0,0 +1,387 @@
+//! Raspberry Pi Pico bring-up, built from `code/rover_rp2040`.
+//!
+//! | GPIO       | wired to                              |
+//! |------------|---------------------------------------|
+//! | 0, 1       | UART0 TX/RX, to the host              |
+//! | 2-9        | encoders A/B, FL-FR-BL-BR             |
+//! | 10-15      | motor directions, FL-FR-BL            |
+//! | 16-19      | PWM0 A/B and PWM1 A/B, FL-FR-BL-BR    |
+//! | 20, 21     | BR motor direction                    |
+//! | 22         | e-stop, normally closed to ground     |
+//! | 25         | fault LED, the one on the Pico        |
+//! | 26, 27     | I2C1 SDA/SCL, to the IMU              |
+//! | 28         | battery, through the divider          |
+//!
+//! That's every GPIO the Pico breaks out, so there's no user button. With
+//! `usb` the Pico's own USB port is a second host link, see [`usb`].
+
+#[cfg(feature = "usb")]
+mod usb;
+
+use embassy_executor::Spawner;
+use embassy_rp::{
+    adc, bind_interrupts,
+    flash::{self, Flash},
+    gpio::{Input, Level, Output, Pull},
+    i2c,
+    peripherals::{self, UART0},
+    pwm,
+    uart::{self, BufferedUart, BufferedUartRx, BufferedUartTx},
+    watchdog,
+};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex};
+use embassy_time::Duration;
+
+#[cfg(feature = "magnetometer")]
+use rover_lib::compass::Qmc5883l;
+#[cfg(feature = "imu_icm20948")]
+use rover_lib::imu::Icm20948;
+#[cfg(not(feature = "imu_icm20948"))]
+use rover_lib::imu::Mpu6050;
+#[cfg(feature = "vl53l0x")]
+use rover_lib::tof::Vl53l0x;
+use rover_lib::{
+    encoder::{ExtiCounter, QuadratureEncoder},
+    FourWheelRobotBuilder, MyMotorBuilder,
+};
+use rover_proto::{ResetCause, RX_SIZE};
+
+use super::{wheel, I2cDevice, StatusLight, WheelEncoder, Wheels, ENCODER_TICKS_PER_REV};
+use crate::tasks::{exti_encoder_task, WATCHDOG_TIMEOUT_US};
+#[cfg(feature = "usb")]
+pub use usb::{UsbPort, UsbRx, UsbTx};
+
+#[cfg(feature = "current_sense")]
+compile_error!("the RP2040 has three ADC inputs, current sensing needs four");
+#[cfg(feature = "shell")]
+compile_error!("no pins left for a shell UART on the Pico");
+#[cfg(feature = "bluetooth")]
+compile_error!("no pins left for a Bluetooth UART on the Pico");
+#[cfg(feature = "sbus")]
+compile_error!("no pins left for an SBUS receiver on the Pico");
+#[cfg(feature = "gps")]
+compile_error!("no pins left for a GPS receiver on the Pico");
+#[cfg(any(feature = "rc_pwm", feature = "rc_ppm"))]
+compile_error!("no pins left for an RC receiver on the Pico");
+#[cfg(feature = "ultrasonic")]
+compile_error!("no pins left for ultrasonic rangers on the Pico");
+#[cfg(feature = "line_sensor")]
+compile_error!("no pins left for a line sensor on the Pico");
+#[cfg(feature = "buzzer")]
+compile_error!("no pins left for a buzzer on the Pico");
+#[cfg(feature = "neopixel")]
+compile_error!("no pins left for a NeoPixel strip on the Pico");
+#[cfg(feature = "i2c_target")]
+compile_error!("no pins left for an I2C target on the Pico");
+#[cfg(feature = "nrf24")]
+compile_error!("no pins left for an nRF24 radio on the Pico");
+
+bind_interrupts!(struct Irqs {
+    UART0_IRQ => uart::BufferedInterruptHandler<UART0>;
+    I2C1_IRQ => i2c::InterruptHandler<peripherals::I2C1>;
+    ADC_IRQ_FIFO => adc::InterruptHandler;
+});
+
+const FLASH_SIZE: usize = 2 * 1024 * 1024;
+
+pub type DirPin = Output<'static>;
+pub type EdgeInput = Input<'static>;
+pub type LedPin = Output<'static>;
+
+/// None, every GPIO is taken.
+pub const AUX_NAMES: [&str; 0] = [];
+pub type ConfigFlash = Flash<'static, peripherals::FLASH, flash::Blocking, FLASH_SIZE>;
+pub type HostTx = BufferedUartTx<'static, UART0>;
+pub type HostRx = BufferedUartRx<'static, UART0>;
+
+/// Last 4K sector of the Pico flash.
+pub const CONFIG_OFFSET: u32 = FLASH_SIZE as u32 - 0x1000;
+/// The 4K sector before it.
+pub const MACRO_OFFSET: u32 = CONFIG_OFFSET - 0x1000;
+/// The two 4K sectors before that, so wrapping around keeps half the log.
+pub const EVENT_LOG_OFFSET: u32 = MACRO_OFFSET - EVENT_LOG_SIZE;
+pub const EVENT_LOG_SIZE: u32 = 0x2000;
+
+type I2cBus = i2c::I2c<'static, peripherals::I2C1, i2c::Async>;
+#[cfg(not(feature = "imu_icm20948"))]
+pub type BoardImu = Mpu6050<I2cDevice<I2cBus>>;
+#[cfg(feature = "imu_icm20948")]
+pub type BoardImu = Icm20948<I2cDevice<I2cBus>>;
+#[cfg(feature = "vl53l0x")]
+pub type BoardTof = Vl53l0x<I2cDevice<I2cBus>>;
+#[cfg(feature = "magnetometer")]
+pub type BoardMag = Qmc5883l<I2cDevice<I2cBus>>;
+
+/// 125 MHz over this, 2 kHz.
+const PWM_TOP: u16 = 62_499;
+
+/// One channel of a PWM slice. Both of a slice share its compare register,
+/// so the write is done under a critical section rather than through the
+/// driver.
+pub struct Pwm {
+    slice: usize,
+    b: bool,
+}
+
+impl embedded_hal_1::pwm::ErrorType for Pwm {
+    type Error = core::convert::Infallible;
+}
+
+impl embedded_hal_1::pwm::SetDutyCycle for Pwm {
+    fn max_duty_cycle(&self) -> u16 {
+        PWM_TOP
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        critical_section::with(|_| {
+            embassy_rp::pac::PWM.ch(self.slice).cc().modify(|w| {
+                if self.b {
+                    w.set_b(duty);
+                } else {
+                    w.set_a(duty);
+                }
+            })
+        });
+        Ok(())
+    }
+}
+
+/// Last resort when the motors won't stop: zeroes both motor slices behind
+/// the back of their [`Pwm`]s, effective from the next period.
+pub fn kill_motor_outputs() {
+    for slice in 0..2 {
+        embassy_rp::pac::PWM.ch(slice).cc().write(|w| {
+            w.set_a(0);
+            w.set_b(0);
+        });
+    }
+}
+
+/// For the panic handler: [`kill_motor_outputs`], and drives every motor
+/// direction GPIO low through SIO, whoever owns them.
+pub fn safe_motors() {
+    kill_motor_outputs();
+    let dirs = [10, 11, 12, 13, 14, 15, 20, 21]
+        .iter()
+        .fold(0u32, |mask, pin| mask | 1 << pin);
+    embassy_rp::pac::SIO.gpio_out_clr().write_value(dirs);
+}
+
+pub struct Watchdog(watchdog::Watchdog);
+
+impl Watchdog {
+    pub fn start(&mut self) {
+        self.0
+            .start(Duration::from_micros(WATCHDOG_TIMEOUT_US as u64));
+    }
+
+    pub fn feed(&mut self) {
+        self.0.feed();
+    }
+}
+
+/// What the last reset came from. The RP2040 can't tell a brownout from a
+/// power on, and a software reset leaves no trace.
+pub fn take_reset_cause() -> ResetCause {
+    use embassy_rp::pac::{VREG_AND_CHIP_RESET, WATCHDOG};
+
+    let reason = WATCHDOG.reason().read();
+    let chip = VREG_AND_CHIP_RESET.chip_reset().read();
+    if reason.timer() {
+        ResetCause::Watchdog
+    } else if reason.force() {
+        ResetCause::Software
+    } else if chip.had_por() {
+        ResetCause::PowerOn
+    } else if chip.had_run() {
+        ResetCause::Pin
+    } else {
+        ResetCause::Unknown
+    }
+}
+
+/// Owns the ADC and the battery input.
+pub struct Analog {
+    adc: adc::Adc<'static, adc::Async>,
+    battery: adc::Channel<'static>,
+}
+
+impl Analog {
+    pub async fn battery(&mut self) -> Option<u16> {
+        self.adc.read(&mut self.battery).await.ok()
+    }
+}
+
+/// Everything [`Board::init`] sets up, ready to be handed to the tasks.
+pub struct Board {
+    pub wheels: Wheels,
+    /// FL-FR-BL-BR.
+    pub encoders: [WheelEncoder; 4],
+    pub imu: BoardImu,
+    #[cfg(feature = "vl53l0x")]
+    pub tof: BoardTof,
+    #[cfg(feature = "magnetometer")]
+    pub mag: BoardMag,
+    pub estop: EdgeInput,
+    pub status_light: StatusLight,
+    pub aux: [LedPin; AUX_NAMES.len()],
+    pub watchdog: Watchdog,
+    pub analog: Analog,
+    pub flash: ConfigFlash,
+    pub host_uart: HostUart,
+    #[cfg(feature = "usb")]
+    pub usb: UsbPort,
+}
+
+impl Board {
+    /// Also spawns the encoder tasks, as they own the encoder pins.
+    pub fn init(spawner: Spawner) -> Self {
+        let p = embassy_rp::init(Default::default());
+
+        let wheels = {
+            let config = || {
+                let mut config = pwm::Config::default();
+                config.top = PWM_TOP;
+                config
+            };
+            // Kept alive for good, dropping them would release the pins
+            make_static!(
+                [pwm::Pwm<'static>; 2],
+                [
+                    pwm::Pwm::new_output_ab(p.PWM_SLICE0, p.PIN_16, p.PIN_17, config()),
+                    pwm::Pwm::new_output_ab(p.PWM_SLICE1, p.PIN_18, p.PIN_19, config()),
+                ]
+            );
+            let motor = |slice, b, dir0, dir1| {
+                wheel(
+                    MyMotorBuilder::new()
+                        .pwm(Pwm { slice, b })
+                        .dir_pins(dir0, dir1)
+                        .build(),
+                )
+            };
+
+            FourWheelRobotBuilder::new()
+                .front_left(motor(
+                    0,
+                    false,
+                    Output::new(p.PIN_10, Level::Low),
+                    Output::new(p.PIN_11, Level::Low),
+                ))
+                .front_right(motor(
+                    0,
+                    true,
+                    Output::new(p.PIN_12, Level::Low),
+                    Output::new(p.PIN_13, Level::Low),
+                ))
+                .back_left(motor(
+                    1,
+                    false,
+                    Output::new(p.PIN_14, Level::Low),
+                    Output::new(p.PIN_15, Level::Low),
+                ))
+                .back_right(motor(
+                    1,
+                    true,
+                    Output::new(p.PIN_20, Level::Low),
+                    Output::new(p.PIN_21, Level::Low),
+                ))
+                .build()
+        };
+
+        let encoders = {
+            static COUNTERS: [ExtiCounter; 4] = [const { ExtiCounter::new() }; 4];
+
+            let pins = [
+                (Input::new(p.PIN_2, Pull::Up), Input::new(p.PIN_3, Pull::Up)),
+                (Input::new(p.PIN_4, Pull::Up), Input::new(p.PIN_5, Pull::Up)),
+                (Input::new(p.PIN_6, Pull::Up), Input::new(p.PIN_7, Pull::Up)),
+                (Input::new(p.PIN_8, Pull::Up), Input::new(p.PIN_9, Pull::Up)),
+            ];
+            for ((a, b), counter) in pins.into_iter().zip(&COUNTERS) {
+                spawner.spawn(exti_encoder_task(a, b, counter)).unwrap();
+            }
+
+            let encoders = make_static!(
+                [QuadratureEncoder<&'static ExtiCounter>; 4],
+                COUNTERS.each_ref().map(|c| QuadratureEncoder::new(
+                    c,
+                    ENCODER_TICKS_PER_REV,
+                    false
+                ))
+            );
+            let [fl, fr, bl, br] = encoders;
+            [fl, fr, bl, br].map(|e| -> WheelEncoder { e })
+        };
+
+        let i2c = {
+            let mut config = i2c::Config::default();
+            config.frequency = 400_000;
+            let bus: &'static _ = make_static!(
+                Mutex<NoopRawMutex, I2cBus>,
+                Mutex::new(i2c::I2c::new_async(
+                    p.I2C1, p.PIN_27, p.PIN_26, Irqs, config,
+                ))
+            );
+            bus
+        };
+        let imu = BoardImu::new(I2cDevice::new(i2c), BoardImu::DEFAULT_ADDRESS);
+        #[cfg(feature = "vl53l0x")]
+        let tof = BoardTof::new(I2cDevice::new(i2c), BoardTof::DEFAULT_ADDRESS);
+        #[cfg(feature = "magnetometer")]
+        let mag = BoardMag::new(I2cDevice::new(i2c), BoardMag::DEFAULT_ADDRESS);
+
+        Self {
+            wheels,
+            encoders,
+            imu,
+            #[cfg(feature = "vl53l0x")]
+            tof,
+            #[cfg(feature = "magnetometer")]
+            mag,
+            estop: Input::new(p.PIN_22, Pull::Up),
+            status_light: StatusLight(Output::new(p.PIN_25, Level::Low)),
+            aux: [],
+            watchdog: Watchdog(watchdog::Watchdog::new(p.WATCHDOG)),
+            analog: Analog {
+                adc: adc::Adc::new(p.ADC, Irqs, adc::Config::default()),
+                battery: adc::Channel::new_pin(p.PIN_28, Pull::None),
+            },
+            flash: Flash::new_blocking(p.FLASH),
+            host_uart: HostUart {
+                uart: p.UART0,
+                tx: p.PIN_0,
+                rx: p.PIN_1,
+            },
+            #[cfg(feature = "usb")]
+            usb: UsbPort { usb: p.USB },
+        }
+    }
+}
+
+/// UART0, to the host. Left unconfigured until [`HostUart::init`] so nothing
+/// gets buffered before anyone reads it.
+pub struct HostUart {
+    uart: UART0,
+    tx: peripherals::PIN_0,
+    rx: peripherals::PIN_1,
+}
+
+impl HostUart {
+    pub fn init(self) -> (HostTx, HostRx) {
+        // Static so the TX half can be moved into its own task.
+        let tx_buf = make_static!([u8; 64], [0; 64]);
+        let rx_buf = make_static!([u8; RX_SIZE], [0; RX_SIZE]);
+
+        BufferedUart::new(
+            self.uart,
+            Irqs,
+            self.tx,
+            self.rx,
+            tx_buf,
+            rx_buf,
+            uart::Config::default(),
+        )
+        .split()
+    }
+}
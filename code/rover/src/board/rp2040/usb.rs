@@ -0,0 +1,179 @@
+//! The Pico's own USB port as a CDC-ACM serial device, a second host link
+//! carrying the same frames as UART0.
+
+use core::convert::Infallible;
+
+use embassy_executor::Spawner;
+use embassy_rp::{
+    bind_interrupts,
+    peripherals::USB,
+    usb::{Driver, InterruptHandler},
+};
+use embassy_time::{with_timeout, Duration};
+use embassy_usb::{
+    class::cdc_acm::{CdcAcmClass, Receiver, Sender, State},
+    Builder, UsbDevice,
+};
+use embedded_io_async::{BufRead, ErrorKind, ErrorType, Read, Write};
+
+#[cfg(feature = "mavlink")]
+compile_error!("USB carries the rover_proto frames, build without mavlink");
+
+bind_interrupts!(struct Irqs {
+    USBCTRL_IRQ => InterruptHandler<USB>;
+});
+
+/// The pid.codes test IDs, fine for a device nobody sells.
+const VID: u16 = 0x1209;
+const PID: u16 = 0x0001;
+/// Full speed bulk endpoints.
+const MAX_PACKET: usize = 64;
+/// A host holding the port open without reading it mustn't stall the other
+/// transports for longer.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(50);
+
+type UsbDriver = Driver<'static, USB>;
+
+/// Left alone until [`UsbPort::init`], so the rover doesn't enumerate before
+/// anything answers.
+pub struct UsbPort {
+    pub(super) usb: USB,
+}
+
+impl UsbPort {
+    /// Also spawns the task answering the host's USB requests.
+    pub fn init(self, spawner: Spawner) -> (UsbTx, UsbRx) {
+        let mut config = embassy_usb::Config::new(VID, PID);
+        config.manufacturer = Some("rover_mecanum");
+        config.product = Some("Rover");
+        config.max_power = 100;
+        config.max_packet_size_0 = MAX_PACKET as u8;
+        // An interface association, for Windows to load its CDC driver
+        config.device_class = 0xEF;
+        config.device_sub_class = 0x02;
+        config.device_protocol = 0x01;
+        config.composite_with_iads = true;
+
+        let mut builder = Builder::new(
+            Driver::new(self.usb, Irqs),
+            config,
+            make_static!([u8; 256], [0; 256]),
+            make_static!([u8; 256], [0; 256]),
+            &mut [],
+            make_static!([u8; 64], [0; 64]),
+        );
+        let class = CdcAcmClass::new(
+            &mut builder,
+            make_static!(State<'static>, State::new()),
+            MAX_PACKET as u16,
+        );
+        spawner.spawn(usb_task(builder.build())).unwrap();
+
+        let (tx, rx) = class.split();
+        (
+            UsbTx(tx),
+            UsbRx {
+                rx,
+                buf: [0; MAX_PACKET],
+                start: 0,
+                end: 0,
+            },
+        )
+    }
+}
+
+#[embassy_executor::task]
+async fn usb_task(mut usb: UsbDevice<'static, UsbDriver>) -> ! {
+    usb.run().await
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UsbError {
+    /// Unplugged, or not set up by the host yet.
+    Disconnected,
+    /// The host stopped reading.
+    TimedOut,
+}
+
+impl embedded_io_async::Error for UsbError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Disconnected => ErrorKind::NotConnected,
+            Self::TimedOut => ErrorKind::TimedOut,
+        }
+    }
+}
+
+pub struct UsbTx(Sender<'static, UsbDriver>);
+
+impl UsbTx {
+    /// A program on the host has the port open.
+    pub fn is_connected(&self) -> bool {
+        self.0.dtr()
+    }
+
+    async fn write_packet(&mut self, packet: &[u8]) -> Result<(), UsbError> {
+        with_timeout(WRITE_TIMEOUT, self.0.write_packet(packet))
+            .await
+            .map_err(|_| UsbError::TimedOut)?
+            .map_err(|_| UsbError::Disconnected)
+    }
+}
+
+impl ErrorType for UsbTx {
+    type Error = UsbError;
+}
+
+impl Write for UsbTx {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, UsbError> {
+        let n = buf.len().min(MAX_PACKET);
+        self.write_packet(&buf[..n]).await?;
+        // A full packet doesn't end a transfer, and some hosts hold on to
+        // the data until one ends
+        if n == MAX_PACKET && buf.len() == n {
+            self.write_packet(&[]).await?;
+        }
+        Ok(n)
+    }
+}
+
+/// Reads across unplugging, waiting for the host to come back.
+pub struct UsbRx {
+    rx: Receiver<'static, UsbDriver>,
+    buf: [u8; MAX_PACKET],
+    start: usize,
+    end: usize,
+}
+
+impl ErrorType for UsbRx {
+    type Error = Infallible;
+}
+
+impl BufRead for UsbRx {
+    async fn fill_buf(&mut self) -> Result<&[u8], Infallible> {
+        while self.start == self.end {
+            self.rx.wait_connection().await;
+            // Fails if unplugged meanwhile, to wait again
+            if let Ok(n) = self.rx.read_packet(&mut self.buf).await {
+                self.start = 0;
+                self.end = n;
+            }
+        }
+        Ok(&self.buf[self.start..self.end])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.start = (self.start + amt).min(self.end);
+    }
+}
+
+impl Read for UsbRx {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let data = self.fill_buf().await?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
@@ -0,0 +1,773 @@
+//! STM32F411RE bring-up, on the pins of the [`bsp`] revision.
+
+mod bsp;
+#[cfg(feature = "i2c_target")]
+mod i2c_target;
+mod pwm;
+#[cfg(any(feature = "rc_pwm", feature = "rc_ppm"))]
+mod rc_input;
+
+use embassy_executor::Spawner;
+#[cfg(feature = "buzzer")]
+use embassy_stm32::timer::Channel;
+#[cfg(any(feature = "shell", feature = "bluetooth"))]
+use embassy_stm32::usart::BufferedUart;
+#[cfg(feature = "bluetooth")]
+use embassy_stm32::usart::{BufferedUartRx, BufferedUartTx};
+use embassy_stm32::{
+    adc::Adc,
+    bind_interrupts,
+    exti::ExtiInput,
+    flash::{Blocking, Flash},
+    gpio::{AnyPin, Input, Output},
+    i2c, peripherals,
+    timer::{qei, simple_pwm},
+    usart::{self, RingBufferedUartRx, Uart, UartTx},
+    wdg::IndependentWatchdog,
+};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex};
+#[cfg(feature = "neopixel")]
+use {
+    embassy_stm32::{dma::NoDma, spi, time::Hertz},
+    rover_lib::indicator::{encode_ws2812, ws2812_frame_len, Rgb, WS2812_SPI_HZ},
+};
+
+#[cfg(feature = "magnetometer")]
+use rover_lib::compass::Qmc5883l;
+#[cfg(feature = "imu_icm20948")]
+use rover_lib::imu::Icm20948;
+#[cfg(not(feature = "imu_icm20948"))]
+use rover_lib::imu::Mpu6050;
+#[cfg(feature = "nrf24")]
+use rover_lib::nrf24::Nrf24;
+#[cfg(feature = "vl53l0x")]
+use rover_lib::tof::Vl53l0x;
+use rover_lib::{
+    encoder::{HardwareCounter, QuadratureEncoder, TimerCounter},
+    FourWheelRobotBuilder, MyMotorBuilder,
+};
+use rover_proto::{ResetCause, RX_SIZE};
+
+#[cfg(feature = "bluetooth")]
+pub use bsp::BluetoothUart;
+#[cfg(feature = "gps")]
+pub use bsp::GpsUart;
+pub use bsp::HostUart;
+#[cfg(feature = "ultrasonic")]
+pub use bsp::RangerPins;
+#[cfg(feature = "sbus")]
+pub use bsp::SbusUart;
+#[cfg(feature = "shell")]
+pub use bsp::ShellUart;
+#[cfg(feature = "servo")]
+pub use pwm::ServoPwm;
+pub use pwm::{kill_motor_outputs, Pwm};
+#[cfg(feature = "i2c_target")]
+pub use {bsp::I2cTargetPins, i2c_target::I2cTarget};
+#[cfg(any(feature = "rc_pwm", feature = "rc_ppm"))]
+pub use {bsp::RcCapture, rc_input::RcInput};
+
+#[cfg(not(feature = "neopixel"))]
+use super::StatusLight;
+use super::{wheel, I2cDevice, WheelEncoder, Wheels, ENCODER_TICKS_PER_REV};
+#[cfg(feature = "encoder_exti")]
+use crate::tasks::exti_encoder_task;
+use crate::tasks::WATCHDOG_TIMEOUT_US;
+use bsp::{AnalogPins, EncoderPins, ImuPins, MotorPins, PwmPins};
+use pwm::PwmSplitter;
+
+bind_interrupts!(struct Irqs {
+    USART6 => usart::InterruptHandler<peripherals::USART6>;
+    I2C1_EV => i2c::EventInterruptHandler<peripherals::I2C1>;
+    I2C1_ER => i2c::ErrorInterruptHandler<peripherals::I2C1>;
+});
+
+pub type DirPin = Output<'static, AnyPin>;
+
+/// For the panic handler: [`kill_motor_outputs`] and every direction pin
+/// low, whoever owns them.
+pub fn safe_motors() {
+    kill_motor_outputs();
+    bsp::force_dir_pins_low();
+}
+pub type EdgeInput = ExtiInput<'static, AnyPin>;
+pub type LedPin = Output<'static, AnyPin>;
+pub type ConfigFlash = Flash<'static, Blocking>;
+pub type HostTx = UartTx<'static, peripherals::USART6, peripherals::DMA2_CH6>;
+pub type HostRx = RingBufferedUartRx<'static, peripherals::USART6, peripherals::DMA2_CH1>;
+#[cfg(feature = "shell")]
+pub type ShellSerial = BufferedUart<'static, peripherals::USART2>;
+#[cfg(feature = "bluetooth")]
+pub type BluetoothTx = BufferedUartTx<'static, peripherals::USART2>;
+#[cfg(feature = "bluetooth")]
+pub type BluetoothRx = BufferedUartRx<'static, peripherals::USART2>;
+#[cfg(feature = "sbus")]
+pub type SbusRx = usart::UartRx<'static, peripherals::USART2, peripherals::DMA1_CH5>;
+#[cfg(feature = "gps")]
+pub type GpsRx = usart::UartRx<'static, peripherals::USART2, peripherals::DMA1_CH5>;
+
+/// The AUX outputs, on PA12, PC14 and PC15.
+pub const AUX_NAMES: [&str; 3] = ["lights", "relay", "magnet"];
+
+/// LEDs on the status strip, all showing the same.
+#[cfg(feature = "neopixel")]
+pub const STATUS_PIXELS: usize = 8;
+
+/// 50 Hz, what any servo takes.
+#[cfg(feature = "servo")]
+pub const SERVO_PERIOD_US: u32 = 20_000;
+
+/// Start of the last 128K sector of the STM32F411RE flash.
+pub const CONFIG_OFFSET: u32 = 0x6_0000;
+/// The 128K sector before it.
+pub const MACRO_OFFSET: u32 = 0x4_0000;
+/// The 128K sector before that, leaving the firmware 128K.
+pub const EVENT_LOG_OFFSET: u32 = 0x2_0000;
+pub const EVENT_LOG_SIZE: u32 = 0x2_0000;
+
+type I2cBus = i2c::I2c<'static, peripherals::I2C1, peripherals::DMA1_CH6, peripherals::DMA1_CH0>;
+#[cfg(not(feature = "imu_icm20948"))]
+pub type BoardImu = Mpu6050<I2cDevice<I2cBus>>;
+#[cfg(feature = "imu_icm20948")]
+pub type BoardImu = Icm20948<I2cDevice<I2cBus>>;
+#[cfg(feature = "vl53l0x")]
+pub type BoardTof = Vl53l0x<I2cDevice<I2cBus>>;
+#[cfg(feature = "magnetometer")]
+pub type BoardMag = Qmc5883l<I2cDevice<I2cBus>>;
+#[cfg(feature = "nrf24")]
+pub type BoardRadio = Nrf24<RadioSpi, Output<'static, AnyPin>>;
+
+/// SPI2 with the nRF24's chip select, which has it to itself.
+#[cfg(feature = "nrf24")]
+pub struct RadioSpi {
+    spi: embassy_stm32::spi::Spi<
+        'static,
+        peripherals::SPI2,
+        peripherals::DMA1_CH4,
+        peripherals::DMA1_CH3,
+    >,
+    csn: Output<'static, AnyPin>,
+}
+
+#[cfg(feature = "nrf24")]
+impl embedded_hal_async::spi::ErrorType for RadioSpi {
+    type Error = embassy_stm32::spi::Error;
+}
+
+#[cfg(feature = "nrf24")]
+impl embedded_hal_async::spi::SpiDevice for RadioSpi {
+    async fn transaction(
+        &mut self,
+        operations: &mut [embedded_hal_async::spi::Operation<'_, u8>],
+    ) -> Result<(), Self::Error> {
+        use embedded_hal_async::{
+            delay::DelayNs,
+            spi::{Operation, SpiBus},
+        };
+
+        self.csn.set_low();
+        let mut res = Ok(());
+        for operation in operations {
+            res = match operation {
+                Operation::Read(buf) => self.spi.read(buf).await,
+                Operation::Write(buf) => self.spi.write(buf).await,
+                Operation::Transfer(read, write) => self.spi.transfer(read, write).await,
+                Operation::TransferInPlace(buf) => self.spi.transfer_in_place(buf).await,
+                Operation::DelayNs(ns) => {
+                    embassy_time::Delay.delay_ns(*ns).await;
+                    Ok(())
+                }
+            };
+            if res.is_err() {
+                break;
+            }
+        }
+        self.csn.set_high();
+        res
+    }
+}
+
+struct QeiCounter<'d, T: embassy_stm32::timer::CaptureCompare16bitInstance>(qei::Qei<'d, T>);
+
+impl<'d, T: embassy_stm32::timer::CaptureCompare16bitInstance> HardwareCounter
+    for QeiCounter<'d, T>
+{
+    fn count(&self) -> u16 {
+        self.0.count()
+    }
+}
+
+/// Each LED of the strip at this much of full brightness, out of 255.
+#[cfg(feature = "neopixel")]
+const STATUS_BRIGHTNESS: u8 = 64;
+
+/// A WS2812 strip of [`STATUS_PIXELS`], all lit alike.
+#[cfg(feature = "neopixel")]
+pub struct StatusLight {
+    spi: spi::Spi<'static, peripherals::SPI3, peripherals::DMA1_CH7, NoDma>,
+    frame: [u8; ws2812_frame_len(STATUS_PIXELS)],
+}
+
+#[cfg(feature = "neopixel")]
+impl StatusLight {
+    fn new(pins: bsp::NeopixelPins) -> Self {
+        let mut config = spi::Config::default();
+        // PCLK1 at 16 MHz over 4
+        config.frequency = Hertz(WS2812_SPI_HZ);
+        Self {
+            spi: spi::Spi::new_txonly_nosck(pins.spi, pins.mosi, pins.tx_dma, NoDma, config),
+            frame: [0; ws2812_frame_len(STATUS_PIXELS)],
+        }
+    }
+
+    pub async fn show(&mut self, color: Rgb) {
+        let colors = [color.dimmed(STATUS_BRIGHTNESS); STATUS_PIXELS];
+        encode_ws2812(&colors, &mut self.frame);
+        if let Err(e) = self.spi.write(&self.frame).await {
+            defmt::warn!("status strip write failed: {}", defmt::Debug2Format(&e));
+        }
+    }
+}
+
+/// A passive buzzer, sounding at the PWM frequency.
+#[cfg(feature = "buzzer")]
+pub struct Buzzer(simple_pwm::SimplePwm<'static, peripherals::TIM9>);
+
+#[cfg(feature = "buzzer")]
+impl Buzzer {
+    fn new(pins: bsp::BuzzerPins) -> Self {
+        use embassy_stm32::{gpio::OutputType, time::hz};
+        use simple_pwm::PwmPin;
+
+        let mut pwm = simple_pwm::SimplePwm::new(
+            pins.timer,
+            Some(PwmPin::new_ch1(pins.ch1, OutputType::PushPull)),
+            None,
+            None,
+            None,
+            hz(2_000),
+            Default::default(),
+        );
+        pwm.disable(Channel::Ch1);
+        Self(pwm)
+    }
+
+    /// Silent for zero.
+    pub fn tone(&mut self, hz: u16) {
+        if hz == 0 {
+            self.0.disable(Channel::Ch1);
+            return;
+        }
+        self.0.set_frequency(embassy_stm32::time::hz(hz as u32));
+        // Square wave, the loudest
+        self.0.set_duty(Channel::Ch1, self.0.get_max_duty() / 2);
+        self.0.enable(Channel::Ch1);
+    }
+}
+
+pub struct Watchdog(IndependentWatchdog<'static, peripherals::IWDG>);
+
+impl Watchdog {
+    pub fn start(&mut self) {
+        self.0.unleash();
+    }
+
+    pub fn feed(&mut self) {
+        self.0.pet();
+    }
+}
+
+/// What the last reset came from. Clears the reset flags.
+///
+/// A power on also sets the brownout and pin flags, and any reset the pin
+/// flag, so the most specific one wins.
+pub fn take_reset_cause() -> ResetCause {
+    use embassy_stm32::pac::RCC;
+
+    let csr = RCC.csr().read();
+    RCC.csr().modify(|w| w.set_rmvf(true));
+    if csr.wdgrstf() || csr.wwdgrstf() {
+        ResetCause::Watchdog
+    } else if csr.sftrstf() {
+        ResetCause::Software
+    } else if csr.porrstf() {
+        ResetCause::PowerOn
+    } else if csr.borrstf() {
+        ResetCause::Brownout
+    } else if csr.padrstf() {
+        ResetCause::Pin
+    } else {
+        ResetCause::Unknown
+    }
+}
+
+/// Owns the ADC and the pins it samples.
+pub struct Analog {
+    adc: Adc<'static, peripherals::ADC1>,
+    pins: AnalogPins,
+}
+
+impl Analog {
+    pub async fn battery(&mut self) -> Option<u16> {
+        Some(self.adc.read(&mut self.pins.battery))
+    }
+
+    /// FL-FR-BL-BR.
+    #[cfg(feature = "current_sense")]
+    pub async fn currents(&mut self) -> [u16; 4] {
+        let (fl, fr, bl, br) = &mut self.pins.current;
+        [
+            self.adc.read(fl),
+            self.adc.read(fr),
+            self.adc.read(bl),
+            self.adc.read(br),
+        ]
+    }
+}
+
+/// A row of digital reflectance sensors across the front.
+#[cfg(feature = "line_sensor")]
+pub struct LineSensor([Input<'static, AnyPin>; 5]);
+
+#[cfg(feature = "line_sensor")]
+impl LineSensor {
+    /// Left to right, `true` over the line.
+    pub fn read(&self) -> [bool; 5] {
+        self.0.each_ref().map(Input::is_high)
+    }
+}
+
+/// Everything [`Board::init`] sets up, ready to be handed to the tasks.
+pub struct Board {
+    pub wheels: Wheels,
+    /// FL-FR-BL-BR.
+    pub encoders: [WheelEncoder; 4],
+    pub imu: BoardImu,
+    #[cfg(feature = "vl53l0x")]
+    pub tof: BoardTof,
+    #[cfg(feature = "magnetometer")]
+    pub mag: BoardMag,
+    #[cfg(feature = "nrf24")]
+    pub radio: BoardRadio,
+    pub button: EdgeInput,
+    pub estop: EdgeInput,
+    pub status_light: StatusLight,
+    /// In the order of [`AUX_NAMES`].
+    pub aux: [LedPin; AUX_NAMES.len()],
+    pub watchdog: Watchdog,
+    pub analog: Analog,
+    pub flash: ConfigFlash,
+    pub host_uart: HostUart,
+    #[cfg(feature = "shell")]
+    pub shell_uart: ShellUart,
+    #[cfg(feature = "bluetooth")]
+    pub bluetooth_uart: BluetoothUart,
+    #[cfg(feature = "sbus")]
+    pub sbus_uart: SbusUart,
+    #[cfg(feature = "gps")]
+    pub gps_uart: GpsUart,
+    #[cfg(any(feature = "rc_pwm", feature = "rc_ppm"))]
+    pub rc_capture: RcCapture,
+    #[cfg(feature = "i2c_target")]
+    pub i2c_target: I2cTargetPins,
+    /// Front, right, back, left.
+    #[cfg(feature = "ultrasonic")]
+    pub rangers: [RangerPins; 4],
+    #[cfg(feature = "line_sensor")]
+    pub line_sensor: LineSensor,
+    /// At [`SERVO_PERIOD_US`].
+    #[cfg(feature = "servo")]
+    pub servos: [ServoPwm; 2],
+    #[cfg(feature = "buzzer")]
+    pub buzzer: Buzzer,
+}
+
+impl Board {
+    /// Also spawns the EXTI encoder tasks when enabled, as they own the
+    /// encoder pins.
+    #[cfg_attr(not(feature = "encoder_exti"), allow(unused_variables))]
+    pub fn init(spawner: Spawner) -> Self {
+        let pins = bsp::split(embassy_stm32::init(Default::default()));
+
+        let pwm = {
+            use embassy_stm32::{gpio::OutputType, time::khz};
+            use simple_pwm::PwmPin;
+
+            let PwmPins {
+                timer,
+                ch1,
+                ch2,
+                ch3,
+                ch4,
+            } = pins.pwm;
+            let pwm = simple_pwm::SimplePwm::new(
+                timer,
+                Some(PwmPin::new_ch1(ch1, OutputType::PushPull)),
+                Some(PwmPin::new_ch2(ch2, OutputType::PushPull)),
+                Some(PwmPin::new_ch3(ch3, OutputType::PushPull)),
+                Some(PwmPin::new_ch4(ch4, OutputType::PushPull)),
+                khz(1),
+                Default::default(),
+            );
+
+            PwmSplitter::new(pwm).split()
+        };
+
+        let wheels = {
+            let [fl, fr, bl, br] = pins.motors;
+            let [ch1, ch2, ch3, ch4] = pwm;
+            let motor = |pins: MotorPins, pwm| {
+                wheel(
+                    MyMotorBuilder::new()
+                        .pwm(pwm)
+                        .dir_pins(pins.dir0, pins.dir1)
+                        .build(),
+                )
+            };
+            FourWheelRobotBuilder::new()
+                .front_left(motor(fl, ch1))
+                .front_right(motor(fr, ch2))
+                .back_left(motor(bl, ch3))
+                .back_right(motor(br, ch4))
+                .build()
+        };
+
+        let encoders: [WheelEncoder; 4] = {
+            use qei::{Qei, QeiPin};
+
+            type TimerEncoder<T> = QuadratureEncoder<TimerCounter<QeiCounter<'static, T>>>;
+
+            fn timer_encoder<T: embassy_stm32::timer::CaptureCompare16bitInstance>(
+                qei: Qei<'static, T>,
+            ) -> TimerEncoder<T> {
+                QuadratureEncoder::new(
+                    TimerCounter::new(QeiCounter(qei)),
+                    ENCODER_TICKS_PER_REV,
+                    false,
+                )
+            }
+
+            let EncoderPins { fl, fr, bl, br } = pins.encoders;
+
+            #[cfg(feature = "encoder_exti")]
+            let [fl, fr, br] = {
+                use rover_lib::encoder::ExtiCounter;
+
+                static COUNTERS: [ExtiCounter; 3] = [const { ExtiCounter::new() }; 3];
+                for ([a, b], counter) in [fl, fr, br].into_iter().zip(&COUNTERS) {
+                    spawner.spawn(exti_encoder_task(a, b, counter)).unwrap();
+                }
+
+                let [fl, fr, br] = make_static!(
+                    [QuadratureEncoder<&'static ExtiCounter>; 3],
+                    COUNTERS.each_ref().map(|c| QuadratureEncoder::new(
+                        c,
+                        ENCODER_TICKS_PER_REV,
+                        false
+                    ))
+                );
+                [fl, fr, br].map(|e| -> WheelEncoder { e })
+            };
+            #[cfg(not(feature = "encoder_exti"))]
+            let [fl, fr, br]: [WheelEncoder; 3] = [
+                make_static!(
+                    TimerEncoder<peripherals::TIM2>,
+                    timer_encoder(Qei::new(fl.0, QeiPin::new_ch1(fl.1), QeiPin::new_ch2(fl.2)))
+                ),
+                make_static!(
+                    TimerEncoder<peripherals::TIM3>,
+                    timer_encoder(Qei::new(fr.0, QeiPin::new_ch1(fr.1), QeiPin::new_ch2(fr.2)))
+                ),
+                make_static!(
+                    TimerEncoder<peripherals::TIM5>,
+                    timer_encoder(Qei::new(br.0, QeiPin::new_ch1(br.1), QeiPin::new_ch2(br.2)))
+                ),
+            ];
+            let bl = make_static!(
+                TimerEncoder<peripherals::TIM4>,
+                timer_encoder(Qei::new(bl.0, QeiPin::new_ch1(bl.1), QeiPin::new_ch2(bl.2)))
+            );
+
+            [fl, fr, bl, br]
+        };
+
+        let i2c = {
+            use embassy_stm32::time::khz;
+
+            let ImuPins {
+                i2c,
+                scl,
+                sda,
+                tx_dma,
+                rx_dma,
+            } = pins.imu;
+            let bus: &'static _ = make_static!(
+                Mutex<NoopRawMutex, I2cBus>,
+                Mutex::new(i2c::I2c::new(
+                    i2c,
+                    scl,
+                    sda,
+                    Irqs,
+                    tx_dma,
+                    rx_dma,
+                    khz(400),
+                    Default::default(),
+                ))
+            );
+            bus
+        };
+        let imu = BoardImu::new(I2cDevice::new(i2c), BoardImu::DEFAULT_ADDRESS);
+        #[cfg(feature = "vl53l0x")]
+        let tof = BoardTof::new(I2cDevice::new(i2c), BoardTof::DEFAULT_ADDRESS);
+        #[cfg(feature = "magnetometer")]
+        let mag = BoardMag::new(I2cDevice::new(i2c), BoardMag::DEFAULT_ADDRESS);
+
+        #[cfg(feature = "nrf24")]
+        let radio = {
+            use embassy_stm32::{spi, time::mhz};
+
+            let bsp::RadioPins {
+                spi,
+                sck,
+                miso,
+                mosi,
+                tx_dma,
+                rx_dma,
+                csn,
+                ce,
+            } = pins.radio;
+            let mut config = spi::Config::default();
+            // Of the 10 MHz the nRF24 takes
+            config.frequency = mhz(8);
+            let spi = spi::Spi::new(spi, sck, mosi, miso, tx_dma, rx_dma, config);
+            BoardRadio::new(RadioSpi { spi, csn }, ce)
+        };
+
+        Self {
+            wheels,
+            encoders,
+            imu,
+            #[cfg(feature = "vl53l0x")]
+            tof,
+            #[cfg(feature = "magnetometer")]
+            mag,
+            #[cfg(feature = "nrf24")]
+            radio,
+            button: pins.button,
+            estop: pins.estop,
+            #[cfg(not(feature = "neopixel"))]
+            status_light: StatusLight(pins.status_led),
+            #[cfg(feature = "neopixel")]
+            status_light: StatusLight::new(pins.neopixel),
+            aux: pins.aux,
+            watchdog: Watchdog(IndependentWatchdog::new(pins.watchdog, WATCHDOG_TIMEOUT_US)),
+            analog: Analog {
+                adc: Adc::new(pins.adc, &mut embassy_time::Delay),
+                pins: pins.analog,
+            },
+            flash: Flash::new_blocking(pins.flash),
+            host_uart: pins.host_uart,
+            #[cfg(feature = "shell")]
+            shell_uart: pins.shell_uart,
+            #[cfg(feature = "bluetooth")]
+            bluetooth_uart: pins.bluetooth_uart,
+            #[cfg(feature = "sbus")]
+            sbus_uart: pins.sbus_uart,
+            #[cfg(feature = "gps")]
+            gps_uart: pins.gps_uart,
+            #[cfg(any(feature = "rc_pwm", feature = "rc_ppm"))]
+            rc_capture: pins.rc_capture,
+            #[cfg(feature = "i2c_target")]
+            i2c_target: pins.i2c_target,
+            #[cfg(feature = "ultrasonic")]
+            rangers: pins.rangers,
+            #[cfg(feature = "line_sensor")]
+            line_sensor: LineSensor(pins.line_sensor),
+            #[cfg(feature = "servo")]
+            servos: {
+                use embassy_stm32::{gpio::OutputType, time::hz};
+                use simple_pwm::PwmPin;
+
+                let bsp::ServoPins { timer, ch1, ch2 } = pins.servos;
+                let pwm = simple_pwm::SimplePwm::new(
+                    timer,
+                    Some(PwmPin::new_ch1(ch1, OutputType::PushPull)),
+                    Some(PwmPin::new_ch2(ch2, OutputType::PushPull)),
+                    None,
+                    None,
+                    hz(1_000_000 / SERVO_PERIOD_US),
+                    Default::default(),
+                );
+                pwm::split_servos(pwm)
+            },
+            #[cfg(feature = "buzzer")]
+            buzzer: Buzzer::new(pins.buzzer),
+        }
+    }
+}
+
+/// Left unconfigured until here so nothing gets buffered before anyone reads
+/// it.
+impl HostUart {
+    /// Received by DMA into a ring buffer, read out whenever the line goes
+    /// idle or half of it fills, so a whole frame takes one wake-up rather
+    /// than one per byte.
+    pub fn init(self) -> (HostTx, HostRx) {
+        let ring = make_static!([u8; RX_SIZE], [0; RX_SIZE]);
+
+        let (tx, rx) = Uart::new(
+            self.usart,
+            self.rx,
+            self.tx,
+            Irqs,
+            self.tx_dma,
+            self.rx_dma,
+            usart::Config::default(),
+        )
+        .unwrap()
+        .split();
+        (tx, rx.into_ring_buffered(ring))
+    }
+}
+
+#[cfg(feature = "shell")]
+bind_interrupts!(struct ShellIrqs {
+    USART2 => usart::BufferedInterruptHandler<peripherals::USART2>;
+});
+
+#[cfg(feature = "shell")]
+impl ShellUart {
+    pub fn init(self) -> ShellSerial {
+        let tx_buf = make_static!([u8; 256], [0; 256]);
+        let rx_buf = make_static!([u8; 64], [0; 64]);
+
+        BufferedUart::new(
+            self.usart,
+            ShellIrqs,
+            self.rx,
+            self.tx,
+            tx_buf,
+            rx_buf,
+            usart::Config::default(),
+        )
+        .unwrap()
+    }
+}
+
+#[cfg(feature = "bluetooth")]
+bind_interrupts!(struct BluetoothIrqs {
+    USART2 => usart::BufferedInterruptHandler<peripherals::USART2>;
+});
+
+#[cfg(feature = "bluetooth")]
+impl BluetoothUart {
+    pub fn init(self) -> BluetoothSerial {
+        let tx_buf = make_static!([u8; 64], [0; 64]);
+        let rx_buf = make_static!([u8; RX_SIZE], [0; RX_SIZE]);
+
+        let uart = BufferedUart::new(
+            self.usart,
+            BluetoothIrqs,
+            self.rx,
+            self.tx,
+            tx_buf,
+            rx_buf,
+            usart::Config::default(),
+        )
+        .unwrap();
+        BluetoothSerial {
+            uart,
+            #[cfg(not(feature = "hm10"))]
+            key: self.key,
+        }
+    }
+}
+
+/// The Bluetooth module UART, whole while it's being set up with AT
+/// commands.
+#[cfg(feature = "bluetooth")]
+pub struct BluetoothSerial {
+    uart: BufferedUart<'static, peripherals::USART2>,
+    #[cfg(not(feature = "hm10"))]
+    key: Output<'static, AnyPin>,
+}
+
+#[cfg(feature = "bluetooth")]
+impl BluetoothSerial {
+    pub fn set_baudrate(&mut self, baudrate: u32) {
+        let mut config = usart::Config::default();
+        config.baudrate = baudrate;
+        self.uart.set_config(&config).unwrap();
+    }
+
+    /// Holds the HC-05 in AT command mode, a no-op on the HM-10.
+    pub fn set_command_mode(&mut self, on: bool) {
+        #[cfg(not(feature = "hm10"))]
+        self.key.set_level(on.into());
+        #[cfg(feature = "hm10")]
+        let _ = on;
+    }
+
+    pub fn split(self) -> (BluetoothTx, BluetoothRx) {
+        self.uart.split()
+    }
+}
+
+#[cfg(feature = "bluetooth")]
+impl embedded_io_async::ErrorType for BluetoothSerial {
+    type Error = usart::Error;
+}
+
+#[cfg(feature = "bluetooth")]
+impl embedded_io_async::Read for BluetoothSerial {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        embedded_io_async::Read::read(&mut self.uart, buf).await
+    }
+}
+
+#[cfg(feature = "bluetooth")]
+impl embedded_io_async::Write for BluetoothSerial {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        embedded_io_async::Write::write(&mut self.uart, buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        embedded_io_async::Write::flush(&mut self.uart).await
+    }
+}
+
+#[cfg(feature = "sbus")]
+bind_interrupts!(struct SbusIrqs {
+    USART2 => usart::InterruptHandler<peripherals::USART2>;
+});
+
+#[cfg(feature = "sbus")]
+impl SbusUart {
+    /// Frames are read whole with [`SbusRx::read_until_idle`].
+    pub fn init(self) -> SbusRx {
+        let mut config = usart::Config::default();
+        config.baudrate = 100_000;
+        config.parity = usart::Parity::ParityEven;
+        config.stop_bits = usart::StopBits::STOP2;
+
+        usart::UartRx::new(self.usart, SbusIrqs, self.rx, self.rx_dma, config).unwrap()
+    }
+}
+
+#[cfg(feature = "gps")]
+bind_interrupts!(struct GpsIrqs {
+    USART2 => usart::InterruptHandler<peripherals::USART2>;
+});
+
+#[cfg(feature = "gps")]
+impl GpsUart {
+    /// Sentences come in a burst every second, read with
+    /// [`GpsRx::read_until_idle`].
+    pub fn init(self) -> GpsRx {
+        let mut config = usart::Config::default();
+        // What most receivers start at
+        config.baudrate = 9600;
+
+        usart::UartRx::new(self.usart, GpsIrqs, self.rx, self.rx_dma, config).unwrap()
+    }
+}
@@ -0,0 +1,541 @@
+//! Pin assignments of each hardware revision, picked by feature:
+//! `old_circuit` for the hand wired prototype, `pcb_shield_v0` (the default)
+//! for the Nucleo shield.
+//!
+//! [`split`] hands out the peripherals grouped by what they're wired to,
+//! ready-made for plain GPIOs and raw for the rest, so [`super`] never
+//! names a pin.
+
+use embassy_stm32::{
+    exti::{Channel, ExtiInput},
+    gpio::{AnyPin, Input, Level, Output, Pin, Pull, Speed},
+    pac,
+    peripherals::*,
+    Peripherals,
+};
+
+#[cfg(all(feature = "old_circuit", feature = "current_sense"))]
+compile_error!("current sensing needs PB1, used by the old circuit for a motor");
+#[cfg(all(feature = "current_sense", feature = "shell"))]
+compile_error!("current sensing needs PA2 and PA3, used by the shell UART");
+#[cfg(all(feature = "bluetooth", feature = "shell"))]
+compile_error!("the Bluetooth module and the shell both need USART2");
+#[cfg(all(feature = "bluetooth", feature = "current_sense"))]
+compile_error!("current sensing needs PA2 and PA3, used by the Bluetooth UART");
+#[cfg(all(feature = "sbus", any(feature = "shell", feature = "bluetooth")))]
+compile_error!("the SBUS receiver needs USART2, taken by the shell or Bluetooth");
+#[cfg(all(feature = "sbus", feature = "current_sense"))]
+compile_error!("current sensing needs PA3, used by the SBUS receiver");
+#[cfg(all(
+    feature = "gps",
+    any(feature = "shell", feature = "bluetooth", feature = "sbus")
+))]
+compile_error!("the GPS receiver needs USART2, taken by the shell, Bluetooth or SBUS");
+#[cfg(all(feature = "gps", feature = "current_sense"))]
+compile_error!("current sensing needs PA3, used by the GPS receiver");
+#[cfg(all(
+    any(feature = "rc_pwm", feature = "rc_ppm"),
+    not(feature = "encoder_exti")
+))]
+compile_error!("RC capture needs TIM3, which decodes the FR encoder without encoder_exti");
+#[cfg(all(feature = "rc_pwm", feature = "rc_ppm"))]
+compile_error!("rc_pwm and rc_ppm are two ways of wiring the same receiver, pick one");
+#[cfg(all(feature = "sbus", any(feature = "rc_pwm", feature = "rc_ppm")))]
+compile_error!("one RC receiver at a time");
+#[cfg(all(
+    feature = "nrf24",
+    any(feature = "sbus", feature = "rc_pwm", feature = "rc_ppm")
+))]
+compile_error!("one RC receiver at a time, the nRF24 radio being one");
+#[cfg(all(
+    feature = "nrf24",
+    any(
+        feature = "old_circuit",
+        feature = "ultrasonic",
+        feature = "line_sensor"
+    )
+))]
+compile_error!("the nRF24 radio needs PB2 and PB12-15, used by the old circuit, the rangers or the line sensor");
+#[cfg(all(feature = "rc_pwm", feature = "bluetooth", not(feature = "hm10")))]
+compile_error!("the HC-05 KEY and RC channel 1 both need PB4");
+#[cfg(all(feature = "ultrasonic", feature = "old_circuit"))]
+compile_error!("the ultrasonic rangers need PB2, PB12-15 and PC4, used by the old circuit");
+#[cfg(all(feature = "line_sensor", feature = "old_circuit"))]
+compile_error!("the line sensor needs PB12-15 and PC4, used by the old circuit");
+#[cfg(all(feature = "line_sensor", feature = "ultrasonic"))]
+compile_error!("the line sensor and the ultrasonic rangers both need PB12-15 and PC4");
+#[cfg(all(
+    feature = "servo",
+    any(
+        feature = "shell",
+        feature = "bluetooth",
+        feature = "sbus",
+        feature = "current_sense"
+    )
+))]
+compile_error!("the servos need PA2 and PA3, taken by USART2 or current sensing");
+#[cfg(all(feature = "servo", feature = "gps"))]
+compile_error!("the servos need PA3, used by the GPS receiver");
+#[cfg(all(
+    feature = "buzzer",
+    any(
+        feature = "shell",
+        feature = "bluetooth",
+        feature = "current_sense",
+        feature = "servo"
+    )
+))]
+compile_error!("the buzzer needs TIM9 and PA2, taken by USART2, current sensing or the servos");
+#[cfg(all(feature = "i2c_target", not(feature = "encoder_exti")))]
+compile_error!(
+    "the I2C target needs PB3, which TIM2 decodes the FL encoder on without encoder_exti"
+);
+#[cfg(all(feature = "i2c_target", feature = "ultrasonic"))]
+compile_error!("the I2C target moves the e-stop to PA15, used by the ultrasonic rangers");
+#[cfg(all(
+    feature = "i2c_target",
+    any(
+        feature = "shell",
+        feature = "bluetooth",
+        feature = "sbus",
+        feature = "gps",
+        feature = "current_sense",
+        feature = "servo"
+    )
+))]
+compile_error!(
+    "the I2C target moves the FL encoder to PA3, taken by USART2, current sensing or the servos"
+);
+#[cfg(feature = "usb")]
+compile_error!("USB needs PA11 and PA12, used by the BR motor PWM and the lights");
+
+/// Direction inputs of one motor driver.
+pub struct MotorPins {
+    pub dir0: Output<'static, AnyPin>,
+    pub dir1: Output<'static, AnyPin>,
+}
+
+/// TIM1 channels 1-4, one per motor FL-FR-BL-BR.
+pub struct PwmPins {
+    pub timer: TIM1,
+    pub ch1: PA8,
+    pub ch2: PA9,
+    pub ch3: PA10,
+    pub ch4: PA11,
+}
+
+/// TIM9 channels 1 and 2, servos 0 and 1.
+#[cfg(feature = "servo")]
+pub struct ServoPins {
+    pub timer: TIM9,
+    pub ch1: PA2,
+    pub ch2: PA3,
+}
+
+/// TIM9 channel 1, for a passive buzzer.
+#[cfg(feature = "buzzer")]
+pub struct BuzzerPins {
+    pub timer: TIM9,
+    pub ch1: PA2,
+}
+
+/// A WS2812 strip's data line, clocked out of the MOSI of SPI3 without SCK
+/// (on PB3, an encoder input).
+#[cfg(feature = "neopixel")]
+pub struct NeopixelPins {
+    pub spi: SPI3,
+    pub mosi: PB5,
+    pub tx_dma: DMA1_CH7,
+}
+
+/// Quadrature encoders, FL-FR-BL-BR. With `encoder_exti` all but BL are
+/// decoded from pin interrupts instead of a timer: PB6/PB7 share EXTI lines
+/// 6/7 with the FR encoder. `i2c_target` moves FL's B from PB3 to PA3.
+pub struct EncoderPins {
+    #[cfg(not(feature = "encoder_exti"))]
+    pub fl: (TIM2, PA5, PB3),
+    #[cfg(not(feature = "encoder_exti"))]
+    pub fr: (TIM3, PA6, PA7),
+    #[cfg(not(feature = "encoder_exti"))]
+    pub br: (TIM5, PA0, PA1),
+    #[cfg(feature = "encoder_exti")]
+    pub fl: [ExtiInput<'static, AnyPin>; 2],
+    #[cfg(feature = "encoder_exti")]
+    pub fr: [ExtiInput<'static, AnyPin>; 2],
+    #[cfg(feature = "encoder_exti")]
+    pub br: [ExtiInput<'static, AnyPin>; 2],
+    pub bl: (TIM4, PB6, PB7),
+}
+
+pub struct ImuPins {
+    pub i2c: I2C1,
+    pub scl: PB8,
+    pub sda: PB9,
+    pub tx_dma: DMA1_CH6,
+    pub rx_dma: DMA1_CH0,
+}
+
+pub struct AnalogPins {
+    /// Through a 100k over 10k divider.
+    pub battery: PA4,
+    /// FL-FR-BL-BR.
+    #[cfg(feature = "current_sense")]
+    pub current: (PA2, PA3, PB0, PB1),
+}
+
+/// USART6, to the host.
+pub struct HostUart {
+    pub usart: USART6,
+    pub rx: PC7,
+    pub tx: PC6,
+    pub tx_dma: DMA2_CH6,
+    pub rx_dma: DMA2_CH1,
+}
+
+/// USART2, the ST-LINK virtual COM port on Nucleo boards.
+#[cfg(feature = "shell")]
+pub struct ShellUart {
+    pub usart: USART2,
+    pub rx: PA3,
+    pub tx: PA2,
+}
+
+/// USART2, to an HC-05 or HM-10 module.
+#[cfg(feature = "bluetooth")]
+pub struct BluetoothUart {
+    pub usart: USART2,
+    pub rx: PA3,
+    pub tx: PA2,
+    /// HC-05 KEY, high for AT commands. The HM-10 takes them whenever it's
+    /// not connected.
+    #[cfg(not(feature = "hm10"))]
+    pub key: Output<'static, AnyPin>,
+}
+
+/// USART2 RX only, from an SBUS receiver through an external inverter: the
+/// F411 can't invert its inputs.
+#[cfg(feature = "sbus")]
+pub struct SbusUart {
+    pub usart: USART2,
+    pub rx: PA3,
+    pub rx_dma: DMA1_CH5,
+}
+
+/// USART2 RX only, from a GPS receiver's TX: it needs no setting up.
+#[cfg(feature = "gps")]
+pub struct GpsUart {
+    pub usart: USART2,
+    pub rx: PA3,
+    pub rx_dma: DMA1_CH5,
+}
+
+/// I2C2 as a target, SCL where the e-stop was.
+#[cfg(feature = "i2c_target")]
+pub struct I2cTargetPins {
+    pub i2c: I2C2,
+    pub scl: PB10,
+    pub sda: PB3,
+}
+
+/// SPI2 to an nRF24L01+ module.
+#[cfg(feature = "nrf24")]
+pub struct RadioPins {
+    pub spi: SPI2,
+    pub sck: PB13,
+    pub miso: PB14,
+    pub mosi: PB15,
+    pub tx_dma: DMA1_CH4,
+    pub rx_dma: DMA1_CH3,
+    pub csn: Output<'static, AnyPin>,
+    pub ce: Output<'static, AnyPin>,
+}
+
+/// TIM3 input capture, from an RC receiver. Only free with `encoder_exti`.
+#[cfg(any(feature = "rc_pwm", feature = "rc_ppm"))]
+pub struct RcCapture {
+    pub timer: TIM3,
+    /// Receiver channel 1, aileron.
+    #[cfg(feature = "rc_pwm")]
+    pub ch1: PB4,
+    /// Receiver channel 2, elevator, or the CPPM output.
+    pub ch3: PC8,
+    /// Receiver channel 4, rudder.
+    #[cfg(feature = "rc_pwm")]
+    pub ch4: PC9,
+}
+
+/// TRIG output and ECHO input of one ranger. Echoes on EXTI lines 12, 14,
+/// 15 and 4, free of the encoders and buttons.
+#[cfg(feature = "ultrasonic")]
+pub struct RangerPins {
+    pub trigger: Output<'static, AnyPin>,
+    pub echo: ExtiInput<'static, AnyPin>,
+}
+
+pub struct Pins {
+    pub pwm: PwmPins,
+    /// FL-FR-BL-BR.
+    pub motors: [MotorPins; 4],
+    pub encoders: EncoderPins,
+    pub imu: ImuPins,
+    /// The user button, active low.
+    pub button: ExtiInput<'static, AnyPin>,
+    /// Normally closed to ground, high when tripped. On PA15 with
+    /// `i2c_target`.
+    pub estop: ExtiInput<'static, AnyPin>,
+    #[cfg(not(feature = "neopixel"))]
+    pub status_led: Output<'static, AnyPin>,
+    #[cfg(feature = "neopixel")]
+    pub neopixel: NeopixelPins,
+    /// In the order of [`super::AUX_NAMES`].
+    pub aux: [Output<'static, AnyPin>; 3],
+    pub adc: ADC1,
+    pub analog: AnalogPins,
+    pub watchdog: IWDG,
+    pub flash: FLASH,
+    pub host_uart: HostUart,
+    #[cfg(feature = "shell")]
+    pub shell_uart: ShellUart,
+    #[cfg(feature = "bluetooth")]
+    pub bluetooth_uart: BluetoothUart,
+    #[cfg(feature = "sbus")]
+    pub sbus_uart: SbusUart,
+    #[cfg(feature = "gps")]
+    pub gps_uart: GpsUart,
+    #[cfg(any(feature = "rc_pwm", feature = "rc_ppm"))]
+    pub rc_capture: RcCapture,
+    #[cfg(feature = "i2c_target")]
+    pub i2c_target: I2cTargetPins,
+    #[cfg(feature = "nrf24")]
+    pub radio: RadioPins,
+    /// Front, right, back, left.
+    #[cfg(feature = "ultrasonic")]
+    pub rangers: [RangerPins; 4],
+    /// Line sensor outputs, left to right, high over a dark line.
+    #[cfg(feature = "line_sensor")]
+    pub line_sensor: [Input<'static, AnyPin>; 5],
+    #[cfg(feature = "servo")]
+    pub servos: ServoPins,
+    #[cfg(feature = "buzzer")]
+    pub buzzer: BuzzerPins,
+}
+
+/// The direction pins [`split`] hands out, as GPIO port and pin number.
+#[cfg(feature = "old_circuit")]
+const DIR_PINS: [(pac::gpio::Gpio, usize); 8] = [
+    (pac::GPIOC, 4),
+    (pac::GPIOB, 13),
+    (pac::GPIOB, 14),
+    (pac::GPIOB, 15),
+    (pac::GPIOB, 1),
+    (pac::GPIOB, 2),
+    (pac::GPIOB, 12),
+    (pac::GPIOC, 5),
+];
+#[cfg(not(feature = "old_circuit"))]
+const DIR_PINS: [(pac::gpio::Gpio, usize); 8] = [
+    (pac::GPIOC, 0),
+    (pac::GPIOC, 1),
+    (pac::GPIOC, 2),
+    (pac::GPIOC, 3),
+    (pac::GPIOC, 5),
+    (pac::GPIOC, 10),
+    (pac::GPIOC, 11),
+    (pac::GPIOC, 12),
+];
+
+/// Drives the direction pins low behind the back of their [`Output`]s.
+pub fn force_dir_pins_low() {
+    for (port, pin) in DIR_PINS {
+        port.bsrr().write(|w| w.set_br(pin, true));
+    }
+}
+
+pub fn split(p: Peripherals) -> Pins {
+    let motor = |dir0: AnyPin, dir1: AnyPin| MotorPins {
+        dir0: Output::new(dir0, Level::Low, Speed::Low),
+        dir1: Output::new(dir1, Level::Low, Speed::Low),
+    };
+    #[cfg(feature = "old_circuit")]
+    let motors = [
+        motor(p.PC4.degrade(), p.PB13.degrade()),
+        motor(p.PB14.degrade(), p.PB15.degrade()),
+        motor(p.PB1.degrade(), p.PB2.degrade()),
+        motor(p.PB12.degrade(), p.PC5.degrade()),
+    ];
+    #[cfg(not(feature = "old_circuit"))]
+    let motors = [
+        motor(p.PC0.degrade(), p.PC1.degrade()),
+        motor(p.PC2.degrade(), p.PC3.degrade()),
+        motor(p.PC5.degrade(), p.PC10.degrade()),
+        motor(p.PC11.degrade(), p.PC12.degrade()),
+    ];
+
+    #[cfg(feature = "encoder_exti")]
+    let exti = |pin: AnyPin, ch: embassy_stm32::exti::AnyChannel| {
+        ExtiInput::new(Input::new(pin, Pull::Up), ch)
+    };
+    #[cfg(feature = "encoder_exti")]
+    let encoders = EncoderPins {
+        fl: [
+            exti(p.PA5.degrade(), p.EXTI5.degrade()),
+            #[cfg(not(feature = "i2c_target"))]
+            exti(p.PB3.degrade(), p.EXTI3.degrade()),
+            #[cfg(feature = "i2c_target")]
+            exti(p.PA3.degrade(), p.EXTI3.degrade()),
+        ],
+        fr: [
+            exti(p.PA6.degrade(), p.EXTI6.degrade()),
+            exti(p.PA7.degrade(), p.EXTI7.degrade()),
+        ],
+        br: [
+            exti(p.PA0.degrade(), p.EXTI0.degrade()),
+            exti(p.PA1.degrade(), p.EXTI1.degrade()),
+        ],
+        bl: (p.TIM4, p.PB6, p.PB7),
+    };
+    #[cfg(not(feature = "encoder_exti"))]
+    let encoders = EncoderPins {
+        fl: (p.TIM2, p.PA5, p.PB3),
+        fr: (p.TIM3, p.PA6, p.PA7),
+        br: (p.TIM5, p.PA0, p.PA1),
+        bl: (p.TIM4, p.PB6, p.PB7),
+    };
+
+    #[cfg(feature = "ultrasonic")]
+    let ranger = |trigger: AnyPin, echo: AnyPin, ch: embassy_stm32::exti::AnyChannel| RangerPins {
+        trigger: Output::new(trigger, Level::Low, Speed::Low),
+        echo: ExtiInput::new(Input::new(echo, Pull::Down), ch),
+    };
+
+    Pins {
+        pwm: PwmPins {
+            timer: p.TIM1,
+            ch1: p.PA8,
+            ch2: p.PA9,
+            ch3: p.PA10,
+            ch4: p.PA11,
+        },
+        motors,
+        encoders,
+        imu: ImuPins {
+            i2c: p.I2C1,
+            scl: p.PB8,
+            sda: p.PB9,
+            tx_dma: p.DMA1_CH6,
+            rx_dma: p.DMA1_CH0,
+        },
+        button: ExtiInput::new(Input::new(p.PC13.degrade(), Pull::Up), p.EXTI13.degrade()),
+        #[cfg(not(feature = "i2c_target"))]
+        estop: ExtiInput::new(Input::new(p.PB10.degrade(), Pull::Up), p.EXTI10.degrade()),
+        #[cfg(feature = "i2c_target")]
+        estop: ExtiInput::new(Input::new(p.PA15.degrade(), Pull::Up), p.EXTI15.degrade()),
+        #[cfg(not(feature = "neopixel"))]
+        status_led: Output::new(p.PB5.degrade(), Level::Low, Speed::Low),
+        #[cfg(feature = "neopixel")]
+        neopixel: NeopixelPins {
+            spi: p.SPI3,
+            mosi: p.PB5,
+            tx_dma: p.DMA1_CH7,
+        },
+        // PC14 and PC15 only sink or source 3 mA, enough for a transistor
+        aux: [
+            Output::new(p.PA12.degrade(), Level::Low, Speed::Low),
+            Output::new(p.PC14.degrade(), Level::Low, Speed::Low),
+            Output::new(p.PC15.degrade(), Level::Low, Speed::Low),
+        ],
+        adc: p.ADC1,
+        analog: AnalogPins {
+            battery: p.PA4,
+            #[cfg(feature = "current_sense")]
+            current: (p.PA2, p.PA3, p.PB0, p.PB1),
+        },
+        watchdog: p.IWDG,
+        flash: p.FLASH,
+        host_uart: HostUart {
+            usart: p.USART6,
+            rx: p.PC7,
+            tx: p.PC6,
+            tx_dma: p.DMA2_CH6,
+            rx_dma: p.DMA2_CH1,
+        },
+        #[cfg(feature = "shell")]
+        shell_uart: ShellUart {
+            usart: p.USART2,
+            rx: p.PA3,
+            tx: p.PA2,
+        },
+        #[cfg(feature = "bluetooth")]
+        bluetooth_uart: BluetoothUart {
+            usart: p.USART2,
+            rx: p.PA3,
+            tx: p.PA2,
+            #[cfg(not(feature = "hm10"))]
+            key: Output::new(p.PB4.degrade(), Level::Low, Speed::Low),
+        },
+        #[cfg(feature = "sbus")]
+        sbus_uart: SbusUart {
+            usart: p.USART2,
+            rx: p.PA3,
+            rx_dma: p.DMA1_CH5,
+        },
+        #[cfg(feature = "gps")]
+        gps_uart: GpsUart {
+            usart: p.USART2,
+            rx: p.PA3,
+            rx_dma: p.DMA1_CH5,
+        },
+        #[cfg(any(feature = "rc_pwm", feature = "rc_ppm"))]
+        rc_capture: RcCapture {
+            timer: p.TIM3,
+            #[cfg(feature = "rc_pwm")]
+            ch1: p.PB4,
+            ch3: p.PC8,
+            #[cfg(feature = "rc_pwm")]
+            ch4: p.PC9,
+        },
+        #[cfg(feature = "i2c_target")]
+        i2c_target: I2cTargetPins {
+            i2c: p.I2C2,
+            scl: p.PB10,
+            sda: p.PB3,
+        },
+        #[cfg(feature = "nrf24")]
+        radio: RadioPins {
+            spi: p.SPI2,
+            sck: p.PB13,
+            miso: p.PB14,
+            mosi: p.PB15,
+            tx_dma: p.DMA1_CH4,
+            rx_dma: p.DMA1_CH3,
+            csn: Output::new(p.PB12.degrade(), Level::High, Speed::VeryHigh),
+            ce: Output::new(p.PB2.degrade(), Level::Low, Speed::Low),
+        },
+        #[cfg(feature = "ultrasonic")]
+        rangers: [
+            ranger(p.PB13.degrade(), p.PB12.degrade(), p.EXTI12.degrade()),
+            ranger(p.PA15.degrade(), p.PB14.degrade(), p.EXTI14.degrade()),
+            ranger(p.PD2.degrade(), p.PB15.degrade(), p.EXTI15.degrade()),
+            ranger(p.PB2.degrade(), p.PC4.degrade(), p.EXTI4.degrade()),
+        ],
+        #[cfg(feature = "line_sensor")]
+        line_sensor: [
+            Input::new(p.PB12.degrade(), Pull::None),
+            Input::new(p.PB13.degrade(), Pull::None),
+            Input::new(p.PB14.degrade(), Pull::None),
+            Input::new(p.PB15.degrade(), Pull::None),
+            Input::new(p.PC4.degrade(), Pull::None),
+        ],
+        #[cfg(feature = "servo")]
+        servos: ServoPins {
+            timer: p.TIM9,
+            ch1: p.PA2,
+            ch2: p.PA3,
+        },
+        #[cfg(feature = "buzzer")]
+        buzzer: BuzzerPins {
+            timer: p.TIM9,
+            ch1: p.PA2,
+        },
+    }
+}
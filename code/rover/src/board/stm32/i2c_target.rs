@@ -0,0 +1,167 @@
+//! The [`RegisterMap`] served on I2C2 as a target, for a Raspberry Pi or
+//! another MCU to drive the rover.
+//!
+//! Embassy only drives I2C as a controller, so this is done by hand: the
+//! event interrupt moves the bytes in and out of the map one by one, and
+//! signals [`I2cTarget::written`] when a write ends.
+
+use core::cell::RefCell;
+
+use embassy_stm32::{
+    bind_interrupts,
+    gpio::{
+        low_level::{AFType, Pin as _},
+        Pull,
+    },
+    i2c::{SclPin, SdaPin},
+    interrupt::{self, typelevel::Interrupt as _},
+    pac::{self, i2c::regs::Oar1},
+    peripherals::I2C2,
+    rcc::low_level::RccPeripheral,
+};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex as BlockingMutex},
+    signal::Signal,
+};
+
+use rover_lib::i2c_target::{Inputs, RegisterMap, Writes};
+
+use super::bsp::I2cTargetPins;
+
+static MAP: BlockingMutex<CriticalSectionRawMutex, RefCell<RegisterMap>> =
+    BlockingMutex::new(RefCell::new(RegisterMap::new()));
+/// A transaction wrote something to act on.
+static WRITTEN: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+bind_interrupts!(struct Irqs {
+    I2C2_EV => TargetInterruptHandler;
+    I2C2_ER => TargetInterruptHandler;
+});
+
+pub struct TargetInterruptHandler;
+
+impl interrupt::typelevel::Handler<interrupt::typelevel::I2C2_EV> for TargetInterruptHandler {
+    unsafe fn on_interrupt() {
+        on_event();
+    }
+}
+
+impl interrupt::typelevel::Handler<interrupt::typelevel::I2C2_ER> for TargetInterruptHandler {
+    unsafe fn on_interrupt() {
+        on_event();
+    }
+}
+
+fn enable_interrupts(
+    _irqs: impl interrupt::typelevel::Binding<interrupt::typelevel::I2C2_EV, TargetInterruptHandler>
+        + interrupt::typelevel::Binding<interrupt::typelevel::I2C2_ER, TargetInterruptHandler>,
+) {
+    interrupt::typelevel::I2C2_EV::unpend();
+    interrupt::typelevel::I2C2_ER::unpend();
+    unsafe {
+        interrupt::typelevel::I2C2_EV::enable();
+        interrupt::typelevel::I2C2_ER::enable();
+    }
+}
+
+fn on_event() {
+    let regs = pac::I2C2;
+    let sr1 = regs.sr1().read();
+
+    MAP.lock(|map| {
+        let mut map = map.borrow_mut();
+        if sr1.addr() {
+            // Reading SR2 after SR1 clears ADDR
+            let read = regs.sr2().read().tra();
+            map.start(read);
+            regs.cr2().modify(|w| w.set_itbufen(true));
+        }
+        if sr1.rxne() {
+            map.write(regs.dr().read().dr());
+        }
+        if sr1.txe() && !sr1.af() {
+            regs.dr().write(|w| w.set_dr(map.read()));
+        }
+        if sr1.stopf() {
+            // Cleared by writing CR1 after reading SR1
+            regs.cr1().modify(|_| {});
+            if map.stop() {
+                WRITTEN.signal(());
+            }
+        }
+        if sr1.af() {
+            // The controller ends a read by not acknowledging the last byte,
+            // with no stop seen by the target. TXE stays set until the next
+            // address match, so the buffer interrupts go quiet till then.
+            regs.sr1().modify(|w| w.set_af(false));
+            regs.cr2().modify(|w| w.set_itbufen(false));
+            // A write may have come before it, with a repeated start
+            if map.stop() {
+                WRITTEN.signal(());
+            }
+        }
+    });
+
+    if sr1.berr() || sr1.ovr() {
+        regs.sr1().modify(|w| {
+            w.set_berr(false);
+            w.set_ovr(false);
+        });
+    }
+}
+
+impl I2cTargetPins {
+    /// Answers at the 7-bit `address` from now on.
+    pub fn init(self, address: u8) -> I2cTarget {
+        I2C2::enable_and_reset();
+
+        // Open drain, pulled up by the controller's side like any I2C bus
+        let af = <_ as SclPin<I2C2>>::af_num(&self.scl);
+        self.scl
+            .set_as_af_pull(af, AFType::OutputOpenDrain, Pull::None);
+        let af = <_ as SdaPin<I2C2>>::af_num(&self.sda);
+        self.sda
+            .set_as_af_pull(af, AFType::OutputOpenDrain, Pull::None);
+
+        let regs = pac::I2C2;
+        regs.cr2().write(|w| {
+            w.set_freq((I2C2::frequency().0 / 1_000_000) as u8);
+            w.set_itevten(true);
+            w.set_itbufen(true);
+            w.set_iterren(true);
+        });
+        // Bit 14 must be kept set
+        regs.oar1()
+            .write_value(Oar1(1 << 14 | (address as u32 & 0x7F) << 1));
+        regs.cr1().modify(|w| w.set_pe(true));
+        // Only takes once the peripheral is enabled
+        regs.cr1().modify(|w| w.set_ack(true));
+
+        enable_interrupts(Irqs);
+
+        I2cTarget { _i2c: self.i2c }
+    }
+}
+
+/// A running target, see [`I2cTargetPins::init`].
+pub struct I2cTarget {
+    _i2c: I2C2,
+}
+
+impl I2cTarget {
+    /// What the read-only registers hold from the next transaction on.
+    pub fn update(&self, inputs: Inputs) {
+        MAP.lock(|map| map.borrow_mut().set_inputs(inputs));
+    }
+
+    /// Waits for the controller to write anything to act on.
+    pub async fn written(&self) -> Writes {
+        loop {
+            WRITTEN.wait().await;
+            let writes = MAP.lock(|map| map.borrow_mut().take_writes());
+            if !writes.is_empty() {
+                return writes;
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Splits the TIM1 PWM between the motor drivers, one channel each, and the
+//! TIM9 one between the servos.
+
+use embassy_stm32::{
+    pac, peripherals,
+    timer::{simple_pwm::SimplePwm, Channel},
+};
+
+/// Each handle only touches the compare register of its own channel, so they
+/// share nothing and need no lock.
+pub struct PwmSplitter {
+    pwm: SimplePwm<'static, peripherals::TIM1>,
+}
+
+impl PwmSplitter {
+    pub fn new(pwm: SimplePwm<'static, peripherals::TIM1>) -> Self {
+        Self { pwm }
+    }
+
+    /// Enables all four channels. The timer was set up by the driver, which
+    /// has nothing left to do.
+    pub fn split(mut self) -> [Pwm; 4] {
+        [Channel::Ch1, Channel::Ch2, Channel::Ch3, Channel::Ch4].map(|channel| {
+            self.pwm.enable(channel);
+            Pwm { channel }
+        })
+    }
+}
+
+/// One TIM1 channel.
+pub struct Pwm {
+    channel: Channel,
+}
+
+impl embedded_hal_1::pwm::ErrorType for Pwm {
+    type Error = embedded_hal_1::pwm::ErrorKind;
+}
+
+impl embedded_hal_1::pwm::SetDutyCycle for Pwm {
+    fn max_duty_cycle(&self) -> u16 {
+        // Same as `SimplePwm::get_max_duty`, full on is one past the top
+        pac::TIM1.arr().read().arr().saturating_add(1)
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        if duty > self.max_duty_cycle() {
+            return Err(Self::Error::Other);
+        }
+        pac::TIM1
+            .ccr(self.channel.index())
+            .write(|w| w.set_ccr(duty));
+        Ok(())
+    }
+}
+
+/// One TIM9 channel, at the servo frame rate.
+#[cfg(feature = "servo")]
+pub struct ServoPwm {
+    channel: Channel,
+}
+
+/// Enables both channels, like [`PwmSplitter::split`].
+#[cfg(feature = "servo")]
+pub fn split_servos(mut pwm: SimplePwm<'static, peripherals::TIM9>) -> [ServoPwm; 2] {
+    [Channel::Ch1, Channel::Ch2].map(|channel| {
+        pwm.enable(channel);
+        ServoPwm { channel }
+    })
+}
+
+#[cfg(feature = "servo")]
+impl embedded_hal_1::pwm::ErrorType for ServoPwm {
+    type Error = embedded_hal_1::pwm::ErrorKind;
+}
+
+#[cfg(feature = "servo")]
+impl embedded_hal_1::pwm::SetDutyCycle for ServoPwm {
+    fn max_duty_cycle(&self) -> u16 {
+        pac::TIM9.arr().read().arr().saturating_add(1)
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        if duty > self.max_duty_cycle() {
+            return Err(Self::Error::Other);
+        }
+        pac::TIM9
+            .ccr(self.channel.index())
+            .write(|w| w.set_ccr(duty));
+        Ok(())
+    }
+}
+
+/// Last resort when the motors won't stop: turns off the TIM1 main output
+/// enable, so every PWM output goes idle whatever state the drivers are in.
+pub fn kill_motor_outputs() {
+    pac::TIM1.bdtr().modify(|w| w.set_moe(false));
+}
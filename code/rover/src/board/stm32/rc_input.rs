@@ -0,0 +1,206 @@
+//! RC receiver pulses timed to the microsecond by TIM3 input capture: PWM
+//! with `rc_pwm`, one wire per channel, or CPPM with `rc_ppm`, every channel
+//! in turn on a single wire.
+//!
+//! The capture interrupt keeps the latest width of each channel, and when it
+//! came, for [`RcInput::read`] to pick up.
+
+use core::{
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU16, AtomicU32, Ordering},
+};
+
+use embassy_stm32::{
+    bind_interrupts,
+    gpio::{
+        low_level::{AFType, Pin as _},
+        Pull,
+    },
+    interrupt::{self, typelevel::Interrupt as _},
+    pac::timer::vals::Icf,
+    peripherals::TIM3,
+    rcc::low_level::RccPeripheral,
+    timer::{
+        low_level::{Basic16bitInstance, CaptureCompare16bitInstance, GeneralPurpose16bitInstance},
+        Channel, Channel3Pin, InputCaptureMode, InputTISelection,
+    },
+};
+use embassy_time::{Duration, Instant};
+
+use super::bsp::RcCapture;
+
+/// CPPM receivers send 8 at most.
+pub const RC_CHANNELS: usize = 8;
+
+/// Anything else is noise, or the CPPM sync gap.
+const PULSE_US: RangeInclusive<u16> = 800..=2_200;
+const PULSE_MID_US: u16 = 1_500;
+/// Longer than any CPPM channel: the gap ending a frame.
+#[cfg(feature = "rc_ppm")]
+const PPM_SYNC_US: u16 = 3_000;
+/// A few frames without a pulse and the channel counts as lost.
+const PULSE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Channels that must keep coming for a reading to be good.
+#[cfg(feature = "rc_pwm")]
+const WIRED: &[usize] = &[0, 1, 3];
+#[cfg(feature = "rc_ppm")]
+const WIRED: &[usize] = &[0, 1, 2, 3];
+
+/// Centered until a pulse says otherwise, so unwired channels are neutral.
+static WIDTHS: [AtomicU16; RC_CHANNELS] = [const { AtomicU16::new(PULSE_MID_US) }; RC_CHANNELS];
+/// Tick of the latest good pulse of each channel, 0 for none yet.
+static SEEN: [AtomicU32; RC_CHANNELS] = [const { AtomicU32::new(0) }; RC_CHANNELS];
+
+bind_interrupts!(struct Irqs {
+    TIM3 => CaptureInterruptHandler;
+});
+
+pub struct CaptureInterruptHandler;
+
+impl interrupt::typelevel::Handler<interrupt::typelevel::TIM3> for CaptureInterruptHandler {
+    unsafe fn on_interrupt() {
+        on_capture();
+    }
+}
+
+fn enable_interrupt(
+    _irqs: impl interrupt::typelevel::Binding<interrupt::typelevel::TIM3, CaptureInterruptHandler>,
+) {
+    interrupt::typelevel::TIM3::unpend();
+    unsafe { interrupt::typelevel::TIM3::enable() };
+}
+
+fn store(channel: usize, width: u16) {
+    if PULSE_US.contains(&width) {
+        WIDTHS[channel].store(width, Ordering::Relaxed);
+        SEEN[channel].store(Instant::now().as_ticks() as u32 | 1, Ordering::Relaxed);
+    }
+}
+
+/// Both edges are captured, the pin level tells which one it was.
+#[cfg(feature = "rc_pwm")]
+fn on_capture() {
+    use embassy_stm32::pac::{gpio::vals::Idr, GPIOB, GPIOC};
+
+    static RISE: [AtomicU16; 4] = [const { AtomicU16::new(0) }; 4];
+
+    let regs = TIM3::regs_gp16();
+    let sr = regs.sr().read();
+    let high = |port: embassy_stm32::pac::gpio::Gpio, pin| port.idr().read().idr(pin) == Idr::HIGH;
+    // Timer channel, receiver channel, pin level
+    let inputs = [
+        (0, 0, high(GPIOB, 4)),
+        (2, 1, high(GPIOC, 8)),
+        (3, 3, high(GPIOC, 9)),
+    ];
+
+    for (timer_channel, channel, high) in inputs {
+        if !sr.ccif(timer_channel) {
+            continue;
+        }
+        // Reading it clears the flag
+        let time = regs.ccr(timer_channel).read().ccr();
+        if high {
+            RISE[timer_channel].store(time, Ordering::Relaxed);
+        } else {
+            store(
+                channel,
+                time.wrapping_sub(RISE[timer_channel].load(Ordering::Relaxed)),
+            );
+        }
+    }
+}
+
+/// Rising edges only: each channel is the time from its edge to the next.
+#[cfg(feature = "rc_ppm")]
+fn on_capture() {
+    use core::sync::atomic::AtomicUsize;
+
+    static LAST: AtomicU16 = AtomicU16::new(0);
+    /// Channel the next edge ends, [`RC_CHANNELS`] until a sync gap.
+    static NEXT: AtomicUsize = AtomicUsize::new(RC_CHANNELS);
+
+    let regs = TIM3::regs_gp16();
+    if !regs.sr().read().ccif(2) {
+        return;
+    }
+    let time = regs.ccr(2).read().ccr();
+    let gap = time.wrapping_sub(LAST.load(Ordering::Relaxed));
+    LAST.store(time, Ordering::Relaxed);
+
+    let next = match NEXT.load(Ordering::Relaxed) {
+        _ if gap >= PPM_SYNC_US => 0,
+        next if next < RC_CHANNELS && PULSE_US.contains(&gap) => {
+            store(next, gap);
+            next + 1
+        }
+        _ => RC_CHANNELS,
+    };
+    NEXT.store(next, Ordering::Relaxed);
+}
+
+impl RcCapture {
+    pub fn init(self) -> RcInput {
+        let mut timer = self.timer;
+
+        TIM3::enable_and_reset();
+        // Free running at 1 MHz, wraps every 65 ms
+        let regs = TIM3::regs_gp16();
+        regs.psc()
+            .write(|w| w.set_psc((TIM3::frequency().0 / 1_000_000 - 1) as u16));
+        regs.arr().write(|w| w.set_arr(u16::MAX));
+        regs.egr().write(|w| w.set_ug(true));
+
+        #[cfg(feature = "rc_pwm")]
+        let inputs = {
+            use embassy_stm32::timer::{Channel1Pin, Channel4Pin};
+
+            let af = <_ as Channel1Pin<TIM3>>::af_num(&self.ch1);
+            self.ch1.set_as_af_pull(af, AFType::Input, Pull::Down);
+            let af = <_ as Channel4Pin<TIM3>>::af_num(&self.ch4);
+            self.ch4.set_as_af_pull(af, AFType::Input, Pull::Down);
+            [
+                (Channel::Ch1, InputCaptureMode::BothEdges),
+                (Channel::Ch3, InputCaptureMode::BothEdges),
+                (Channel::Ch4, InputCaptureMode::BothEdges),
+            ]
+        };
+        #[cfg(feature = "rc_ppm")]
+        let inputs = [(Channel::Ch3, InputCaptureMode::Rising)];
+
+        let af = <_ as Channel3Pin<TIM3>>::af_num(&self.ch3);
+        self.ch3.set_as_af_pull(af, AFType::Input, Pull::Down);
+
+        for (channel, mode) in inputs {
+            timer.set_input_ti_selection(channel, InputTISelection::Normal);
+            timer.set_input_capture_filter(channel, Icf::FCK_INT_N8);
+            timer.set_input_capture_mode(channel, mode);
+            timer.enable_channel(channel, true);
+            timer.enable_input_interrupt(channel, true);
+        }
+        timer.start();
+
+        enable_interrupt(Irqs);
+
+        RcInput { _timer: timer }
+    }
+}
+
+/// A running capture, see [`RcCapture::init`].
+pub struct RcInput {
+    _timer: TIM3,
+}
+
+impl RcInput {
+    /// Latest pulse widths in µs, receiver channels from 0. `None` once any
+    /// wired channel stopped coming.
+    pub fn read(&self) -> Option<[u16; RC_CHANNELS]> {
+        let now = Instant::now().as_ticks() as u32;
+        let fresh = WIRED.iter().all(|&channel| {
+            let seen = SEEN[channel].load(Ordering::Relaxed);
+            seen != 0 && now.wrapping_sub(seen) as u64 <= PULSE_TIMEOUT.as_ticks()
+        });
+        fresh.then(|| WIDTHS.each_ref().map(|width| width.load(Ordering::Relaxed)))
+    }
+}
@@ -0,0 +1,126 @@
+//! The user button, its [`Gesture`]s published on the event bus and acted
+//! on by [`actions_task`]:
+//!
+//! - a short press arms, or disarms,
+//! - a long press clears the e-stop when latched, runs the self-test when
+//!   disarmed,
+//! - a double press starts the demo, or stops it.
+
+use core::sync::atomic::Ordering;
+
+use embassy_executor::task;
+use embassy_futures::select::select;
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+use embassy_time::{Instant, Timer};
+use uom::si::{angle::degree, f32::Angle};
+
+use rover_lib::{
+    arming::ModeEvent,
+    button::ButtonTiming,
+    iface::{MecanumPower, Turn},
+    profile::Segment,
+    Gesture, GestureDecoder, Mode,
+};
+use rover_proto::{AckCode, Segments};
+
+use crate::{
+    arming,
+    board::{EdgeInput, Robot},
+    comms::{self, Autonomy},
+    events::{self, BusEvent},
+    log::{info, warn},
+    profile, selftest,
+    tasks::{self, ESTOP},
+};
+
+/// The long press is also the hold clearing the e-stop, long enough not to
+/// be done by accident.
+const TIMING: ButtonTiming = ButtonTiming {
+    debounce_ms: 20,
+    long_press_ms: 3_000,
+    double_press_ms: 300,
+};
+
+/// The button is active low.
+#[task]
+pub async fn button_task(mut button: EdgeInput) {
+    let mut decoder = GestureDecoder::new(TIMING);
+    loop {
+        match decoder.deadline() {
+            Some(ms) => {
+                select(
+                    button.wait_for_any_edge(),
+                    Timer::at(Instant::from_millis(ms)),
+                )
+                .await;
+            }
+            None => button.wait_for_any_edge().await,
+        }
+        let now = Instant::now().as_millis();
+        if let Some(gesture) = decoder.update(button.is_low(), now) {
+            events::publish(BusEvent::Button(gesture));
+        }
+    }
+}
+
+#[task]
+pub async fn actions_task(
+    robot: &'static Mutex<NoopRawMutex, Robot>,
+    feed: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    let mut events = events::subscribe();
+    loop {
+        let BusEvent::Button(gesture) = events.next_message_pure().await else {
+            continue;
+        };
+        info!(Safety, "button: {}", gesture);
+        match gesture {
+            Gesture::Short => {
+                let event = match arming::mode().may_drive() {
+                    true => ModeEvent::Disarm,
+                    false => ModeEvent::Arm,
+                };
+                _ = arming::handle(event);
+            }
+            Gesture::Long if ESTOP.load(Ordering::Relaxed) => {
+                if tasks::clear_estop() != AckCode::Ok {
+                    warn!(Safety, "e-stop still pressed");
+                }
+            }
+            Gesture::Long if arming::mode() == Mode::Disarmed => {
+                let report = selftest::run(robot, feed).await;
+                selftest::announce(report).await;
+            }
+            Gesture::Long => warn!(Safety, "disarm before the self-test"),
+            Gesture::Double if comms::autonomy() == Some(Autonomy::Profile) => profile::abort(),
+            Gesture::Double => _ = comms::play_profile(demo()),
+        }
+    }
+}
+
+/// A square strafed clockwise, forward first, then a turn on the spot each
+/// way.
+fn demo() -> Segments {
+    let power = MecanumPower::new(0.3);
+    let segment = |duration_ms, power, angle, turn| Segment {
+        duration_ms,
+        power,
+        angle: Angle::new::<degree>(angle),
+        turn: Turn::new(turn),
+    };
+    let still = MecanumPower::new(0.0);
+    [
+        segment(1_500, power, 90.0, 0.0),
+        segment(1_500, power, 0.0, 0.0),
+        segment(1_500, power, 270.0, 0.0),
+        segment(1_500, power, 180.0, 0.0),
+        segment(1_000, still, 0.0, 0.5),
+        segment(1_000, still, 0.0, -0.5),
+    ]
+    .into_iter()
+    .collect()
+}
@@ -0,0 +1,29 @@
+//! Alerts played on the buzzer, one at a time, from anywhere.
+
+use embassy_executor::task;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::Timer;
+
+use rover_lib::buzzer::Alert;
+
+use crate::board::Buzzer;
+
+static ALERTS: Channel<CriticalSectionRawMutex, Alert, 4> = Channel::new();
+
+/// Queues `alert` behind the ones still playing, dropping it if they pile
+/// up.
+pub fn alert(alert: Alert) {
+    _ = ALERTS.try_send(alert);
+}
+
+#[task]
+pub async fn buzzer_task(mut buzzer: Buzzer) {
+    loop {
+        let alert = ALERTS.receive().await;
+        for note in alert.tune() {
+            buzzer.tone(note.hz);
+            Timer::after_millis(note.ms as u64).await;
+        }
+        buzzer.tone(0);
+    }
+}
@@ -0,0 +1,953 @@
+//! Host link: the [`rover_proto`] messages over the host UART, their
+//! encoding, and what the rover does with them.
+//!
+//! The same frames also run over a Bluetooth module with `bluetooth` and
+//! the USB port with `usb`. Frames from any transport are served alike, and
+//! replies go out on those the host was heard on lately, see
+//! [`rover_lib::transport`]. With `mavlink` the host UART speaks MAVLink
+//! instead, see [`crate::mavlink`].
+
+use core::{
+    cell::{Cell, RefCell},
+    sync::atomic::Ordering,
+};
+
+use defmt::Debug2Format;
+#[cfg(not(feature = "mavlink"))]
+use defmt::Display2Format;
+use embassy_executor::task;
+use embassy_futures::select::{select, select3, Either3};
+use embassy_sync::{
+    blocking_mutex::{
+        raw::{CriticalSectionRawMutex, NoopRawMutex},
+        Mutex as BlockingMutex,
+    },
+    channel::Channel,
+    mutex::Mutex,
+    signal,
+    watch::Watch,
+};
+use embassy_time::{Duration, Instant, Ticker, Timer};
+#[cfg(not(feature = "mavlink"))]
+use embedded_io_async::{Read, Write};
+
+#[cfg(not(feature = "mavlink"))]
+use rover_lib::transport::{Transport, TransportSet, TransportTracker, KEEPALIVE_MS};
+use rover_lib::{
+    arming::ModeEvent,
+    iface::MecanumPower,
+    input_shaping,
+    link_stats::{self, LinkMonitor},
+    mux::{MuxError, Source},
+    profile::Segment,
+    rc,
+    schedule::Scheduler,
+    Angle, AsyncMecanumRobot, CommandMux, DriveFrame, LowVoltageAction, Move, Turn,
+};
+#[cfg(not(feature = "mavlink"))]
+use rover_proto::{
+    framing::{self, Packet, Splitter},
+    ProtocolMode, RX_SIZE, TX_SIZE,
+};
+use rover_proto::{
+    params, Ack, AckCode, Capabilities, Chassis, Command, Config, FirmwareVersion, Hello, Pong,
+    RxBody, RxMessage, Segments, Telemetry, TimeSync, TxMessage, PROTOCOL_VERSION,
+};
+
+#[cfg(feature = "bluetooth")]
+use crate::board::{BluetoothRx, BluetoothTx};
+#[cfg(not(feature = "mavlink"))]
+use crate::board::{HostRx, HostTx};
+#[cfg(feature = "usb")]
+use crate::board::{UsbRx, UsbTx};
+use crate::{
+    arming, aux_outputs,
+    board::{current_limiter, current_limiter_mut, power_budget, wheels, Robot},
+    config::{self, config, save_config, set_config, Store},
+    events::{self, BlackBox},
+    log::{self, every, info, warn},
+    macros, moves,
+    navigation::{self, Frame, PROGRESS},
+    profile,
+    tasks::{
+        clear_estop, failsafe_stage, front_distance, trip_estop, ATTITUDE, BATTERY, BATTERY_LOW,
+        ESTOP, FAULT, LOW_BATTERY_POWER_SCALE, POSE, RANGES, RESET_CAUSE, SAFETY_TRIPPED, TILTED,
+    },
+};
+
+/// Last drive command applied to the robot.
+static COMMAND: Watch<CriticalSectionRawMutex, Command, 2> = Watch::new();
+
+static MUX: BlockingMutex<CriticalSectionRawMutex, RefCell<CommandMux>> =
+    BlockingMutex::new(RefCell::new(CommandMux::new()));
+
+/// Claims control of the robot for `source` until the safety timeout, returns
+/// whether it's in control. Sources should only drive if so.
+pub fn claim(source: Source) -> bool {
+    let lease = config().safety_timeout_ms as u64;
+    MUX.lock(|mux| {
+        mux.borrow_mut()
+            .claim(source, Instant::now().as_millis(), lease)
+    })
+}
+
+/// [`claim`] for a drive command from the host, which is also dropped when
+/// it comes faster than the rate limit. [`AckCode::Ok`] if it may be applied.
+fn claim_host_command() -> AckCode {
+    let config = config();
+    let res = MUX.lock(|mux| {
+        let mut mux = mux.borrow_mut();
+        mux.set_rate_limit(Source::Host, config.max_command_rate_hz);
+        mux.command(
+            Source::Host,
+            Instant::now().as_millis(),
+            config.safety_timeout_ms as u64,
+        )
+    });
+    match res {
+        Ok(()) => AckCode::Ok,
+        Err(MuxError::RateLimited) => AckCode::RateLimited,
+        Err(_) => refusal(),
+    }
+}
+
+/// Keeps the host's control, if it has a claim still, without a command.
+fn renew_host() {
+    let lease = config().safety_timeout_ms as u64;
+    MUX.lock(|mux| {
+        mux.borrow_mut()
+            .renew(Source::Host, Instant::now().as_millis(), lease)
+    });
+}
+
+/// Claims control until [`release`]d.
+pub fn hold(source: Source) {
+    MUX.lock(|mux| mux.borrow_mut().hold(source));
+}
+
+pub fn release(source: Source) {
+    MUX.lock(|mux| mux.borrow_mut().release(source));
+}
+
+pub fn active_source() -> Option<Source> {
+    MUX.lock(|mux| mux.borrow().active(Instant::now().as_millis()))
+}
+
+/// The autonomous behaviours, which take turns driving as
+/// [`Source::Autonomous`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Autonomy {
+    Navigation,
+    Profile,
+    Move,
+    #[cfg(feature = "line_sensor")]
+    LineFollow,
+}
+
+/// The last one started.
+static AUTONOMY: BlockingMutex<CriticalSectionRawMutex, Cell<Option<Autonomy>>> =
+    BlockingMutex::new(Cell::new(None));
+
+pub fn autonomy() -> Option<Autonomy> {
+    AUTONOMY.lock(|owner| owner.get())
+}
+
+/// Hands the robot over to `behaviour`, cancelling the others.
+fn start_autonomy(behaviour: Autonomy) {
+    AUTONOMY.lock(|owner| owner.set(Some(behaviour)));
+    if behaviour != Autonomy::Navigation {
+        navigation::cancel();
+    }
+    if behaviour != Autonomy::Profile {
+        profile::abort();
+    }
+    if behaviour != Autonomy::Move {
+        moves::abort();
+    }
+    #[cfg(feature = "line_sensor")]
+    if behaviour != Autonomy::LineFollow {
+        crate::line_follow::set_enabled(false);
+    }
+}
+
+/// Hands the robot over to the profile, which replaces any playing.
+pub fn play_profile(segments: Segments) -> AckCode {
+    if !segments.iter().all(Segment::is_valid) {
+        return AckCode::OutOfRange;
+    }
+    start_autonomy(Autonomy::Profile);
+    profile::play(segments);
+    AckCode::Ok
+}
+
+/// Hands the robot over to the move, which replaces any going.
+pub fn start_move(mv: Move) -> AckCode {
+    if !mv.is_valid() {
+        return AckCode::OutOfRange;
+    }
+    start_autonomy(Autonomy::Move);
+    moves::start(mv);
+    AckCode::Ok
+}
+
+/// Stops the robot if `behaviour` is driving it and lets go of it, unless
+/// another behaviour took over meanwhile.
+pub async fn stop_autonomy(robot: &Mutex<NoopRawMutex, Robot>, behaviour: Autonomy) {
+    let owned = AUTONOMY.lock(|owner| {
+        let owned = owner.get() == Some(behaviour);
+        if owned {
+            owner.set(None);
+        }
+        owned
+    });
+    if owned {
+        halt_autonomy(robot).await;
+    }
+}
+
+/// Like [`stop_autonomy`], but `behaviour` keeps the robot to carry on with
+/// later.
+pub async fn pause_autonomy(robot: &Mutex<NoopRawMutex, Robot>, behaviour: Autonomy) {
+    if AUTONOMY.lock(|owner| owner.get() == Some(behaviour)) {
+        halt_autonomy(robot).await;
+    }
+}
+
+async fn halt_autonomy(robot: &Mutex<NoopRawMutex, Robot>) {
+    if active_source() == Some(Source::Autonomous) {
+        apply_drive(robot, Command::default(), DriveFrame::Robot).await;
+    }
+    release(Source::Autonomous);
+}
+
+/// Why a [`claim`] was refused.
+pub fn refusal() -> AckCode {
+    match active_source() {
+        Some(Source::Estop) => AckCode::Estopped,
+        _ => AckCode::NotSelected,
+    }
+}
+
+pub static TX_QUEUE: Channel<CriticalSectionRawMutex, TxMessage, 4> = Channel::new();
+
+#[cfg(not(feature = "mavlink"))]
+fn encode_tx_message(msg: &TxMessage, out: &mut [u8]) -> Option<usize> {
+    match config().protocol {
+        #[cfg(feature = "alloc")]
+        ProtocolMode::Json => {
+            let raw = serde_json::to_vec(msg).ok()?;
+            framing::encode(config::rover_id(), &raw, out).ok()
+        }
+        // Refused when validating the config
+        #[cfg(not(feature = "alloc"))]
+        ProtocolMode::Json => None,
+        ProtocolMode::Binary => {
+            let mut raw = [0u8; TX_SIZE];
+            let raw = rover_lib::wire::to_frame(msg, &mut raw).ok()?;
+            framing::encode(config::rover_id(), raw, out).ok()
+        }
+    }
+}
+
+/// Every transport, for [`tx_task`].
+#[cfg(not(feature = "mavlink"))]
+pub struct Transmitters {
+    pub host: HostTx,
+    #[cfg(feature = "bluetooth")]
+    pub bluetooth: BluetoothTx,
+    #[cfg(feature = "usb")]
+    pub usb: UsbTx,
+}
+
+#[cfg(not(feature = "mavlink"))]
+impl Transmitters {
+    /// The transports a host could be listening on. The UARTs always might.
+    fn connected(&self) -> TransportSet {
+        let mut connected = [false; Transport::ALL.len()];
+        connected[Transport::Uart as usize] = true;
+        #[cfg(feature = "bluetooth")]
+        {
+            connected[Transport::Bluetooth as usize] = true;
+        }
+        #[cfg(feature = "usb")]
+        {
+            connected[Transport::Usb as usize] = self.usb.is_connected();
+        }
+        connected
+    }
+}
+
+/// Which transports the host is on.
+#[cfg(not(feature = "mavlink"))]
+static TRANSPORTS: BlockingMutex<CriticalSectionRawMutex, RefCell<TransportTracker>> =
+    BlockingMutex::new(RefCell::new(TransportTracker::new(KEEPALIVE_MS)));
+
+#[cfg(not(feature = "mavlink"))]
+#[task]
+pub async fn tx_task(mut tx: Transmitters) {
+    let mut out = [0u8; framing::max_packet_len(TX_SIZE)];
+
+    loop {
+        let msg = TX_QUEUE.receive().await;
+        let Some(n) = encode_tx_message(&msg, &mut out) else {
+            warn!(Comms, "failed to encode tx message");
+            continue;
+        };
+        let to = TRANSPORTS.lock(|transports| {
+            transports
+                .borrow()
+                .destinations(Instant::now().as_millis(), tx.connected())
+        });
+        if to[Transport::Uart as usize] {
+            write_frame(&mut tx.host, &out[..n]).await;
+        }
+        #[cfg(feature = "bluetooth")]
+        if to[Transport::Bluetooth as usize] {
+            write_frame(&mut tx.bluetooth, &out[..n]).await;
+        }
+        #[cfg(feature = "usb")]
+        if to[Transport::Usb as usize] {
+            write_frame(&mut tx.usb, &out[..n]).await;
+        }
+    }
+}
+
+#[cfg(not(feature = "mavlink"))]
+async fn write_frame(tx: &mut impl Write, frame: &[u8]) {
+    _ = tx
+        .write_all(frame)
+        .await
+        .inspect_err(|e| warn!(Comms, "failed to write tx frame: {}", Debug2Format(e)));
+}
+
+#[task]
+pub async fn telemetry_task(robot: &'static Mutex<NoopRawMutex, Robot>) {
+    let mut command = COMMAND.anon_receiver();
+    let mut events = events::subscribe();
+
+    loop {
+        let period = config().telemetry_period_ms;
+        if period == 0 {
+            Timer::after_millis(*Config::TELEMETRY_PERIOD_MS.end() as u64).await;
+            continue;
+        }
+        // Bus events go out right away rather than at the end of the period
+        select(
+            Timer::after_millis(period as u64),
+            events.next_message_pure(),
+        )
+        .await;
+
+        let robot = robot.lock().await;
+        let telemetry = Telemetry {
+            uptime_ms: Instant::now().as_millis(),
+            command: command.try_get().unwrap_or_default(),
+            powers: wheels(&robot).powers(),
+            safety_tripped: SAFETY_TRIPPED.load(Ordering::Relaxed),
+            estop: ESTOP.load(Ordering::Relaxed),
+            battery: BATTERY.try_get(),
+            currents: cfg!(feature = "current_sense").then(|| current_limiter(&robot).currents()),
+            overcurrent_tripped: current_limiter(&robot).tripped(),
+            fault: FAULT.load(Ordering::Relaxed),
+            attitude: ATTITUDE.try_get(),
+            source: active_source(),
+            ranges: RANGES.try_get(),
+            navigation: PROGRESS.try_get().flatten(),
+            front_distance: front_distance(),
+            framing_errors: LINK.lock(|link| link.borrow().crc_errors()),
+            reset_cause: RESET_CAUSE.try_get().unwrap_or_default(),
+            aux: aux_outputs::states(),
+            mode: arming::mode(),
+            power_scale: power_budget(&robot).scale(),
+            tilted: TILTED.load(Ordering::Relaxed),
+            #[cfg(feature = "gps")]
+            gps: crate::gps::FIX.try_get().flatten(),
+            #[cfg(not(feature = "gps"))]
+            gps: None,
+            #[cfg(feature = "ekf")]
+            estimate: crate::estimator::ESTIMATE.try_get(),
+            #[cfg(not(feature = "ekf"))]
+            estimate: None,
+            pose: POSE.try_get(),
+            #[cfg(feature = "nrf24")]
+            radio_quality: Some(crate::rc::RADIO_QUALITY.load(Ordering::Relaxed)),
+            #[cfg(not(feature = "nrf24"))]
+            radio_quality: None,
+            rx_overflows: LINK.lock(|link| link.borrow().overflows()),
+            failsafe: failsafe_stage(),
+        };
+        drop(robot);
+        // Telemetry is best effort: drop it rather than stall behind a full
+        // queue.
+        _ = TX_QUEUE.try_send(TxMessage::Telemetry(telemetry));
+    }
+}
+
+fn pong(host_ms: u32) -> Pong {
+    Pong {
+        host_ms,
+        uptime_ms: Instant::now().as_millis(),
+        mode: arming::mode(),
+        source: active_source(),
+        safety_tripped: SAFETY_TRIPPED.load(Ordering::Relaxed),
+        estop: ESTOP.load(Ordering::Relaxed),
+        fault: FAULT.load(Ordering::Relaxed),
+    }
+}
+
+fn hello() -> Hello {
+    Hello {
+        firmware: FirmwareVersion::try_from(env!("CARGO_PKG_VERSION")).unwrap_or_default(),
+        protocol: PROTOCOL_VERSION,
+        capabilities: Capabilities {
+            chassis: if cfg!(feature = "differential") {
+                Chassis::Differential
+            } else {
+                Chassis::Mecanum
+            },
+            encoders: true,
+            imu: ATTITUDE.try_get().is_some(),
+            current_sense: cfg!(feature = "current_sense"),
+            ranging: cfg!(any(feature = "ultrasonic", feature = "vl53l0x")),
+            json: cfg!(feature = "alloc"),
+            #[cfg(feature = "servo")]
+            servos: crate::servo::SERVO_COUNT as u8,
+            #[cfg(not(feature = "servo"))]
+            servos: 0,
+        },
+        reset_cause: RESET_CAUSE.try_get().unwrap_or_default(),
+    }
+}
+
+/// Both encodings are accepted: a packet starting with `{` is JSON, which
+/// takes `alloc`, anything else a binary frame.
+#[cfg(not(feature = "mavlink"))]
+fn decode_rx_message(packet: &[u8]) -> Option<RxMessage> {
+    if packet.first() == Some(&b'{') {
+        #[cfg(feature = "alloc")]
+        return serde_json::from_slice(packet).ok();
+        #[cfg(not(feature = "alloc"))]
+        {
+            warn!(Comms, "JSON needs the alloc feature");
+            return None;
+        }
+    }
+    rover_lib::wire::from_frame(packet)
+        .inspect_err(|e| warn!(Comms, "error decoding binary frame: {}", Display2Format(e)))
+        .ok()
+}
+
+/// What every transport received, and lost.
+static LINK: BlockingMutex<CriticalSectionRawMutex, RefCell<LinkMonitor>> =
+    BlockingMutex::new(RefCell::new(LinkMonitor::new()));
+
+pub fn frame_received() {
+    LINK.lock(|link| link.borrow_mut().frame(Instant::now().as_millis()));
+}
+
+/// A packet dropped as corrupted.
+pub fn framing_error() {
+    LINK.lock(|link| link.borrow_mut().crc_error());
+}
+
+/// Received bytes lost before they could be parsed.
+pub fn rx_overflow() {
+    LINK.lock(|link| link.borrow_mut().overflow());
+}
+
+/// Sends the [`TxMessage::LinkStats`] every [`link_stats::PERIOD_MS`].
+#[task]
+pub async fn link_stats_task() {
+    let mut ticker = Ticker::every(Duration::from_millis(link_stats::PERIOD_MS));
+    loop {
+        ticker.next().await;
+        let stats = LINK.lock(|link| link.borrow_mut().publish(Instant::now().as_millis()));
+        // Best effort, like telemetry
+        _ = TX_QUEUE.try_send(TxMessage::LinkStats(stats));
+    }
+}
+
+/// Frames decoded from every transport, in arrival order.
+pub static RX_QUEUE: Channel<CriticalSectionRawMutex, RxMessage, 4> = Channel::new();
+
+const SCHEDULE_SIZE: usize = 8;
+
+/// Messages [`hold_until`] for later, without their `seq` and `at_ms`.
+static SCHEDULE: BlockingMutex<
+    CriticalSectionRawMutex,
+    RefCell<Scheduler<RxMessage, SCHEDULE_SIZE>>,
+> = BlockingMutex::new(RefCell::new(Scheduler::new()));
+static SCHEDULED: signal::Signal<CriticalSectionRawMutex, ()> = signal::Signal::new();
+/// Held messages fallen due, apart from [`RX_QUEUE`] as they aren't the host
+/// talking and mustn't keep the safety timer quiet.
+static DUE_QUEUE: Channel<CriticalSectionRawMutex, RxMessage, 4> = Channel::new();
+
+/// Holds `body` until `at_ms`.
+fn hold_until(at_ms: u64, body: RxBody) -> AckCode {
+    let held = RxMessage {
+        seq: None,
+        body,
+        at_ms: None,
+    };
+    match SCHEDULE.lock(|schedule| schedule.borrow_mut().push(at_ms, held)) {
+        Ok(()) => {
+            SCHEDULED.signal(());
+            AckCode::Ok
+        }
+        Err(_) => AckCode::ScheduleFull,
+    }
+}
+
+/// Hands the held messages back to [`serve`] as they fall due, as if they
+/// had just arrived, but for the safety timer.
+#[task]
+pub async fn schedule_task() {
+    loop {
+        match SCHEDULE.lock(|schedule| schedule.borrow().next_ms()) {
+            Some(at_ms) => {
+                select(Timer::at(Instant::from_millis(at_ms)), SCHEDULED.wait()).await;
+            }
+            None => SCHEDULED.wait().await,
+        }
+        let now_ms = Instant::now().as_millis();
+        while let Some(rx_message) = SCHEDULE.lock(|schedule| schedule.borrow_mut().pop_due(now_ms))
+        {
+            DUE_QUEUE.send(rx_message).await;
+        }
+    }
+}
+
+#[cfg(not(feature = "mavlink"))]
+#[task]
+pub async fn host_rx_task(rx: HostRx) {
+    receive(rx, Transport::Uart).await
+}
+
+#[cfg(feature = "bluetooth")]
+#[task]
+pub async fn bluetooth_rx_task(rx: BluetoothRx) {
+    receive(rx, Transport::Bluetooth).await
+}
+
+#[cfg(feature = "usb")]
+#[task]
+pub async fn usb_rx_task(rx: UsbRx) {
+    receive(rx, Transport::Usb).await
+}
+
+/// Decodes frames off one transport into [`RX_QUEUE`].
+#[cfg(not(feature = "mavlink"))]
+async fn receive(mut rx: impl Read, transport: Transport) -> ! {
+    let mut splitter = Splitter::<{ framing::max_packet_len(RX_SIZE) }>::new();
+    let mut chunk = [0u8; 64];
+
+    loop {
+        let n = match rx.read(&mut chunk).await {
+            Ok(n) => n,
+            Err(e) => {
+                warn!(
+                    Comms,
+                    "{} lost bytes: {}",
+                    Debug2Format(&transport),
+                    Debug2Format(&e)
+                );
+                rx_overflow();
+                splitter.reset();
+                continue;
+            }
+        };
+        let mut data = &chunk[..n];
+
+        every!(
+            1_000,
+            debug!(
+                Comms,
+                "received raw: {:?}",
+                Debug2Format(&core::str::from_utf8(data))
+            )
+        );
+
+        while !data.is_empty() {
+            let (taken, packet) = splitter.push(data);
+            data = &data[taken..];
+            match packet {
+                Some(Ok(packet)) => deliver(packet, transport).await,
+                Some(Err(e)) => {
+                    warn!(Comms, "dropping packet: {}", Display2Format(&e));
+                    rx_overflow();
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+/// Checks and decodes one packet, without its terminator, into [`RX_QUEUE`].
+/// Those for other rovers on the bus are dropped, and broadcasts handled
+/// here, unanswered.
+#[cfg(not(feature = "mavlink"))]
+async fn deliver(packet: &[u8], transport: Transport) {
+    let mut raw = [0u8; framing::HEADER_LEN + RX_SIZE + framing::TRAILER_LEN];
+    let Packet { address, payload } = match framing::decode(packet, &mut raw) {
+        Ok(packet) => packet,
+        Err(e) => {
+            warn!(Comms, "dropping packet: {}", Display2Format(&e));
+            framing_error();
+            return;
+        }
+    };
+    if !framing::addressed_to(address, config::rover_id()) {
+        return;
+    }
+    let Some(rx_message) = decode_rx_message(payload) else {
+        LINK.lock(|link| link.borrow_mut().decode_error());
+        return;
+    };
+    if address == framing::BROADCAST {
+        // Every rover answering at once would garble the bus
+        if let RxBody::Estop = rx_message.body {
+            trip_estop();
+        }
+        return;
+    }
+    frame_received();
+
+    let arrived = TRANSPORTS.lock(|transports| {
+        transports
+            .borrow_mut()
+            .heard(transport, Instant::now().as_millis())
+    });
+    if arrived {
+        info!(Comms, "host on {}", Debug2Format(&transport));
+    }
+    RX_QUEUE.send(rx_message).await;
+}
+
+/// Handles messages from every transport until the links die, which they
+/// don't.
+pub async fn serve(
+    robot: &Mutex<NoopRawMutex, Robot>,
+    store: &mut Store,
+    feed: &signal::Signal<CriticalSectionRawMutex, ()>,
+) -> ! {
+    let mut p = MecanumPower::default();
+    let mut th = Angle::default();
+    let mut tu = Turn::default();
+    let mut frame = DriveFrame::default();
+    let mut black_box = BlackBox::open(store);
+
+    loop {
+        let rx_message =
+            match select3(RX_QUEUE.receive(), DUE_QUEUE.receive(), events::next()).await {
+                Either3::First(rx_message) => {
+                    feed.signal(());
+                    rx_message
+                }
+                Either3::Second(rx_message) => rx_message,
+                Either3::Third(event) => {
+                    black_box.write(store, event);
+                    continue;
+                }
+            };
+
+        if let Some(at_ms) = rx_message
+            .at_ms
+            .filter(|&at_ms| at_ms > Instant::now().as_millis())
+        {
+            let code = hold_until(at_ms, rx_message.body);
+            if let Some(seq) = rx_message.seq {
+                TX_QUEUE.send(TxMessage::Ack(Ack { seq, code })).await;
+            }
+            continue;
+        }
+
+        let claim = match rx_message.body {
+            RxBody::Drive(_) | RxBody::Sticks { .. } => {
+                LINK.lock(|link| link.borrow_mut().command());
+                claim_host_command()
+            }
+            _ => AckCode::Ok,
+        };
+        let code = match rx_message.body {
+            RxBody::Drive(_) | RxBody::Sticks { .. } if claim != AckCode::Ok => claim,
+            RxBody::Drive(drive) => {
+                let mut change_needed = false;
+
+                drive.p.inspect(|v| {
+                    p = *v;
+                    change_needed = true;
+                });
+                drive.th.inspect(|v| {
+                    th = *v;
+                    change_needed = true;
+                });
+                drive.tu.inspect(|v| {
+                    tu = *v;
+                    change_needed = true;
+                });
+
+                if change_needed {
+                    apply_drive(robot, Command { p, th, tu }, frame).await
+                } else {
+                    AckCode::Ok
+                }
+            }
+            RxBody::Sticks { x, y, rot } => {
+                let config = config();
+                let shape =
+                    |axis| input_shaping::shape(axis, config.stick_deadzone, config.stick_expo);
+                (p, th, tu) = rc::mix(shape(x), shape(y), shape(rot));
+                apply_shaped_drive(robot, Command { p, th, tu }, frame).await
+            }
+            RxBody::SetDriveFrame(new_frame) => {
+                info!(Drive, "drive frame: {}", Debug2Format(&new_frame));
+                frame = new_frame;
+                AckCode::Ok
+            }
+            RxBody::Calibrate(trims) => {
+                let new_config = Config {
+                    wheel_trims: trims,
+                    ..config()
+                };
+                match set_config(robot, new_config).await {
+                    AckCode::Ok => save_config(store),
+                    code => code,
+                }
+            }
+            RxBody::SetConfig(new_config) => set_config(robot, new_config).await,
+            RxBody::SaveConfig => save_config(store),
+            RxBody::Estop => {
+                trip_estop();
+                AckCode::Ok
+            }
+            RxBody::ClearEstop => clear_estop(),
+            RxBody::ClearOvercurrent => {
+                current_limiter_mut(&mut *robot.lock().await).reset_trip();
+                AckCode::Ok
+            }
+            RxBody::Config(msg) => config::update(msg),
+            #[cfg(feature = "line_sensor")]
+            RxBody::LineFollow(on) => {
+                if on {
+                    start_autonomy(Autonomy::LineFollow);
+                }
+                crate::line_follow::set_enabled(on);
+                AckCode::Ok
+            }
+            #[cfg(not(feature = "line_sensor"))]
+            RxBody::LineFollow(_) => AckCode::Unsupported,
+            #[cfg(feature = "servo")]
+            RxBody::SetServo { index, angle } => crate::servo::set(index, angle),
+            #[cfg(not(feature = "servo"))]
+            RxBody::SetServo { .. } => AckCode::Unsupported,
+            RxBody::Aux { name, action } => aux_outputs::apply(&name, action),
+            RxBody::Arm(true) => arming::ack(arming::handle(ModeEvent::Arm)),
+            RxBody::Arm(false) => arming::ack(arming::handle(ModeEvent::Disarm)),
+            RxBody::SetLogLevel { category, level } => {
+                log::set_level(category, level);
+                AckCode::Ok
+            }
+            RxBody::Move(mv) => start_move(mv),
+            RxBody::AbortMove => {
+                moves::abort();
+                AckCode::Ok
+            }
+            #[cfg(feature = "magnetometer")]
+            RxBody::CalibrateCompass => crate::compass::calibrate(),
+            #[cfg(not(feature = "magnetometer"))]
+            RxBody::CalibrateCompass => AckCode::Unsupported,
+            RxBody::Navigate(route) => {
+                if !route.is_empty() {
+                    start_autonomy(Autonomy::Navigation);
+                }
+                navigation::navigate(route, Frame::Odometry);
+                AckCode::Ok
+            }
+            #[cfg(feature = "gps")]
+            RxBody::NavigateGps(points) => match crate::gps::local_route(&points) {
+                Ok((origin, route)) => {
+                    if !route.is_empty() {
+                        start_autonomy(Autonomy::Navigation);
+                    }
+                    navigation::navigate(route, Frame::Gps { origin });
+                    AckCode::Ok
+                }
+                Err(code) => code,
+            },
+            #[cfg(not(feature = "gps"))]
+            RxBody::NavigateGps(_) => AckCode::Unsupported,
+            RxBody::Profile(segments) => play_profile(segments),
+            RxBody::PauseProfile => {
+                profile::pause();
+                AckCode::Ok
+            }
+            RxBody::ResumeProfile => {
+                profile::resume();
+                AckCode::Ok
+            }
+            RxBody::AbortProfile => {
+                profile::abort();
+                AckCode::Ok
+            }
+            RxBody::StoreMacro(new) => macros::store_macro(store, new),
+            RxBody::RunMacro(name) => match macros::find(store, &name) {
+                Some(segments) => {
+                    info!(Comms, "running macro {}", name.as_str());
+                    start_autonomy(Autonomy::Profile);
+                    profile::play(segments);
+                    AckCode::Ok
+                }
+                None => AckCode::NotFound,
+            },
+            RxBody::DeleteMacro(name) => macros::delete_macro(store, &name),
+            RxBody::ListMacros => {
+                TX_QUEUE.send(TxMessage::Macros(macros::names(store))).await;
+                AckCode::Ok
+            }
+            RxBody::GetConfig => {
+                TX_QUEUE.send(TxMessage::Config(config())).await;
+                AckCode::Ok
+            }
+            RxBody::Hello { protocol } => {
+                TX_QUEUE.send(TxMessage::Hello(hello())).await;
+                if protocol == PROTOCOL_VERSION {
+                    AckCode::Ok
+                } else {
+                    warn!(Comms, "host speaks protocol {}", protocol);
+                    AckCode::Incompatible
+                }
+            }
+            RxBody::DumpEvents => black_box.dump(store).await,
+            RxBody::ClearEvents => black_box.clear(store),
+            RxBody::GetParam(name) => match params::find(&name) {
+                Some(def) => {
+                    TX_QUEUE.send(TxMessage::Param(def.param(&config()))).await;
+                    AckCode::Ok
+                }
+                None => AckCode::NotFound,
+            },
+            RxBody::SetParam(param) => config::set_param(robot, &param).await,
+            RxBody::ListParams => {
+                let config = config();
+                for def in params::PARAMS {
+                    TX_QUEUE.send(TxMessage::Param(def.param(&config))).await;
+                }
+                AckCode::Ok
+            }
+            RxBody::Ping(host_ms) => {
+                renew_host();
+                TX_QUEUE.send(TxMessage::Pong(pong(host_ms))).await;
+                AckCode::Ok
+            }
+            RxBody::Stamp(host_ms) => {
+                LINK.lock(|link| link.borrow_mut().stamp(host_ms, Instant::now().as_millis()));
+                AckCode::Ok
+            }
+            RxBody::TimeSync(host_ms) => {
+                let rover_ms = Instant::now().as_millis();
+                TX_QUEUE
+                    .send(TxMessage::TimeSync(TimeSync { host_ms, rover_ms }))
+                    .await;
+                AckCode::Ok
+            }
+            RxBody::ClearSchedule => {
+                SCHEDULE.lock(|schedule| schedule.borrow_mut().clear());
+                AckCode::Ok
+            }
+        };
+
+        if let Some(seq) = rx_message.seq {
+            TX_QUEUE.send(TxMessage::Ack(Ack { seq, code })).await;
+        }
+    }
+}
+
+/// Heading used by field-oriented drive: the IMU one when available, the
+/// odometry one otherwise.
+fn heading() -> Angle {
+    ATTITUDE
+        .try_get()
+        .map(|attitude| attitude.heading)
+        .or_else(|| POSE.try_get().map(|pose| pose.heading))
+        .unwrap_or_default()
+}
+
+/// Drives through the [`Config::power_shaping`] and [`Config::turn_shaping`]
+/// response curves.
+pub async fn apply_drive(
+    robot: &Mutex<NoopRawMutex, Robot>,
+    command: Command,
+    frame: DriveFrame,
+) -> AckCode {
+    let config = config();
+    let command = Command {
+        p: config.power_shaping.apply_power(command.p),
+        tu: config.turn_shaping.apply_turn(command.tu),
+        ..command
+    };
+    apply_shaped_drive(robot, command, frame).await
+}
+
+/// [`apply_drive`] for a command already shaped, like the sticks with their
+/// own deadzone and expo.
+async fn apply_shaped_drive(
+    robot: &Mutex<NoopRawMutex, Robot>,
+    command: Command,
+    frame: DriveFrame,
+) -> AckCode {
+    COMMAND.sender().send(command);
+
+    let Command { p, th, tu } = command;
+    let th = frame.to_robot(th, heading());
+    every!(
+        1_000,
+        debug!(
+            Drive,
+            "p: {}, th: {}, tu: {}",
+            p.inner(),
+            th.get::<uom::si::angle::radian>(),
+            tu.inner()
+        )
+    );
+    // Checked with the robot locked so a drive can't sneak in right after
+    // the e-stop braked
+    let mut robot = robot.lock().await;
+    if FAULT.load(Ordering::Relaxed) {
+        return AckCode::Fault;
+    }
+    if ESTOP.load(Ordering::Relaxed) {
+        return AckCode::Estopped;
+    }
+    if TILTED.load(Ordering::Relaxed) {
+        return AckCode::Tilted;
+    }
+    if current_limiter(&robot).tripped() {
+        return AckCode::Overcurrent;
+    }
+    let config = config();
+    let (p, tu) = match BATTERY_LOW.load(Ordering::Relaxed) {
+        false => (p, tu),
+        true => match config.low_voltage_action {
+            LowVoltageAction::Warn => (p, tu),
+            LowVoltageAction::LimitPower => (
+                p.saturating_scale(LOW_BATTERY_POWER_SCALE),
+                tu.saturating_scale(LOW_BATTERY_POWER_SCALE),
+            ),
+            LowVoltageAction::Neutral => return AckCode::LowBattery,
+        },
+    };
+    let (p, th) = config.collision_guard.apply(p, th, front_distance());
+    if let Err(e) = arming::handle(ModeEvent::Drive) {
+        return arming::ack(Err(e));
+    }
+    match robot.drive(p, th, tu).await {
+        Ok(()) => {
+            every!(1_000, info!(Drive, "all went well"));
+            AckCode::Ok
+        }
+        Err(_) => {
+            warn!(Drive, "failed to drive robot");
+            AckCode::DriveFailed
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! The magnetometer of `magnetometer`: its calibrated field pulls the gyro
+//! heading towards magnetic north, making the heading of field-oriented
+//! drive and of the odometry, so navigation, absolute.
+//!
+//! [`RxBody::CalibrateCompass`] turns the robot on the spot to calibrate it.
+//!
+//! [`RxBody::CalibrateCompass`]: rover_proto::RxBody::CalibrateCompass
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use defmt::Display2Format;
+use embassy_executor::task;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Instant, Ticker};
+use uom::si::angle::degree;
+
+use rover_lib::{
+    compass::{compass_heading, MagCalibrator},
+    iface::{MecanumPower, Turn},
+    profile::Segment,
+    Angle, Attitude, CompassParams, ComplementaryFilter, Magnetometer,
+};
+use rover_proto::{AckCode, ConfigMessage, TxMessage};
+
+use crate::{
+    board::BoardMag,
+    comms::{self, TX_QUEUE},
+    config::{self, config},
+    log::{info, warn},
+};
+
+const COMPASS_PERIOD: Duration = Duration::from_millis(20);
+/// A couple of turns at the spin below.
+const CALIBRATION_TIME: Duration = Duration::from_secs(15);
+const CALIBRATION_SPIN: f32 = 0.3;
+
+/// The latest calibrated field, taken by the IMU task.
+static FIELD: Signal<CriticalSectionRawMutex, [f32; 3]> = Signal::new();
+static CALIBRATE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+/// Set once the heading was set from the compass, the gyro only keeping it
+/// there since.
+pub static ABSOLUTE_HEADING: AtomicBool = AtomicBool::new(false);
+
+pub fn calibrate() -> AckCode {
+    CALIBRATE.signal(());
+    AckCode::Ok
+}
+
+/// Corrects the heading of `filter`, tilted by `attitude`, with the latest
+/// field if there's a new one.
+pub fn fuse(filter: &mut ComplementaryFilter, attitude: &Attitude) {
+    let weight = config().compass.weight;
+    let Some(field) = FIELD.try_take() else {
+        return;
+    };
+    if weight == 0.0 {
+        return;
+    }
+    let heading = compass_heading(field, attitude);
+    if ABSOLUTE_HEADING.load(Ordering::Relaxed) {
+        filter.correct_heading(heading, weight);
+    } else {
+        info!(
+            Sensors,
+            "heading from the compass: {} degrees",
+            heading.get::<degree>()
+        );
+        filter.reset_heading(heading);
+        ABSOLUTE_HEADING.store(true, Ordering::Relaxed);
+    }
+}
+
+#[task]
+pub async fn compass_task(mut mag: BoardMag) {
+    if let Err(e) = mag.init().await {
+        warn!(
+            Sensors,
+            "magnetometer not available: {}",
+            Display2Format(&e)
+        );
+        return;
+    }
+
+    let mut ticker = Ticker::every(COMPASS_PERIOD);
+    loop {
+        match select(ticker.next(), CALIBRATE.wait()).await {
+            Either::First(()) => match mag.read().await {
+                Ok(raw) => {
+                    if let Some(calibration) = config().compass.calibration {
+                        FIELD.signal(calibration.apply(raw));
+                    }
+                }
+                Err(e) => warn!(Sensors, "magnetometer read failed: {}", Display2Format(&e)),
+            },
+            Either::Second(()) => run_calibration(&mut mag, &mut ticker).await,
+        }
+    }
+}
+
+/// Spins on the spot as a profile, so only when it may drive, collecting
+/// the field meanwhile.
+async fn run_calibration(mag: &mut BoardMag, ticker: &mut Ticker) {
+    info!(
+        Sensors,
+        "calibrating the compass, turn the robot if it doesn't"
+    );
+    let spin = Segment {
+        duration_ms: CALIBRATION_TIME.as_millis() as u32,
+        power: MecanumPower::new(0.0),
+        angle: Angle::default(),
+        turn: Turn::new(CALIBRATION_SPIN),
+    };
+    _ = comms::play_profile([spin].into_iter().collect());
+
+    let mut calibrator = MagCalibrator::new();
+    let end = Instant::now() + CALIBRATION_TIME;
+    while Instant::now() < end {
+        ticker.next().await;
+        if let Ok(raw) = mag.read().await {
+            calibrator.add(raw);
+        }
+    }
+
+    let calibration = calibrator.result();
+    match calibration {
+        Some(calibration) => {
+            info!(Sensors, "compass calibrated");
+            _ = config::update(ConfigMessage::Compass(CompassParams {
+                calibration: Some(calibration),
+                ..config().compass
+            }));
+            ABSOLUTE_HEADING.store(false, Ordering::Relaxed);
+        }
+        None => warn!(
+            Sensors,
+            "compass not calibrated, the robot didn't turn enough"
+        ),
+    }
+    _ = TX_QUEUE.try_send(TxMessage::CompassCalibration(calibration));
+}
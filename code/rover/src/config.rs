@@ -0,0 +1,123 @@
+//! Runtime configuration: the values the tasks read through [`config()`],
+//! and how they're changed and stored. Tasks that have to act on a change
+//! wait on [`CHANGES`] instead.
+
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use defmt::{info, warn, Display2Format};
+use embassy_sync::{
+    blocking_mutex::{
+        raw::{CriticalSectionRawMutex, NoopRawMutex},
+        Mutex as BlockingMutex,
+    },
+    mutex::Mutex,
+    watch::Watch,
+};
+use rover_lib::ConfigStore;
+use rover_proto::{params, AckCode, Config, ConfigMessage, Param, ProtocolMode};
+
+use crate::board::{
+    calibration_mut, power_budget_mut, thermal_mut, wheels_mut, ConfigFlash, Robot,
+};
+
+/// Bump whenever [`Config`] changes layout.
+pub const CONFIG_VERSION: u16 = 24;
+
+pub type Store = ConfigStore<ConfigFlash, { Config::STORE_SIZE }>;
+
+const _: () = assert!(Store::CAPACITY >= Config::MAX_WIRE_SIZE);
+
+/// JSON takes `alloc`.
+const DEFAULT_PROTOCOL: ProtocolMode =
+    if cfg!(any(feature = "binary_protocol", not(feature = "alloc"))) {
+        ProtocolMode::Binary
+    } else {
+        ProtocolMode::Json
+    };
+
+/// [`Config::is_valid`], and within what this build supports.
+fn is_supported(config: &Config) -> bool {
+    config.is_valid() && (cfg!(feature = "alloc") || config.protocol == ProtocolMode::Binary)
+}
+
+static CONFIG: BlockingMutex<CriticalSectionRawMutex, Cell<Config>> =
+    BlockingMutex::new(Cell::new(Config::new(DEFAULT_PROTOCOL)));
+
+/// The configuration, sent again on every change.
+pub static CHANGES: Watch<CriticalSectionRawMutex, Config, 2> = Watch::new();
+
+pub fn config() -> Config {
+    CONFIG.lock(Cell::get)
+}
+
+static ROVER_ID: AtomicU8 = AtomicU8::new(Config::new(DEFAULT_PROTOCOL).rover_id);
+
+/// [`Config::rover_id`] as it was on boot.
+#[cfg(not(feature = "mavlink"))]
+pub fn rover_id() -> u8 {
+    ROVER_ID.load(Ordering::Relaxed)
+}
+
+fn set(config: Config) {
+    CONFIG.lock(|c| c.set(config));
+    CHANGES.sender().send(config);
+}
+
+/// Changes a single value, if it's within bounds.
+pub fn update(msg: ConfigMessage) -> AckCode {
+    let mut config = config();
+    match config.apply(msg) {
+        Ok(()) => {
+            set(config);
+            AckCode::Ok
+        }
+        Err(code) => code,
+    }
+}
+
+/// Replaces the defaults with the stored configuration, if there's a valid
+/// one.
+pub fn load(store: &mut Store) {
+    match store.load::<Config>() {
+        Ok(stored) if is_supported(&stored) => set(stored),
+        Ok(_) => warn!("stored config out of range, using defaults"),
+        Err(e) => info!("no stored config ({}), using defaults", Display2Format(&e)),
+    }
+    ROVER_ID.store(config().rover_id, Ordering::Relaxed);
+}
+
+/// Validates and applies a whole new configuration. Values the tasks read
+/// through [`config()`] are picked up on their own, the rest is pushed here.
+pub async fn set_config(robot: &Mutex<NoopRawMutex, Robot>, new_config: Config) -> AckCode {
+    if !is_supported(&new_config) {
+        return AckCode::OutOfRange;
+    }
+    set(new_config);
+    let mut robot = robot.lock().await;
+    calibration_mut(&mut robot).set_trims(new_config.wheel_trims);
+    power_budget_mut(&mut robot).set_budget(new_config.power_budget);
+    thermal_mut(&mut robot).set_params(new_config.thermal);
+    wheels_mut(&mut robot).set_inverted(new_config.wheel_inverted);
+    AckCode::Ok
+}
+
+/// Changes a single parameter, like [`set_config`].
+pub async fn set_param(robot: &Mutex<NoopRawMutex, Robot>, param: &Param) -> AckCode {
+    match params::apply(&config(), &param.name, param.value) {
+        Ok(new_config) => set_config(robot, new_config).await,
+        Err(code) => code,
+    }
+}
+
+pub fn save_config(store: &mut Store) -> AckCode {
+    match store.save(&config()) {
+        Ok(()) => AckCode::Ok,
+        Err(e) => {
+            warn!("failed to store config: {}", Display2Format(&e));
+            AckCode::StorageFailed
+        }
+    }
+}
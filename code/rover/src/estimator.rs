@@ -0,0 +1,79 @@
+//! The pose estimate of `ekf`, in place of the plain odometry: the wheel
+//! velocities, the IMU heading and, with `gps`, the GPS fixes fused by a
+//! [`PoseEstimator`]. It's published as the [`POSE`] everything drives on.
+
+use defmt::Debug2Format;
+use embassy_executor::task;
+use embassy_futures::select::{select3, Either3};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal, watch::Watch};
+use embassy_time::{Duration, Instant};
+use uom::si::f32::Length;
+
+use rover_lib::{Estimate, PoseEstimator};
+
+use crate::{
+    board::geometry,
+    config::config,
+    log::debug,
+    tasks::{ATTITUDE, POSE, WHEELS},
+};
+
+const ESTIMATE_LOG_PERIOD: Duration = Duration::from_secs(1);
+
+pub static ESTIMATE: Watch<CriticalSectionRawMutex, Estimate, 1> = Watch::new();
+/// A GPS position, in the frame of the estimate.
+static POSITION: Signal<CriticalSectionRawMutex, (Length, Length)> = Signal::new();
+
+pub fn fuse_position(x: Length, y: Length) {
+    POSITION.signal((x, y));
+}
+
+#[task]
+pub async fn estimator_task() {
+    let Some(mut wheels) = WHEELS.receiver() else {
+        defmt::error!("no receiver left for wheel readings");
+        return;
+    };
+    let Some(mut attitude) = ATTITUDE.receiver() else {
+        defmt::error!("no receiver left for attitude");
+        return;
+    };
+    let pose = POSE.sender();
+    let estimate = ESTIMATE.sender();
+    let mut estimator = PoseEstimator::new(config().estimator);
+    let mut last = Instant::now();
+    let mut last_log = last;
+
+    loop {
+        let measurement = select3(wheels.changed(), attitude.changed(), POSITION.wait()).await;
+        let now = Instant::now();
+        estimator.set_params(config().estimator);
+        estimator.predict((now - last).as_micros() as f32 * 1e-6);
+        last = now;
+
+        match measurement {
+            Either3::First(readings) => {
+                // The encoders count like their motors turn, backwards on
+                // inverted wheels
+                let mut velocities = readings.map(|r| r.velocity);
+                for (velocity, inverted) in velocities.iter_mut().zip(config().wheel_inverted) {
+                    if inverted {
+                        *velocity = -*velocity;
+                    }
+                }
+                estimator.update_odometry(geometry().chassis_velocity(velocities));
+            }
+            Either3::Second(attitude) => estimator.update_heading(attitude.heading),
+            Either3::Third((x, y)) => estimator.update_position(x, y),
+        }
+
+        let new = estimator.estimate();
+        pose.send(new.pose);
+        estimate.send(new);
+
+        if last_log.elapsed() >= ESTIMATE_LOG_PERIOD {
+            last_log = Instant::now();
+            debug!(Sensors, "estimate: {}", Debug2Format(&new));
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Black box: faults are [`record`]ed from anywhere and written to the
+//! event log in flash by [`comms::serve`], which owns the flash, so they
+//! survive the reset that often follows.
+//!
+//! Also the event bus: what the tasks react to is [`publish`]ed to every
+//! [`subscribe`]r, the black box being one through [`log_task`].
+//!
+//! [`comms::serve`]: crate::comms::serve
+
+use defmt::{warn, Debug2Format, Display2Format};
+use embassy_executor::task;
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    channel::Channel,
+    pubsub::{self, PubSubChannel},
+};
+use embassy_time::Instant;
+
+use rover_lib::{event_log::Event, EventLog, FailsafeStage, Gesture};
+use rover_proto::{AckCode, TxMessage};
+
+use crate::{
+    board::{EVENT_LOG_OFFSET, EVENT_LOG_SIZE},
+    comms::TX_QUEUE,
+    config::Store,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusEvent {
+    /// The safety timer tripped while driving.
+    LinkLost,
+    EstopPressed,
+    /// FL-FR-BL-BR index.
+    OverCurrent {
+        wheel: u8,
+    },
+    LowBattery,
+    WaypointReached {
+        waypoint: u8,
+    },
+    /// Disarmed while it could drive, see [`crate::arming`].
+    Disarmed,
+    /// Of the user button, acted on by [`crate::button`].
+    Button(Gesture),
+    /// Tilted past the limit, about to tip over.
+    Tilted,
+    /// The stage the failsafe moved to, [`FailsafeStage::Linked`] once the
+    /// host is back.
+    Failsafe(FailsafeStage),
+}
+
+const BUS_SIZE: usize = 8;
+/// The indicator, the black box, telemetry, the safety timer, arming and
+/// the button actions.
+const BUS_SUBSCRIBERS: usize = 6;
+
+pub type Subscriber =
+    pubsub::Subscriber<'static, CriticalSectionRawMutex, BusEvent, BUS_SIZE, BUS_SUBSCRIBERS, 0>;
+
+static BUS: PubSubChannel<CriticalSectionRawMutex, BusEvent, BUS_SIZE, BUS_SUBSCRIBERS, 0> =
+    PubSubChannel::new();
+
+/// Never waits: a subscriber falling behind misses the oldest events.
+pub fn publish(event: BusEvent) {
+    BUS.immediate_publisher().publish_immediate(event);
+}
+
+/// Panics past [`BUS_SUBSCRIBERS`].
+pub fn subscribe() -> Subscriber {
+    BUS.subscriber().unwrap()
+}
+
+/// Records the bus events worth keeping.
+#[task]
+pub async fn log_task() {
+    let mut events = subscribe();
+    loop {
+        let event = match events.next_message_pure().await {
+            BusEvent::LinkLost => Event::SafetyTimeout,
+            BusEvent::EstopPressed => Event::Estop,
+            BusEvent::OverCurrent { wheel } => Event::Overcurrent { wheel },
+            BusEvent::LowBattery => Event::LowBattery,
+            BusEvent::Tilted => Event::Tilt,
+            BusEvent::Failsafe(FailsafeStage::Faulted) => Event::Failsafe,
+            BusEvent::WaypointReached { .. }
+            | BusEvent::Disarmed
+            | BusEvent::Button(_)
+            | BusEvent::Failsafe(_) => continue,
+        };
+        record(event);
+    }
+}
+
+/// Recorded but not written yet, with their uptime in ms.
+static PENDING: Channel<CriticalSectionRawMutex, (u32, Event), 8> = Channel::new();
+
+/// Queues `event` for the log, dropping it if a burst filled the queue.
+pub fn record(event: Event) {
+    let uptime_ms = Instant::now().as_millis() as u32;
+    if PENDING.try_send((uptime_ms, event)).is_err() {
+        warn!("event log queue full, dropped {}", Debug2Format(&event));
+    }
+}
+
+pub async fn next() -> (u32, Event) {
+    PENDING.receive().await
+}
+
+/// The event log, or nothing if the flash couldn't be read: events are then
+/// only dropped.
+pub struct BlackBox(Option<EventLog>);
+
+impl BlackBox {
+    pub fn open(store: &mut Store) -> Self {
+        Self(
+            EventLog::open(store.inner_mut(), EVENT_LOG_OFFSET, EVENT_LOG_SIZE)
+                .inspect_err(|e| warn!("no event log: {}", Display2Format(e)))
+                .ok(),
+        )
+    }
+
+    pub fn write(&mut self, store: &mut Store, (uptime_ms, event): (u32, Event)) {
+        let Some(log) = &mut self.0 else {
+            return;
+        };
+        _ = log
+            .append(store.inner_mut(), uptime_ms, event)
+            .inspect_err(|e| warn!("failed to log event: {}", Display2Format(e)));
+    }
+
+    pub async fn dump(&self, store: &mut Store) -> AckCode {
+        let Some(log) = &self.0 else {
+            return AckCode::StorageFailed;
+        };
+        for index in 0..log.capacity() {
+            match log.read(store.inner_mut(), index) {
+                Ok(Some(entry)) => TX_QUEUE.send(TxMessage::Event(entry)).await,
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("failed to read event log: {}", Display2Format(&e));
+                    return AckCode::StorageFailed;
+                }
+            }
+        }
+        AckCode::Ok
+    }
+
+    pub fn clear(&mut self, store: &mut Store) -> AckCode {
+        let Some(log) = &mut self.0 else {
+            return AckCode::StorageFailed;
+        };
+        match log.clear(store.inner_mut()) {
+            Ok(()) => AckCode::Ok,
+            Err(e) => {
+                warn!("failed to clear event log: {}", Display2Format(&e));
+                AckCode::StorageFailed
+            }
+        }
+    }
+}
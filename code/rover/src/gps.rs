@@ -0,0 +1,161 @@
+//! The GPS receiver of `gps`: its fixes go out with the telemetry, and with
+//! the compass heading they let [`RxBody::NavigateGps`] drive outdoors.
+//!
+//! Between fixes, a second or so apart, the position is carried on with the
+//! odometry, whose heading is the compass one. With `ekf` the fixes are
+//! fused into the pose estimate instead.
+//!
+//! [`RxBody::NavigateGps`]: rover_proto::RxBody::NavigateGps
+
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use defmt::Debug2Format;
+use embassy_executor::task;
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex as BlockingMutex},
+    watch::Watch,
+};
+use uom::si::{angle::radian, f32::Angle, length::meter};
+
+use rover_lib::{
+    gps::{GpsReading, NmeaParser},
+    navigator::Waypoint,
+    GeoPoint, GpsFix, Pose,
+};
+use rover_proto::{AckCode, GpsRoute, Route};
+
+use crate::{
+    board::GpsRx,
+    compass::ABSOLUTE_HEADING,
+    config::config,
+    log::{info, warn},
+    tasks::POSE,
+};
+
+/// The last fix, kept when the receiver loses it.
+pub static FIX: Watch<CriticalSectionRawMutex, Option<GpsFix>, 1> = Watch::new();
+static HAS_FIX: AtomicBool = AtomicBool::new(false);
+/// Where the last fix was, and the pose then. With `ekf` the first one
+/// since the heading is absolute, the others being fused from there.
+static LAST: BlockingMutex<CriticalSectionRawMutex, Cell<Option<(GeoPoint, Pose)>>> =
+    BlockingMutex::new(Cell::new(None));
+
+#[task]
+pub async fn gps_task(mut rx: GpsRx) {
+    let mut parser = NmeaParser::new();
+    let mut buf = [0u8; 128];
+
+    loop {
+        let n = match rx.read_until_idle(&mut buf).await {
+            Ok(n) => n,
+            Err(e) => {
+                warn!(Sensors, "gps read failed: {}", Debug2Format(&e));
+                continue;
+            }
+        };
+        for &byte in &buf[..n] {
+            match parser.push(byte) {
+                Ok(Some(reading)) => update(reading),
+                Ok(None) => {}
+                Err(e) => warn!(Sensors, "bad NMEA sentence: {}", Debug2Format(&e)),
+            }
+        }
+    }
+}
+
+fn update(reading: GpsReading) {
+    let had_fix = HAS_FIX.load(Ordering::Relaxed);
+    match reading {
+        GpsReading::Fix(fix) => {
+            if !had_fix {
+                info!(Sensors, "gps fix, {} satellites", fix.satellites);
+            }
+            let pose = POSE.try_get().unwrap_or_default();
+            #[cfg(not(feature = "ekf"))]
+            LAST.lock(|last| last.set(Some((fix.position, pose))));
+            #[cfg(feature = "ekf")]
+            fuse(fix.position, pose);
+            FIX.sender().send(Some(fix));
+            HAS_FIX.store(true, Ordering::Relaxed);
+        }
+        GpsReading::NoFix => {
+            if had_fix {
+                warn!(Sensors, "gps fix lost");
+            }
+            HAS_FIX.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Fuses the fix into the pose estimate, put in its frame from where the
+/// first one was. Not before the heading is absolute, the frame not facing
+/// north until then.
+#[cfg(feature = "ekf")]
+fn fuse(position: GeoPoint, pose: Pose) {
+    if !ABSOLUTE_HEADING.load(Ordering::Relaxed) {
+        return;
+    }
+    let (datum, then) = LAST.lock(|last| {
+        let datum = last.get().unwrap_or((position, pose));
+        last.set(Some(datum));
+        datum
+    });
+    let (x, y) = position.offset_from(&datum, config().gps.declination());
+    crate::estimator::fuse_position(then.x + x, then.y + y);
+}
+
+/// The pose in the frame facing magnetic north at `origin`: where a fix
+/// was, moved on by the pose since.
+pub fn pose(origin: &GeoPoint) -> Pose {
+    let now = POSE.try_get().unwrap_or_default();
+    let Some((position, then)) = LAST.lock(Cell::get) else {
+        return now;
+    };
+    let (x, y) = position.offset_from(origin, config().gps.declination());
+    Pose {
+        x: x + now.x - then.x,
+        y: y + now.y - then.y,
+        heading: now.heading,
+    }
+}
+
+/// The `points` as waypoints from where the robot is, each to be reached
+/// facing along the leg leading to it.
+pub fn local_route(points: &GpsRoute) -> Result<(GeoPoint, Route), AckCode> {
+    if points.is_empty() {
+        return Ok((GeoPoint::default(), Route::new()));
+    }
+    if !points.iter().all(GeoPoint::is_valid) {
+        return Err(AckCode::OutOfRange);
+    }
+    let origin = match LAST.lock(Cell::get) {
+        Some((position, _))
+            if HAS_FIX.load(Ordering::Relaxed) && ABSOLUTE_HEADING.load(Ordering::Relaxed) =>
+        {
+            position
+        }
+        _ => return Err(AckCode::NoFix),
+    };
+
+    let declination = config().gps.declination();
+    let start = pose(&origin);
+    let (mut from, mut heading) = ((start.x, start.y), start.heading);
+    let route = points
+        .iter()
+        .map(|point| {
+            let (x, y) = point.offset_from(&origin, declination);
+            let dx = (x - from.0).get::<meter>();
+            let dy = (y - from.1).get::<meter>();
+            if dx != 0.0 || dy != 0.0 {
+                // Counter-clockwise from forward
+                heading = Angle::new::<radian>(libm::atan2f(-dx, dy));
+            }
+            from = (x, y);
+            Waypoint { x, y, heading }
+        })
+        .collect();
+    Ok((origin, route))
+}
@@ -0,0 +1,83 @@
+//! The parking brake of [`HoldParams`]: armed with nobody driving, the pose
+//! the robot stopped at is held on odometry with the navigation parameters,
+//! as [`Source::Hold`] so anyone driving takes over right away.
+//!
+//! [`HoldParams`]: rover_lib::HoldParams
+
+use embassy_executor::task;
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+use embassy_time::{Duration, Ticker};
+
+use rover_lib::{
+    mux::Source,
+    navigator::{Navigator, Waypoint},
+    DriveFrame,
+};
+use rover_proto::Command;
+
+use crate::{
+    arming,
+    board::Robot,
+    comms::{active_source, apply_drive, claim, release},
+    config::config,
+    log::info,
+    tasks::{ATTITUDE, POSE},
+};
+
+/// As often as the odometry updates.
+const HOLD_PERIOD: Duration = Duration::from_millis(20);
+
+#[task]
+pub async fn hold_task(
+    robot: &'static Mutex<NoopRawMutex, Robot>,
+    feed: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    let mut navigator = Navigator::new(config().navigation);
+    let mut ticker = Ticker::every(HOLD_PERIOD);
+    let dt = HOLD_PERIOD.as_micros() as f32 * 1e-6;
+    let mut target: Option<Waypoint> = None;
+
+    loop {
+        ticker.next().await;
+        let idle = matches!(active_source(), None | Some(Source::Hold));
+        let wants_hold = idle
+            && arming::mode().may_drive()
+            && config()
+                .hold
+                .wants_hold(ATTITUDE.try_get().as_ref(), target.is_some());
+        if !wants_hold {
+            if target.take().is_some() {
+                info!(Drive, "position hold off");
+                if active_source() == Some(Source::Hold) {
+                    apply_drive(robot, Command::default(), DriveFrame::Robot).await;
+                }
+                release(Source::Hold);
+            }
+            continue;
+        }
+
+        let pose = POSE.try_get().unwrap_or_default();
+        let target = *target.get_or_insert_with(|| {
+            info!(Drive, "holding position");
+            navigator.set_params(config().navigation);
+            navigator.reset();
+            Waypoint {
+                x: pose.x,
+                y: pose.y,
+                heading: pose.heading,
+            }
+        });
+        if claim(Source::Hold) {
+            feed.signal(());
+            let command = match navigator.update(&pose, &target, dt) {
+                Some((p, th, tu)) => Command { p, th, tu },
+                None => Command::default(),
+            };
+            apply_drive(robot, command, DriveFrame::Robot).await;
+        }
+    }
+}
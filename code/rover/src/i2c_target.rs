@@ -0,0 +1,136 @@
+//! The rover as an I2C target with `i2c_target`, see
+//! [`rover_lib::i2c_target`] for the registers.
+//!
+//! What the controller writes is handed to [`comms::serve`] like host
+//! messages, so it drives and arms under the same rules. The read-only
+//! registers are refreshed every [`UPDATE_PERIOD`].
+//!
+//! [`comms::serve`]: crate::comms::serve
+
+use core::sync::atomic::Ordering;
+
+use embassy_executor::task;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+use embassy_time::{Duration, Ticker};
+
+use rover_lib::{
+    i2c_target::{Fault, Inputs, Status, TargetCommand, Writes},
+    Mode,
+};
+use rover_proto::{DriveMessage, RxBody, RxMessage};
+
+use crate::{
+    arming,
+    board::{current_limiter, I2cTarget, Robot},
+    comms::RX_QUEUE,
+    tasks::{BATTERY, BATTERY_LOW, ESTOP, FAULT, POSE, SAFETY_TRIPPED, TILTED},
+};
+
+#[cfg(feature = "mavlink")]
+compile_error!(
+    "the I2C target hands its writes to the rover_proto host link, build without mavlink"
+);
+
+const UPDATE_PERIOD: Duration = Duration::from_millis(50);
+
+#[task]
+pub async fn i2c_target_task(
+    target: I2cTarget,
+    robot: &'static Mutex<NoopRawMutex, Robot>,
+    feed: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    let mut update = Ticker::every(UPDATE_PERIOD);
+
+    loop {
+        match select(update.next(), target.written()).await {
+            Either::First(()) => target.update(inputs(robot).await),
+            Either::Second(writes) => apply(writes, feed).await,
+        }
+    }
+}
+
+async fn inputs(robot: &Mutex<NoopRawMutex, Robot>) -> Inputs {
+    let overcurrent = current_limiter(&*robot.lock().await).tripped();
+
+    let mode = arming::mode();
+    let mut status = 0;
+    if matches!(mode, Mode::Armed | Mode::Driving) {
+        status |= Status::ARMED;
+    }
+    if matches!(mode, Mode::Driving) {
+        status |= Status::DRIVING;
+    }
+    if SAFETY_TRIPPED.load(Ordering::Relaxed) {
+        status |= Status::TIMED_OUT;
+    }
+    if BATTERY_LOW.load(Ordering::Relaxed) {
+        status |= Status::BATTERY_LOW;
+    }
+
+    let mut fault = 0;
+    if FAULT.load(Ordering::Relaxed) {
+        fault |= Fault::DRIVE;
+    }
+    if ESTOP.load(Ordering::Relaxed) {
+        fault |= Fault::ESTOP;
+    }
+    if overcurrent {
+        fault |= Fault::OVERCURRENT;
+    }
+    if TILTED.load(Ordering::Relaxed) {
+        fault |= Fault::TILTED;
+    }
+
+    Inputs {
+        status: Status(status),
+        fault: Fault(fault),
+        pose: POSE.try_get().unwrap_or_default(),
+        battery: BATTERY.try_get(),
+    }
+}
+
+async fn apply(writes: Writes, feed: &Signal<CriticalSectionRawMutex, ()>) {
+    if writes.keepalive {
+        feed.signal(());
+    }
+    // Commands first: an arm and a drive in one go drive
+    if let Some(command) = writes.command {
+        let body = match command {
+            TargetCommand::Arm => RxBody::Arm(true),
+            TargetCommand::Disarm => RxBody::Arm(false),
+            TargetCommand::Stop => RxBody::Drive(DriveMessage {
+                p: Some(Default::default()),
+                th: None,
+                tu: Some(Default::default()),
+            }),
+            TargetCommand::ClearEstop => RxBody::ClearEstop,
+            TargetCommand::ClearOvercurrent => RxBody::ClearOvercurrent,
+        };
+        RX_QUEUE
+            .send(RxMessage {
+                seq: None,
+                body,
+                at_ms: None,
+            })
+            .await;
+    }
+    if let Some((p, th, tu)) = writes.drive {
+        let body = RxBody::Drive(DriveMessage {
+            p: Some(p),
+            th: Some(th),
+            tu: Some(tu),
+        });
+        RX_QUEUE
+            .send(RxMessage {
+                seq: None,
+                body,
+                at_ms: None,
+            })
+            .await;
+    }
+}
@@ -0,0 +1,81 @@
+//! The status light, showing the most urgent [`Status`] of the rover, and
+//! the buzzer sounding the bus events worth hearing.
+
+use core::sync::atomic::Ordering;
+
+use embassy_executor::task;
+use embassy_time::{Duration, Instant, Ticker};
+
+#[cfg(feature = "buzzer")]
+use rover_lib::buzzer::Alert;
+use rover_lib::indicator::{Rgb, Status};
+
+use crate::{
+    board::StatusLight,
+    events::{self, BusEvent},
+    tasks::{BATTERY_LOW, ESTOP, FAULT, SAFETY_TRIPPED, TILTED},
+};
+
+const INDICATOR_PERIOD: Duration = Duration::from_millis(50);
+/// How long the light flashes white for a waypoint reached.
+const WAYPOINT_FLASH: Duration = Duration::from_millis(300);
+
+#[task]
+pub async fn indicator_task(mut light: StatusLight) {
+    let mut ticker = Ticker::every(INDICATOR_PERIOD);
+    let mut events = events::subscribe();
+    // The safety timer starts tripped, it's only fed once driven
+    let mut linked = false;
+    let mut flash_until = Instant::MIN;
+    let mut shown = None;
+    loop {
+        ticker.next().await;
+        while let Some(event) = events.try_next_message_pure() {
+            if let BusEvent::WaypointReached { .. } = event {
+                flash_until = Instant::now() + WAYPOINT_FLASH;
+            }
+            #[cfg(feature = "buzzer")]
+            if let Some(alert) = alert(event) {
+                crate::buzzer::alert(alert);
+            }
+        }
+
+        linked |= !SAFETY_TRIPPED.load(Ordering::Relaxed);
+        let now = Instant::now();
+        let color = if now < flash_until {
+            Rgb::WHITE
+        } else {
+            status(linked).pattern().color_at(now.as_millis())
+        };
+        if shown != Some(color) {
+            light.show(color).await;
+            shown = Some(color);
+        }
+    }
+}
+
+fn status(linked: bool) -> Status {
+    let tripped = SAFETY_TRIPPED.load(Ordering::Relaxed);
+    [
+        (FAULT.load(Ordering::Relaxed), Status::Fault),
+        (TILTED.load(Ordering::Relaxed), Status::Fault),
+        (ESTOP.load(Ordering::Relaxed), Status::Estop),
+        (linked && tripped, Status::SafetyTripped),
+        (BATTERY_LOW.load(Ordering::Relaxed), Status::LowBattery),
+        (linked, Status::Driving),
+    ]
+    .into_iter()
+    .filter_map(|(active, status)| active.then_some(status))
+    .max()
+    .unwrap_or(Status::WaitingForLink)
+}
+
+#[cfg(feature = "buzzer")]
+fn alert(event: BusEvent) -> Option<Alert> {
+    match event {
+        BusEvent::LinkLost => Some(Alert::LinkLost),
+        BusEvent::LowBattery => Some(Alert::LowBattery),
+        BusEvent::EstopPressed | BusEvent::Tilted => Some(Alert::Estop),
+        _ => None,
+    }
+}
@@ -0,0 +1,74 @@
+//! Line following, started and stopped with [`RxBody::LineFollow`].
+//!
+//! The robot follows as [`Source::Autonomous`], so anyone else driving takes
+//! over, and stops for good once it loses the line.
+//!
+//! [`RxBody::LineFollow`]: rover_proto::RxBody::LineFollow
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_executor::task;
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+use embassy_time::{Duration, Ticker};
+use rover_lib::{line::LineFollower, mux::Source, DriveFrame};
+use rover_proto::Command;
+
+use crate::{
+    board::{LineSensor, Robot},
+    comms::{apply_drive, claim, stop_autonomy, Autonomy},
+    config::config,
+    log::{info, warn},
+};
+
+const LINE_FOLLOW_PERIOD: Duration = Duration::from_millis(10);
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(on: bool) {
+    if ENABLED.load(Ordering::Relaxed) != on {
+        info!(Drive, "line following {}", if on { "on" } else { "off" });
+        ENABLED.store(on, Ordering::Relaxed);
+    }
+}
+
+#[task]
+pub async fn line_follow_task(
+    sensor: LineSensor,
+    robot: &'static Mutex<NoopRawMutex, Robot>,
+    feed: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    let mut follower = LineFollower::new(config().line_follow);
+    let mut ticker = Ticker::every(LINE_FOLLOW_PERIOD);
+    let dt = LINE_FOLLOW_PERIOD.as_micros() as f32 * 1e-6;
+    let mut following = false;
+
+    loop {
+        ticker.next().await;
+        if !ENABLED.load(Ordering::Relaxed) {
+            if following {
+                following = false;
+                stop_autonomy(robot, Autonomy::LineFollow).await;
+            }
+            continue;
+        }
+        if !following {
+            following = true;
+            follower.reset();
+        }
+
+        follower.set_params(config().line_follow);
+        let Some((p, th, tu)) = follower.update(&sensor.read(), dt) else {
+            warn!(Drive, "line lost, stopping");
+            ENABLED.store(false, Ordering::Relaxed);
+            continue;
+        };
+        if claim(Source::Autonomous) {
+            feed.signal(());
+            apply_drive(robot, Command { p, th, tu }, DriveFrame::Robot).await;
+        }
+    }
+}
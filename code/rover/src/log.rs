@@ -0,0 +1,113 @@
+//! Log macros taking the [`Category`] of the subsystem first, each logging
+//! from its own [`Level`] up, set from the host or the shell:
+//!
+//! ```ignore
+//! warn!(Safety, "battery low: {} V", volts);
+//! every!(1_000, debug!(Comms, "received {} bytes", len));
+//! ```
+//!
+//! [`every!`] rate limits the logs that come with every byte or frame,
+//! which would otherwise flood RTT at high command rates.
+//!
+//! Errors go straight to `defmt::error!`, never filtered.
+
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::Instant;
+
+use rover_lib::log::{Category, Level, RateLimit};
+
+/// Whatever defmt was built with gets through until told otherwise.
+static LEVELS: [AtomicU8; Category::COUNT] =
+    [const { AtomicU8::new(Level::Trace as u8) }; Category::COUNT];
+
+pub fn set_level(category: Category, level: Level) {
+    defmt::info!("{} logs from {} up", category, level);
+    LEVELS[category as usize].store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(category: Category, level: Level) -> bool {
+    level as u8 >= LEVELS[category as usize].load(Ordering::Relaxed)
+}
+
+/// The state of an [`every!`] call site.
+pub struct Limit(Mutex<CriticalSectionRawMutex, Cell<RateLimit>>);
+
+impl Limit {
+    pub const fn new() -> Self {
+        Self(Mutex::new(Cell::new(RateLimit::new())))
+    }
+
+    /// The number dropped since the last one, if this one may be logged.
+    pub fn allow(&self, period_ms: u32) -> Option<u32> {
+        let now_ms = Instant::now().as_millis();
+        self.0.lock(|cell| {
+            let mut limit = cell.get();
+            let allowed = limit.allow(now_ms, period_ms);
+            cell.set(limit);
+            allowed
+        })
+    }
+}
+
+macro_rules! level {
+    (trace) => {
+        rover_lib::log::Level::Trace
+    };
+    (debug) => {
+        rover_lib::log::Level::Debug
+    };
+    (info) => {
+        rover_lib::log::Level::Info
+    };
+    (warn) => {
+        rover_lib::log::Level::Warn
+    };
+}
+
+macro_rules! log_at {
+    ($level:ident, $category:ident, $($arg:tt)+) => {
+        if $crate::log::enabled(
+            rover_lib::log::Category::$category,
+            $crate::log::level!($level),
+        ) {
+            defmt::$level!($($arg)+);
+        }
+    };
+}
+
+macro_rules! debug {
+    ($category:ident, $($arg:tt)+) => { $crate::log::log_at!(debug, $category, $($arg)+) };
+}
+
+macro_rules! info {
+    ($category:ident, $($arg:tt)+) => { $crate::log::log_at!(info, $category, $($arg)+) };
+}
+
+macro_rules! warn {
+    ($category:ident, $($arg:tt)+) => { $crate::log::log_at!(warn, $category, $($arg)+) };
+}
+
+/// Logs at most once every `$period_ms`, then how many were dropped.
+macro_rules! every {
+    ($period_ms:expr, $level:ident!($category:ident, $($arg:tt)+)) => {{
+        static LIMIT: $crate::log::Limit = $crate::log::Limit::new();
+        if $crate::log::enabled(
+            rover_lib::log::Category::$category,
+            $crate::log::level!($level),
+        ) {
+            if let Some(dropped) = LIMIT.allow($period_ms) {
+                if dropped > 0 {
+                    defmt::$level!("({} dropped)", dropped);
+                }
+                defmt::$level!($($arg)+);
+            }
+        }
+    }};
+}
+
+pub(crate) use {debug, every, info, level, log_at, warn};
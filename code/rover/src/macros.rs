@@ -0,0 +1,94 @@
+//! Named motion profiles kept in flash, so a demo runs with a single
+//! [`RxBody::RunMacro`] and no host streaming the segments.
+//!
+//! All the macros are stored together in their own erase block, next to the
+//! configuration one.
+//!
+//! [`RxBody::RunMacro`]: rover_proto::RxBody::RunMacro
+
+use defmt::{info, warn, Display2Format};
+use heapless::Vec;
+
+use rover_lib::{ConfigError, ConfigStore};
+use rover_proto::{AckCode, Macro, MacroName, Segments, MAX_MACROS};
+
+use crate::{
+    board::{ConfigFlash, MACRO_OFFSET},
+    config::Store,
+};
+
+/// Bump whenever [`Macro`] changes layout.
+const MACRO_VERSION: u16 = 1;
+
+/// Holds all the macros, header included.
+const MACRO_STORE_SIZE: usize = 1024;
+
+type MacroStore<'a> = ConfigStore<&'a mut ConfigFlash, MACRO_STORE_SIZE>;
+
+/// The macros share the flash of the configuration.
+fn macro_store(store: &mut Store) -> MacroStore<'_> {
+    ConfigStore::new(store.inner_mut(), MACRO_OFFSET, MACRO_VERSION)
+}
+
+type Macros = Vec<Macro, MAX_MACROS>;
+
+fn load(store: &mut Store) -> Macros {
+    match macro_store(store).load::<Macros>() {
+        Ok(macros) => macros,
+        Err(ConfigError::Empty) => Vec::new(),
+        Err(e) => {
+            warn!("no stored macros ({})", Display2Format(&e));
+            Vec::new()
+        }
+    }
+}
+
+fn save(store: &mut Store, macros: &[Macro]) -> AckCode {
+    match macro_store(store).save(macros) {
+        Ok(()) => AckCode::Ok,
+        Err(e) => {
+            warn!("failed to store macros: {}", Display2Format(&e));
+            AckCode::StorageFailed
+        }
+    }
+}
+
+pub fn names(store: &mut Store) -> Vec<MacroName, MAX_MACROS> {
+    load(store).into_iter().map(|m| m.name).collect()
+}
+
+/// The segments of the macro called `name`.
+pub fn find(store: &mut Store, name: &str) -> Option<Segments> {
+    load(store)
+        .into_iter()
+        .find(|m| m.name.as_str() == name)
+        .map(|m| m.segments)
+}
+
+/// Stores `new`, replacing the macro of the same name if any.
+pub fn store_macro(store: &mut Store, new: Macro) -> AckCode {
+    if !new.is_valid() {
+        return AckCode::OutOfRange;
+    }
+    let mut macros = load(store);
+    macros.retain(|m| m.name != new.name);
+    info!(
+        "storing macro {} of {} segments",
+        new.name.as_str(),
+        new.segments.len()
+    );
+    if macros.push(new).is_err() {
+        return AckCode::OutOfRange;
+    }
+    save(store, &macros)
+}
+
+pub fn delete_macro(store: &mut Store, name: &str) -> AckCode {
+    let mut macros = load(store);
+    let count = macros.len();
+    macros.retain(|m| m.name.as_str() != name);
+    if macros.len() == count {
+        return AckCode::NotFound;
+    }
+    save(store, &macros)
+}
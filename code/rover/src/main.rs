@@ -1,159 +1,107 @@
 #![no_std]
 #![no_main]
 
+#[cfg(feature = "alloc")]
 extern crate alloc;
 
-use alloc::{rc::Rc, sync::Arc};
-use cobs::CobsDecoder;
-use defmt::{debug, warn, Debug2Format, Display2Format};
-use embassy_futures::select::Either;
+/// Moves `$value` into a static of its own, for a `&'static mut` to it
+/// without a heap. Panics if run twice.
+macro_rules! make_static {
+    ($ty:ty, $value:expr) => {{
+        static CELL: rover_lib::StaticCell<$ty> = rover_lib::StaticCell::new();
+        CELL.init($value)
+    }};
+}
+
+mod arming;
+mod aux_outputs;
+#[cfg(feature = "bluetooth")]
+mod bluetooth;
+mod board;
+#[cfg(not(feature = "rp2040"))]
+mod button;
+#[cfg(feature = "buzzer")]
+mod buzzer;
+mod comms;
+#[cfg(feature = "magnetometer")]
+mod compass;
+mod config;
+#[cfg(feature = "ekf")]
+mod estimator;
+mod events;
+#[cfg(feature = "gps")]
+mod gps;
+mod hold;
+#[cfg(feature = "i2c_target")]
+mod i2c_target;
+mod indicator;
+#[cfg(feature = "line_sensor")]
+mod line_follow;
+mod log;
+mod macros;
+#[cfg(feature = "mavlink")]
+mod mavlink;
+mod moves;
+mod navigation;
+#[cfg(feature = "safe_panic")]
+mod panic;
+mod profile;
+#[cfg(any(
+    feature = "sbus",
+    feature = "rc_pwm",
+    feature = "rc_ppm",
+    feature = "nrf24"
+))]
+mod rc;
+mod selftest;
+#[cfg(feature = "servo")]
+mod servo;
+#[cfg(feature = "shell")]
+mod shell;
+mod tasks;
+#[cfg(feature = "uart_log")]
+mod uart_log;
+
+#[cfg(all(feature = "uart_log", feature = "defmt-rtt"))]
+compile_error!("uart_log replaces RTT, build without `debug`");
+
+#[cfg(feature = "bluetooth")]
+use defmt::{warn, Display2Format};
+use embassy_executor::Spawner;
 use embassy_sync::{
-    blocking_mutex::raw::{self as raw_mutex, CriticalSectionRawMutex, NoopRawMutex},
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
     mutex::Mutex,
     signal,
 };
+#[cfg(feature = "alloc")]
 use embedded_alloc::LlffHeap as Heap;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use uom::si::angle;
 
+#[cfg(feature = "alloc")]
 #[global_allocator]
 static HEAP: Heap = Heap::empty();
 
-use core::cell::RefCell;
-
-#[cfg(not(feature = "defmt"))]
+#[cfg(feature = "defmt-rtt")]
+use defmt_rtt as _;
+#[cfg(not(any(feature = "defmt", feature = "safe_panic")))]
 use panic_halt as _;
-#[cfg(feature = "defmt")]
-use {defmt_rtt as _, panic_probe as _};
-
-use embassy_executor::{task, Spawner};
-use embassy_stm32::{
-    bind_interrupts,
-    exti::{Channel, ExtiInput},
-    gpio::{AnyPin, Input, Output, Pin},
-    peripherals,
-    timer::simple_pwm,
-    usart::{self, BufferedUart},
-};
-use embassy_time::{Duration, Timer};
-use embedded_hal_02::PwmPin;
-
-use defmt::info;
-
-use embedded_io_async::BufRead;
+#[cfg(all(feature = "defmt", not(feature = "safe_panic")))]
+use panic_probe as _;
 
+#[cfg(feature = "buzzer")]
+use rover_lib::buzzer::Alert;
+#[cfg(feature = "differential")]
+use rover_lib::DifferentialRobot;
 use rover_lib::{
-    iface::{FWRMerror, MecanumPower},
-    my_lib::MyFourWheelRobotError,
-    Angle, MecanumRobot, MyFourWheelRobot, MyMotor, Turn,
+    CalibratedRobot, ConfigStore, CurrentLimited, PowerBudget, SlewLimiter, StabilizedRobot,
+    ThermalDerating,
 };
 
-struct PwmWrapper<C, T, D, P: embedded_hal_02::Pwm<Channel = C, Time = T, Duty = D>> {
-    pwm: Rc<RefCell<P>>,
-    channel: C,
-}
-
-impl<C, T, D, P> PwmWrapper<C, T, D, P>
-where
-    P: embedded_hal_02::Pwm<Channel = C, Time = T, Duty = D>,
-{
-    pub fn new(pwm: Rc<RefCell<P>>, channel: C) -> Self {
-        Self { pwm, channel }
-    }
-}
-
-impl<C: Copy, T, D, P: embedded_hal_02::Pwm<Channel = C, Time = T, Duty = D>> PwmPin
-    for PwmWrapper<C, T, D, P>
-{
-    type Duty = D;
-
-    fn disable(&mut self) {
-        self.pwm.borrow_mut().disable(self.channel);
-    }
-    fn enable(&mut self) {
-        self.pwm.borrow_mut().enable(self.channel);
-    }
-
-    fn get_duty(&self) -> Self::Duty {
-        self.pwm.borrow_mut().get_duty(self.channel)
-    }
-    fn get_max_duty(&self) -> Self::Duty {
-        self.pwm.borrow_mut().get_max_duty()
-    }
-    fn set_duty(&mut self, duty: Self::Duty) {
-        self.pwm.borrow_mut().set_duty(self.channel, duty);
-    }
-}
-
-impl<C, T, D, P: embedded_hal_02::Pwm<Channel = C, Time = T, Duty = D>>
-    embedded_hal_1::pwm::ErrorType for PwmWrapper<C, T, D, P>
-{
-    type Error = embedded_hal_1::pwm::ErrorKind;
-}
-impl<C: Copy, T, D, P> embedded_hal_1::pwm::SetDutyCycle for PwmWrapper<C, T, D, P>
-where
-    D: TryFrom<u16> + Into<u16>,
-    P: embedded_hal_02::Pwm<Channel = C, Time = T, Duty = D>,
-{
-    fn max_duty_cycle(&self) -> u16 {
-        self.get_max_duty().into()
-    }
-    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
-        self.set_duty(duty.try_into().map_err(|_| Self::Error::Other)?);
-        Ok(())
-    }
-}
-
-#[embassy_executor::task]
-async fn rover_task(
-    button: ExtiInput<'static, AnyPin>,
-    robot: Arc<
-        Mutex<raw_mutex::NoopRawMutex, dyn MecanumRobot<Error = FWRMerror<MyFourWheelRobotError>>>,
-    >,
-) {
-    generic_rover_task(button, robot).await;
-}
-
-async fn generic_rover_task<E: core::error::Error>(
-    mut button: ExtiInput<'_, AnyPin>,
-    robot: Arc<Mutex<raw_mutex::NoopRawMutex, dyn (MecanumRobot<Error = E>)>>,
-) {
-    loop {
-        button.wait_for_low().await;
-        info!("making robot go forward");
-        robot
-            .lock()
-            .await
-            .drive(
-                MecanumPower::new(1.0),
-                Angle::new::<angle::radian>(core::f32::consts::FRAC_PI_2),
-                Turn::new(0.0),
-            )
-            .unwrap();
-
-        button.wait_for_high().await;
-        info!("putting robot in neutral");
-        robot.lock().await.neutral().unwrap();
-    }
-}
-
-bind_interrupts!(struct Irqs {
-    USART6 => usart::BufferedInterruptHandler<peripherals::USART6>;
-});
-
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct RxMessage {
-    p: Option<MecanumPower>,
-    th: Option<Angle>,
-    tu: Option<Turn>,
-}
+use board::{Board, Robot};
+use config::{config, Store};
 
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
-    let p = embassy_stm32::init(Default::default());
-
-    // allocator
+    #[cfg(feature = "alloc")]
     {
         use core::mem::MaybeUninit;
         const HEAP_SIZE: usize = 0x4000;
@@ -161,232 +109,181 @@ async fn main(spawner: Spawner) {
         unsafe { HEAP.init(HEAP_MEM.as_ptr() as usize, HEAP_SIZE) }
     }
 
-    let pwm = {
-        use embassy_stm32::{gpio::OutputType, time::khz, timer::Channel};
-        use simple_pwm::PwmPin;
-
-        let channels = (
-            Some(PwmPin::new_ch1(p.PA8, OutputType::PushPull)),
-            Some(PwmPin::new_ch2(p.PA9, OutputType::PushPull)),
-            Some(PwmPin::new_ch3(p.PA10, OutputType::PushPull)),
-            Some(PwmPin::new_ch4(p.PA11, OutputType::PushPull)),
-        );
-
-        let mut pwm = simple_pwm::SimplePwm::new(
-            p.TIM1,
-            channels.0,
-            channels.1,
-            channels.2,
-            channels.3,
-            khz(1),
-            Default::default(),
-        );
-
-        pwm.enable(Channel::Ch1);
-        pwm.enable(Channel::Ch2);
-        pwm.enable(Channel::Ch3);
-        pwm.enable(Channel::Ch4);
-
-        Rc::new(RefCell::new(pwm))
-    };
-
-    let robot = {
-        use embassy_stm32::{
-            gpio::{Level, Speed},
-            timer::Channel,
-        };
-        use embedded_hal_1::digital::PinState;
-
-        if cfg!(feature = "old_circuit") {
-            MyFourWheelRobot::new(
-                MyMotor::new(
-                    PwmWrapper::new(Rc::clone(&pwm), Channel::Ch1),
-                    Output::new(p.PC4.degrade(), Level::Low, Speed::Low),
-                    Output::new(p.PB13.degrade(), Level::Low, Speed::Low),
-                    PinState::High,
-                ),
-                MyMotor::new(
-                    PwmWrapper::new(Rc::clone(&pwm), Channel::Ch2),
-                    Output::new(p.PB14.degrade(), Level::Low, Speed::Low),
-                    Output::new(p.PB15.degrade(), Level::Low, Speed::Low),
-                    PinState::High,
-                ),
-                MyMotor::new(
-                    PwmWrapper::new(Rc::clone(&pwm), Channel::Ch3),
-                    Output::new(p.PB1.degrade(), Level::Low, Speed::Low),
-                    Output::new(p.PB2.degrade(), Level::Low, Speed::Low),
-                    PinState::High,
-                ),
-                MyMotor::new(
-                    PwmWrapper::new(Rc::clone(&pwm), Channel::Ch4),
-                    Output::new(p.PB12.degrade(), Level::Low, Speed::Low),
-                    Output::new(p.PC5.degrade(), Level::Low, Speed::Low),
-                    PinState::High,
-                ),
-            )
-        } else {
-            MyFourWheelRobot::new(
-                MyMotor::new(
-                    PwmWrapper::new(Rc::clone(&pwm), Channel::Ch1),
-                    Output::new(p.PC0.degrade(), Level::Low, Speed::Low),
-                    Output::new(p.PC1.degrade(), Level::Low, Speed::Low),
-                    PinState::High,
+    tasks::log_reset_cause();
+    #[cfg(feature = "safe_panic")]
+    panic::log_panic_reset();
+    let board = Board::init(spawner);
+
+    let mut store: Store =
+        ConfigStore::new(board.flash, board::CONFIG_OFFSET, config::CONFIG_VERSION);
+    config::load(&mut store);
+
+    let mut wheels = board.wheels;
+    wheels.set_inverted(config().wheel_inverted);
+    let drivetrain = SlewLimiter::new(
+        CalibratedRobot::new(
+            PowerBudget::new(
+                ThermalDerating::new(
+                    CurrentLimited::new(wheels, config().current_limit()),
+                    config().thermal,
                 ),
-                MyMotor::new(
-                    PwmWrapper::new(Rc::clone(&pwm), Channel::Ch2),
-                    Output::new(p.PC2.degrade(), Level::Low, Speed::Low),
-                    Output::new(p.PC3.degrade(), Level::Low, Speed::Low),
-                    PinState::High,
-                ),
-                MyMotor::new(
-                    PwmWrapper::new(Rc::clone(&pwm), Channel::Ch3),
-                    Output::new(p.PC5.degrade(), Level::Low, Speed::Low),
-                    Output::new(p.PC10.degrade(), Level::Low, Speed::Low),
-                    PinState::High,
-                ),
-                MyMotor::new(
-                    PwmWrapper::new(Rc::clone(&pwm), Channel::Ch4),
-                    Output::new(p.PC11.degrade(), Level::Low, Speed::Low),
-                    Output::new(p.PC12.degrade(), Level::Low, Speed::Low),
-                    PinState::High,
-                ),
-            )
-        }
-    };
-
-    let button: ExtiInput<'static, AnyPin> = ExtiInput::new(
-        Input::new(p.PC13.degrade(), embassy_stm32::gpio::Pull::Up),
-        p.EXTI13.degrade(),
+                config().power_budget,
+            ),
+            config().wheel_trims,
+        ),
+        config().slew_rate,
     );
-    let robot_m = Arc::new(Mutex::new(robot));
-
-    static SIGNAL: signal::Signal<CriticalSectionRawMutex, ()> = const {signal::Signal::new()};
-
-    spawner.spawn(rover_task(button, robot_m.clone())).unwrap();
-    spawner.spawn(safety_timer(robot_m.clone(), &SIGNAL)).unwrap();
-
-    const RX_SIZE: usize = 128;
-
-    let mut tx_buf = [0u8; 32];
-    let mut rx_buf = [0u8; RX_SIZE];
-
-    let buf_usart = BufferedUart::new(
-        p.USART6,
-        Irqs,
-        p.PC7,
-        p.PC6,
-        &mut tx_buf,
-        &mut rx_buf,
-        usart::Config::default(),
-    )
-    .unwrap();
-
-    #[allow(unused)]
-    let (mut tx, mut rx) = buf_usart.split();
-
-    let mut p = MecanumPower::default();
-    let mut th = Angle::default();
-    let mut tu = Turn::default();
-
-    loop {
-        let mut decode_out = [0u8; RX_SIZE];
-
-        let mut decoder = CobsDecoder::new(&mut decode_out);
-        let size = loop {
-            let buf = rx.fill_buf().await.unwrap();
-            let len = buf.len();
-
-            debug!(
-                "received raw: {:?}",
-                Debug2Format(&core::str::from_utf8(buf))
-            );
-
-            match decoder.push(buf) {
-                Ok(Some((n, m))) => {
-                    rx.consume(m);
-                    break Some(n);
-                }
-                Ok(None) => {
-                    rx.consume(len);
-                }
-                Err(_) => {
-                    rx.consume(len);
-                    warn!("error decoding cobs");
-                    break None;
-                }
-            }
-        };
-
-        if let Some(size) = size {
-            let packet_raw = &decode_out[..size];
-
-            let Ok(rx_message) = serde_json::from_slice::<RxMessage>(packet_raw) else {
-                continue;
-            };
-            SIGNAL.signal(());
-
-            let mut change_needed = false;
-
-            rx_message.p.inspect(|v| {
-                p = *v;
-                change_needed = true;
-            });
-            rx_message.th.inspect(|v| {
-                th = *v;
-                change_needed = true;
-            });
-            rx_message.tu.inspect(|v| {
-                tu = *v;
-                change_needed = true;
-            });
-
-            if change_needed {
-                debug!(
-                    "p: {}, th: {}, tu: {}",
-                    p.inner(),
-                    th.get::<uom::si::angle::radian>(),
-                    tu.inner()
-                );
-                _ = robot_m
-                    .lock()
-                    .await
-                    .drive(p, th, tu)
-                    .inspect(|_| info!("all went well"))
-                    .inspect_err(|_| warn!("failed to drive robot"));
-            };
-        }
+    #[cfg(feature = "differential")]
+    let drivetrain = DifferentialRobot::new(drivetrain);
+    let robot = StabilizedRobot::new(drivetrain, config().heading_gains);
+    let robot_m: &'static Mutex<NoopRawMutex, Robot> =
+        make_static!(Mutex<NoopRawMutex, Robot>, Mutex::new(robot));
+
+    spawner.spawn(tasks::encoder_task(board.encoders)).unwrap();
+    #[cfg(not(feature = "ekf"))]
+    spawner.spawn(tasks::odometry_task()).unwrap();
+    #[cfg(feature = "ekf")]
+    spawner.spawn(estimator::estimator_task()).unwrap();
+    spawner.spawn(tasks::imu_task(board.imu)).unwrap();
+    #[cfg(feature = "ultrasonic")]
+    spawner.spawn(tasks::ranger_task(board.rangers)).unwrap();
+    #[cfg(feature = "vl53l0x")]
+    spawner.spawn(tasks::tof_task(board.tof)).unwrap();
+    #[cfg(feature = "magnetometer")]
+    spawner.spawn(compass::compass_task(board.mag)).unwrap();
+    #[cfg(feature = "gps")]
+    spawner.spawn(gps::gps_task(board.gps_uart.init())).unwrap();
+    spawner.spawn(tasks::heading_hold_task(robot_m)).unwrap();
+    #[cfg(feature = "closed_loop")]
+    spawner.spawn(tasks::velocity_task(robot_m)).unwrap();
+
+    static SIGNAL: signal::Signal<CriticalSectionRawMutex, ()> = const { signal::Signal::new() };
+
+    spawner.spawn(tasks::slew_task(robot_m)).unwrap();
+    // The Pico has no user button, nor a pin left for one
+    #[cfg(not(feature = "rp2040"))]
+    {
+        spawner.spawn(button::button_task(board.button)).unwrap();
+        spawner
+            .spawn(button::actions_task(robot_m, &SIGNAL))
+            .unwrap();
     }
-}
-
-type SafetyMutex = CriticalSectionRawMutex;
+    spawner.spawn(tasks::estop_task(board.estop)).unwrap();
+    spawner
+        .spawn(indicator::indicator_task(board.status_light))
+        .unwrap();
+    spawner.spawn(tasks::watchdog_task(board.watchdog)).unwrap();
+    spawner.spawn(events::log_task()).unwrap();
+    spawner.spawn(arming::arming_task()).unwrap();
+    #[cfg(feature = "buzzer")]
+    spawner.spawn(buzzer::buzzer_task(board.buzzer)).unwrap();
+    spawner
+        .spawn(tasks::analog_task(board.analog, robot_m))
+        .unwrap();
+    spawner
+        .spawn(tasks::safety_timer(robot_m, &SIGNAL))
+        .unwrap();
+
+    // The UART isn't up yet, so nothing sent meanwhile gets driven later
+    let report = selftest::run(robot_m, &SIGNAL).await;
+    #[cfg(feature = "buzzer")]
+    if report.ok() {
+        buzzer::alert(Alert::Startup);
+    }
+    selftest::announce(report).await;
 
-#[task]
-async fn safety_timer(
-    robot: Arc<
-        Mutex<NoopRawMutex, dyn MecanumRobot<Error = FWRMerror<MyFourWheelRobotError>>>,
-    >,
-    sig: &'static signal::Signal<SafetyMutex, ()>,
-) {
-    safety_timer_generic(robot, sig).await;
-}
+    #[cfg(feature = "bluetooth")]
+    let (bluetooth_tx, bluetooth_rx) = {
+        let mut serial = board.bluetooth_uart.init();
+        if let Err(e) = bluetooth::configure(&mut serial).await {
+            warn!("bluetooth setup failed: {}", Display2Format(&e));
+        }
+        serial.split()
+    };
 
-async fn safety_timer_generic<E: core::error::Error>(
-    robot: Arc<Mutex<NoopRawMutex, dyn (MecanumRobot<Error = E>)>>,
-    sig: &'static signal::Signal<SafetyMutex, ()>,
-) {
-    loop {
-        let Either::First(_) =
-            embassy_futures::select::select(async { Timer::after_millis(500).await }, async {
-                sig.wait().await
-            })
-            .await
-        else {
-            continue;
-        };
-        robot
-            .lock()
-            .await
-            .neutral()
-            .expect("failed to stop robot in safety timer");
+    let (tx, rx) = board.host_uart.init();
+    #[cfg(feature = "usb")]
+    let (usb_tx, usb_rx) = board.usb.init(spawner);
+    #[cfg(not(feature = "mavlink"))]
+    {
+        spawner
+            .spawn(comms::tx_task(comms::Transmitters {
+                host: tx,
+                #[cfg(feature = "bluetooth")]
+                bluetooth: bluetooth_tx,
+                #[cfg(feature = "usb")]
+                usb: usb_tx,
+            }))
+            .unwrap();
+        spawner.spawn(comms::host_rx_task(rx)).unwrap();
+        #[cfg(feature = "usb")]
+        spawner.spawn(comms::usb_rx_task(usb_rx)).unwrap();
+    }
+    #[cfg(feature = "mavlink")]
+    {
+        spawner.spawn(mavlink::tx_task(tx)).unwrap();
+        spawner.spawn(mavlink::rx_task(rx)).unwrap();
     }
+    #[cfg(feature = "bluetooth")]
+    spawner
+        .spawn(comms::bluetooth_rx_task(bluetooth_rx))
+        .unwrap();
+    spawner.spawn(comms::telemetry_task(robot_m)).unwrap();
+    spawner.spawn(comms::link_stats_task()).unwrap();
+    spawner.spawn(comms::schedule_task()).unwrap();
+
+    #[cfg(feature = "sbus")]
+    spawner
+        .spawn(rc::sbus_task(board.sbus_uart.init(), robot_m, &SIGNAL))
+        .unwrap();
+
+    #[cfg(any(feature = "rc_pwm", feature = "rc_ppm"))]
+    spawner
+        .spawn(rc::rc_input_task(board.rc_capture.init(), robot_m, &SIGNAL))
+        .unwrap();
+
+    #[cfg(feature = "nrf24")]
+    spawner
+        .spawn(rc::radio_task(board.radio, robot_m, &SIGNAL))
+        .unwrap();
+
+    #[cfg(feature = "i2c_target")]
+    spawner
+        .spawn(i2c_target::i2c_target_task(
+            board
+                .i2c_target
+                .init(rover_lib::i2c_target::DEFAULT_ADDRESS),
+            robot_m,
+            &SIGNAL,
+        ))
+        .unwrap();
+
+    spawner
+        .spawn(navigation::navigation_task(robot_m, &SIGNAL))
+        .unwrap();
+    spawner
+        .spawn(profile::profile_task(robot_m, &SIGNAL))
+        .unwrap();
+    spawner.spawn(moves::moves_task(robot_m, &SIGNAL)).unwrap();
+    spawner.spawn(hold::hold_task(robot_m, &SIGNAL)).unwrap();
+
+    aux_outputs::init(board.aux);
+    #[cfg(feature = "servo")]
+    servo::init(board.servos);
+
+    #[cfg(feature = "line_sensor")]
+    spawner
+        .spawn(line_follow::line_follow_task(
+            board.line_sensor,
+            robot_m,
+            &SIGNAL,
+        ))
+        .unwrap();
+
+    #[cfg(feature = "shell")]
+    spawner
+        .spawn(shell::shell_task(board.shell_uart.init(), robot_m, &SIGNAL))
+        .unwrap();
+
+    comms::serve(robot_m, &mut store, &SIGNAL).await
 }
@@ -0,0 +1,238 @@
+//! The host link in MAVLink with `mavlink`, for ground control stations
+//! like QGroundControl in place of rover_ctl.
+//!
+//! A [`ManualControl`] joystick drives like [`RxBody::Sticks`] and
+//! `MAV_CMD_COMPONENT_ARM_DISARM` arms, both handed to [`comms::serve`]
+//! like any host message. The telemetry goes out as [`AttitudeMsg`] and
+//! [`SysStatus`], with a [`Heartbeat`] every second for the station to
+//! keep seeing the rover.
+//!
+//! [`comms::serve`]: crate::comms::serve
+
+use defmt::Debug2Format;
+use embassy_executor::task;
+use embassy_futures::select::{select3, Either3};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Duration, Ticker};
+use embedded_io_async::{Read, Write};
+use uom::si::{angle::radian, electric_potential::millivolt};
+
+use rover_lib::{
+    mavlink::{
+        AttitudeMsg, CommandAck, CommandLong, Heartbeat, ManualControl, MavMessage, MavParser,
+        MavResult, MavState, MavWriter, SysStatus, MAV_AUTOPILOT_GENERIC,
+        MAV_CMD_COMPONENT_ARM_DISARM, MAV_MODE_FLAG_MANUAL_INPUT_ENABLED,
+        MAV_MODE_FLAG_SAFETY_ARMED, MAV_TYPE_GROUND_ROVER, MAX_FRAME,
+    },
+    Mode,
+};
+use rover_proto::{Ack, AckCode, RxBody, RxMessage, Telemetry, TxMessage};
+
+use crate::{
+    arming,
+    board::{HostRx, HostTx},
+    comms::{frame_received, framing_error, rx_overflow, RX_QUEUE, TX_QUEUE},
+    log::warn,
+};
+
+#[cfg(feature = "bluetooth")]
+compile_error!("mavlink takes the host link bluetooth shares, build without it");
+#[cfg(feature = "uart_log")]
+compile_error!("mavlink takes the host link uart_log logs on, build without it");
+
+const SYSTEM_ID: u8 = 1;
+/// `MAV_COMP_ID_AUTOPILOT1`, which ground stations look for.
+const COMPONENT_ID: u8 = 1;
+const HEARTBEAT_PERIOD: Duration = Duration::from_secs(1);
+/// Of the arm and disarm commands handed to `serve`, to tell their acks.
+const ARM_SEQ: u32 = u32::MAX;
+
+const SENSOR_3D_GYRO: u32 = 0x01;
+const SENSOR_3D_ACCEL: u32 = 0x02;
+const SENSOR_MOTOR_OUTPUTS: u32 = 0x8000;
+
+/// Answers to commands, for [`tx_task`] to send.
+static COMMAND_ACKS: Channel<CriticalSectionRawMutex, CommandAck, 2> = Channel::new();
+
+#[task]
+pub async fn rx_task(mut rx: HostRx) {
+    let mut parser = MavParser::new();
+    let mut buf = [0u8; 64];
+
+    loop {
+        let n = match rx.read(&mut buf).await {
+            Ok(n) => n,
+            Err(e) => {
+                warn!(Comms, "mavlink read failed: {}", Debug2Format(&e));
+                rx_overflow();
+                continue;
+            }
+        };
+        for &byte in &buf[..n] {
+            match parser.push(byte) {
+                Ok(Some(frame)) => {
+                    frame_received();
+                    if let Some(control) = frame.message::<ManualControl>() {
+                        manual_control(control).await;
+                    } else if let Some(command) = frame.message::<CommandLong>() {
+                        command_long(command).await;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    warn!(Comms, "dropping mavlink frame: {}", Debug2Format(&e));
+                    framing_error();
+                }
+            }
+        }
+    }
+}
+
+fn for_us(target_system: u8) -> bool {
+    target_system == 0 || target_system == SYSTEM_ID
+}
+
+async fn manual_control(control: ManualControl) {
+    if !for_us(control.target) {
+        return;
+    }
+    let body = RxBody::Sticks {
+        x: ManualControl::normalized(control.y),
+        y: ManualControl::normalized(control.x),
+        rot: ManualControl::normalized(control.r),
+    };
+    RX_QUEUE
+        .send(RxMessage {
+            seq: None,
+            body,
+            at_ms: None,
+        })
+        .await;
+}
+
+async fn command_long(command: CommandLong) {
+    if !for_us(command.target_system) {
+        return;
+    }
+    match command.command {
+        MAV_CMD_COMPONENT_ARM_DISARM => {
+            let body = RxBody::Arm(command.params[0] > 0.5);
+            RX_QUEUE
+                .send(RxMessage {
+                    seq: Some(ARM_SEQ),
+                    body,
+                    at_ms: None,
+                })
+                .await;
+        }
+        _ => {
+            COMMAND_ACKS
+                .send(CommandAck {
+                    command: command.command,
+                    result: MavResult::Unsupported,
+                })
+                .await
+        }
+    }
+}
+
+#[task]
+pub async fn tx_task(mut tx: HostTx) {
+    let mut writer = MavWriter::new(SYSTEM_ID, COMPONENT_ID);
+    let mut heartbeat = Ticker::every(HEARTBEAT_PERIOD);
+
+    loop {
+        match select3(heartbeat.next(), TX_QUEUE.receive(), COMMAND_ACKS.receive()).await {
+            Either3::First(()) => {
+                let mode = arming::mode();
+                let mut base_mode = MAV_MODE_FLAG_MANUAL_INPUT_ENABLED;
+                if matches!(mode, Mode::Armed | Mode::Driving) {
+                    base_mode |= MAV_MODE_FLAG_SAFETY_ARMED;
+                }
+                let system_status = match mode {
+                    Mode::Disarmed | Mode::Armed => MavState::Standby,
+                    Mode::Driving => MavState::Active,
+                    Mode::Fault | Mode::Estop => MavState::Emergency,
+                    Mode::Failsafe => MavState::Critical,
+                };
+                let msg = Heartbeat {
+                    custom_mode: 0,
+                    mav_type: MAV_TYPE_GROUND_ROVER,
+                    autopilot: MAV_AUTOPILOT_GENERIC,
+                    base_mode,
+                    system_status,
+                };
+                send(&mut tx, &mut writer, &msg).await;
+            }
+            Either3::Second(TxMessage::Telemetry(telemetry)) => {
+                if let Some(attitude) = attitude(&telemetry) {
+                    send(&mut tx, &mut writer, &attitude).await;
+                }
+                send(&mut tx, &mut writer, &sys_status(&telemetry)).await;
+            }
+            Either3::Second(TxMessage::Ack(Ack { seq: ARM_SEQ, code })) => {
+                let result = match code {
+                    AckCode::Ok => MavResult::Accepted,
+                    AckCode::Estopped | AckCode::Tilted | AckCode::LowBattery => {
+                        MavResult::TemporarilyRejected
+                    }
+                    _ => MavResult::Denied,
+                };
+                let msg = CommandAck {
+                    command: MAV_CMD_COMPONENT_ARM_DISARM,
+                    result,
+                };
+                send(&mut tx, &mut writer, &msg).await;
+            }
+            // Nothing else has a MAVLink message
+            Either3::Second(_) => {}
+            Either3::Third(ack) => send(&mut tx, &mut writer, &ack).await,
+        }
+    }
+}
+
+async fn send(tx: &mut HostTx, writer: &mut MavWriter, msg: &impl MavMessage) {
+    let mut out = [0u8; MAX_FRAME];
+    let frame = writer.write(msg, &mut out);
+    _ = tx
+        .write_all(frame)
+        .await
+        .inspect_err(|e| warn!(Comms, "mavlink write failed: {}", Debug2Format(e)));
+}
+
+/// In the aircraft convention, yaw clockwise where the heading isn't.
+fn attitude(telemetry: &Telemetry) -> Option<AttitudeMsg> {
+    let attitude = telemetry.attitude?;
+    Some(AttitudeMsg {
+        time_boot_ms: telemetry.uptime_ms as u32,
+        roll: attitude.roll.get::<radian>(),
+        pitch: attitude.pitch.get::<radian>(),
+        yaw: -attitude.heading.get::<radian>(),
+        ..Default::default()
+    })
+}
+
+fn sys_status(telemetry: &Telemetry) -> SysStatus {
+    let imu = if telemetry.attitude.is_some() {
+        SENSOR_3D_GYRO | SENSOR_3D_ACCEL
+    } else {
+        0
+    };
+    let healthy_motors = if telemetry.fault || telemetry.overcurrent_tripped {
+        0
+    } else {
+        SENSOR_MOTOR_OUTPUTS
+    };
+    SysStatus {
+        sensors_present: imu | SENSOR_MOTOR_OUTPUTS,
+        sensors_enabled: imu | SENSOR_MOTOR_OUTPUTS,
+        sensors_health: imu | healthy_motors,
+        voltage_battery: telemetry
+            .battery
+            .map_or(u16::MAX, |v| v.get::<millivolt>() as u16),
+        current_battery: -1,
+        errors_comm: telemetry.framing_errors.min(u16::MAX as u32) as u16,
+        battery_remaining: -1,
+        ..Default::default()
+    }
+}
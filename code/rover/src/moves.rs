@@ -0,0 +1,137 @@
+//! The moves of [`RxBody::Move`], a set distance or angle driven on
+//! odometry with the navigation parameters.
+//!
+//! The robot drives as [`Source::Autonomous`], like navigation. The end of
+//! each move is reported with [`TxMessage::Move`].
+//!
+//! [`RxBody::Move`]: rover_proto::RxBody::Move
+
+use core::convert::Infallible;
+
+use embassy_executor::task;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+use embassy_time::{Duration, Ticker};
+
+use rover_lib::{
+    iface::{MecanumPower, Turn},
+    moves::{self, PoseSource},
+    mux::Source,
+    navigator::Navigator,
+    Angle, AsyncMecanumRobot, DriveFrame, Move, Pose,
+};
+use rover_proto::{Command, TxMessage};
+
+use crate::{
+    board::Robot,
+    comms::{apply_drive, claim, stop_autonomy, Autonomy, TX_QUEUE},
+    config::config,
+    log::info,
+    tasks::POSE,
+};
+
+/// As often as the odometry updates.
+const MOVE_PERIOD: Duration = Duration::from_millis(20);
+
+enum MoveCommand {
+    Start(Move),
+    Abort,
+}
+
+static COMMANDS: Signal<CriticalSectionRawMutex, MoveCommand> = Signal::new();
+
+/// Replaces the move going, if any.
+pub fn start(mv: Move) {
+    info!(Drive, "move: {}", defmt::Debug2Format(&mv));
+    COMMANDS.signal(MoveCommand::Start(mv));
+}
+
+pub fn abort() {
+    COMMANDS.signal(MoveCommand::Abort);
+}
+
+/// The odometry, until a command comes in.
+struct Poses<'a> {
+    ticker: Ticker,
+    next: &'a mut Option<MoveCommand>,
+}
+
+impl PoseSource for Poses<'_> {
+    async fn next(&mut self) -> Option<Pose> {
+        match select(self.ticker.next(), COMMANDS.wait()).await {
+            Either::First(()) => Some(POSE.try_get().unwrap_or_default()),
+            Either::Second(command) => {
+                *self.next = Some(command);
+                None
+            }
+        }
+    }
+}
+
+/// Drives through the mux, when it has the robot.
+struct Autonomous<'a> {
+    robot: &'a Mutex<NoopRawMutex, Robot>,
+    feed: &'a Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl AsyncMecanumRobot for Autonomous<'_> {
+    type Error = Infallible;
+
+    async fn drive(&mut self, p: MecanumPower, th: Angle, tu: Turn) -> Result<(), Infallible> {
+        if claim(Source::Autonomous) {
+            self.feed.signal(());
+            apply_drive(self.robot, Command { p, th, tu }, DriveFrame::Robot).await;
+        }
+        Ok(())
+    }
+
+    /// [`stop_autonomy`] stops the robot once the move is over.
+    async fn neutral(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+#[task]
+pub async fn moves_task(
+    robot: &'static Mutex<NoopRawMutex, Robot>,
+    feed: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    let mut navigator = Navigator::new(config().navigation);
+    let dt = MOVE_PERIOD.as_micros() as f32 * 1e-6;
+    let mut next = None;
+
+    loop {
+        let command = match next.take() {
+            Some(command) => command,
+            None => COMMANDS.wait().await,
+        };
+        let MoveCommand::Start(mv) = command else {
+            continue;
+        };
+
+        navigator.set_params(config().navigation);
+        let mut poses = Poses {
+            ticker: Ticker::every(MOVE_PERIOD),
+            next: &mut next,
+        };
+        let Ok(outcome) = moves::run(
+            &mut Autonomous { robot, feed },
+            &mut poses,
+            &mut navigator,
+            mv,
+            dt,
+        )
+        .await;
+
+        // Unless another move takes over right away
+        if !matches!(next, Some(MoveCommand::Start(_))) {
+            stop_autonomy(robot, Autonomy::Move).await;
+        }
+        info!(Drive, "move {}", outcome);
+        _ = TX_QUEUE.try_send(TxMessage::Move(outcome));
+    }
+}
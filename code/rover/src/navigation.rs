@@ -0,0 +1,151 @@
+//! Driving through the waypoints of [`RxBody::Navigate`], on odometry, or
+//! of [`RxBody::NavigateGps`] on GPS.
+//!
+//! The robot drives as [`Source::Autonomous`], so anyone else driving takes
+//! over and the route carries on once they let go. Each waypoint reached and
+//! the end of the route are reported with [`TxMessage::Navigation`].
+//!
+//! [`RxBody::Navigate`]: rover_proto::RxBody::Navigate
+//! [`RxBody::NavigateGps`]: rover_proto::RxBody::NavigateGps
+
+use embassy_executor::task;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+    watch::Watch,
+};
+use embassy_time::{Duration, Ticker};
+
+#[cfg(feature = "gps")]
+use rover_lib::GeoPoint;
+use rover_lib::{
+    mux::Source,
+    navigator::{NavParams, Navigator},
+    DriveFrame, Pose,
+};
+use rover_proto::{Command, NavEvent, NavProgress, Route, TxMessage};
+
+use crate::{
+    board::Robot,
+    comms::{apply_drive, claim, stop_autonomy, Autonomy, TX_QUEUE},
+    config::config,
+    events::{self, BusEvent},
+    log::info,
+    tasks::POSE,
+};
+
+/// As often as the odometry updates.
+const NAVIGATION_PERIOD: Duration = Duration::from_millis(20);
+
+static ROUTE: Signal<CriticalSectionRawMutex, (Route, Frame)> = Signal::new();
+/// `None` when not navigating.
+pub static PROGRESS: Watch<CriticalSectionRawMutex, Option<NavProgress>, 1> = Watch::new();
+
+/// What the waypoints of a route are relative to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frame {
+    Odometry,
+    /// Facing magnetic north at `origin`, see [`crate::gps::pose`].
+    #[cfg(feature = "gps")]
+    Gps {
+        origin: GeoPoint,
+    },
+}
+
+impl Frame {
+    fn pose(&self) -> Pose {
+        match self {
+            Frame::Odometry => POSE.try_get().unwrap_or_default(),
+            #[cfg(feature = "gps")]
+            Frame::Gps { origin } => crate::gps::pose(origin),
+        }
+    }
+
+    fn params(&self) -> NavParams {
+        let config = config();
+        match self {
+            Frame::Odometry => config.navigation,
+            #[cfg(feature = "gps")]
+            Frame::Gps { .. } => NavParams {
+                tolerance_mm: (config.gps.tolerance_m * 1000.0) as u16,
+                ..config.navigation
+            },
+        }
+    }
+}
+
+/// Replaces the route being driven, an empty one stops.
+pub fn navigate(route: Route, frame: Frame) {
+    info!(Drive, "route of {} waypoints", route.len());
+    ROUTE.signal((route, frame));
+}
+
+/// Stops the route being driven, if any.
+pub fn cancel() {
+    if PROGRESS.try_get().flatten().is_some() {
+        navigate(Route::new(), Frame::Odometry);
+    }
+}
+
+#[task]
+pub async fn navigation_task(
+    robot: &'static Mutex<NoopRawMutex, Robot>,
+    feed: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    let mut navigator = Navigator::new(config().navigation);
+    let mut ticker = Ticker::every(NAVIGATION_PERIOD);
+    let dt = NAVIGATION_PERIOD.as_micros() as f32 * 1e-6;
+    let progress = PROGRESS.sender();
+    let (mut route, mut frame) = ROUTE.wait().await;
+
+    loop {
+        let mut next = None;
+        'route: for (i, waypoint) in route.iter().enumerate() {
+            navigator.reset();
+            loop {
+                if let Either::Second(new_route) = select(ticker.next(), ROUTE.wait()).await {
+                    next = Some(new_route);
+                    break 'route;
+                }
+
+                let pose = frame.pose();
+                navigator.set_params(frame.params());
+                progress.send(Some(NavProgress {
+                    waypoint: i as u8,
+                    waypoints: route.len() as u8,
+                    distance: Navigator::distance(&pose, waypoint),
+                }));
+                let Some((p, th, tu)) = navigator.update(&pose, waypoint, dt) else {
+                    events::publish(BusEvent::WaypointReached { waypoint: i as u8 });
+                    _ = TX_QUEUE.try_send(TxMessage::Navigation(NavEvent::Reached {
+                        waypoint: i as u8,
+                    }));
+                    break;
+                };
+                if claim(Source::Autonomous) {
+                    feed.signal(());
+                    apply_drive(robot, Command { p, th, tu }, DriveFrame::Robot).await;
+                }
+            }
+        }
+
+        if let Some(next) = next {
+            _ = TX_QUEUE.try_send(TxMessage::Navigation(NavEvent::Cancelled));
+            (route, frame) = next;
+            if !route.is_empty() {
+                continue;
+            }
+        } else if !route.is_empty() {
+            _ = TX_QUEUE.try_send(TxMessage::Navigation(NavEvent::Done));
+        }
+        stop(robot).await;
+        (route, frame) = ROUTE.wait().await;
+    }
+}
+
+async fn stop(robot: &Mutex<NoopRawMutex, Robot>) {
+    PROGRESS.sender().send(None);
+    stop_autonomy(robot, Autonomy::Navigation).await;
+}
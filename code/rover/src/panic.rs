@@ -0,0 +1,57 @@
+//! Panic handler in place of panic-probe and panic-halt, which leave the
+//! PWM at its last duty: cuts the motor outputs through the registers, since
+//! whatever owned them may be mid-update, then resets.
+
+use core::{
+    mem::MaybeUninit,
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use defmt::warn;
+use rover_lib::event_log::Event;
+
+use crate::{board::safe_motors, events};
+
+/// Set before the reset, kept across it in RAM that isn't initialized at
+/// boot.
+#[link_section = ".uninit.PANIC"]
+static mut PANICKED: MaybeUninit<u32> = MaybeUninit::uninit();
+const PANIC_MAGIC: u32 = 0x5041_4E43;
+
+/// Guards against the logging panicking in turn, e.g. when the panic came
+/// from within the logger.
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+    safe_motors();
+    // SAFETY: interrupts are off, nothing else runs
+    unsafe {
+        core::ptr::addr_of_mut!(PANICKED)
+            .cast::<u32>()
+            .write_volatile(PANIC_MAGIC)
+    };
+    // Plain load and store, the Cortex-M0+ has no swap and interrupts are off
+    if !PANICKING.load(Ordering::Relaxed) {
+        PANICKING.store(true, Ordering::Relaxed);
+        defmt::error!("{}", defmt::Display2Format(info));
+    }
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Logs and records whether the last reset came from a panic.
+pub fn log_panic_reset() {
+    // SAFETY: read once at boot, before anything can panic
+    let panicked = unsafe {
+        let panicked = core::ptr::addr_of_mut!(PANICKED).cast::<u32>();
+        let value = panicked.read_volatile();
+        panicked.write_volatile(0);
+        value
+    };
+    if panicked == PANIC_MAGIC {
+        events::record(Event::Panic);
+        warn!("reset after a panic");
+    }
+}
@@ -0,0 +1,146 @@
+//! Playback of the motion profiles of [`RxBody::Profile`]: each segment is
+//! driven until its deadline, the deadlines following on from each other so
+//! the timing doesn't drift over a long profile.
+//!
+//! The robot drives as [`Source::Autonomous`]. Someone else taking over
+//! doesn't stop the clock, pausing does.
+//!
+//! [`RxBody::Profile`]: rover_proto::RxBody::Profile
+
+use embassy_executor::task;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Timer};
+
+use rover_lib::{mux::Source, profile::Segment, DriveFrame};
+use rover_proto::{Command, ProfileEvent, Segments, TxMessage};
+
+use crate::{
+    board::Robot,
+    comms::{apply_drive, claim, pause_autonomy, stop_autonomy, Autonomy, TX_QUEUE},
+    log::info,
+};
+
+/// Drive commands are repeated this often during a segment, to keep the
+/// claim and the safety timer going.
+const KEEPALIVE: Duration = Duration::from_millis(100);
+
+enum ProfileCommand {
+    Play(Segments),
+    Pause,
+    Resume,
+    Abort,
+}
+
+static COMMANDS: Signal<CriticalSectionRawMutex, ProfileCommand> = Signal::new();
+
+/// Replaces the profile playing, if any.
+pub fn play(segments: Segments) {
+    info!(Drive, "profile of {} segments", segments.len());
+    COMMANDS.signal(ProfileCommand::Play(segments));
+}
+
+pub fn pause() {
+    COMMANDS.signal(ProfileCommand::Pause);
+}
+
+pub fn resume() {
+    COMMANDS.signal(ProfileCommand::Resume);
+}
+
+pub fn abort() {
+    COMMANDS.signal(ProfileCommand::Abort);
+}
+
+fn report(event: ProfileEvent) {
+    _ = TX_QUEUE.try_send(TxMessage::Profile(event));
+}
+
+#[task]
+pub async fn profile_task(
+    robot: &'static Mutex<NoopRawMutex, Robot>,
+    feed: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    let mut next = None;
+    loop {
+        let segments = match next.take() {
+            Some(segments) => segments,
+            None => match COMMANDS.wait().await {
+                ProfileCommand::Play(segments) => segments,
+                _ => continue,
+            },
+        };
+        next = run(robot, feed, &segments).await;
+    }
+}
+
+/// Plays `segments` through. Returns the profile sent meanwhile, to play
+/// next.
+async fn run(
+    robot: &Mutex<NoopRawMutex, Robot>,
+    feed: &Signal<CriticalSectionRawMutex, ()>,
+    segments: &[Segment],
+) -> Option<Segments> {
+    let mut deadline = Instant::now();
+
+    for (index, segment) in segments.iter().enumerate() {
+        deadline += Duration::from_millis(segment.duration_ms as u64);
+        report(ProfileEvent::Segment {
+            index: index as u16,
+        });
+        let command = Command {
+            p: segment.power,
+            th: segment.angle,
+            tu: segment.turn,
+        };
+
+        loop {
+            if claim(Source::Autonomous) {
+                feed.signal(());
+                apply_drive(robot, command, DriveFrame::Robot).await;
+            }
+
+            let wake = deadline.min(Instant::now() + KEEPALIVE);
+            match select(Timer::at(wake), COMMANDS.wait()).await {
+                Either::First(()) if Instant::now() >= deadline => break,
+                Either::First(()) | Either::Second(ProfileCommand::Resume) => {}
+                Either::Second(ProfileCommand::Pause) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    pause_autonomy(robot, Autonomy::Profile).await;
+                    report(ProfileEvent::Paused);
+                    loop {
+                        match COMMANDS.wait().await {
+                            ProfileCommand::Resume => break,
+                            ProfileCommand::Pause => {}
+                            ProfileCommand::Abort => return aborted(robot, None).await,
+                            ProfileCommand::Play(next) => return aborted(robot, Some(next)).await,
+                        }
+                    }
+                    deadline = Instant::now() + remaining;
+                    report(ProfileEvent::Resumed);
+                }
+                Either::Second(ProfileCommand::Abort) => return aborted(robot, None).await,
+                Either::Second(ProfileCommand::Play(next)) => {
+                    return aborted(robot, Some(next)).await
+                }
+            }
+        }
+    }
+
+    stop_autonomy(robot, Autonomy::Profile).await;
+    report(ProfileEvent::Done);
+    None
+}
+
+/// Stops, unless another profile takes over right away.
+async fn aborted(robot: &Mutex<NoopRawMutex, Robot>, next: Option<Segments>) -> Option<Segments> {
+    if next.is_none() {
+        stop_autonomy(robot, Autonomy::Profile).await;
+    }
+    report(ProfileEvent::Aborted);
+    next
+}
@@ -0,0 +1,238 @@
+//! Driving from a hobby RC transmitter, through an SBUS receiver with `sbus`
+//! or a PWM/CPPM one with `rc_pwm`/`rc_ppm`, or from a handheld on the
+//! [`radio`] link with `nrf24`.
+//!
+//! Moving a stick claims control of the rover as [`Source::RcManual`], which
+//! lapses once the sticks are left centered. In control, readings feed the
+//! safety timer like host messages do, and a receiver going into failsafe
+//! stops the rover and holds it as [`Source::RcFailsafe`] until it's back. A
+//! dead or unplugged SBUS one lets the safety timer do it.
+//!
+//! [`radio`]: rover_lib::radio
+
+#[cfg(feature = "nrf24")]
+use core::sync::atomic::{AtomicU8, Ordering};
+
+#[cfg(any(feature = "sbus", feature = "nrf24"))]
+use defmt::Debug2Format;
+use embassy_executor::task;
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+#[cfg(feature = "sbus")]
+use rover_lib::sbus::{self, SbusFrame};
+use rover_lib::{mux::Source, DriveFrame, RcMapping};
+#[cfg(feature = "nrf24")]
+use rover_lib::{
+    nrf24::{Nrf24Error, MAX_PAYLOAD},
+    radio::{self, ControlPacket, RadioLink, StatusPacket},
+};
+use rover_proto::Command;
+#[cfg(feature = "nrf24")]
+use uom::si::electric_potential::millivolt;
+
+#[cfg(any(feature = "rc_pwm", feature = "rc_ppm"))]
+use crate::board::RcInput;
+#[cfg(feature = "sbus")]
+use crate::board::SbusRx;
+#[cfg(feature = "nrf24")]
+use crate::{arming, board::BoardRadio, log::every, tasks::BATTERY};
+use crate::{
+    board::Robot,
+    comms::{active_source, apply_drive, claim, hold, release},
+    log::{info, warn},
+};
+
+/// Turns receiver readings into drive commands, whatever the receiver.
+struct RcLink {
+    mapping: RcMapping,
+    failsafe: bool,
+}
+
+impl RcLink {
+    const fn new() -> Self {
+        Self {
+            mapping: RcMapping::AETR,
+            failsafe: false,
+        }
+    }
+
+    /// `axes` are the normalized channels, `None` when the receiver is in
+    /// failsafe.
+    async fn update(
+        &mut self,
+        axes: Option<&[f32]>,
+        robot: &Mutex<NoopRawMutex, Robot>,
+        feed: &Signal<CriticalSectionRawMutex, ()>,
+    ) {
+        let Some(axes) = axes else {
+            if !self.failsafe {
+                self.failsafe = true;
+                if active_source() == Some(Source::RcManual) {
+                    warn!(Comms, "rc receiver in failsafe, stopping");
+                    hold(Source::RcFailsafe);
+                    apply_drive(robot, Command::default(), DriveFrame::Robot).await;
+                }
+            }
+            return;
+        };
+        if self.failsafe {
+            info!(Comms, "rc receiver back");
+            self.failsafe = false;
+            release(Source::RcFailsafe);
+        }
+
+        let (p, th, tu) = self.mapping.command(axes);
+        // Centered sticks keep control but don't take it
+        let moved = p.inner() > 0.0 || tu.inner() != 0.0;
+        let in_control = if moved {
+            claim(Source::RcManual)
+        } else {
+            active_source() == Some(Source::RcManual)
+        };
+        if in_control {
+            feed.signal(());
+            apply_drive(robot, Command { p, th, tu }, DriveFrame::Robot).await;
+        }
+    }
+}
+
+#[cfg(feature = "sbus")]
+#[task]
+pub async fn sbus_task(
+    mut rx: SbusRx,
+    robot: &'static Mutex<NoopRawMutex, Robot>,
+    feed: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    let mut link = RcLink::new();
+    // One spare byte, so two frames run together don't pass for one
+    let mut buf = [0u8; sbus::FRAME_LEN + 1];
+
+    loop {
+        let n = match rx.read_until_idle(&mut buf).await {
+            Ok(n) => n,
+            Err(e) => {
+                warn!(Comms, "sbus read failed: {}", Debug2Format(&e));
+                continue;
+            }
+        };
+        let frame = match SbusFrame::decode(&buf[..n]) {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!(Comms, "bad sbus frame: {}", Debug2Format(&e));
+                continue;
+            }
+        };
+        if frame.frame_lost {
+            continue;
+        }
+
+        let axes = frame.axes();
+        let axes = (!frame.failsafe).then_some(&axes[..]);
+        link.update(axes, robot, feed).await;
+    }
+}
+
+/// PWM and CPPM receivers send a frame every 20 ms or so.
+#[cfg(any(feature = "rc_pwm", feature = "rc_ppm"))]
+const RC_INPUT_PERIOD: embassy_time::Duration = embassy_time::Duration::from_millis(20);
+
+/// Those receivers have no failsafe flag: they stop pulsing, or send the
+/// failsafe positions set on them, so lost pulses count as failsafe here.
+#[cfg(any(feature = "rc_pwm", feature = "rc_ppm"))]
+#[task]
+pub async fn rc_input_task(
+    input: RcInput,
+    robot: &'static Mutex<NoopRawMutex, Robot>,
+    feed: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    let mut link = RcLink::new();
+    let mut ticker = embassy_time::Ticker::every(RC_INPUT_PERIOD);
+
+    loop {
+        ticker.next().await;
+        let axes = input
+            .read()
+            .map(|widths| widths.map(rover_lib::rc::pulse_to_axis));
+        link.update(axes.as_ref().map(|axes| &axes[..]), robot, feed)
+            .await;
+    }
+}
+
+/// Of the handheld's packets, in percent, for telemetry.
+#[cfg(feature = "nrf24")]
+pub static RADIO_QUALITY: AtomicU8 = AtomicU8::new(0);
+
+/// Listens for the handheld, following its hops, and answers each packet
+/// with the rover's status. Losing it for [`radio::FAILSAFE_MS`] counts as
+/// failsafe.
+#[cfg(feature = "nrf24")]
+#[task]
+pub async fn radio_task(
+    mut radio: BoardRadio,
+    robot: &'static Mutex<NoopRawMutex, Robot>,
+    feed: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    let mut delay = embassy_time::Delay;
+    let mut rc = RcLink::new();
+    let mut link = RadioLink::new();
+    let init = async {
+        radio.init(radio::DEFAULT_ADDRESS, link.channel()).await?;
+        radio.listen(&mut delay).await
+    };
+    if let Err(e) = init.await {
+        warn!(Comms, "no nRF24 radio: {}", Debug2Format(&e));
+        return;
+    }
+    info!(Comms, "nRF24 radio listening");
+
+    let mut ticker = embassy_time::Ticker::every(embassy_time::Duration::from_millis(1));
+    let mut buf = [0u8; MAX_PAYLOAD];
+    loop {
+        ticker.next().await;
+        let now = embassy_time::Instant::now().as_millis();
+
+        let result: Result<(), Nrf24Error> = async {
+            if let Some(n) = radio.receive(&mut buf).await? {
+                match ControlPacket::decode(&buf[..n]) {
+                    Ok(packet) => {
+                        link.received(&packet, now);
+                        radio.set_channel(link.channel(), &mut delay).await?;
+                        let status = StatusPacket {
+                            seq: packet.seq,
+                            quality: link.quality(),
+                            mode: arming::mode(),
+                            battery_mv: BATTERY
+                                .try_get()
+                                .map(|v| v.get::<millivolt>().clamp(0.0, u16::MAX as f32) as u16)
+                                .unwrap_or(0),
+                        };
+                        radio.set_ack_payload(&status.encode()).await?;
+                        rc.update(Some(&packet.normalized()), robot, feed).await;
+                    }
+                    Err(e) => {
+                        every!(
+                            1_000,
+                            warn!(Comms, "bad radio packet: {}", Debug2Format(&e))
+                        );
+                    }
+                }
+            }
+            if link.poll(now) {
+                radio.set_channel(link.channel(), &mut delay).await?;
+            }
+            Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            every!(1_000, warn!(Comms, "nRF24 failed: {}", Debug2Format(&e)));
+        }
+
+        if link.is_lost(now) {
+            rc.update(None, robot, feed).await;
+        }
+        RADIO_QUALITY.store(link.quality(), Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,172 @@
+//! Check of the motors, encoders, current sensors and IMU, at boot and from
+//! the user button.
+//!
+//! Each wheel is pulsed at low power in both directions with the others held
+//! in neutral, so the robot shuffles a little but doesn't go anywhere.
+
+use core::sync::atomic::Ordering;
+
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+use embassy_time::{with_timeout, Duration, Ticker, Timer};
+use rover_lib::{iface::MotorPower, FourWheeledRobot};
+use rover_proto::{SelfTestReport, TxMessage};
+#[cfg(feature = "current_sense")]
+use uom::si::{electric_current::milliampere, f32::ElectricCurrent};
+
+#[cfg(feature = "current_sense")]
+use crate::{board::current_limiter, config::config};
+use crate::{
+    board::{wheels_mut, Robot, ENCODER_TICKS_PER_REV},
+    comms::TX_QUEUE,
+    log::{info, warn},
+    tasks::{ESTOP, FAULT, IMU_READY, WHEELS},
+};
+
+/// Encoder check bits, FL-FR-BL-BR.
+pub const ENCODER: [u16; 4] = [1 << 0, 1 << 1, 1 << 2, 1 << 3];
+/// Current sense check bits, FL-FR-BL-BR.
+#[cfg(feature = "current_sense")]
+pub const CURRENT: [u16; 4] = [1 << 4, 1 << 5, 1 << 6, 1 << 7];
+pub const IMU: u16 = 1 << 8;
+
+const PULSE_POWER: f32 = 0.25;
+const PULSE_TIME: Duration = Duration::from_millis(300);
+const SAMPLE_PERIOD: Duration = Duration::from_millis(20);
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+/// A twentieth of a wheel turn.
+const MIN_PULSE_TICKS: i32 = ENCODER_TICKS_PER_REV as i32 / 20;
+#[cfg(feature = "current_sense")]
+const MIN_CURRENT_MA: f32 = 50.0;
+/// Leaves room for the gyro calibration.
+const IMU_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `feed` is the safety timer signal, kept fed while a wheel is pulsed.
+pub async fn run(
+    robot: &Mutex<NoopRawMutex, Robot>,
+    feed: &Signal<CriticalSectionRawMutex, ()>,
+) -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+
+    // Before moving anything: the gyro calibration wants the robot still
+    let imu = with_timeout(IMU_TIMEOUT, IMU_READY.wait())
+        .await
+        .unwrap_or(false);
+    // Signaled once, left for the runs from the user button
+    IMU_READY.signal(imu);
+    report.record(IMU, imu);
+
+    if ESTOP.load(Ordering::Relaxed) || FAULT.load(Ordering::Relaxed) {
+        warn!(Sensors, "robot stopped, skipping motor self-test");
+        return report;
+    }
+
+    for wheel in 0..4 {
+        let mut moved = true;
+        #[cfg(feature = "current_sense")]
+        let mut drew = true;
+
+        for direction in [1.0, -1.0] {
+            let Some(pulse) = pulse(robot, feed, wheel, direction).await else {
+                moved = false;
+                #[cfg(feature = "current_sense")]
+                {
+                    drew = false;
+                }
+                continue;
+            };
+            moved &= pulse.ticks * direction as i32 >= MIN_PULSE_TICKS;
+            #[cfg(feature = "current_sense")]
+            {
+                let min = ElectricCurrent::new::<milliampere>(MIN_CURRENT_MA);
+                let max = config().current_limit().limit;
+                drew &= (min..=max).contains(&pulse.peak_current);
+            }
+        }
+
+        report.record(ENCODER[wheel], moved);
+        #[cfg(feature = "current_sense")]
+        report.record(CURRENT[wheel], drew);
+    }
+
+    report
+}
+
+/// Logs the `report` and sends it to the host.
+pub async fn announce(report: SelfTestReport) {
+    #[cfg(feature = "buzzer")]
+    if !report.ok() {
+        crate::buzzer::alert(rover_lib::buzzer::Alert::SelfTestFailed);
+    }
+    if report.ok() {
+        info!(Sensors, "self-test passed");
+    } else {
+        warn!(
+            Sensors,
+            "self-test failed: {=u16:#b} of {=u16:#b}",
+            report.tested & !report.passed,
+            report.tested
+        );
+    }
+    TX_QUEUE.send(TxMessage::SelfTest(report)).await;
+}
+
+struct Pulse {
+    ticks: i32,
+    #[cfg(feature = "current_sense")]
+    peak_current: ElectricCurrent,
+}
+
+/// Drives a single wheel for [`PULSE_TIME`], `None` if the motor failed.
+async fn pulse(
+    robot: &Mutex<NoopRawMutex, Robot>,
+    feed: &Signal<CriticalSectionRawMutex, ()>,
+    wheel: usize,
+    direction: f32,
+) -> Option<Pulse> {
+    let ticks = || WHEELS.try_get().map_or(0, |readings| readings[wheel].ticks);
+
+    let mut powers = [MotorPower::new(0.0); 4];
+    powers[wheel] = MotorPower::new(direction * PULSE_POWER);
+    let [fl, fr, bl, br] = powers;
+
+    let before = ticks();
+    feed.signal(());
+    let driven = wheels_mut(&mut *robot.lock().await).drive(fl, fr, bl, br);
+
+    #[cfg(feature = "current_sense")]
+    let mut peak_current = ElectricCurrent::new::<milliampere>(0.0);
+    let mut ticker = Ticker::every(SAMPLE_PERIOD);
+    for _ in 0..PULSE_TIME.as_ticks() / SAMPLE_PERIOD.as_ticks() {
+        ticker.next().await;
+        feed.signal(());
+        #[cfg(feature = "current_sense")]
+        {
+            let current = current_limiter(&*robot.lock().await).currents()[wheel].abs();
+            peak_current = peak_current.max(current);
+        }
+    }
+    let after = ticks();
+
+    let stopped = wheels_mut(&mut *robot.lock().await).neutral();
+    Timer::after(SETTLE_TIME).await;
+
+    if let Err(e) = driven.and(stopped) {
+        warn!(
+            Sensors,
+            "wheel {} failed self-test pulse: {}",
+            wheel,
+            defmt::Debug2Format(&e)
+        );
+        return None;
+    }
+
+    Some(Pulse {
+        ticks: after - before,
+        #[cfg(feature = "current_sense")]
+        peak_current,
+    })
+}
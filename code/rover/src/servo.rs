@@ -0,0 +1,37 @@
+//! The auxiliary servos of the board, moved by the host.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use rover_lib::{servo::ServoError, Angle, Servo, ServoCalibration};
+use rover_proto::AckCode;
+
+use crate::board::{ServoPwm, SERVO_PERIOD_US};
+
+pub const SERVO_COUNT: usize = 2;
+
+static SERVOS: Mutex<CriticalSectionRawMutex, RefCell<Option<[Servo<ServoPwm>; SERVO_COUNT]>>> =
+    Mutex::new(RefCell::new(None));
+
+/// The servos stay limp until first moved.
+pub fn init(pwms: [ServoPwm; SERVO_COUNT]) {
+    let servos = pwms.map(|pwm| Servo::new(pwm, SERVO_PERIOD_US, ServoCalibration::default()));
+    SERVOS.lock(|cell| cell.replace(Some(servos)));
+}
+
+pub fn set(index: u8, angle: Angle) -> AckCode {
+    SERVOS.lock(|cell| {
+        let mut servos = cell.borrow_mut();
+        let Some(servo) = servos
+            .as_mut()
+            .and_then(|servos| servos.get_mut(index as usize))
+        else {
+            return AckCode::NotFound;
+        };
+        match servo.set_angle(angle) {
+            Ok(()) => AckCode::Ok,
+            Err(ServoError::OutOfRange) => AckCode::OutOfRange,
+            Err(_) => AckCode::DriveFailed,
+        }
+    })
+}
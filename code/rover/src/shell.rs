@@ -0,0 +1,293 @@
+//! Line based text shell for bench debugging, on [`ShellUart`].
+//!
+//! [`ShellUart`]: crate::board::ShellUart
+//!
+//! Commands go through the same paths as the host protocol ones, safety
+//! timer included: a `drive` stops after the safety timeout like any other
+//! command.
+
+use core::fmt::Write as _;
+
+use defmt::warn;
+use embassy_executor::task;
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+use embedded_io_async::{BufRead, Write};
+use heapless::String;
+use rover_lib::{
+    arming::ModeEvent,
+    iface::MecanumPower,
+    log::{Category, Level},
+    mux::Source,
+    Angle, AsyncMecanumRobot, DriveFrame, Turn,
+};
+use rover_proto::{params, AckCode, Command, Config};
+use uom::si::{angle::degree, electric_potential::volt, length::meter};
+
+use crate::{
+    arming,
+    board::{current_limiter_mut, Robot, ShellSerial},
+    comms::{apply_drive, claim, refusal},
+    config::{config, set_config},
+    log,
+    tasks::{clear_estop, BATTERY, POSE, WHEELS},
+};
+
+const LINE_SIZE: usize = 64;
+/// Room for every parameter, the longest reply.
+const OUT_SIZE: usize = 2048;
+
+type Out = String<OUT_SIZE>;
+
+const HELP: &str = "\
+arm|disarm\r
+drive <power> <angle deg> <turn>\r
+forward <power>\r
+strafe <power> <angle deg>\r
+rotate <turn>\r
+stop\r
+neutral\r
+get config|<key>\r
+params\r
+set <key> <value>\r
+dump odom|wheels|battery\r
+clear estop|overcurrent\r
+log comms|drive|safety|sensors trace|debug|info|warn|error\r
+";
+
+#[task]
+pub async fn shell_task(
+    mut uart: ShellSerial,
+    robot: &'static Mutex<NoopRawMutex, Robot>,
+    feed: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    let mut line = [0u8; LINE_SIZE];
+    let mut len = 0;
+    let mut out = Out::new();
+
+    _ = uart.write_all(b"\r\n> ").await;
+    loop {
+        let Ok(buf) = uart.fill_buf().await else {
+            warn!("shell uart read failed");
+            continue;
+        };
+        let Some(&byte) = buf.first() else {
+            continue;
+        };
+        uart.consume(1);
+
+        out.clear();
+        match byte {
+            b'\r' | b'\n' => {
+                _ = out.push_str("\r\n");
+                // Not UTF-8 can only be garbage
+                let text = core::str::from_utf8(&line[..len]).unwrap_or_default();
+                if !text.trim().is_empty() {
+                    run(text, robot, feed, &mut out).await;
+                }
+                _ = out.push_str("> ");
+                len = 0;
+            }
+            // Backspace and delete
+            0x08 | 0x7f if len > 0 => {
+                len -= 1;
+                _ = out.push_str("\x08 \x08");
+            }
+            b' '..=b'~' if len < LINE_SIZE => {
+                line[len] = byte;
+                len += 1;
+                _ = out.push(byte as char);
+            }
+            _ => continue,
+        }
+        _ = uart.write_all(out.as_bytes()).await;
+    }
+}
+
+async fn run(
+    line: &str,
+    robot: &Mutex<NoopRawMutex, Robot>,
+    feed: &Signal<CriticalSectionRawMutex, ()>,
+    out: &mut Out,
+) {
+    let mut args = line.split_whitespace();
+    let code = match (args.next(), args.next(), args.next(), args.next()) {
+        (Some("help"), ..) => {
+            _ = out.push_str(HELP);
+            return;
+        }
+        (Some(name @ ("drive" | "forward" | "strafe" | "rotate" | "stop")), a, b, c) => {
+            let Some(command) = motion(name, [a, b, c]) else {
+                _ = out.push_str("expected numbers, try help\r\n");
+                return;
+            };
+            if claim(Source::Shell) {
+                feed.signal(());
+                apply_drive(robot, command, DriveFrame::default()).await
+            } else {
+                refusal()
+            }
+        }
+        (Some("arm"), None, ..) => arming::ack(arming::handle(ModeEvent::Arm)),
+        (Some("disarm"), None, ..) => arming::ack(arming::handle(ModeEvent::Disarm)),
+        (Some("neutral"), None, ..) => match robot.lock().await.neutral().await {
+            Ok(()) => AckCode::Ok,
+            Err(_) => AckCode::DriveFailed,
+        },
+        (Some("get"), Some("config"), None, _) => {
+            #[cfg(feature = "alloc")]
+            match serde_json::to_string(&config()) {
+                Ok(json) => {
+                    _ = write!(out, "{json}\r\n");
+                }
+                Err(_) => {
+                    _ = out.push_str("failed to encode config\r\n");
+                }
+            }
+            #[cfg(not(feature = "alloc"))]
+            {
+                _ = write!(out, "{:?}\r\n", config());
+            }
+            return;
+        }
+        (Some("get"), Some(key), None, _) => {
+            match params::find(key) {
+                Some(def) => _ = write!(out, "{}\r\n", (def.get)(&config())),
+                None => _ = out.push_str("unknown key\r\n"),
+            }
+            return;
+        }
+        (Some("params"), None, ..) => {
+            let config = config();
+            for def in params::PARAMS {
+                _ = write!(out, "{}: {}\r\n", def.name, (def.get)(&config));
+            }
+            return;
+        }
+        (Some("set"), Some(key), Some(value), None) => match set(config(), key, value) {
+            Some(new_config) => set_config(robot, new_config).await,
+            None => {
+                _ = out.push_str("unknown key or bad value\r\n");
+                return;
+            }
+        },
+        (Some("dump"), Some(what), None, _) => {
+            dump(what, out);
+            return;
+        }
+        (Some("clear"), Some("estop"), None, _) => clear_estop(),
+        (Some("log"), Some(category), Some(level), None) => {
+            match (Category::from_name(category), Level::from_name(level)) {
+                (Some(category), Some(level)) => {
+                    log::set_level(category, level);
+                    AckCode::Ok
+                }
+                _ => {
+                    _ = out.push_str("unknown category or level\r\n");
+                    return;
+                }
+            }
+        }
+        (Some("clear"), Some("overcurrent"), None, _) => {
+            current_limiter_mut(&mut *robot.lock().await).reset_trip();
+            AckCode::Ok
+        }
+        _ => {
+            _ = out.push_str("unknown command, try help\r\n");
+            return;
+        }
+    };
+
+    match code {
+        AckCode::Ok => {
+            _ = out.push_str("ok\r\n");
+        }
+        code => {
+            _ = write!(out, "error: {code:?}\r\n");
+        }
+    }
+}
+
+/// The [`Command`] of a motion shortcut, like the [`MecanumRobot`] ones.
+///
+/// [`MecanumRobot`]: rover_lib::MecanumRobot
+fn motion(name: &str, args: [Option<&str>; 3]) -> Option<Command> {
+    let mut numbers = [0.0f32; 3];
+    let mut count = 0;
+    for arg in args.into_iter().flatten() {
+        numbers[count] = arg.parse().ok()?;
+        count += 1;
+    }
+    let [a, b, c] = numbers;
+    let (p, th, tu) = match (name, count) {
+        ("drive", 3) => (a, b, c),
+        ("forward", 1) => (libm::fabsf(a), if a < 0.0 { 270.0 } else { 90.0 }, 0.0),
+        ("strafe", 2) => (a, b, 0.0),
+        ("rotate", 1) => (0.0, 90.0, a),
+        ("stop", 0) => (0.0, 90.0, 0.0),
+        _ => return None,
+    };
+    Some(Command {
+        p: MecanumPower::new(p),
+        th: Angle::new::<degree>(th),
+        tu: Turn::new(tu),
+    })
+}
+
+/// Sets a single [`Config`] value, `pid.*` sets it for all four wheels.
+/// `config` with the parameter `key` set to `value`, `pid.<gain>` setting
+/// that gain of every wheel.
+fn set(config: Config, key: &str, value: &str) -> Option<Config> {
+    if let Some(gain) = key.strip_prefix("pid.") {
+        return ["fl", "fr", "bl", "br"]
+            .iter()
+            .try_fold(config, |config, wheel| {
+                let mut key = String::<16>::new();
+                write!(key, "wheel.{wheel}.{gain}").ok()?;
+                set(config, &key, value)
+            });
+    }
+    let value = (params::find(key)?.get)(&config).parse_as(value)?;
+    params::apply(&config, key, value).ok()
+}
+
+fn dump(what: &str, out: &mut Out) {
+    _ = match what {
+        "odom" => match POSE.try_get() {
+            Some(pose) => write!(
+                out,
+                "x: {:.3} m, y: {:.3} m, heading: {:.1} deg\r\n",
+                pose.x.get::<meter>(),
+                pose.y.get::<meter>(),
+                pose.heading.get::<degree>()
+            ),
+            None => write!(out, "no odometry yet\r\n"),
+        },
+        "wheels" => match WHEELS.try_get() {
+            Some(wheels) => {
+                wheels
+                    .iter()
+                    .zip(["fl", "fr", "bl", "br"])
+                    .try_for_each(|(wheel, name)| {
+                        write!(
+                            out,
+                            "{name}: {} ticks, {:.1} deg/s\r\n",
+                            wheel.ticks,
+                            wheel
+                                .velocity
+                                .get::<uom::si::angular_velocity::degree_per_second>()
+                        )
+                    })
+            }
+            None => write!(out, "no encoder readings yet\r\n"),
+        },
+        "battery" => match BATTERY.try_get() {
+            Some(voltage) => write!(out, "{:.2} V\r\n", voltage.get::<volt>()),
+            None => write!(out, "no battery reading yet\r\n"),
+        },
+        _ => write!(out, "dump odom, wheels or battery\r\n"),
+    };
+}
@@ -0,0 +1,863 @@
+//! The long running tasks, and the state they share with the rest of the
+//! firmware.
+
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use defmt::{Debug2Format, Display2Format};
+use embassy_executor::task;
+use embassy_futures::select::{select, select4, Either, Either4};
+use embassy_sync::{
+    blocking_mutex::{
+        raw::{CriticalSectionRawMutex, NoopRawMutex},
+        Mutex as BlockingMutex,
+    },
+    mutex::Mutex,
+    signal,
+    watch::Watch,
+};
+use embassy_time::{Duration, Instant, Ticker, Timer};
+use uom::si::{
+    angle,
+    electric_potential::{millivolt, volt},
+    f32::{AngularVelocity, ElectricPotential, Length, Time},
+};
+#[cfg(feature = "current_sense")]
+use uom::si::{electric_current::ampere, f32::ElectricCurrent};
+
+#[cfg(not(feature = "ekf"))]
+use rover_lib::Odometry;
+use rover_lib::{
+    arming::ModeEvent, event_log::Event, mux::Source, Angle, AsyncMecanumRobot, Attitude,
+    BatteryMonitor, ComplementaryFilter, CurrentLimited, Encoder, Failsafe, FailsafeStage,
+    Heartbeats, Imu, LowVoltageAction, NeutralMode, Pose, ThermalDerating, TiltGuard,
+};
+#[cfg(any(feature = "closed_loop", feature = "current_sense"))]
+use rover_proto::TxMessage;
+use rover_proto::{AckCode, ResetCause};
+
+#[cfg(not(feature = "ekf"))]
+use crate::board::geometry;
+#[cfg(feature = "closed_loop")]
+use crate::board::wheels_mut;
+#[cfg(feature = "vl53l0x")]
+use crate::board::BoardTof;
+#[cfg(feature = "ultrasonic")]
+use crate::board::RangerPins;
+#[cfg(feature = "current_sense")]
+use crate::board::{current_limiter_mut, CURRENT_SENSE_V_PER_A};
+#[cfg(any(feature = "closed_loop", feature = "current_sense"))]
+use crate::comms::TX_QUEUE;
+use crate::{
+    arming,
+    board::{
+        adc_volts, drivetrain_mut, kill_motor_outputs, take_reset_cause, thermal_mut, Analog,
+        BoardImu, EdgeInput, Robot, Watchdog, WheelEncoder, Wheels, BATTERY_DIVIDER,
+    },
+    comms,
+    config::{self, config},
+    events::{self, BusEvent},
+    log::{debug, info, warn},
+};
+
+const ENCODER_PERIOD: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WheelReading {
+    pub ticks: i32,
+    pub angle: Angle,
+    pub velocity: AngularVelocity,
+}
+
+pub static WHEELS: Watch<CriticalSectionRawMutex, [WheelReading; 4], 4> = Watch::new();
+
+/// Always used on the RP2040, which has no quadrature decoder.
+#[cfg(any(feature = "encoder_exti", feature = "rp2040"))]
+#[task(pool_size = 4)]
+pub async fn exti_encoder_task(
+    mut a: EdgeInput,
+    mut b: EdgeInput,
+    counter: &'static rover_lib::encoder::ExtiCounter,
+) {
+    loop {
+        counter.on_edge(a.is_high(), b.is_high());
+        embassy_futures::select::select(a.wait_for_any_edge(), b.wait_for_any_edge()).await;
+    }
+}
+
+#[task]
+pub async fn encoder_task(mut encoders: [WheelEncoder; 4]) {
+    let sender = WHEELS.sender();
+    let mut ticker = Ticker::every(ENCODER_PERIOD);
+    let mut last = Instant::now();
+    HEARTBEATS.register(Beat::Encoders as u8);
+
+    loop {
+        ticker.next().await;
+        HEARTBEATS.beat(Beat::Encoders as u8);
+        let now = Instant::now();
+        let dt = Time::new::<uom::si::time::microsecond>((now - last).as_micros() as f32);
+        last = now;
+
+        let mut readings = [WheelReading::default(); 4];
+        for (encoder, reading) in encoders.iter_mut().zip(readings.iter_mut()) {
+            let Ok(()) = encoder.update(dt);
+            *reading = WheelReading {
+                ticks: encoder.ticks(),
+                angle: encoder.angle(),
+                velocity: encoder.angular_velocity(),
+            };
+        }
+        sender.send(readings);
+    }
+}
+
+#[cfg(feature = "closed_loop")]
+#[task]
+pub async fn velocity_task(robot: &'static Mutex<NoopRawMutex, Robot>) {
+    let Some(mut wheels) = WHEELS.receiver() else {
+        defmt::error!("no receiver left for wheel readings");
+        return;
+    };
+    let Some(mut config_changes) = config::CHANGES.receiver() else {
+        defmt::error!("no receiver left for config changes");
+        return;
+    };
+    let mut last = Instant::now();
+    let mut stalls = [false; 4];
+    HEARTBEATS.register(Beat::Velocity as u8);
+
+    loop {
+        let readings = wheels.changed().await;
+        HEARTBEATS.beat(Beat::Velocity as u8);
+        let now = Instant::now();
+        let dt = Time::new::<uom::si::time::microsecond>((now - last).as_micros() as f32);
+        last = now;
+
+        let mut robot = robot.lock().await;
+        let robot = wheels_mut(&mut robot);
+        if let Some(config) = config_changes.try_changed() {
+            robot.set_gains(config.wheel_gains);
+        }
+        _ = robot
+            .update(readings.map(|r| r.velocity), dt)
+            .inspect_err(|e| warn!(Drive, "velocity loop failed: {}", Debug2Format(e)));
+
+        let new_stalls = robot.stalls();
+        for (wheel, (&stalled, was_stalled)) in new_stalls.iter().zip(stalls).enumerate() {
+            if stalled && !was_stalled {
+                warn!(Drive, "wheel {} stalled", wheel);
+                events::record(Event::Stall { wheel: wheel as u8 });
+                _ = TX_QUEUE.try_send(TxMessage::Stall { wheel: wheel as u8 });
+            }
+        }
+        stalls = new_stalls;
+    }
+}
+
+#[cfg(not(feature = "ekf"))]
+const POSE_LOG_PERIOD: Duration = Duration::from_secs(1);
+
+/// From the odometry, or the estimator with `ekf`.
+pub static POSE: Watch<CriticalSectionRawMutex, Pose, 4> = Watch::new();
+
+#[cfg(not(feature = "ekf"))]
+#[task]
+pub async fn odometry_task() {
+    let Some(mut wheels) = WHEELS.receiver() else {
+        defmt::error!("no receiver left for wheel readings");
+        return;
+    };
+    let sender = POSE.sender();
+    let mut odometry = Odometry::new(geometry());
+    let mut last_log = Instant::now();
+
+    loop {
+        let readings = wheels.changed().await;
+        // The encoders count like their motors turn, backwards on inverted
+        // wheels
+        let mut angles = readings.map(|r| r.angle);
+        for (angle, inverted) in angles.iter_mut().zip(config().wheel_inverted) {
+            if inverted {
+                *angle = -*angle;
+            }
+        }
+        // Turning from the absolute heading, for navigation to share it
+        #[cfg(feature = "magnetometer")]
+        if crate::compass::ABSOLUTE_HEADING.load(Ordering::Relaxed) {
+            if let Some(attitude) = ATTITUDE.try_get() {
+                odometry.reset(Pose {
+                    heading: attitude.heading,
+                    ..odometry.pose()
+                });
+            }
+        }
+        let pose = odometry.update(angles);
+        sender.send(pose);
+
+        if last_log.elapsed() >= POSE_LOG_PERIOD {
+            last_log = Instant::now();
+            debug!(
+                Sensors,
+                "pose: x: {} m, y: {} m, heading: {} rad",
+                pose.x.get::<uom::si::length::meter>(),
+                pose.y.get::<uom::si::length::meter>(),
+                pose.heading.get::<angle::radian>()
+            );
+        }
+    }
+}
+
+const SLEW_PERIOD: Duration = Duration::from_millis(10);
+
+#[task]
+pub async fn slew_task(robot: &'static Mutex<NoopRawMutex, Robot>) {
+    let mut ticker = Ticker::every(SLEW_PERIOD);
+    let dt = Time::new::<uom::si::time::microsecond>(SLEW_PERIOD.as_micros() as f32);
+    HEARTBEATS.register(Beat::Slew as u8);
+
+    loop {
+        ticker.next().await;
+        HEARTBEATS.beat(Beat::Slew as u8);
+        let mut robot = robot.lock().await;
+        let drivetrain = drivetrain_mut(&mut robot);
+        let config = config();
+        drivetrain.set_rate(config.slew_rate);
+        drivetrain.set_soft_start_rate(config.soft_start_rate);
+        if swap_flag(&SOFT_START, false) {
+            drivetrain.soft_start();
+        }
+        _ = drivetrain
+            .update(dt)
+            .inspect_err(|e| warn!(Drive, "slew limiter failed to drive: {}", Debug2Format(e)));
+
+        // With current sensing the motors are heated by what they draw.
+        #[cfg(not(feature = "current_sense"))]
+        update_thermal(&mut robot, |thermal| thermal.update(dt));
+    }
+}
+
+/// Runs one step of the thermal estimate, logging when derating starts and
+/// stops.
+fn update_thermal<E: core::fmt::Debug>(
+    robot: &mut Robot,
+    update: impl FnOnce(&mut ThermalDerating<CurrentLimited<Wheels>>) -> Result<(), E>,
+) {
+    let thermal = thermal_mut(robot);
+    let was_derating = thermal.derating();
+    _ = update(thermal).inspect_err(|e| {
+        warn!(
+            Safety,
+            "thermal derating failed to drive: {}",
+            Debug2Format(e)
+        )
+    });
+    match (was_derating, thermal.derating()) {
+        (false, true) => warn!(Safety, "motors hot, derating to {}", thermal.ceilings()),
+        (true, false) => info!(Safety, "motors cooled down"),
+        _ => {}
+    }
+}
+
+#[task]
+pub async fn heading_hold_task(robot: &'static Mutex<NoopRawMutex, Robot>) {
+    let Some(mut attitude) = ATTITUDE.receiver() else {
+        defmt::error!("no receiver left for attitude");
+        return;
+    };
+    let mut last = Instant::now();
+
+    loop {
+        let heading = attitude.changed().await.heading;
+        let now = Instant::now();
+        let dt = Time::new::<uom::si::time::microsecond>((now - last).as_micros() as f32);
+        last = now;
+
+        let mut robot = robot.lock().await;
+        let gains = config().heading_gains;
+        if robot.gains() != gains {
+            robot.set_gains(gains);
+        }
+        _ = robot
+            .update_heading(heading, dt)
+            .inspect_err(|e| warn!(Drive, "heading hold failed to drive: {}", Debug2Format(e)));
+    }
+}
+
+const BATTERY_PERIOD: Duration = Duration::from_millis(100);
+const BATTERY_FILTER_ALPHA: f32 = 0.9;
+const BATTERY_HYSTERESIS_V: f32 = 0.2;
+/// Power and turn scale while the battery is low and the action is
+/// [`LowVoltageAction::LimitPower`].
+pub const LOW_BATTERY_POWER_SCALE: f32 = 0.5;
+
+pub static BATTERY: Watch<CriticalSectionRawMutex, ElectricPotential, 1> = Watch::new();
+pub static BATTERY_LOW: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "current_sense")]
+const CURRENT_SENSE_PERIOD: Duration = Duration::from_millis(10);
+
+/// Owns the ADC: samples the motor currents and, less often, the battery.
+#[task]
+pub async fn analog_task(mut analog: Analog, robot: &'static Mutex<NoopRawMutex, Robot>) {
+    let mut monitor = BatteryMonitor::new(
+        BATTERY_FILTER_ALPHA,
+        ElectricPotential::new::<volt>(BATTERY_HYSTERESIS_V),
+    );
+    #[cfg(feature = "current_sense")]
+    let (period, battery_every) = (
+        CURRENT_SENSE_PERIOD,
+        (BATTERY_PERIOD.as_ticks() / CURRENT_SENSE_PERIOD.as_ticks()) as u32,
+    );
+    #[cfg(not(feature = "current_sense"))]
+    let (period, battery_every) = (BATTERY_PERIOD, 1);
+    let mut ticker = Ticker::every(period);
+    HEARTBEATS.register(Beat::Analog as u8);
+
+    for tick in 0u32.. {
+        ticker.next().await;
+        HEARTBEATS.beat(Beat::Analog as u8);
+
+        #[cfg(feature = "current_sense")]
+        {
+            let currents = analog
+                .currents()
+                .await
+                .map(|raw| ElectricCurrent::new::<ampere>(adc_volts(raw) / CURRENT_SENSE_V_PER_A));
+            let dt = Time::new::<uom::si::time::microsecond>(period.as_micros() as f32);
+
+            let mut robot = robot.lock().await;
+            let limiter = current_limiter_mut(&mut robot);
+            limiter.set_limit(config().current_limit());
+            match limiter.update(currents, dt) {
+                Ok(Some(event)) => {
+                    warn!(Safety, "overcurrent on wheel {}", event.wheel);
+                    events::publish(BusEvent::OverCurrent { wheel: event.wheel });
+                    _ = TX_QUEUE.try_send(TxMessage::Overcurrent(event));
+                }
+                Ok(None) => {}
+                Err(e) => warn!(
+                    Safety,
+                    "current limiter failed to drive: {}",
+                    Debug2Format(&e)
+                ),
+            }
+
+            let rated = config().current_limit().limit.get::<ampere>();
+            let loads = currents.map(|current| current.get::<ampere>() / rated);
+            update_thermal(&mut robot, |thermal| thermal.update_with_loads(dt, loads));
+        }
+
+        if tick % battery_every != 0 {
+            continue;
+        }
+        let Some(raw) = analog.battery().await else {
+            warn!(Safety, "battery reading failed");
+            continue;
+        };
+        let measured = ElectricPotential::new::<volt>(adc_volts(raw) * BATTERY_DIVIDER);
+
+        let config = config();
+        let was_low = monitor.is_low();
+        let threshold = ElectricPotential::new::<millivolt>(config.battery_low_mv as f32);
+        let low = monitor.update(measured, threshold);
+        if let Some(voltage) = monitor.voltage() {
+            BATTERY.sender().send(voltage);
+        }
+        BATTERY_LOW.store(low, Ordering::Relaxed);
+
+        if low && !was_low {
+            warn!(Safety, "battery low: {} V", measured.get::<volt>());
+            events::publish(BusEvent::LowBattery);
+            if config.low_voltage_action == LowVoltageAction::Neutral {
+                if let Err(e) = robot.lock().await.neutral().await {
+                    warn!(
+                        Safety,
+                        "failed to stop on low battery: {}",
+                        Debug2Format(&e)
+                    );
+                }
+            }
+        } else if !low && was_low {
+            info!(Safety, "battery recovered");
+        }
+    }
+}
+
+/// Periodic tasks the watchdog waits on.
+#[derive(Debug, Clone, Copy)]
+enum Beat {
+    Encoders,
+    Slew,
+    #[cfg(feature = "closed_loop")]
+    Velocity,
+    Analog,
+}
+
+static HEARTBEATS: Heartbeats = Heartbeats::new();
+
+pub const WATCHDOG_TIMEOUT_US: u32 = 2_000_000;
+/// Every registered task must beat at least this often.
+const WATCHDOG_CHECK_PERIOD: Duration = Duration::from_millis(500);
+
+/// Stale heartbeats bitmap of the last watchdog reset, kept across it in
+/// RAM that isn't initialized at boot. Only valid after [`HUNG_MAGIC`].
+#[link_section = ".uninit.WATCHDOG"]
+static mut HUNG_TASKS: core::mem::MaybeUninit<[u32; 2]> = core::mem::MaybeUninit::uninit();
+const HUNG_MAGIC: u32 = 0x5741_5443;
+
+/// Runs on the same executor as everything else, so a stuck executor also
+/// stops the feeding.
+#[task]
+pub async fn watchdog_task(mut wdg: Watchdog) {
+    wdg.start();
+    let mut ticker = Ticker::every(WATCHDOG_CHECK_PERIOD);
+    loop {
+        ticker.next().await;
+        let stale = HEARTBEATS.stale();
+        if stale == 0 {
+            wdg.feed();
+            continue;
+        }
+
+        defmt::error!("hung tasks {=u32:b}, waiting for the watchdog", stale);
+        // SAFETY: only written here, read once at boot before this task runs
+        unsafe {
+            core::ptr::addr_of_mut!(HUNG_TASKS)
+                .cast::<[u32; 2]>()
+                .write_volatile([HUNG_MAGIC, stale])
+        };
+    }
+}
+
+/// What the last reset came from, set once at boot by [`log_reset_cause`].
+pub static RESET_CAUSE: Watch<CriticalSectionRawMutex, ResetCause, 1> = Watch::new();
+
+/// Logs and records what the last reset came from, and which tasks were hung
+/// if it was the watchdog.
+pub fn log_reset_cause() {
+    // SAFETY: the watchdog task isn't running yet
+    let [magic, hung] = unsafe {
+        let hung = core::ptr::addr_of_mut!(HUNG_TASKS).cast::<[u32; 2]>();
+        let value = hung.read_volatile();
+        hung.write_volatile([0; 2]);
+        value
+    };
+
+    let cause = take_reset_cause();
+    RESET_CAUSE.sender().send(cause);
+    match cause {
+        ResetCause::Watchdog => {
+            events::record(Event::WatchdogReset);
+            if magic == HUNG_MAGIC {
+                warn!(Safety, "reset by the watchdog, hung tasks {=u32:b}", hung);
+            } else {
+                warn!(Safety, "reset by the watchdog");
+            }
+        }
+        ResetCause::Brownout => {
+            events::record(Event::BrownoutReset);
+            warn!(Safety, "reset by a brownout");
+        }
+        cause => info!(Safety, "reset cause: {}", Debug2Format(&cause)),
+    }
+}
+
+const IMU_PERIOD: Duration = Duration::from_millis(10);
+const GYRO_CALIBRATION_SAMPLES: u32 = 200;
+const ATTITUDE_FILTER_ALPHA: f32 = 0.98;
+
+pub static ATTITUDE: Watch<CriticalSectionRawMutex, Attitude, 4> = Watch::new();
+/// Set while tilted past [`Config::tilt`], drive commands being refused.
+///
+/// [`Config::tilt`]: rover_proto::Config::tilt
+pub static TILTED: AtomicBool = AtomicBool::new(false);
+/// Signaled once by [`imu_task`]: whether the IMU came up and is calibrated.
+pub static IMU_READY: signal::Signal<CriticalSectionRawMutex, bool> = signal::Signal::new();
+
+/// Keeps the attitude estimate up to date. The robot must stand still for
+/// the first couple of seconds, while the gyro bias is measured.
+#[task]
+pub async fn imu_task(mut imu: BoardImu) {
+    if let Err(e) = imu.init().await {
+        warn!(Sensors, "imu not available: {}", Display2Format(&e));
+        IMU_READY.signal(false);
+        return;
+    }
+
+    let mut filter = ComplementaryFilter::new(ATTITUDE_FILTER_ALPHA);
+    let mut ticker = Ticker::every(IMU_PERIOD);
+
+    let mut bias = [0.0f32; 3];
+    let mut samples = 0;
+    for _ in 0..GYRO_CALIBRATION_SAMPLES {
+        ticker.next().await;
+        if let Ok(reading) = imu.read().await {
+            bias.iter_mut().zip(reading.gyro).for_each(|(b, g)| *b += g);
+            samples += 1;
+        }
+    }
+    filter.set_gyro_bias(bias.map(|b| b / samples.max(1) as f32));
+    info!(Sensors, "imu calibrated over {} samples", samples);
+    IMU_READY.signal(samples > 0);
+
+    let sender = ATTITUDE.sender();
+    let mut guard = TiltGuard::new(config().tilt);
+    let mut last = Instant::now();
+
+    loop {
+        ticker.next().await;
+        let now = Instant::now();
+        let dt = Time::new::<uom::si::time::microsecond>((now - last).as_micros() as f32);
+        last = now;
+
+        let attitude = match imu.read().await {
+            Ok(reading) => filter.update(reading, dt),
+            Err(e) => {
+                warn!(Sensors, "imu read failed: {}", Display2Format(&e));
+                continue;
+            }
+        };
+        #[cfg(feature = "magnetometer")]
+        let attitude = {
+            crate::compass::fuse(&mut filter, &attitude);
+            filter.attitude()
+        };
+        sender.send(attitude);
+
+        guard.set_params(config().tilt);
+        let tilt = attitude.tilt();
+        match guard.update(tilt, IMU_PERIOD.as_millis() as u32) {
+            Some(true) => {
+                defmt::error!(
+                    "tilted {} degrees, cutting the drive",
+                    tilt.get::<angle::degree>()
+                );
+                TILTED.store(true, Ordering::Relaxed);
+                SOFT_START.store(true, Ordering::Relaxed);
+                TILT_CUT.signal(());
+                events::publish(BusEvent::Tilted);
+            }
+            Some(false) => {
+                TILTED.store(false, Ordering::Relaxed);
+                info!(Safety, "level again");
+            }
+            None => {}
+        }
+    }
+}
+
+/// Latest distance seen by each ultrasonic ranger, front, right, back, left.
+/// Never sent without `ultrasonic`.
+pub static RANGES: Watch<CriticalSectionRawMutex, [Option<Length>; 4], 4> = Watch::new();
+
+/// Latest VL53L0X reading, `None` for nothing in range. Never sent without
+/// `vl53l0x`.
+pub static TOF_RANGE: Watch<CriticalSectionRawMutex, Option<Length>, 4> = Watch::new();
+
+/// Distance to the nearest obstacle ahead, from whichever front sensors are
+/// fitted.
+pub fn front_distance() -> Option<Length> {
+    let ultrasonic = RANGES.try_get().and_then(|ranges| ranges[0]);
+    let tof = TOF_RANGE.try_get().flatten();
+    match (ultrasonic, tof) {
+        (Some(ultrasonic), Some(tof)) => Some(if tof < ultrasonic { tof } else { ultrasonic }),
+        (ultrasonic, tof) => ultrasonic.or(tof),
+    }
+}
+
+/// A bit over the sensor's measurement time.
+#[cfg(feature = "vl53l0x")]
+const TOF_PERIOD: Duration = Duration::from_millis(35);
+
+#[cfg(feature = "vl53l0x")]
+#[task]
+pub async fn tof_task(mut tof: BoardTof) {
+    if let Err(e) = tof.init().await {
+        warn!(
+            Sensors,
+            "tof rangefinder not available: {}",
+            Display2Format(&e)
+        );
+        return;
+    }
+
+    let sender = TOF_RANGE.sender();
+    let mut ticker = Ticker::every(TOF_PERIOD);
+    loop {
+        ticker.next().await;
+        match tof.read().await {
+            Ok(range) => sender.send(range),
+            Err(rover_lib::tof::TofError::NotReady) => {}
+            Err(e) => warn!(Sensors, "tof read failed: {}", Display2Format(&e)),
+        }
+    }
+}
+
+/// Rangers take turns, so none hears another's echo.
+#[cfg(feature = "ultrasonic")]
+const RANGER_PERIOD: Duration = Duration::from_millis(30);
+/// The echo starts half a millisecond or so after the trigger.
+#[cfg(feature = "ultrasonic")]
+const ECHO_START_TIMEOUT: Duration = Duration::from_millis(5);
+
+/// Echoes are timed with the tick, so to 30 µs or 5 mm.
+#[cfg(feature = "ultrasonic")]
+#[task]
+pub async fn ranger_task(mut rangers: [RangerPins; 4]) {
+    let sender = RANGES.sender();
+    let mut ranges = [None; 4];
+    let mut ticker = Ticker::every(RANGER_PERIOD);
+
+    loop {
+        for (range, ranger) in ranges.iter_mut().zip(&mut rangers) {
+            ticker.next().await;
+            *range = ping(ranger).await;
+            sender.send(ranges);
+        }
+    }
+}
+
+#[cfg(feature = "ultrasonic")]
+async fn ping(ranger: &mut RangerPins) -> Option<Length> {
+    use embassy_time::with_timeout;
+    use rover_lib::ultrasonic;
+
+    // Still echoing the last ping, which would pass for this one
+    if ranger.echo.is_high() {
+        return None;
+    }
+    ranger.trigger.set_high();
+    Timer::after_micros(ultrasonic::TRIGGER_PULSE_US).await;
+    ranger.trigger.set_low();
+
+    with_timeout(ECHO_START_TIMEOUT, ranger.echo.wait_for_high())
+        .await
+        .ok()?;
+    let start = Instant::now();
+    with_timeout(
+        Duration::from_micros(ultrasonic::MAX_ECHO_US),
+        ranger.echo.wait_for_low(),
+    )
+    .await
+    .ok()?;
+    ultrasonic::echo_to_distance((Instant::now() - start).as_micros())
+}
+
+/// Whether the safety timer has put the robot in neutral since the last
+/// message.
+pub static SAFETY_TRIPPED: AtomicBool = AtomicBool::new(true);
+
+/// Set when the safety timer or the e-stop stopped the robot, for the slew
+/// task to soft start the next commands.
+static SOFT_START: AtomicBool = AtomicBool::new(false);
+
+/// Latched when the e-stop input trips, drive commands are refused until it's
+/// cleared.
+pub static ESTOP: AtomicBool = AtomicBool::new(false);
+/// Current level of the e-stop input, it can't be cleared while still active.
+static ESTOP_ACTIVE: AtomicBool = AtomicBool::new(false);
+/// The e-stop switch is normally closed to ground, so a cut wire stops the
+/// robot too.
+#[task]
+pub async fn estop_task(mut input: EdgeInput) {
+    loop {
+        input.wait_for_high().await;
+        ESTOP_ACTIVE.store(true, Ordering::Relaxed);
+        trip_estop();
+
+        input.wait_for_low().await;
+        ESTOP_ACTIVE.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Wakes the safety timer to brake. Unlike the bus, which drops events for a
+/// subscriber lagging behind, it can't be missed.
+static ESTOP_BRAKE: signal::Signal<SafetyMutex, ()> = signal::Signal::new();
+/// Wakes the safety timer to cut the drive, like [`ESTOP_BRAKE`].
+static TILT_CUT: signal::Signal<SafetyMutex, ()> = signal::Signal::new();
+
+/// Latches the e-stop, from the input or the host, and has the robot braked.
+pub fn trip_estop() {
+    ESTOP.store(true, Ordering::Relaxed);
+    comms::hold(Source::Estop);
+    defmt::error!("emergency stop");
+    SOFT_START.store(true, Ordering::Relaxed);
+    ESTOP_BRAKE.signal(());
+    events::publish(BusEvent::EstopPressed);
+}
+
+pub fn clear_estop() -> AckCode {
+    if ESTOP_ACTIVE.load(Ordering::Relaxed) {
+        return AckCode::Estopped;
+    }
+    if swap_flag(&ESTOP, false) {
+        comms::release(Source::Estop);
+        _ = arming::handle(ModeEvent::EstopCleared);
+        info!(Safety, "e-stop cleared");
+    }
+    AckCode::Ok
+}
+
+/// [`AtomicBool::swap`], which the Cortex-M0+ doesn't have.
+fn swap_flag(flag: &AtomicBool, value: bool) -> bool {
+    critical_section::with(|_| {
+        let old = flag.load(Ordering::Relaxed);
+        flag.store(value, Ordering::Relaxed);
+        old
+    })
+}
+
+pub type SafetyMutex = CriticalSectionRawMutex;
+
+/// Steps through the [`failsafe`] stages while the host is quiet. With a
+/// [`Config::safety_ramp_ms`], the wheels ramp down before the stop, unless
+/// the host comes back first. Brakes on [`trip_estop`] too, and coasts when
+/// tilted.
+///
+/// [`failsafe`]: rover_lib::failsafe
+/// [`Config::safety_ramp_ms`]: rover_proto::Config::safety_ramp_ms
+#[task]
+pub async fn safety_timer(
+    robot: &'static Mutex<NoopRawMutex, Robot>,
+    sig: &'static signal::Signal<SafetyMutex, ()>,
+) {
+    let mut events = events::subscribe();
+    let mut failsafe = Failsafe::new();
+    let mut heard = Instant::now();
+    // Once stopped, the stop is repeated every timeout
+    let mut stopped_at = None;
+    // When the ramp down ends, while there is one
+    let mut ramp_end = None;
+    loop {
+        let config = config();
+        let timeout = Duration::from_millis(config.safety_timeout_ms as u64);
+        let next_stage = failsafe
+            .next_ms(&config.failsafe, config.safety_timeout_ms)
+            .map(|ms| heard + Duration::from_millis(ms as u64));
+        let wake = [ramp_end, next_stage, stopped_at.map(|at| at + timeout)]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(Instant::MAX);
+        match select4(
+            Timer::at(wake),
+            sig.wait(),
+            select(ESTOP_BRAKE.wait(), TILT_CUT.wait()),
+            events.next_message_pure(),
+        )
+        .await
+        {
+            Either4::First(()) => {
+                let now = Instant::now();
+                if ramp_end.is_some_and(|end| end <= now) {
+                    ramp_end = None;
+                    stop(robot, config.safety_stop).await;
+                    stopped_at = Some(now);
+                    continue;
+                }
+                let quiet_ms = (now - heard).as_millis();
+                let Some(stage) =
+                    failsafe.update(&config.failsafe, config.safety_timeout_ms, quiet_ms)
+                else {
+                    if failsafe.stage() >= FailsafeStage::Stopped {
+                        stop(robot, config.safety_stop).await;
+                        stopped_at = Some(now);
+                    }
+                    continue;
+                };
+                FAILSAFE.lock(|cell| cell.set(stage));
+                events::publish(BusEvent::Failsafe(stage));
+                if stage == FailsafeStage::Slowed {
+                    drivetrain_mut(&mut *robot.lock().await).set_scale(config.failsafe.slow_power);
+                    continue;
+                }
+                if !swap_flag(&SAFETY_TRIPPED, true) {
+                    events::publish(BusEvent::LinkLost);
+                    SOFT_START.store(true, Ordering::Relaxed);
+                    if stage == FailsafeStage::Stopped && config.safety_ramp_ms > 0 {
+                        let ramp_ms = config.safety_ramp_ms;
+                        let mut robot = robot.lock().await;
+                        drivetrain_mut(&mut robot)
+                            .ramp_down(Time::new::<uom::si::time::millisecond>(ramp_ms as f32));
+                        ramp_end = Some(now + Duration::from_millis(ramp_ms as u64));
+                        continue;
+                    }
+                }
+                ramp_end = None;
+                stop(robot, config.safety_stop).await;
+                stopped_at = Some(now);
+            }
+            Either4::Second(()) => {
+                heard = Instant::now();
+                stopped_at = None;
+                SAFETY_TRIPPED.store(false, Ordering::Relaxed);
+                if failsafe.reset() != FailsafeStage::Linked {
+                    FAILSAFE.lock(|cell| cell.set(FailsafeStage::Linked));
+                    events::publish(BusEvent::Failsafe(FailsafeStage::Linked));
+                    let mut robot = robot.lock().await;
+                    let drivetrain = drivetrain_mut(&mut robot);
+                    drivetrain.set_scale(1.0);
+                    if ramp_end.take().is_some() {
+                        drivetrain.resume();
+                    }
+                }
+            }
+            Either4::Third(cut) => {
+                let mode = match cut {
+                    Either::First(()) => NeutralMode::Brake,
+                    Either::Second(()) => NeutralMode::Coast,
+                };
+                ramp_end = None;
+                stop(robot, mode).await;
+            }
+            Either4::Fourth(BusEvent::Disarmed) => {
+                ramp_end = None;
+                stop(robot, config.safety_stop).await;
+            }
+            Either4::Fourth(_) => {}
+        }
+    }
+}
+
+static FAILSAFE: BlockingMutex<CriticalSectionRawMutex, Cell<FailsafeStage>> =
+    BlockingMutex::new(Cell::new(FailsafeStage::Linked));
+
+pub fn failsafe_stage() -> FailsafeStage {
+    FAILSAFE.lock(Cell::get)
+}
+
+/// Retries [`STOP_ATTEMPTS`] times, then cuts the motor outputs and latches
+/// [`FAULT`].
+async fn stop<R: AsyncMecanumRobot>(robot: &Mutex<NoopRawMutex, R>, mode: NeutralMode) {
+    let mut robot = robot.lock().await;
+    for attempt in 1..=STOP_ATTEMPTS {
+        let result = match mode {
+            NeutralMode::Coast => robot.neutral().await,
+            NeutralMode::Brake => robot.brake().await,
+        };
+        match result {
+            Ok(()) => return,
+            Err(e) => warn!(
+                Safety,
+                "stop attempt {} failed: {}",
+                attempt,
+                Debug2Format(&e)
+            ),
+        }
+    }
+    if !swap_flag(&FAULT, true) {
+        defmt::error!("failed to stop robot, cutting motor outputs");
+        kill_motor_outputs();
+        events::record(Event::Fault);
+        _ = arming::handle(ModeEvent::Fault);
+    }
+}
+
+const STOP_ATTEMPTS: u32 = 3;
+
+/// Latched when the robot couldn't be stopped and the motor outputs were cut
+/// at the timer, only a reset clears it.
+pub static FAULT: AtomicBool = AtomicBool::new(false);
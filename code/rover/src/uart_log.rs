@@ -0,0 +1,100 @@
+//! defmt logger sending each log frame over the host link as
+//! [`TxMessage::Log`], in place of RTT for when no probe is attached. The
+//! frames still need the firmware ELF to decode:
+//! `rover_ctl <port> log | defmt-print -e <elf>`.
+//!
+//! Frames are dropped rather than wait for room in [`TX_QUEUE`], and so are
+//! those too long for a [`LogFrame`].
+
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use critical_section::RestoreState;
+use rover_proto::{LogFrame, TxMessage};
+
+use crate::comms::TX_QUEUE;
+
+#[defmt::global_logger]
+struct Logger;
+
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Only touched between `acquire` and `release`, inside a critical section.
+struct State {
+    restore: RestoreState,
+    encoder: defmt::Encoder,
+    frame: LogFrame,
+    overflowed: bool,
+}
+
+struct Shared(UnsafeCell<State>);
+
+// Guarded by the critical section held from `acquire` to `release`
+unsafe impl Sync for Shared {}
+
+static STATE: Shared = Shared(UnsafeCell::new(State {
+    restore: RestoreState::invalid(),
+    encoder: defmt::Encoder::new(),
+    frame: LogFrame::new(),
+    overflowed: false,
+}));
+
+fn push(frame: &mut LogFrame, overflowed: &mut bool, bytes: &[u8]) {
+    if frame.extend_from_slice(bytes).is_err() {
+        *overflowed = true;
+    }
+}
+
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {
+        let restore = unsafe { critical_section::acquire() };
+        if TAKEN.load(Ordering::Relaxed) {
+            panic!("defmt logger taken reentrantly")
+        }
+        TAKEN.store(true, Ordering::Relaxed);
+
+        let state = unsafe { &mut *STATE.0.get() };
+        state.restore = restore;
+        state.frame.clear();
+        state.overflowed = false;
+        let State {
+            encoder,
+            frame,
+            overflowed,
+            ..
+        } = state;
+        encoder.start_frame(|b| push(frame, overflowed, b));
+    }
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {
+        let state = unsafe { &mut *STATE.0.get() };
+        let State {
+            encoder,
+            frame,
+            overflowed,
+            ..
+        } = state;
+        encoder.end_frame(|b| push(frame, overflowed, b));
+        if !state.overflowed {
+            _ = TX_QUEUE.try_send(TxMessage::Log(state.frame.clone()));
+        }
+
+        TAKEN.store(false, Ordering::Relaxed);
+        unsafe { critical_section::release(state.restore) };
+    }
+
+    unsafe fn write(bytes: &[u8]) {
+        let state = unsafe { &mut *STATE.0.get() };
+        let State {
+            encoder,
+            frame,
+            overflowed,
+            ..
+        } = state;
+        encoder.write(bytes, |b| push(frame, overflowed, b));
+    }
+}
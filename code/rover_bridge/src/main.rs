@@ -0,0 +1,230 @@
+//! Drives the rover over Wi-Fi: relays UDP datagrams to the rover host UART
+//! and back.
+//!
+//! Each datagram carries one unencoded frame, the bridge adds and strips the
+//! COBS framing. The rover doesn't know the difference, and a dead link trips
+//! its safety timer like a dead cable.
+//!
+//! Frames from the rover go to whoever sent the last datagram, none are sent
+//! before that.
+//!
+//! Set `SSID` and `PASSWORD` in the environment when building. Wiring: GPIO21
+//! (TX) to the rover RX, GPIO20 (RX) to the rover TX.
+
+#![no_std]
+#![no_main]
+
+use core::cell::Cell;
+
+use embassy_executor::Spawner;
+use embassy_net::{
+    udp::{PacketMetadata, UdpSocket},
+    IpEndpoint, Runner, StackResources,
+};
+use embassy_time::{Duration, Timer};
+use embedded_io_async::{Read, Write};
+use esp_backtrace as _;
+use esp_hal::{
+    rng::Rng,
+    timer::{systimer::SystemTimer, timg::TimerGroup},
+    uart::{self, Uart, UartRx, UartTx},
+    Async,
+};
+use esp_wifi::{
+    wifi::{
+        ClientConfiguration, Configuration, WifiController, WifiDevice, WifiEvent, WifiStaDevice,
+        WifiState,
+    },
+    EspWifiController,
+};
+use log::{info, warn};
+use static_cell::StaticCell;
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+
+const PORT: u16 = 4242;
+
+/// Largest frame either way, the rover takes at most 128 bytes.
+const FRAME_SIZE: usize = 256;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[esp_hal_embassy::main]
+async fn main(spawner: Spawner) {
+    esp_println::logger::init_logger_from_env();
+    let peripherals = esp_hal::init(esp_hal::Config::default());
+    esp_alloc::heap_allocator!(72 * 1024);
+
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    let mut rng = Rng::new(peripherals.RNG);
+    let seed = (rng.random() as u64) << 32 | rng.random() as u64;
+
+    static WIFI: StaticCell<EspWifiController<'static>> = StaticCell::new();
+    let wifi = WIFI.init(esp_wifi::init(timg0.timer0, rng, peripherals.RADIO_CLK).unwrap());
+    let (device, controller) =
+        esp_wifi::wifi::new_with_mode(wifi, peripherals.WIFI, WifiStaDevice).unwrap();
+
+    let systimer =
+        SystemTimer::new(peripherals.SYSTIMER).split::<esp_hal::timer::systimer::Target>();
+    esp_hal_embassy::init(systimer.alarm0);
+
+    static RESOURCES: StaticCell<StackResources<3>> = StaticCell::new();
+    let (stack, runner) = embassy_net::new(
+        device,
+        embassy_net::Config::dhcpv4(Default::default()),
+        RESOURCES.init(StackResources::new()),
+        seed,
+    );
+    spawner.spawn(connection_task(controller)).unwrap();
+    spawner.spawn(net_task(runner)).unwrap();
+
+    let uart = Uart::new_with_config(
+        peripherals.UART1,
+        uart::Config::default(),
+        peripherals.GPIO20,
+        peripherals.GPIO21,
+    )
+    .unwrap()
+    .into_async();
+    let (rx, tx) = uart.split();
+
+    stack.wait_config_up().await;
+    if let Some(config) = stack.config_v4() {
+        info!("listening on {}:{}", config.address.address(), PORT);
+    }
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; 4 * FRAME_SIZE];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0; 4 * FRAME_SIZE];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(PORT).unwrap();
+
+    let peer = Cell::new(None);
+    embassy_futures::join::join(
+        udp_to_uart(&socket, tx, &peer),
+        uart_to_udp(&socket, rx, &peer),
+    )
+    .await;
+}
+
+async fn udp_to_uart(
+    socket: &UdpSocket<'_>,
+    mut tx: UartTx<'static, Async>,
+    peer: &Cell<Option<IpEndpoint>>,
+) {
+    let mut frame = [0u8; FRAME_SIZE];
+    let mut encoded = [0u8; FRAME_SIZE + FRAME_SIZE / 254 + 2];
+
+    loop {
+        let (n, meta) = match socket.recv_from(&mut frame).await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("udp receive failed: {:?}", e);
+                continue;
+            }
+        };
+        if peer.replace(Some(meta.endpoint)) != Some(meta.endpoint) {
+            info!("driven from {}", meta.endpoint);
+        }
+
+        let max = encoded.len() - 1;
+        let Ok(len) = cobs::try_encode(&frame[..n], &mut encoded[..max]) else {
+            warn!("frame too long, dropped");
+            continue;
+        };
+        encoded[len] = 0;
+        if let Err(e) = tx.write_all(&encoded[..=len]).await {
+            warn!("uart write failed: {:?}", e);
+        }
+    }
+}
+
+async fn uart_to_udp(
+    socket: &UdpSocket<'_>,
+    mut rx: UartRx<'static, Async>,
+    peer: &Cell<Option<IpEndpoint>>,
+) {
+    let mut buf = [0u8; 64];
+    let mut encoded = [0u8; FRAME_SIZE + FRAME_SIZE / 254 + 1];
+    // Keeps counting past the buffer, to drop the whole frame
+    let mut len = 0;
+
+    loop {
+        let n = match rx.read(&mut buf).await {
+            Ok(n) => n,
+            Err(e) => {
+                warn!("uart read failed: {:?}", e);
+                continue;
+            }
+        };
+
+        for &byte in &buf[..n] {
+            if byte != 0 {
+                if let Some(slot) = encoded.get_mut(len) {
+                    *slot = byte;
+                }
+                len += 1;
+                continue;
+            }
+
+            let encoded = match encoded.get_mut(..core::mem::take(&mut len)) {
+                Some(encoded) if !encoded.is_empty() => encoded,
+                Some(_) => continue,
+                None => {
+                    warn!("frame from the rover too long, dropped");
+                    continue;
+                }
+            };
+            let Ok(decoded) = cobs::decode_in_place(encoded) else {
+                warn!("bad frame from the rover, dropped");
+                continue;
+            };
+            let Some(peer) = peer.get() else {
+                continue;
+            };
+            if let Err(e) = socket.send_to(&encoded[..decoded], peer).await {
+                warn!("udp send failed: {:?}", e);
+            }
+        }
+    }
+}
+
+#[embassy_executor::task]
+async fn connection_task(mut controller: WifiController<'static>) {
+    loop {
+        if esp_wifi::wifi::wifi_state() == WifiState::StaConnected {
+            controller.wait_for_event(WifiEvent::StaDisconnected).await;
+            warn!("wifi disconnected");
+            Timer::after(RECONNECT_DELAY).await;
+        }
+        if !matches!(controller.is_started(), Ok(true)) {
+            let config = Configuration::Client(ClientConfiguration {
+                ssid: SSID.try_into().unwrap(),
+                password: PASSWORD.try_into().unwrap(),
+                ..Default::default()
+            });
+            controller.set_configuration(&config).unwrap();
+            controller.start_async().await.unwrap();
+        }
+
+        match controller.connect_async().await {
+            Ok(()) => info!("wifi connected to {}", SSID),
+            Err(e) => {
+                warn!("wifi connection failed: {:?}", e);
+                Timer::after(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+#[embassy_executor::task]
+async fn net_task(mut runner: Runner<'static, WifiDevice<'static, WifiStaDevice>>) {
+    runner.run().await
+}
@@ -0,0 +1,104 @@
+//! WASD from the terminal. A terminal only reports key presses, repeated
+//! while held, so a key counts as held until its repeats stop.
+
+use std::{
+    io::{self, Read},
+    os::fd::AsRawFd,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::protocol::Sticks;
+
+/// Longer than the delay before the terminal starts repeating a key.
+const HOLD: Duration = Duration::from_millis(550);
+/// Keys go less than full power, they have no in between.
+const KEY_POWER: f32 = 0.6;
+
+/// Puts the terminal in raw mode until dropped.
+pub struct Keyboard {
+    saved: libc::termios,
+    keys: mpsc::Receiver<u8>,
+    /// When each of `wasdqe` was last pressed.
+    pressed: [Option<Instant>; 6],
+    quit: bool,
+}
+
+impl Keyboard {
+    pub fn new() -> io::Result<Self> {
+        let fd = io::stdin().as_raw_fd();
+        // SAFETY: stdin stays open, and `saved` is only used after
+        // `tcgetattr` filled it
+        let saved = unsafe {
+            let mut saved = core::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(fd, &mut saved) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = saved;
+            // Ctrl-C included, to quit through `Drop`
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            saved
+        };
+
+        let (tx, keys) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                let Ok(byte) = byte else { return };
+                if tx.send(byte.to_ascii_lowercase()).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(Self {
+            saved,
+            keys,
+            pressed: [None; 6],
+            quit: false,
+        })
+    }
+
+    /// Escape, `x` or Ctrl-C.
+    pub fn quit(&self) -> bool {
+        self.quit
+    }
+
+    pub fn sticks(&mut self) -> Sticks {
+        let now = Instant::now();
+        while let Ok(key) = self.keys.try_recv() {
+            match key {
+                0x1b | b'x' | 0x03 => self.quit = true,
+                // Space lets go of everything at once
+                b' ' => self.pressed = [None; 6],
+                _ => {
+                    if let Some(i) = b"wasdqe".iter().position(|&k| k == key) {
+                        self.pressed[i] = Some(now);
+                    }
+                }
+            }
+        }
+
+        let held = self
+            .pressed
+            .map(|at| at.is_some_and(|at| now.duration_since(at) < HOLD));
+        let axis = |plus: bool, minus: bool| KEY_POWER * (plus as u8 as f32 - minus as u8 as f32);
+        let [w, a, s, d, q, e] = held;
+        Sticks {
+            x: axis(d, a),
+            y: axis(w, s),
+            rot: axis(e, q),
+        }
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        // SAFETY: same as in `new`
+        unsafe { libc::tcsetattr(io::stdin().as_raw_fd(), libc::TCSANOW, &self.saved) };
+    }
+}
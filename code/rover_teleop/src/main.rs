@@ -0,0 +1,136 @@
+//! Teleop from a host: WASD in the terminal, or the sticks of a gamepad,
+//! sent to the rover over its serial link.
+//!
+//! `rover_teleop [--binary] [--baud <rate>] [--id <rover id>] <port>`
+//!
+//! `--id` picks one of the rovers sharing the bus, 0 by default.
+//!
+//! Keys: `w`/`s` forward and back, `a`/`d` strafe, `q`/`e` turn, space to
+//! let go of everything and escape, `x` or Ctrl-C to quit. Gamepad: left stick to
+//! move, right one to turn.
+//!
+//! Commands repeat at a fixed rate while the controls are held, keeping the
+//! rover's claim alive. Once they're released a stop goes out and then
+//! nothing, so the safety timer takes over and the rover is free for other
+//! sources. Should teleop die instead, the safety timer stops the rover all
+//! the same.
+
+mod keyboard;
+mod protocol;
+
+use std::{
+    io::Write,
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant},
+};
+
+use gilrs::{Axis, Gilrs};
+use rover_proto::framing;
+
+use keyboard::Keyboard;
+use protocol::{Encoding, Sticks};
+
+/// Well within the default safety timeout.
+const PERIOD: Duration = Duration::from_millis(50);
+/// Gamepad sticks never quite center, the rover shapes the rest.
+const STICK_DEADZONE: f32 = 0.1;
+
+fn main() -> ExitCode {
+    let mut encoding = Encoding::Json;
+    let mut baud = 115_200;
+    let mut id = 0;
+    let mut port = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--binary" => encoding = Encoding::Binary,
+            "--baud" => match args.next().and_then(|b| b.parse().ok()) {
+                Some(b) => baud = b,
+                None => return usage(),
+            },
+            "--id" => match args.next().and_then(|id| id.parse().ok()) {
+                Some(arg) if arg != framing::BROADCAST => id = arg,
+                _ => return usage(),
+            },
+            _ if port.is_none() => port = Some(arg),
+            _ => return usage(),
+        }
+    }
+    let Some(port) = port else {
+        return usage();
+    };
+
+    let mut serial = match serialport::new(&port, baud)
+        .timeout(Duration::from_millis(100))
+        .open()
+    {
+        Ok(serial) => serial,
+        Err(e) => {
+            eprintln!("failed to open {port}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    // Not fatal, the keyboard is enough
+    let mut gilrs = Gilrs::new()
+        .inspect_err(|e| eprintln!("no gamepads: {e}"))
+        .ok();
+    let mut keyboard = match Keyboard::new() {
+        Ok(keyboard) => keyboard,
+        Err(e) => {
+            eprintln!("failed to set up the terminal: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut released = true;
+    let mut next = Instant::now();
+    while !keyboard.quit() {
+        let keys = keyboard.sticks();
+        let pad = gilrs.as_mut().map(gamepad).unwrap_or_default();
+        let sticks = if pad.is_neutral() { keys } else { pad };
+
+        // Held, or just released: one last stop
+        if !sticks.is_neutral() || !released {
+            let Some(packet) = protocol::encode(sticks, encoding, id) else {
+                eprintln!("failed to encode");
+                return ExitCode::FAILURE;
+            };
+            if let Err(e) = serial.write_all(&packet) {
+                eprintln!("failed to send: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+        released = sticks.is_neutral();
+
+        next += PERIOD;
+        thread::sleep(next.saturating_duration_since(Instant::now()));
+    }
+    ExitCode::SUCCESS
+}
+
+fn usage() -> ExitCode {
+    eprintln!("usage: rover_teleop [--binary] [--baud <rate>] [--id <rover id>] <port>");
+    ExitCode::FAILURE
+}
+
+/// The first connected gamepad, neutral without any.
+fn gamepad(gilrs: &mut Gilrs) -> Sticks {
+    while gilrs.next_event().is_some() {}
+    let Some((_, pad)) = gilrs.gamepads().next() else {
+        return Sticks::default();
+    };
+    let axis = |axis| {
+        let value = pad.value(axis);
+        if value.abs() < STICK_DEADZONE {
+            0.0
+        } else {
+            value
+        }
+    };
+    Sticks {
+        x: axis(Axis::LeftStickX),
+        y: axis(Axis::LeftStickY),
+        rot: axis(Axis::RightStickX),
+    }
+}
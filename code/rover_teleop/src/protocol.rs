@@ -0,0 +1,47 @@
+//! Teleop's side of the host link: unsequenced [`RxBody::Sticks`].
+
+use rover_proto::{framing, RxBody, RxMessage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Sticks {
+    /// Strafes right.
+    pub x: f32,
+    /// Goes forward.
+    pub y: f32,
+    /// Turns clockwise.
+    pub rot: f32,
+}
+
+impl Sticks {
+    pub fn is_neutral(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    Binary,
+}
+
+/// A whole packet for rover `id`, terminating zero included. Unsequenced,
+/// as a lost one is superseded by the next anyway.
+pub fn encode(sticks: Sticks, encoding: Encoding, id: u8) -> Option<Vec<u8>> {
+    let Sticks { x, y, rot } = sticks;
+    let msg = RxMessage {
+        seq: None,
+        at_ms: None,
+        body: RxBody::Sticks { x, y, rot },
+    };
+    let raw = match encoding {
+        Encoding::Json => serde_json::to_vec(&msg).ok()?,
+        Encoding::Binary => {
+            let mut raw = [0u8; 64];
+            rover_lib::wire::to_frame(&msg, &mut raw).ok()?.to_vec()
+        }
+    };
+    let mut packet = vec![0u8; framing::max_packet_len(raw.len())];
+    let n = framing::encode(id, &raw, &mut packet).ok()?;
+    packet.truncate(n);
+    Some(packet)
+}